@@ -0,0 +1,182 @@
+//! Python bindings for ReadChop
+//!
+//! Reads are classified by the same splitter as the command-line tool, so a
+//! notebook sees exactly the assignments a run would make.
+
+use bio::io::fastq::Record;
+use clap::Parser;
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyIterator;
+use readchop_core::args::Args;
+use readchop_core::fastq::ReadInfo;
+use readchop_core::pattern::{self, PatternConfiguration};
+use readchop_core::splitter;
+use std::collections::VecDeque;
+
+/// Quality assumed for reads passed without one (Phred 40)
+const DEFAULT_QUALITY: u8 = b'I';
+
+/// Matching configuration built from the command-line options
+///
+/// `options` takes any further flags of the main command, e.g.
+/// `["-e", "0.15,0.15", "--trim-mode", "1"]`.
+#[pyclass(module = "readchop")]
+struct Config {
+    pattern_config: PatternConfiguration,
+}
+
+#[pymethods]
+impl Config {
+    #[new]
+    #[pyo3(signature = (pattern_db, pattern_files, options = Vec::new()))]
+    fn new(pattern_db: String, pattern_files: Vec<String>, options: Vec<String>) -> PyResult<Self> {
+        let mut command_line = vec!["readchop".to_string(), "-d".to_string(), pattern_db, "-p".to_string()];
+        command_line.extend(pattern_files);
+        command_line.extend(options);
+        let args = Args::try_parse_from(command_line).map_err(|error| PyValueError::new_err(error.to_string()))?;
+        Ok(Self { pattern_config: pattern::load_patterns(&args) })
+    }
+}
+
+/// Assignment of one read, or of a sub-read cut at an internal adapter
+#[pyclass(module = "readchop", get_all)]
+#[derive(Clone)]
+struct SplitResult {
+    /// Input read name, with `/1` or `/2` for sub-reads
+    name: String,
+    /// `valid`, `unknown`, `filtered`, `fusion`, `ambiguous` or `unexpected`
+    sequence_type: String,
+    /// Matched pattern name per round
+    match_names: Vec<String>,
+    /// Matched pattern type per round
+    match_types: Vec<String>,
+    /// `fs`, `rs` or `unknown`
+    strand: String,
+    /// Output file the read is written to, without `.fq.gz`
+    output_name: String,
+    /// ID of the output record
+    record_id: String,
+    /// Whether the read is written to an output file
+    written: bool,
+    /// Start of the written part of the read
+    trim_start: usize,
+    /// End of the written part of the read, exclusive
+    trim_end: usize,
+}
+
+impl SplitResult {
+    /// Result fields of a classified read
+    fn from_read_info(read_info: &ReadInfo) -> Self {
+        let (trim_start, trim_end) = read_info.trim_positions;
+        Self {
+            name: read_info.original_id.clone(),
+            sequence_type: read_info.sequence_type.clone(),
+            match_names: read_info.match_names.clone(),
+            match_types: read_info.match_types.clone(),
+            strand: read_info.strand_orientation.clone(),
+            output_name: read_info.output_filename.clone(),
+            record_id: read_info.record_id.clone(),
+            written: read_info.should_write_to_fastq,
+            trim_start,
+            trim_end: if trim_end == 0 { read_info.sequence_length } else { trim_end },
+        }
+    }
+}
+
+#[pymethods]
+impl SplitResult {
+    fn __repr__(&self) -> String {
+        format!(
+            "SplitResult(name={:?}, sequence_type={:?}, match_names={:?}, strand={:?})",
+            self.name, self.sequence_type, self.match_names, self.strand
+        )
+    }
+}
+
+/// Classify a read and any sub-reads cut from it
+fn classify(name: &str, sequence: &str, quality: Option<&str>, config: &Config) -> PyResult<Vec<SplitResult>> {
+    let quality = match quality {
+        Some(quality) if quality.len() != sequence.len() => {
+            return Err(PyValueError::new_err(format!("Quality of {} does not match its sequence length", name)));
+        }
+        Some(quality) => quality.as_bytes().to_vec(),
+        None => vec![DEFAULT_QUALITY; sequence.len()],
+    };
+    let read_info = ReadInfo::new(Record::with_attrs(name, None, sequence.as_bytes(), &quality));
+    Ok(splitter::classify_read(read_info, &config.pattern_config)
+        .iter()
+        .map(SplitResult::from_read_info)
+        .collect())
+}
+
+/// Classify one read, returning the assignment of the whole read
+#[pyfunction]
+#[pyo3(signature = (sequence, config, quality = None, name = "read"))]
+fn classify_read(sequence: &str, config: PyRef<'_, Config>, quality: Option<&str>, name: &str) -> PyResult<SplitResult> {
+    let mut results = classify(name, sequence, quality, &config)?;
+    Ok(results.remove(0))
+}
+
+/// Iterator over the assignments of a stream of reads
+#[pyclass(module = "readchop")]
+struct Demultiplexer {
+    records: Py<PyIterator>,
+    config: Py<Config>,
+    pending: VecDeque<SplitResult>,
+    read_count: usize,
+}
+
+#[pymethods]
+impl Demultiplexer {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<SplitResult>> {
+        loop {
+            if let Some(result) = self.pending.pop_front() {
+                return Ok(Some(result));
+            }
+            let Some(record) = self.records.bind(py).clone().next() else {
+                return Ok(None);
+            };
+            let record = record?;
+            self.read_count += 1;
+
+            // Records are a bare sequence, (name, sequence) or (name, sequence, quality)
+            let (name, sequence, quality) = if let Ok(sequence) = record.extract::<String>() {
+                (format!("read_{}", self.read_count), sequence, None)
+            } else if let Ok((name, sequence, quality)) = record.extract::<(String, String, Option<String>)>() {
+                (name, sequence, quality)
+            } else if let Ok((name, sequence)) = record.extract::<(String, String)>() {
+                (name, sequence, None)
+            } else {
+                return Err(PyTypeError::new_err("Records should be a sequence or a (name, sequence[, quality]) tuple"));
+            };
+            let config = self.config.borrow(py);
+            self.pending.extend(classify(&name, &sequence, quality.as_deref(), &config)?);
+        }
+    }
+}
+
+/// Classify reads from an iterable lazily, yielding one result per read and sub-read
+#[pyfunction]
+fn demultiplex(records: &Bound<'_, PyAny>, config: Py<Config>) -> PyResult<Demultiplexer> {
+    Ok(Demultiplexer {
+        records: records.iter()?.unbind(),
+        config,
+        pending: VecDeque::new(),
+        read_count: 0,
+    })
+}
+
+#[pymodule]
+fn readchop(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Config>()?;
+    module.add_class::<SplitResult>()?;
+    module.add_class::<Demultiplexer>()?;
+    module.add_function(wrap_pyfunction!(classify_read, module)?)?;
+    module.add_function(wrap_pyfunction!(demultiplex, module)?)?;
+    Ok(())
+}
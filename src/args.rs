@@ -28,7 +28,7 @@ pub struct Args {
     #[command(subcommand)]
     pub command: Option<Commands>,
 
-    /// Input file paths
+    /// Input file paths, directories, globs, or https:// and s3:// URLs
     #[arg(short, long, num_args = 1.., value_delimiter = ' ')]
     pub inputs: Vec<String>,
     
@@ -36,8 +36,12 @@ pub struct Args {
     #[arg(short, long, default_value = "outdir")]
     pub outdir: String,
     
-    /// Number of threads
-    #[arg(short, long, default_value = "20")]
+    /// Overwrite the outputs of an earlier run in the output directory
+    #[arg(long = "force")]
+    pub force: bool,
+    
+    /// Number of threads, 0 for one per available CPU
+    #[arg(short, long, default_value = "0")]
     pub threads: usize,
     
     /// Minimum sequence length filter threshold
@@ -45,14 +49,26 @@ pub struct Args {
     pub min_length: usize,
     
     /// Pattern file list
-    #[arg(short, long, required = true, num_args = 1.., value_delimiter = ' ')]
+    #[arg(short, long, required_unless_present_any = ["trim_only", "kit"], num_args = 1.., value_delimiter = ' ')]
     pub pattern_files: Option<Vec<String>>,
     
+    /// Trim any database sequence found at either read end and write all reads to trimmed.fq.gz, without pattern files
+    #[arg(long = "trim-only", conflicts_with = "pattern_files")]
+    pub trim_only: bool,
+    
     /// Pattern database file
-    #[arg(short = 'd', long = "db", required = true)]
+    #[arg(short = 'd', long = "db", required_unless_present = "kit")]
     pub pattern_db_file: Option<String>,
     
-    /// Fusion detection file
+    /// Embedded barcode kit used instead of --db and --pattern-files, by vendor name such as SQK-NBD114-96
+    #[arg(long, conflicts_with_all = ["pattern_db_file", "pattern_files"], value_parser = validate_kit)]
+    pub kit: Option<String>,
+    
+    /// Pattern database format, `auto` detecting FASTA and CSV by extension or a leading `>`
+    #[arg(long = "db-format", default_value = "auto", value_parser = ["auto", "tsv", "csv", "fasta"])]
+    pub database_format: String,
+    
+    /// Fusion detection file: pattern names with an optional class column, each searched on both strands
     #[arg(short = 'f', long = "fusion", default_value = "")]
     pub fusion_file: String,
     
@@ -60,10 +76,66 @@ pub struct Args {
     #[arg(long = "fe", default_value = "0.2")]
     pub fusion_error_rate: f32,
     
+    /// Fusion read handling: drop, write to fusion.fq.gz, split at the internal match, or tag the ID
+    #[arg(long = "fusion-action", default_value = "drop", value_parser = ["drop", "write", "split", "tag"])]
+    pub fusion_action: String,
+    
+    /// Shorthand for --fusion-action split
+    #[arg(long = "split-fusion")]
+    pub split_fusion: bool,
+    
+    /// Handling of fusion reads per fusion file class, CLASS=ACTION with the --fusion-action values, e.g. 'spike-in=write vector=drop'; a written class goes to <class>.fq.gz
+    #[arg(long = "fusion-class-action", num_args = 1.., value_delimiter = ' ', value_parser = validate_fusion_class_action)]
+    pub fusion_class_actions: Vec<(String, String)>,
+    
+    /// Region searched for fusion patterns: between the outermost round's matches (insert), between the innermost round's matches (inner), or the whole read
+    #[arg(long = "fusion-window", default_value = "insert", value_parser = ["insert", "inner", "read"])]
+    pub fusion_window: String,
+    
+    /// Ignore fusion pattern hits within this many bases of the fusion window ends, e.g. primers overlapping the barcodes
+    #[arg(long = "fusion-min-distance-from-ends", default_value = "0")]
+    pub fusion_min_end_distance: usize,
+    
+    /// Cut concatemer reads at internal end patterns of the outermost round and write each insert as its own record, suffixed /1, /2, ...
+    #[arg(long = "recursive-split")]
+    pub recursive_split: bool,
+    
+    /// Sample sheet mapping pattern names or types to sample IDs (TSV: pattern, sample, optional expected relative read share)
+    #[arg(long = "sample-sheet")]
+    pub sample_sheet: Option<String>,
+    
+    /// Warn about barcodes with this many times fewer or more reads than expected, also listed in warnings.json (0: no check)
+    #[arg(long = "balance-fold", default_value = "3", value_parser = validate_balance_fold)]
+    pub balance_fold: f64,
+    
+    /// Only write reads assigned to these pattern names (one per line), others count as unexpected
+    #[arg(long = "expected-barcodes")]
+    pub expected_barcodes: Option<String>,
+    
+    /// Truth labels (TSV: read ID, expected pattern name) to evaluate precision and recall against
+    #[arg(long = "truth")]
+    pub truth: Option<String>,
+    
     /// Log recording interval
     #[arg(short = 'n', long = "num", default_value = "500000")]
     pub log_interval: u32,
     
+    /// Log more detail, -v for debug and -vv for trace messages [default: info, or RUST_LOG]
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
+    
+    /// Log less, -q for warnings only, -qq for errors only and -qqq for nothing
+    #[arg(short, long, action = clap::ArgAction::Count, global = true, conflicts_with = "verbose")]
+    pub quiet: u8,
+    
+    /// Also write log lines, uncolored, to this file
+    #[arg(long = "log-file", global = true)]
+    pub log_file: Option<String>,
+    
+    /// Log line format: text, or json with one object per line
+    #[arg(long = "log-format", default_value = "text", value_parser = ["text", "json"], global = true)]
+    pub log_format: String,
+    
     /// Search window size <left window, right window>
     #[arg(short, long, value_delimiter = ',', default_value = "400,400")]
     pub window_size: Vec<usize>,
@@ -76,8 +148,20 @@ pub struct Args {
     #[arg(long, default_value = "0")]
     pub trim_mode: usize,
     
-    /// Write type: names=use names, type=use types
-    #[arg(long, default_value = "type", value_parser = ["names", "type"])]
+    /// Bases to move the left cut position inward (negative: outward), one value per round or the last reused
+    #[arg(long = "trim-offset-left", num_args = 1.., value_delimiter = ',', default_value = "0", allow_negative_numbers = true)]
+    pub trim_offset_left: Vec<isize>,
+    
+    /// Bases to move the right cut position inward (negative: outward), one value per round or the last reused
+    #[arg(long = "trim-offset-right", num_args = 1.., value_delimiter = ',', default_value = "0", allow_negative_numbers = true)]
+    pub trim_offset_right: Vec<isize>,
+    
+    /// Patterns kept at the trim cut: none, left, right or both, one value per round or the last reused [default: none for --trim-mode 0, both otherwise]
+    #[arg(long = "keep-pattern", num_args = 1.., value_delimiter = ',', value_parser = ["none", "left", "right", "both"])]
+    pub keep_pattern: Vec<String>,
+    
+    /// Write type: names=use names, type=use types, flat=types joined by _, barcode-only=outermost type, none=one file
+    #[arg(long, default_value = "type", value_parser = ["names", "type", "flat", "barcode-only", "none"])]
     pub write_type: String,
     
     /// Pattern matching type: single=single pattern, dual=dual pattern
@@ -96,19 +180,278 @@ pub struct Args {
     #[arg(long = "maxdist", num_args = 1.., value_delimiter = ',', default_value = "4")]
     pub max_distance: Vec<usize>,
     
+    /// Ambiguous pattern positions left out of the length error rates apply to: flanking (leading and trailing N), all (every non-ACGT base) or none
+    #[arg(long = "effective-length", default_value = "flanking", value_parser = ["flanking", "all", "none"])]
+    pub effective_length: String,
+    
+    /// Absolute edit distance cap per round, applied when stricter than the --pattern_error_rate budget (one value per round or the last reused)
+    #[arg(long = "max-edits", num_args = 1.., value_delimiter = ',')]
+    pub max_edits: Vec<u8>,
+    
     /// Record ID separator
-    #[arg(long = "id_sep", default_value = "%")]
+    #[arg(long = "id_sep", default_value = "%", value_parser = validate_id_separator)]
     pub id_separator: String,
+    
+    /// Where output records carry the match annotation: joined into the read ID, or in the description after the original read ID
+    #[arg(long = "tag-location", default_value = "id", value_parser = ["id", "description"])]
+    pub tag_location: String,
+    
+    /// Stop searching a window once a match scores at or below this edit distance (ignored with --min-score-margin, which needs every pattern scored)
+    #[arg(long = "early-exit-score", default_value = "0")]
+    pub early_exit_score: usize,
+    
+    /// Anchored matching: left patterns must start within N bp of the 5' end and right patterns end within N bp of the 3' end [0: off]
+    #[arg(long = "anchor", default_value = "0")]
+    pub anchor: usize,
+    
+    /// Barcodes at fixed coordinates, compared by Hamming distance instead of aligned: start,len per round from each read end, `-` for an aligned round
+    #[arg(long = "fixed-position", num_args = 1.., value_delimiter = ' ', value_parser = validate_fixed_position)]
+    pub fixed_position: Vec<Option<(usize, usize)>>,
+    
+    /// Dual-end resolution: best-score, both-required (pairs must be known), or either end; unknown pairs go to index_hop
+    #[arg(long = "dual-policy", default_value = "best-score", value_parser = ["best-score", "both-required", "either"])]
+    pub dual_policy: String,
+    
+    /// Mark reads ambiguous when the runner-up pattern scores fewer than M edits worse than the best [0: off]
+    #[arg(long = "min-score-margin", default_value = "0")]
+    pub min_score_margin: usize,
+    
+    /// Reverse-complement reads matched on the reverse strand (rs) so each output shares one orientation
+    #[arg(long = "orient")]
+    pub orient: bool,
+    
+    /// Classify only the first N reads, print the projected distribution and ask before the full run
+    #[arg(long = "preview", default_value = "0")]
+    pub preview: usize,
+    
+    /// Choose per-round error rates from a permissive classification of the first N reads, replacing -e
+    #[arg(long = "auto-tune", default_value = "0", conflicts_with = "trim_only")]
+    pub auto_tune: usize,
+    
+    /// Remember up to N window searches per thread and reuse them for reads with identical ends, 0 to disable
+    #[arg(long = "score-cache", default_value = "0")]
+    pub score_cache: usize,
+    
+    /// Continue after --preview without asking for confirmation
+    #[arg(long = "yes")]
+    pub yes: bool,
+    
+    /// Directory for temporary spill files [default: system temp directory]
+    #[arg(long = "tmpdir")]
+    pub tmpdir: Option<String>,
+    
+    /// Index read files, I1 and optionally I2, read in step with the inputs R1 and optionally R2: patterns are matched in I1 followed by the reverse complement of I2, and R1 (with R2 to <output>_R2.fq.gz, or interleaved) is written untrimmed
+    #[arg(long = "index-reads", num_args = 1..=2, value_delimiter = ' ', conflicts_with_all = ["emit_raw_copy", "recursive_split", "trim_only", "watch", "preview"])]
+    pub index_reads: Vec<String>,
+    
+    /// Inputs hold read pairs as consecutive R1/R2 records: patterns are matched in R1, and each pair is written interleaved with R2 untrimmed; fusion reads are not split
+    #[arg(long = "interleaved", conflicts_with_all = ["emit_raw_copy", "recursive_split"])]
+    pub interleaved: bool,
+    
+    /// Also write the untrimmed record to a parallel raw/ output tree
+    #[arg(long = "emit-raw-copy")]
+    pub emit_raw_copy: bool,
+    
+    /// Write the read log per output, as <output>.reads_log.gz next to each FASTQ file, instead of one reads_log.gz
+    #[arg(long = "shard-read-log")]
+    pub shard_read_log: bool,
+    
+    /// Gzip compression level of the read log, 0 (fastest) to 9 (smallest)
+    #[arg(long = "log-compression-level", default_value = "6", value_parser = clap::value_parser!(u32).range(0..=9))]
+    pub log_compression_level: u32,
+    
+    /// Start a new read log file every N reads, as reads_log.0001.gz, reads_log.0002.gz, ... (0: one file)
+    #[arg(long = "rotate-read-log", default_value = "0", conflicts_with = "shard_read_log")]
+    pub rotate_read_log: u64,
+    
+    /// Do not write the per-read log at all
+    #[arg(long = "no-read-log", conflicts_with_all = ["shard_read_log", "rotate_read_log"])]
+    pub no_read_log: bool,
+    
+    /// Also write one JSON object per read (matches with scores and coordinates, assignment, trim positions) to reads_log.jsonl.gz; with --no-read-log it replaces the TSV log
+    #[arg(long = "json-log")]
+    pub json_log: bool,
+    
+    /// Format of the per-read log: tsv (reads_log.gz) or parquet (reads_log.parquet, typed columns with the matched ends split into pattern, score and coordinates)
+    #[arg(long = "read-log-format", default_value = "tsv", value_parser = ["tsv", "parquet"], conflicts_with_all = ["shard_read_log", "rotate_read_log", "no_read_log"])]
+    pub read_log_format: String,
+    
+    /// Pattern names to exclude from matching, e.g. BC12,BC47
+    #[arg(long = "disable-patterns", value_delimiter = ',')]
+    pub disabled_patterns: Vec<String>,
+    
+    /// Use small buffers and flush output eagerly, for quick interactive runs on few reads
+    #[arg(long = "low-latency")]
+    pub low_latency: bool,
+    
+    /// Unix socket emitting JSON progress events and accepting pause/resume/flush-stats/stop-after-current-file
+    #[arg(long = "control-socket")]
+    pub control_socket: Option<String>,
+    
+    /// Serve reads processed, valid rate, per-barcode counts and throughput for Prometheus on this TCP port, e.g. during --watch runs
+    #[arg(long = "metrics-port")]
+    pub metrics_port: Option<u16>,
+    
+    /// Maximum number of simultaneously open output files, least recently used are closed [0: no limit]
+    #[arg(long = "max-open-files", default_value = "0")]
+    pub max_open_files: usize,
+    
+    /// Outputs with fewer reads are written to low_count.fq.gz instead [0: keep all outputs]
+    #[arg(long = "min-reads-per-barcode", default_value = "0")]
+    pub min_reads_per_barcode: usize,
+    
+    /// Process only the first N selected reads [0: all reads]
+    #[arg(long = "head", default_value = "0")]
+    pub head: usize,
+    
+    /// Randomly keep this fraction of reads
+    #[arg(long = "sample-fraction", default_value = "1.0", value_parser = validate_fraction)]
+    pub sample_fraction: f64,
+    
+    /// Random seed for --sample-fraction
+    #[arg(long = "seed", default_value = "0")]
+    pub seed: u64,
+    
+    /// Write all output records, uncompressed and prefixed with the read name, to standard output
+    #[arg(long = "stdout")]
+    pub stdout: bool,
+    
+    /// Pipe each output's uncompressed records into this shell command, {name} standing for the output path without suffix, e.g. 'minimap2 -a ref.fa - > {name}.sam'
+    #[arg(long = "pipe", conflicts_with_all = ["stdout", "output_url", "emit_raw_copy", "max_open_files"])]
+    pub pipe: Option<String>,
+    
+    /// Upload each output as gzip FASTQ below this s3:// prefix with the AWS CLI instead of writing it locally
+    #[arg(long = "output-url", value_parser = validate_s3_url, conflicts_with_all = ["stdout", "emit_raw_copy", "max_open_files"])]
+    pub output_url: Option<String>,
+    
+    /// Compress each output file by streaming its records into this shell command, which writes gzip to standard output, e.g. 'pigz -p4' or 'bgzip -@4'
+    #[arg(long = "external-compressor", conflicts_with_all = ["stdout", "pipe", "output_url"])]
+    pub external_compressor: Option<String>,
+    
+    /// Hash every output file and read log while it is written and list the digests in checksums.txt, checkable with md5sum -c or sha256sum -c
+    #[arg(long = "checksums", value_parser = ["md5", "sha256"], conflicts_with_all = ["stdout", "pipe", "output_url"])]
+    pub checksums: Option<String>,
+    
+    /// Fail on non-IUPAC characters in pattern sequences instead of treating them as N
+    #[arg(long = "strict-bases")]
+    pub strict_bases: bool,
+    
+    /// Database names or pattern file pairs listed twice: warn and skip the repeated line, or fail
+    #[arg(long = "duplicate-patterns", default_value = "warn", value_parser = ["warn", "fail"])]
+    pub duplicate_patterns: String,
+    
+    /// Append left/right match scores and coordinates of each round to output record IDs
+    #[arg(long = "annotate-score")]
+    pub annotate_score: bool,
+    
+    /// Trim low-quality read ends below this Phred score (modified Mott algorithm, 0 = off)
+    #[arg(long = "qual-trim", default_value = "0")]
+    pub qual_trim: u8,
+    
+    /// Trim a homopolymer tail of at least this length next to the 3' primer, e.g. A,10
+    #[arg(long = "trim-polyx", value_parser = validate_polyx)]
+    pub trim_polyx: Option<(u8, usize)>,
+    
+    /// Count reads per combination of these fields in grouped_stats.tsv: ONT header keys (channel, start_time, barcode, ...), sequence_type or match/name/type/strand with a round number
+    #[arg(long = "group-by", value_delimiter = ',', value_parser = validate_header_group_field)]
+    pub group_by: Vec<String>,
+    
+    /// Also split output files by the --group-by fields, e.g. barcode01/channel_123.fq.gz
+    #[arg(long = "group-output", requires = "group_by")]
+    pub group_output: bool,
+    
+    /// Output path of each file below the output directory, e.g. {run_id}/{date}/{output}: {output} is the --write-type path, {run_id} and {date} come from --run-id/--run-date or the ONT header, other fields as in --group-by
+    #[arg(long = "outdir-template", value_parser = validate_outdir_template)]
+    pub outdir_template: Option<String>,
+    
+    /// Run ID for {run_id} in --outdir-template [default: runid header field]
+    #[arg(long = "run-id", requires = "outdir_template")]
+    pub run_id: Option<String>,
+    
+    /// Date for {date} in --outdir-template [default: date of the start_time header field]
+    #[arg(long = "run-date", requires = "outdir_template")]
+    pub run_date: Option<String>,
+    
+    /// Write yield and valid rate per N minutes of ONT start_time to time_stats.tsv (reads without it are binned by read order) [0: off]
+    #[arg(long = "time-bin", default_value = "0")]
+    pub time_bin: u64,
+    
+    /// Find duplicate reads per output file by UMI (`umi=` header field, else leading bases), whole sequence or prefix
+    #[arg(long = "dedup", value_parser = ["umi", "exact", "prefix"])]
+    pub dedup: Option<String>,
+    
+    /// Bases used as the duplicate key by the umi and prefix modes [default: 12 for umi, 100 for prefix]
+    #[arg(long = "dedup-length", requires = "dedup")]
+    pub dedup_length: Option<usize>,
+    
+    /// What to do with duplicates: drop them, or tag their ID with `dup` and keep them
+    #[arg(long = "dedup-action", default_value = "drop", value_parser = ["drop", "tag"], requires = "dedup")]
+    pub dedup_action: String,
+    
+    /// Write per-thread splitting and writing times, Myers invocations and exact-hit rates to this TSV
+    #[arg(long = "perf-report")]
+    pub perf_report: Option<String>,
+    
+    /// Threads used to decompress each gzip input (BGZF inputs are inflated in parallel)
+    #[arg(long = "decompress-threads", default_value = "4")]
+    pub decompress_threads: usize,
+    
+    /// Number of input files read in parallel
+    #[arg(long = "reader-threads", default_value = "1")]
+    pub reader_threads: usize,
+    
+    /// Keep input read order in the output files and read log (files are read one at a time)
+    #[arg(long = "ordered")]
+    pub ordered: bool,
+    
+    /// Thread split between splitting and writing: balanced (80/20), priority (splitting first), fixed (even), or adaptive (rebalanced at runtime)
+    #[arg(long = "thread-strategy", default_value = "balanced", value_parser = ["balanced", "priority", "fixed", "adaptive"])]
+    pub thread_strategy: String,
+    
+    /// Keep polling the input directories or globs and process new FASTQ files as they appear
+    #[arg(long = "watch", requires = "inputs")]
+    pub watch: bool,
+    
+    /// Seconds between input scans and statistics updates with --watch
+    #[arg(long = "watch-interval", default_value = "10")]
+    pub watch_interval: u64,
+    
+    /// Stop watching once this file (or glob) exists, otherwise run until interrupted
+    #[arg(long = "watch-sentinel", requires = "watch")]
+    pub watch_sentinel: Option<String>,
 }
 
 /// Subcommand enumeration
 #[derive(Subcommand, Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
 pub enum Commands {
     /// Encrypt database file
+    #[cfg(feature = "encryption")]
     Encrypt {
         /// Database file to encrypt
         file: String,
     },
+    /// Recompute statistics from the reads_log.gz of a previous run
+    Stats {
+        /// Read log written by a previous run
+        #[arg(short, long)]
+        log: String,
+        /// Output directory for the regenerated reports
+        #[arg(short, long, default_value = "stats")]
+        outdir: String,
+        /// Fields grouped in grouped_stats.tsv: sequence_type, or matchN/nameN/typeN/strandN for round N
+        #[arg(long = "group-by", value_delimiter = ',', value_parser = validate_group_field)]
+        group_by: Vec<String>,
+    },
+    /// Combine the statistics of several output directories, e.g. per-flowcell runs of one library
+    MergeStats {
+        /// Output directories of the runs to combine
+        #[arg(required = true, num_args = 1..)]
+        directories: Vec<String>,
+        /// Output directory for the combined reports
+        #[arg(short, long, default_value = "merged_stats")]
+        outdir: String,
+    },
     /// Preview barcode detection results (with color highlighting)
     View {
         /// Input file paths
@@ -120,8 +463,8 @@ pub enum Commands {
         /// Pattern database file
         #[arg(short = 'd', long = "db", required = true)]
         pattern_db_file: String,
-        /// Number of threads
-        #[arg(short, long, default_value = "20")]
+        /// Number of threads, 0 for one per available CPU
+        #[arg(short, long, default_value = "0")]
         threads: usize,
         /// Minimum sequence length filter threshold
         #[arg(short, long, default_value = "100")]
@@ -148,8 +491,88 @@ pub enum Commands {
         #[arg(long = "maxdist", num_args = 1.., value_delimiter = ',', default_value = "4")]
         max_distance: Vec<usize>,
         /// Record ID separator
-        #[arg(long = "id_sep", default_value = "%")]
+        #[arg(long = "id_sep", default_value = "%", value_parser = validate_id_separator)]
         id_separator: String,
+        /// Stop after printing this many reads (0: no limit)
+        #[arg(long, default_value = "0")]
+        limit: usize,
+        /// Only print reads that were assigned (matched) or not (unmatched)
+        #[arg(long, value_parser = ["matched", "unmatched"])]
+        only: Option<String>,
+        /// Output format: colored text, one JSON object per line, or TSV
+        #[arg(long, default_value = "pretty", value_parser = ["pretty", "json", "tsv"])]
+        format: String,
+        /// Maximum displayed sequence width (default: terminal width, 0: no limit)
+        #[arg(long)]
+        width: Option<usize>,
+        /// Page through reads interactively instead of printing them
+        #[arg(long)]
+        tui: bool,
+    },
+    /// Generate FASTQ reads carrying known pattern combinations, with truth labels
+    Simulate {
+        /// Pattern file list, one per round from the outermost pattern inwards
+        #[arg(short, long, required = true, num_args = 1.., value_delimiter = ' ')]
+        pattern_files: Vec<String>,
+        /// Pattern database file
+        #[arg(short = 'd', long = "db", required = true)]
+        pattern_db_file: String,
+        /// Output FASTQ file, gzip compressed when it ends with .gz
+        #[arg(short, long, default_value = "simulated.fq.gz")]
+        output: String,
+        /// Truth label TSV: read ID, strand, chimera flag and pattern pair and type per round
+        #[arg(long, default_value = "simulated_truth.tsv")]
+        truth: String,
+        /// Number of reads to simulate
+        #[arg(short = 'n', long, default_value = "1000")]
+        reads: usize,
+        /// Insert length range <min length, max length>
+        #[arg(long, default_value = "500,3000", value_parser = validate_length_range)]
+        length: (usize, usize),
+        /// Per-base error rate, split evenly across substitutions, insertions and deletions
+        #[arg(long = "error-rate", default_value = "0.05", value_parser = validate_probability)]
+        error_rate: f64,
+        /// Fraction of reads built from two molecules
+        #[arg(long = "chimera-fraction", default_value = "0", value_parser = validate_probability)]
+        chimera_fraction: f64,
+        /// Maximum random bases outside the outermost patterns
+        #[arg(long, default_value = "30")]
+        flank: usize,
+        /// Random seed
+        #[arg(long, default_value = "1")]
+        seed: u64,
+    },
+    /// Run the whole pipeline on a bundled toy dataset and check the read counts and assignments
+    Selftest {
+        /// Number of reads to simulate
+        #[arg(short = 'n', long, default_value = "200")]
+        reads: usize,
+        /// Random seed
+        #[arg(long, default_value = "1")]
+        seed: u64,
+        /// Directory for the toy dataset and run outputs, kept afterwards [default: temporary directory, removed]
+        #[arg(long)]
+        directory: Option<String>,
+    },
+    /// Check the gzip integrity and records of every output file and its read count against the read log and statistics
+    Verify {
+        /// Output directory of the run
+        #[arg(short, long)]
+        outdir: String,
+        /// Verification report [default: <outdir>/verify_report.tsv]
+        #[arg(long)]
+        report: Option<String>,
+    },
+    /// List the embedded barcode kits available to --kit
+    ListKits,
+    /// Print the barcodes of an embedded kit as a database TSV, or export its database and pattern file
+    DescribeKit {
+        /// Kit name
+        #[arg(value_parser = validate_kit)]
+        kit: String,
+        /// Directory receiving `<kit>.tsv` and `<kit>.list` for use with --db and --pattern-files
+        #[arg(short, long)]
+        output: Option<String>,
     },
 }
 
@@ -165,13 +588,162 @@ fn validate_error_rate(input: &str) -> Result<(f32, f32), String> {
     let right_rate = error_rates[1].parse::<f32>();
     
     match (left_rate, right_rate) {
-        (Ok(left), Ok(right)) if left >= 0.0 && left <= 0.5 && right >= 0.0 && right <= 0.5 => {
+        (Ok(left), Ok(right)) if (0.0..=0.5).contains(&left) && (0.0..=0.5).contains(&right) => {
             Ok((left, right))
         },
         _ => Err("Error rate parameter error. Should be a floating point number between 0 and 0.5.".to_string()),
     }
 }
 
+/// Validate the record ID separator, which must not end the ID or read as a mate suffix
+fn validate_id_separator(input: &str) -> Result<String, String> {
+    if input.is_empty() {
+        return Err("ID separator must not be empty".to_string());
+    }
+    if input.chars().any(|character| character.is_whitespace() || character.is_control() || character == '/') {
+        return Err(format!("ID separator '{}' contains whitespace, control characters or '/', which FASTQ tools treat as the end of the read ID or a mate suffix", input.escape_default()));
+    }
+    Ok(input.to_string())
+}
+
+/// Validate a fixed barcode position, `start,len` or `-` for none
+fn validate_fixed_position(input: &str) -> Result<Option<(usize, usize)>, String> {
+    if input == "-" {
+        return Ok(None);
+    }
+    match input.split_once(',').map(|(start, length)| (start.parse::<usize>(), length.parse::<usize>())) {
+        Some((Ok(start), Ok(length))) if length > 0 => Ok(Some((start, length))),
+        _ => Err("Fixed position should be start,len with a length above 0, or - for none".to_string()),
+    }
+}
+
+/// Validate a fusion class action, `class=action`
+fn validate_fusion_class_action(input: &str) -> Result<(String, String), String> {
+    match input.split_once('=') {
+        Some((fusion_class, action)) if !fusion_class.is_empty() && ["drop", "write", "split", "tag"].contains(&action) => {
+            Ok((fusion_class.to_string(), action.to_string()))
+        }
+        _ => Err("Fusion class action should be class=action with action one of drop, write, split or tag, e.g. spike-in=write".to_string()),
+    }
+}
+
+/// Validate an embedded kit name, returning its canonical spelling
+fn validate_kit(input: &str) -> Result<String, String> {
+    match crate::kits::find_kit(input) {
+        Some(kit) => Ok(kit.name.to_string()),
+        None => {
+            let names: Vec<&str> = crate::kits::KITS.iter().map(|kit| kit.name).collect();
+            Err(format!("Unknown kit, available kits: {}", names.join(", ")))
+        }
+    }
+}
+
+/// Validate an S3 output prefix
+fn validate_s3_url(input: &str) -> Result<String, String> {
+    match input.strip_prefix("s3://") {
+        Some(path) if !path.is_empty() => Ok(input.to_string()),
+        _ => Err("Output URL should be an s3:// bucket or prefix, e.g. s3://bucket/run1".to_string()),
+    }
+}
+
+/// Validate a grouping field of the stats subcommand
+fn validate_group_field(input: &str) -> Result<String, String> {
+    if input == "sequence_type" || crate::stats::split_group_field(input).is_some() {
+        Ok(input.to_string())
+    } else {
+        Err("Group field should be sequence_type or match/name/type/strand followed by a round number, e.g. type1".to_string())
+    }
+}
+
+/// Validate a grouping field of the main command, per-read fields or any header key
+fn validate_header_group_field(input: &str) -> Result<String, String> {
+    if !input.is_empty() && !input.contains(|character: char| character.is_whitespace() || character == '=') {
+        Ok(input.to_string())
+    } else {
+        Err("Group field should be a header key without spaces or '=', e.g. channel".to_string())
+    }
+}
+
+/// Validate an output path template, every placeholder naming a field
+fn validate_outdir_template(input: &str) -> Result<String, String> {
+    let error = |message: &str| Err(format!("{}, e.g. {{run_id}}/{{date}}/{{output}}", message));
+    if input.starts_with('/') || input.split('/').any(|component| ["", ".", ".."].contains(&component)) {
+        return error("Output directory template should be a relative path without empty, . or .. components");
+    }
+    if !input.contains('{') {
+        return error("Output directory template should use fields in braces");
+    }
+    let mut rest = input;
+    while let Some(start) = rest.find('{') {
+        let Some(length) = rest[start..].find('}') else {
+            return error("Output directory template has an unclosed {");
+        };
+        if rest[..start].contains('}') {
+            return error("Output directory template has an unopened }");
+        }
+        let field = &rest[start + 1..start + length];
+        if !["output", "run_id", "date"].contains(&field) && validate_header_group_field(field).is_err() {
+            return error(&format!("Output directory template has an invalid field {{{}}}", field));
+        }
+        rest = &rest[start + length + 1..];
+    }
+    if rest.contains('}') {
+        return error("Output directory template has an unopened }");
+    }
+    Ok(input.to_string())
+}
+
+/// Validate a fraction in (0, 1]
+fn validate_fraction(input: &str) -> Result<f64, String> {
+    match input.parse::<f64>() {
+        Ok(fraction) if fraction > 0.0 && fraction <= 1.0 => Ok(fraction),
+        _ => Err("Fraction should be a floating point number greater than 0 and at most 1.".to_string()),
+    }
+}
+
+/// Validate a balance warning fold change, above 1 or 0 to disable the check
+fn validate_balance_fold(input: &str) -> Result<f64, String> {
+    match input.parse::<f64>() {
+        Ok(fold) if fold == 0.0 || fold > 1.0 => Ok(fold),
+        _ => Err("Balance fold should be a number above 1, or 0 to disable the check.".to_string()),
+    }
+}
+
+/// Validate a probability in [0, 1]
+fn validate_probability(input: &str) -> Result<f64, String> {
+    match input.parse::<f64>() {
+        Ok(probability) if (0.0..=1.0).contains(&probability) => Ok(probability),
+        _ => Err("Probability should be a floating point number between 0 and 1.".to_string()),
+    }
+}
+
+/// Validate a length range of two comma-separated values
+fn validate_length_range(input: &str) -> Result<(usize, usize), String> {
+    let error = || "Length range should be two comma-separated lengths with min <= max, e.g. 500,3000".to_string();
+    let (min_length, max_length) = input.split_once(',').ok_or_else(error)?;
+    match (min_length.trim().parse::<usize>(), max_length.trim().parse::<usize>()) {
+        (Ok(min_length), Ok(max_length)) if min_length <= max_length => Ok((min_length, max_length)),
+        _ => Err(error()),
+    }
+}
+
+/// Validate a homopolymer base and minimum tail length
+fn validate_polyx(input: &str) -> Result<(u8, usize), String> {
+    let error = || "Poly-X parameter should be one of A, T, G, C followed by a minimum length, e.g. A,10".to_string();
+    let (base, min_length) = input.split_once(',').ok_or_else(error)?;
+    let base = match base.to_ascii_uppercase().as_str() {
+        "A" => b'A',
+        "T" => b'T',
+        "G" => b'G',
+        "C" => b'C',
+        _ => return Err(error()),
+    };
+    match min_length.parse::<usize>() {
+        Ok(min_length) if min_length > 0 => Ok((base, min_length)),
+        _ => Err(error()),
+    }
+}
+
 impl Args {
     /// Get pattern file list, return empty vector if None
     pub fn get_pattern_files(&self) -> Vec<String> {
@@ -183,6 +755,15 @@ impl Args {
         self.pattern_db_file.clone().unwrap_or_default()
     }
     
+    /// Get fusion read handling, with --split-fusion taking precedence
+    pub fn get_fusion_action(&self) -> String {
+        if self.split_fusion {
+            "split".to_string()
+        } else {
+            self.fusion_action.clone()
+        }
+    }
+    
     /// Check if fusion detection is enabled
     pub fn is_fusion_detection_enabled(&self) -> bool {
         !self.fusion_file.is_empty()
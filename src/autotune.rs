@@ -0,0 +1,126 @@
+use crate::args::Args;
+use crate::fastq::{is_stream_input, read_first_records};
+use crate::pattern::load_patterns;
+use crate::splitter::{classify_read, Matcher};
+use log::{info, warn};
+use std::path::Path;
+
+/// Error rate of the calibration pass, loose enough that unmatched ends still report their best hit
+const CALIBRATION_ERROR_RATE: f32 = 0.35;
+
+/// Width of the histogram bins the threshold is chosen from
+const BIN_WIDTH: f64 = 0.01;
+
+/// Matches needed on a read end before its threshold is tuned
+const MIN_MATCHES: usize = 20;
+
+/// Fraction of matches a cluster of chance hits holds at least
+const MIN_NOISE_FRACTION: f64 = 0.05;
+
+/// Distance below the calibration error rate within which chance hits cluster
+const NOISE_MARGIN: f64 = 0.1;
+
+/// Edit distance per pattern base of each match in the calibration pass, per round and read end
+type RoundRates = Vec<(Vec<f64>, Vec<f64>)>;
+
+/// Choose per-round error rates from the first `args.auto_tune` reads
+///
+/// The reads are classified at a permissive error rate, and each read end's
+/// threshold is placed in the valley between true matches, a few edits per
+/// pattern, and chance hits near the permissive limit. Ends with too few
+/// matches keep their `-e` value.
+pub fn tune_error_rates(args: &Args) -> Vec<(f32, f32)> {
+    if args.inputs.is_empty() {
+        panic!("--auto-tune requires input files, standard input cannot be read twice");
+    }
+    if let Some(input) = args.inputs.iter().find(|input| is_stream_input(Path::new(input))) {
+        panic!("--auto-tune reads the input twice, so it cannot read the pipe {}", input);
+    }
+    info!("Calibrating error rates on the first {} reads", args.auto_tune);
+    let mut calibration_args = args.clone();
+    calibration_args.pattern_error_rate = vec![(CALIBRATION_ERROR_RATE, CALIBRATION_ERROR_RATE)];
+    let pattern_config = load_patterns(&calibration_args);
+
+    let rounds = pattern_config.pattern_arguments.len();
+    let mut rates: RoundRates = vec![(Vec::new(), Vec::new()); rounds];
+    let match_rate = |round: usize, matcher: &Matcher| -> Option<f64> {
+        if !matcher.status {
+            return None;
+        }
+        let pattern_database = &pattern_config.pattern_arguments[round].pattern_database;
+        let pattern = pattern_database.forward_patterns.get(matcher.get_pattern())?;
+        let pattern_length = pattern_config.pattern_arguments[round].effective_length.pattern_length(pattern.as_bytes());
+        (pattern_length > 0).then(|| matcher.get_score() as f64 / pattern_length as f64)
+    };
+    for read_info in read_first_records(&args.inputs, args.auto_tune, args.interleaved) {
+        let classified_read = classify_read(read_info, &pattern_config).swap_remove(0);
+        for (round, split_type) in classified_read.split_types.iter().enumerate().take(rounds) {
+            rates[round].0.extend(match_rate(round, &split_type.left_matcher));
+            rates[round].1.extend(match_rate(round, &split_type.right_matcher));
+        }
+    }
+
+    let mut tuned = Vec::with_capacity(rounds);
+    for (round, (left_rates, right_rates)) in rates.iter().enumerate() {
+        let (left_default, right_default) = args.pattern_error_rate[round.min(args.pattern_error_rate.len() - 1)];
+        let left = choose_threshold(left_rates).unwrap_or(left_default as f64) as f32;
+        let right = choose_threshold(right_rates).unwrap_or(right_default as f64) as f32;
+        info!(
+            "Round {}: error rate {},{} from {} left and {} right matches",
+            round + 1, left, right, left_rates.len(), right_rates.len()
+        );
+        tuned.push((left, right));
+    }
+    tuned
+}
+
+/// Error rate separating true matches from chance hits, None with too few matches
+///
+/// Otsu's method splits the histogram where the between-class variance is
+/// largest. Unless the upper class is a cluster of chance hits near the
+/// calibration error rate, the 99th percentile of the rates is used instead
+/// so true matches are not cut.
+fn choose_threshold(rates: &[f64]) -> Option<f64> {
+    if rates.len() < MIN_MATCHES {
+        warn!("Only {} matches on a read end, keeping its -e error rate", rates.len());
+        return None;
+    }
+    let bin_count = (CALIBRATION_ERROR_RATE as f64 / BIN_WIDTH).ceil() as usize + 1;
+    let bin = |rate: f64| ((rate / BIN_WIDTH) as usize).min(bin_count - 1);
+    let mut histogram = vec![0usize; bin_count];
+    for &rate in rates {
+        histogram[bin(rate)] += 1;
+    }
+
+    let total = rates.len() as f64;
+    let total_sum: f64 = histogram.iter().enumerate().map(|(index, &count)| index as f64 * count as f64).sum();
+    let (mut low_count, mut low_sum) = (0.0, 0.0);
+    let (mut best_variance, mut best_bin) = (0.0, 0);
+    for (index, &count) in histogram.iter().enumerate().take(bin_count - 1) {
+        low_count += count as f64;
+        low_sum += index as f64 * count as f64;
+        let high_count = total - low_count;
+        if low_count == 0.0 || high_count == 0.0 {
+            continue;
+        }
+        let mean_difference = low_sum / low_count - (total_sum - low_sum) / high_count;
+        let variance = low_count * high_count * mean_difference * mean_difference;
+        if variance > best_variance {
+            (best_variance, best_bin) = (variance, index);
+        }
+    }
+
+    let noise_rates: Vec<f64> = rates.iter().copied().filter(|&rate| bin(rate) > best_bin).collect();
+    let noise_mean = noise_rates.iter().sum::<f64>() / noise_rates.len().max(1) as f64;
+    let threshold = if (noise_rates.len() as f64) < total * MIN_NOISE_FRACTION
+        || noise_mean < CALIBRATION_ERROR_RATE as f64 - NOISE_MARGIN
+    {
+        let mut sorted_rates = rates.to_vec();
+        sorted_rates.sort_by(f64::total_cmp);
+        sorted_rates[((total * 0.99) as usize).min(sorted_rates.len() - 1)]
+    } else {
+        (best_bin + 1) as f64 * BIN_WIDTH - f64::EPSILON
+    };
+    // Rounded up to two decimals, so the highest accepted rate stays within the budget
+    Some(((threshold * 100.0).ceil() / 100.0).min(CALIBRATION_ERROR_RATE as f64))
+}
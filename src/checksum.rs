@@ -0,0 +1,272 @@
+use log::info;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Read, Result, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Per-round shift amounts of MD5
+const MD5_SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+
+/// MD5 round constants, the integer part of |sin(i + 1)| * 2^32
+const MD5_CONSTANTS: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee,
+    0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be,
+    0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa,
+    0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed,
+    0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c,
+    0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05,
+    0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039,
+    0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1,
+    0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+/// Streaming MD5, as printed by md5sum
+#[derive(Clone)]
+struct Md5 {
+    state: [u32; 4],
+    /// Bytes of the current incomplete block
+    block: [u8; 64],
+    block_length: usize,
+    /// Total bytes hashed
+    length: u64,
+}
+
+impl Md5 {
+    fn new() -> Self {
+        Self { state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476], block: [0; 64], block_length: 0, length: 0 }
+    }
+
+    fn update(&mut self, mut bytes: &[u8]) {
+        self.length += bytes.len() as u64;
+        while !bytes.is_empty() {
+            let taken = (64 - self.block_length).min(bytes.len());
+            self.block[self.block_length..self.block_length + taken].copy_from_slice(&bytes[..taken]);
+            self.block_length += taken;
+            bytes = &bytes[taken..];
+            if self.block_length == 64 {
+                let block = self.block;
+                self.compress(&block);
+                self.block_length = 0;
+            }
+        }
+    }
+
+    /// Mix one 64-byte block into the state
+    fn compress(&mut self, block: &[u8; 64]) {
+        let words: Vec<u32> = block.chunks_exact(4).map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]])).collect();
+        let [mut a, mut b, mut c, mut d] = self.state;
+        for round in 0..64 {
+            let (mixed, word) = match round / 16 {
+                0 => ((b & c) | (!b & d), round),
+                1 => ((d & b) | (!d & c), (5 * round + 1) % 16),
+                2 => (b ^ c ^ d, (3 * round + 5) % 16),
+                _ => (c ^ (b | !d), (7 * round) % 16),
+            };
+            let shift = MD5_SHIFTS[(round / 16) * 4 + round % 4];
+            let rotated = a.wrapping_add(mixed).wrapping_add(MD5_CONSTANTS[round]).wrapping_add(words[word]).rotate_left(shift);
+            (a, b, c, d) = (d, b.wrapping_add(rotated), b, c);
+        }
+        for (value, added) in self.state.iter_mut().zip([a, b, c, d]) {
+            *value = value.wrapping_add(added);
+        }
+    }
+
+    /// Digest of the bytes hashed so far, padding a copy of the state
+    fn digest(&self) -> Vec<u8> {
+        let mut padded = self.clone();
+        let bit_length = self.length.wrapping_mul(8);
+        padded.update(&[0x80]);
+        while padded.block_length != 56 {
+            padded.update(&[0]);
+        }
+        padded.update(&bit_length.to_le_bytes());
+        padded.state.iter().flat_map(|value| value.to_le_bytes()).collect()
+    }
+}
+
+/// Running hash of one file
+#[derive(Clone)]
+enum ChecksumHasher {
+    Md5(Box<Md5>),
+    Sha256(Box<Sha256>),
+}
+
+impl ChecksumHasher {
+    /// Hasher for a --checksums algorithm, `md5` or `sha256`
+    fn new(algorithm: &str) -> Self {
+        match algorithm {
+            "md5" => ChecksumHasher::Md5(Box::new(Md5::new())),
+            "sha256" => ChecksumHasher::Sha256(Box::new(Sha256::new())),
+            _ => panic!("Unknown checksum algorithm: {}", algorithm),
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        match self {
+            ChecksumHasher::Md5(hasher) => hasher.update(bytes),
+            ChecksumHasher::Sha256(hasher) => hasher.update(bytes),
+        }
+    }
+
+    /// Hex digest of the bytes hashed so far
+    fn hex_digest(&self) -> String {
+        let digest = match self {
+            ChecksumHasher::Md5(hasher) => hasher.digest(),
+            ChecksumHasher::Sha256(hasher) => hasher.as_ref().clone().finalize().to_vec(),
+        };
+        digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+/// Checksums of the files written during a run
+///
+/// Files are hashed as their bytes are written. A file closed and reopened in
+/// append mode picks up the state it was closed with, so each digest covers
+/// the whole file. Files written by another process are registered without a
+/// hasher and read back once when the table is written.
+pub struct ChecksumRegistry {
+    algorithm: String,
+    /// Digest state by file path, None for files hashed after the run
+    files: Mutex<HashMap<PathBuf, Option<ChecksumHasher>>>,
+}
+
+impl ChecksumRegistry {
+    pub fn new(algorithm: &str) -> Arc<Self> {
+        Arc::new(Self { algorithm: algorithm.to_string(), files: Mutex::new(HashMap::new()) })
+    }
+
+    /// Start or resume hashing a file, `append` keeping the bytes hashed before it was closed
+    pub fn start(self: &Arc<Self>, path: &Path, append: bool) -> FileChecksum {
+        let stored = self.files.lock().unwrap().remove(path).flatten();
+        let hasher = match stored {
+            Some(hasher) if append => hasher,
+            _ => ChecksumHasher::new(&self.algorithm),
+        };
+        FileChecksum { registry: Arc::clone(self), path: path.to_path_buf(), hasher }
+    }
+
+    /// Register a file written by another process, hashed from disk when the table is written
+    pub fn hash_when_written(&self, path: &Path) {
+        self.files.lock().unwrap().insert(path.to_path_buf(), None);
+    }
+
+    /// Write checksums.txt in md5sum/sha256sum format, paths relative to the output directory
+    pub fn write_table(&self, output_directory: &str) -> Result<()> {
+        let directory = Path::new(output_directory);
+        let mut rows = Vec::new();
+        for (path, hasher) in self.files.lock().unwrap().iter() {
+            let digest = match hasher {
+                Some(hasher) => hasher.hex_digest(),
+                None => hash_file(path, &self.algorithm)?,
+            };
+            let relative_path = path.strip_prefix(directory).unwrap_or(path);
+            rows.push((relative_path.display().to_string(), digest));
+        }
+        rows.sort();
+        let mut writer = BufWriter::new(File::create(directory.join("checksums.txt"))?);
+        for (path, digest) in &rows {
+            writeln!(writer, "{}  {}", digest, path)?;
+        }
+        writer.flush()?;
+        info!("{} checksums of {} files written to checksums.txt", self.algorithm.to_uppercase(), rows.len());
+        Ok(())
+    }
+}
+
+/// Hash state of a file being written, stored back in its registry when finished
+pub struct FileChecksum {
+    registry: Arc<ChecksumRegistry>,
+    path: PathBuf,
+    hasher: ChecksumHasher,
+}
+
+impl FileChecksum {
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.hasher.update(bytes);
+    }
+
+    /// Record the digest state once the file is complete
+    pub fn finish(self) {
+        self.registry.files.lock().unwrap().insert(self.path, Some(self.hasher));
+    }
+}
+
+/// Writer hashing every byte handed to the inner writer
+pub struct ChecksumWriter<W: Write> {
+    inner: W,
+    checksum: Option<FileChecksum>,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    pub fn new(inner: W, checksum: Option<FileChecksum>) -> Self {
+        Self { inner, checksum }
+    }
+
+    /// Flush the inner writer and record the digest state
+    pub fn finish(mut self) -> Result<W> {
+        self.inner.flush()?;
+        if let Some(checksum) = self.checksum {
+            checksum.finish();
+        }
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buffer: &[u8]) -> Result<usize> {
+        let written = self.inner.write(buffer)?;
+        if let Some(checksum) = self.checksum.as_mut() {
+            checksum.update(&buffer[..written]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Hex digest of a file read from disk
+fn hash_file(path: &Path, algorithm: &str) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = ChecksumHasher::new(algorithm);
+    let mut buffer = vec![0u8; 1024 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.hex_digest())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_md5_matches_reference_digests() {
+        let digest = |bytes: &[u8]| {
+            let mut hasher = ChecksumHasher::new("md5");
+            // Split updates must give the digest of the whole input
+            let (head, tail) = bytes.split_at(bytes.len() / 3);
+            hasher.update(head);
+            hasher.update(tail);
+            hasher.hex_digest()
+        };
+        assert_eq!(digest(b""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(digest(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(digest(b"The quick brown fox jumps over the lazy dog"), "9e107d9d372bb6826bd81d3542a419d6");
+        assert_eq!(digest(&[b'a'; 1000]), "cabe45dcc9ae5b66ba86600cca6b8ba8");
+    }
+}
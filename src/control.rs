@@ -0,0 +1,165 @@
+use crate::utils::json_string;
+use log::{info, warn};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Time an event may take to reach a client before the client is dropped
+const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Control socket emitting JSON progress events and accepting run commands
+///
+/// Commands are newline-terminated words: `pause`, `resume`, `flush-stats`
+/// and `stop-after-current-file`.
+pub struct ControlSocket {
+    path: PathBuf,
+    clients: Mutex<Vec<UnixStream>>,
+    paused: AtomicBool,
+    flush_stats_requested: AtomicBool,
+    stop_after_current_file: AtomicBool,
+}
+
+impl ControlSocket {
+    /// Bind the socket and start accepting connections in the background
+    pub fn start(path: &str) -> Arc<Self> {
+        let path = PathBuf::from(path);
+        // Only a socket left by an earlier run is replaced, never a file given by mistake
+        if let Ok(metadata) = std::fs::symlink_metadata(&path) {
+            if !metadata.file_type().is_socket() {
+                panic!("Control socket path exists and is not a socket: {}", path.display());
+            }
+            std::fs::remove_file(&path)
+                .unwrap_or_else(|_| panic!("Unable to remove stale control socket: {}", path.display()));
+        }
+        let listener = UnixListener::bind(&path)
+            .unwrap_or_else(|_| panic!("Unable to bind control socket: {}", path.display()));
+        info!("Listening for control commands on {}", path.display());
+
+        let control = Arc::new(Self {
+            path,
+            clients: Mutex::new(Vec::new()),
+            paused: AtomicBool::new(false),
+            flush_stats_requested: AtomicBool::new(false),
+            stop_after_current_file: AtomicBool::new(false),
+        });
+
+        let accept_control = Arc::clone(&control);
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                accept_control.add_client(stream);
+            }
+        });
+
+        control
+    }
+
+    /// Register a client for events and read its commands on a separate thread
+    ///
+    /// Events are written with a timeout, so a client that stops reading is
+    /// dropped instead of stalling the run.
+    fn add_client(self: &Arc<Self>, stream: UnixStream) {
+        if let Err(error) = stream.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT)) {
+            warn!("Failed to accept control connection: {}", error);
+            return;
+        }
+        let command_stream = match stream.try_clone() {
+            Ok(command_stream) => command_stream,
+            Err(error) => {
+                warn!("Failed to accept control connection: {}", error);
+                return;
+            }
+        };
+        self.clients.lock().unwrap().push(stream);
+
+        let control = Arc::clone(self);
+        std::thread::spawn(move || {
+            for line in BufReader::new(command_stream).lines().map_while(Result::ok) {
+                control.handle_command(line.trim());
+            }
+        });
+    }
+
+    /// Apply a single command and acknowledge it to all clients
+    fn handle_command(&self, command: &str) {
+        match command {
+            "" => return,
+            "pause" => self.paused.store(true, Ordering::Relaxed),
+            "resume" => self.paused.store(false, Ordering::Relaxed),
+            "flush-stats" => self.flush_stats_requested.store(true, Ordering::Relaxed),
+            "stop-after-current-file" => self.stop_after_current_file.store(true, Ordering::Relaxed),
+            _ => {
+                self.emit(&format!("{{\"event\":\"error\",\"message\":{}}}", json_string(&format!("unknown command: {}", command))));
+                return;
+            }
+        }
+        info!("Control command received: {}", command);
+        self.emit(&format!("{{\"event\":\"ack\",\"command\":{}}}", json_string(command)));
+    }
+
+    /// Send one JSON event line to every connected client, dropping closed and stalled connections
+    pub fn emit(&self, event: &str) {
+        let line = format!("{}\n", event);
+        let mut clients = self.clients.lock().unwrap();
+        clients.retain_mut(|client| match client.write_all(line.as_bytes()) {
+            Ok(()) => true,
+            Err(error) => {
+                if matches!(error.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) {
+                    warn!("Dropping control client that stopped reading events");
+                }
+                let _ = client.shutdown(std::net::Shutdown::Both);
+                false
+            }
+        });
+    }
+
+    /// Emit a progress event
+    pub fn emit_progress(&self, processed_reads: usize, elapsed: Duration) {
+        self.emit(&format!(
+            "{{\"event\":\"progress\",\"processed\":{},\"elapsed_seconds\":{:.3},\"paused\":{}}}",
+            processed_reads,
+            elapsed.as_secs_f64(),
+            self.paused.load(Ordering::Relaxed)
+        ));
+    }
+
+    /// Emit an event for a fully read input file
+    pub fn emit_file_done(&self, file_path: &Path) {
+        self.emit(&format!(
+            "{{\"event\":\"file_done\",\"file\":{}}}",
+            json_string(&file_path.display().to_string())
+        ));
+    }
+
+    /// Block while the run is paused
+    pub fn wait_while_paused(&self) {
+        while self.paused.load(Ordering::Relaxed) {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    /// Take a pending flush-stats request
+    pub fn take_flush_stats_request(&self) -> bool {
+        self.flush_stats_requested.swap(false, Ordering::Relaxed)
+    }
+
+    /// Whether no further input files should be opened
+    pub fn should_stop_after_current_file(&self) -> bool {
+        self.stop_after_current_file.load(Ordering::Relaxed)
+    }
+
+    /// Emit the final event and remove the socket file
+    pub fn finish(&self, processed_reads: usize, elapsed: Duration) {
+        self.emit(&format!(
+            "{{\"event\":\"finished\",\"processed\":{},\"elapsed_seconds\":{:.3}}}",
+            processed_reads,
+            elapsed.as_secs_f64()
+        ));
+        if let Err(error) = std::fs::remove_file(&self.path) {
+            warn!("Failed to remove control socket {}: {}", self.path.display(), error);
+        }
+    }
+}
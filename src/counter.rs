@@ -1,9 +1,81 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs::File;
-use std::path::Path;
-use log::info;
-use crate::fastq::{ReadInfo, ReadInfoStats};
+use std::path::{Path, PathBuf};
+use log::{error, info, warn};
 use std::io::Write;
+use crate::metrics::write_metric;
+use crate::utils::json_string;
+
+/// Per-read fields counted by the statistics manager
+///
+/// Holds no sequence or quality data, so counting never keeps a read's bases alive.
+#[derive(Debug, Clone)]
+pub struct ReadStats {
+    /// Assignment category such as `valid`, `unknown` or `fusion`
+    pub sequence_type: String,
+    /// Input read length
+    pub sequence_length: usize,
+    /// Matched pattern type per round, padded to three with `default`
+    pub match_types: Vec<String>,
+    /// Matched pattern name per round, padded to three with `default`
+    pub match_names: Vec<String>,
+    /// Bases removed by quality trimming
+    pub quality_trimmed_bases: usize,
+    /// Bases removed by homopolymer tail trimming
+    pub polyx_trimmed_bases: usize,
+    /// Values of the grouping fields, empty when not grouping
+    pub group_values: Vec<String>,
+    /// Sequencing start time in seconds since the epoch, when known
+    pub start_time: Option<f64>,
+    /// Pattern name and edit distance of each accepted end match
+    pub match_distances: Vec<(String, i32)>,
+    /// Class of the internal fusion pattern, empty when none was found
+    pub fusion_class: String,
+    /// Strand the patterns matched on, `fs`, `rs` or `unknown`
+    pub strand: String,
+}
+
+/// Reads binned by read order when no read carries a start time
+const ORDER_BIN_READS: u64 = 10_000;
+
+/// Yield counted in one time bin
+#[derive(Default)]
+struct TimeBin {
+    reads: u64,
+    bases: u64,
+    valid_reads: u64,
+    valid_bases: u64,
+}
+
+/// Reads counted by the strand their patterns matched on
+#[derive(Default)]
+struct StrandCounts {
+    forward: u64,
+    reverse: u64,
+    unknown: u64,
+}
+
+impl StrandCounts {
+    fn add(&mut self, strand: &str, reads: u64) {
+        match strand {
+            "fs" => self.forward += reads,
+            "rs" => self.reverse += reads,
+            _ => self.unknown += reads,
+        }
+    }
+}
+
+/// Expected labels read from a truth file and the predictions made for them
+struct TruthEvaluation {
+    /// Expected pattern name by read ID
+    labels: HashMap<String, String>,
+    /// Round whose pattern name is compared, 0-based
+    round: usize,
+    /// Prediction counts by expected label
+    confusion: BTreeMap<String, BTreeMap<String, u32>>,
+    /// Pattern names predicted for assigned reads
+    assigned_labels: BTreeSet<String>,
+}
 
 /// Statistics manager structure
 pub struct StatisticsManager {
@@ -27,6 +99,38 @@ pub struct StatisticsManager {
     valid_bases: u32,
     /// Post-processing GC content
     after_gc_content: f64,
+    /// Bases removed by quality trimming
+    quality_trimmed_bases: u64,
+    /// Bases removed by homopolymer tail trimming
+    polyx_trimmed_bases: u64,
+    /// Accuracy evaluation against truth labels
+    truth: Option<TruthEvaluation>,
+    /// Reads that could not be written to their output files
+    write_failures: u64,
+    /// Reads whose classification failed, missing from the outputs
+    split_failures: u64,
+    /// Fields reads are grouped by in grouped_stats.tsv
+    group_by: Vec<String>,
+    /// Read and base counts per combination of group values
+    groups: BTreeMap<Vec<String>, (u64, u64)>,
+    /// Minutes per time bin, 0 when not binning
+    time_bin: u64,
+    /// Yield by start time bin, seconds since the epoch divided by the bin length
+    time_bins: BTreeMap<i64, TimeBin>,
+    /// Yield by read order bin, for reads without a start time
+    order_bins: BTreeMap<u64, TimeBin>,
+    /// Accepted match counts by pattern name and edit distance
+    distance_histograms: BTreeMap<String, BTreeMap<i32, u64>>,
+    /// Reads carrying an internal fusion pattern, by pattern class
+    fusion_classes: BTreeMap<String, u64>,
+    /// Strand counts of all reads
+    strands: StrandCounts,
+    /// Strand counts of the valid reads by barcode
+    barcode_strands: BTreeMap<String, StrandCounts>,
+    /// Fold change from the expected read share that is warned about, 0 to skip the check
+    balance_fold: f64,
+    /// Expected relative read share by sample, empty for a uniform distribution over the barcodes
+    sample_weights: BTreeMap<String, f64>,
 }
 
 impl StatisticsManager {
@@ -38,6 +142,9 @@ impl StatisticsManager {
         counters.insert("filtered".to_string(), 0);
         counters.insert("unknown".to_string(), 0);
         counters.insert("fusion".to_string(), 0);
+        counters.insert("ambiguous".to_string(), 0);
+        counters.insert("unexpected".to_string(), 0);
+        counters.insert("concatemer".to_string(), 0);
         
         Self {
             counters,
@@ -50,64 +157,207 @@ impl StatisticsManager {
             valid_reads: 0,
             valid_bases: 0,
             after_gc_content: 0.5,
+            quality_trimmed_bases: 0,
+            polyx_trimmed_bases: 0,
+            truth: None,
+            write_failures: 0,
+            split_failures: 0,
+            group_by: Vec::new(),
+            groups: BTreeMap::new(),
+            time_bin: 0,
+            time_bins: BTreeMap::new(),
+            order_bins: BTreeMap::new(),
+            distance_histograms: BTreeMap::new(),
+            fusion_classes: BTreeMap::new(),
+            strands: StrandCounts::default(),
+            barcode_strands: BTreeMap::new(),
+            balance_fold: 0.0,
+            sample_weights: BTreeMap::new(),
         }
     }
     
-    /// Process single read - memory optimized (deprecated, use process_read_stats instead)
-    #[deprecated(note = "Use process_read_stats for better memory efficiency")]
-    pub fn process_read(&mut self, read_info: &ReadInfo) {
-        // Convert to stats structure for processing
-        let read_stats = read_info.create_stats_copy();
-        self.process_read_stats(&read_stats);
+    /// Warn about barcodes whose read share is this many times off its expected share
+    pub fn set_balance_check(&mut self, fold: f64, sample_weights: &HashMap<String, f64>) {
+        self.balance_fold = fold;
+        self.sample_weights = sample_weights.iter().map(|(sample, weight)| (sample.clone(), *weight)).collect();
+    }
+    
+    /// Count reads per combination of these fields, taken from each read's group values
+    pub fn set_group_by(&mut self, group_by: Vec<String>) {
+        self.group_by = group_by;
+    }
+    
+    /// Bin yield by this many minutes of sequencing start time
+    pub fn set_time_bin(&mut self, minutes: u64) {
+        self.time_bin = minutes;
+    }
+    
+    /// Load truth labels of read ID and expected pattern name
+    ///
+    /// A header starting with `read_id` selects the `barcode` column, or else the
+    /// last `patternN` column as written by `simulate`; without a header the
+    /// second column is compared with the last round.
+    pub fn load_truth_labels(&mut self, file_path: &str, rounds: usize) {
+        let content = std::fs::read_to_string(file_path)
+            .unwrap_or_else(|_| panic!("Unable to find truth label file: {}", file_path));
+        let mut lines = content.lines().filter(|line| !line.is_empty() && !line.starts_with('#')).peekable();
+        
+        let (column, round) = match lines.peek() {
+            Some(header) if header.starts_with("read_id") => {
+                let columns: Vec<&str> = lines.next().unwrap_or_default().split('\t').collect();
+                let pattern_column = columns.iter().enumerate().rev().find_map(|(index, column)| {
+                    column.strip_prefix("pattern").and_then(|round| round.parse::<usize>().ok()).map(|round| (index, round.saturating_sub(1)))
+                });
+                match columns.iter().position(|column| *column == "barcode") {
+                    Some(index) => (index, rounds.saturating_sub(1)),
+                    None => pattern_column
+                        .unwrap_or_else(|| panic!("Truth label header needs a barcode or patternN column: {}", file_path)),
+                }
+            }
+            _ => (1, rounds.saturating_sub(1)),
+        };
+        
+        let mut labels = HashMap::new();
+        for line in lines {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let label = fields
+                .get(column)
+                .unwrap_or_else(|| panic!("Truth label line has no column {}: {}", column + 1, line));
+            labels.insert(fields[0].to_string(), label.to_string());
+        }
+        
+        info!("Truth labels loaded successfully: {} ({} reads, round {})", file_path, labels.len(), round + 1);
+        self.truth = Some(TruthEvaluation {
+            labels,
+            round,
+            confusion: BTreeMap::new(),
+            assigned_labels: BTreeSet::new(),
+        });
+    }
+    
+    /// Compare a read's assignment with its truth label, if it has one
+    pub fn evaluate_truth(&mut self, read_id: &str, read_stats: &ReadStats) {
+        let Some(truth) = &mut self.truth else {
+            return;
+        };
+        let Some(expected) = truth.labels.get(read_id) else {
+            return;
+        };
+        // Reads that were not assigned are predicted as their sequence type
+        let predicted = if read_stats.sequence_type == "valid" {
+            let name = read_stats.match_names.get(truth.round).cloned().unwrap_or_default();
+            truth.assigned_labels.insert(name.clone());
+            name
+        } else {
+            read_stats.sequence_type.clone()
+        };
+        *truth.confusion.entry(expected.clone()).or_default().entry(predicted).or_insert(0) += 1;
+    }
+    
+    /// Write confusion.tsv and per-barcode precision, recall and F1 to accuracy.tsv
+    pub fn write_truth_statistics(&self) {
+        let Some(truth) = &self.truth else {
+            return;
+        };
+        let predicted_labels: BTreeSet<&String> = truth.confusion.values().flat_map(|predictions| predictions.keys()).collect();
+        let count = |expected: &str, predicted: &str| {
+            truth.confusion.get(expected).and_then(|predictions| predictions.get(predicted)).copied().unwrap_or(0)
+        };
+        
+        let file_path = Path::new(&self.output_directory).join("confusion.tsv");
+        let mut file = File::create(&file_path).expect("Failed to create confusion matrix file");
+        let header: Vec<&str> = predicted_labels.iter().map(|label| label.as_str()).collect();
+        writeln!(file, "expected\t{}", header.join("\t")).expect("Failed to write table header");
+        for expected in truth.confusion.keys() {
+            let counts: Vec<String> = predicted_labels.iter().map(|predicted| count(expected, predicted).to_string()).collect();
+            writeln!(file, "{}\t{}", expected, counts.join("\t")).expect("Failed to write confusion matrix");
+        }
+        
+        let file_path = Path::new(&self.output_directory).join("accuracy.tsv");
+        let mut file = File::create(&file_path).expect("Failed to create accuracy file");
+        writeln!(file, "barcode\ttrue_positives\tfalse_positives\tfalse_negatives\tprecision\trecall\tf1")
+            .expect("Failed to write table header");
+        let barcodes: BTreeSet<&String> = truth.confusion.keys().chain(truth.assigned_labels.iter()).collect();
+        for barcode in barcodes {
+            let true_positives = count(barcode, barcode);
+            let predicted_total: u32 = truth.confusion.keys().map(|expected| count(expected, barcode)).sum();
+            let expected_total: u32 = truth.confusion.get(barcode).map(|predictions| predictions.values().sum()).unwrap_or(0);
+            let ratio = |numerator: u32, denominator: u32| if denominator > 0 { numerator as f64 / denominator as f64 } else { 0.0 };
+            let precision = ratio(true_positives, predicted_total);
+            let recall = ratio(true_positives, expected_total);
+            let f1 = if precision + recall > 0.0 { 2.0 * precision * recall / (precision + recall) } else { 0.0 };
+            writeln!(
+                file,
+                "{}\t{}\t{}\t{}\t{:.4}\t{:.4}\t{:.4}",
+                barcode,
+                true_positives,
+                predicted_total - true_positives,
+                expected_total - true_positives,
+                precision,
+                recall,
+                f1
+            ).expect("Failed to write accuracy statistics");
+        }
+        info!("Accuracy against truth labels written to {}", file_path.display());
     }
     
     /// Process read using lightweight stats structure - memory optimized
-    pub fn process_read_stats(&mut self, read_stats: &ReadInfoStats) {
+    pub fn process_read_stats(&mut self, read_stats: &ReadStats) {
         self.total_reads += 1;
         self.total_bases += read_stats.sequence_length as u32;
+        self.quality_trimmed_bases += read_stats.quality_trimmed_bases as u64;
+        self.polyx_trimmed_bases += read_stats.polyx_trimmed_bases as u64;
         
         // Update basic counter
         *self.counters.entry(read_stats.sequence_type.clone()).or_insert(0) += 1;
         
+        if !read_stats.group_values.is_empty() {
+            let group = self.groups.entry(read_stats.group_values.clone()).or_insert((0, 0));
+            group.0 += 1;
+            group.1 += read_stats.sequence_length as u64;
+        }
+        
+        if self.time_bin > 0 {
+            let time_bin = match read_stats.start_time {
+                Some(start_time) => self.time_bins.entry((start_time / (self.time_bin * 60) as f64).floor() as i64).or_default(),
+                None => self.order_bins.entry((self.total_reads as u64 - 1) / ORDER_BIN_READS).or_default(),
+            };
+            time_bin.reads += 1;
+            time_bin.bases += read_stats.sequence_length as u64;
+            if read_stats.sequence_type == "valid" {
+                time_bin.valid_reads += 1;
+                time_bin.valid_bases += read_stats.sequence_length as u64;
+            }
+        }
+        
+        if !read_stats.fusion_class.is_empty() {
+            *self.fusion_classes.entry(read_stats.fusion_class.clone()).or_insert(0) += 1;
+        }
+        
+        self.strands.add(&read_stats.strand, 1);
+        
+        for (pattern_name, distance) in &read_stats.match_distances {
+            let histogram = self.distance_histograms.entry(pattern_name.clone()).or_default();
+            *histogram.entry(*distance).or_insert(0) += 1;
+        }
+        
         // If valid sequence, perform detailed statistics
         if read_stats.sequence_type == "valid" {
             self.valid_reads += 1;
             self.valid_bases += read_stats.sequence_length as u32;
+            let pattern_types = [&read_stats.match_types[2], &read_stats.match_types[1], &read_stats.match_types[0]];
+            self.barcode_strands.entry(barcode_name(pattern_types)).or_default().add(&read_stats.strand, 1);
             self.update_detailed_statistics_from_stats(read_stats);
         }
         
         // Periodic memory cleanup to prevent excessive memory growth - unified frequency
-        if self.total_reads % 500000 == 0 {
+        if self.total_reads.is_multiple_of(500000) {
             self.cleanup_memory();
         }
     }
     
-    /// Update detailed statistics
-    fn update_detailed_statistics(&mut self, read_info: &ReadInfo) {
-        let primer = read_info.match_names[0].clone();
-        let index = read_info.match_names[1].clone();
-        let barcode = read_info.match_names[2].clone();
-        let primer_type = read_info.match_types[0].clone();
-        let index_type = read_info.match_types[1].clone();
-        let barcode_type = read_info.match_types[2].clone();
-        
-        // Update name counter
-        let barcode_map = self.valid_name_counters
-            .entry(barcode.clone())
-            .or_insert_with(HashMap::new);
-        let index_map = barcode_map.entry(index.clone()).or_insert_with(HashMap::new);
-        *index_map.entry(primer).or_insert(0) += 1;
-        
-        // Update type counter
-        let barcode_type_map = self.valid_type_counters
-            .entry(barcode_type)
-            .or_insert_with(HashMap::new);
-        let index_type_map = barcode_type_map.entry(index_type).or_insert_with(HashMap::new);
-        *index_type_map.entry(primer_type).or_insert(0) += 1;
-    }
-    
     /// Update detailed statistics from lightweight stats structure
-    fn update_detailed_statistics_from_stats(&mut self, read_stats: &ReadInfoStats) {
+    fn update_detailed_statistics_from_stats(&mut self, read_stats: &ReadStats) {
         let primer = read_stats.match_names[0].clone();
         let index = read_stats.match_names[1].clone();
         let barcode = read_stats.match_names[2].clone();
@@ -118,18 +368,98 @@ impl StatisticsManager {
         // Update name counter
         let barcode_map = self.valid_name_counters
             .entry(barcode.clone())
-            .or_insert_with(HashMap::new);
-        let index_map = barcode_map.entry(index.clone()).or_insert_with(HashMap::new);
+            .or_default();
+        let index_map = barcode_map.entry(index.clone()).or_default();
         *index_map.entry(primer).or_insert(0) += 1;
         
         // Update type counter
         let barcode_type_map = self.valid_type_counters
             .entry(barcode_type)
-            .or_insert_with(HashMap::new);
-        let index_type_map = barcode_type_map.entry(index_type).or_insert_with(HashMap::new);
+            .or_default();
+        let index_type_map = barcode_type_map.entry(index_type).or_default();
         *index_type_map.entry(primer_type).or_insert(0) += 1;
     }
     
+    /// Write read and base counts per combination of the grouping fields, if grouping
+    pub fn write_grouped_statistics(&self) {
+        if self.group_by.is_empty() {
+            return;
+        }
+        let total_reads: u64 = self.groups.values().map(|(reads, _)| reads).sum();
+        let file_path = Path::new(&self.output_directory).join("grouped_stats.tsv");
+        let mut file = File::create(&file_path).expect("Failed to create grouped statistics file");
+        
+        writeln!(file, "{}\treads\tbases\trate", self.group_by.join("\t")).expect("Failed to write table header");
+        for (key, (reads, bases)) in &self.groups {
+            let rate = if total_reads > 0 { *reads as f64 / total_reads as f64 * 100.0 } else { 0.0 };
+            writeln!(file, "{}\t{}\t{}\t{:.2}", key.join("\t"), reads, bases, rate)
+                .expect("Failed to write grouped statistics");
+        }
+        info!("Grouped statistics written to {}", file_path.display());
+    }
+    
+    /// Write yield, valid rate and cumulative yield per time bin, if binning
+    ///
+    /// Bins start at minutes since the earliest start time; without any start time
+    /// reads are binned by read order instead, and reads lacking one in a timed run
+    /// are summed in a final `-` row.
+    pub fn write_time_statistics(&self) {
+        if self.time_bin == 0 {
+            return;
+        }
+        let file_path = Path::new(&self.output_directory).join("time_stats.tsv");
+        let mut file = File::create(&file_path).expect("Failed to create time statistics file");
+        
+        let mut rows: Vec<(String, &TimeBin)> = Vec::new();
+        let untimed = TimeBin {
+            reads: self.order_bins.values().map(|time_bin| time_bin.reads).sum(),
+            bases: self.order_bins.values().map(|time_bin| time_bin.bases).sum(),
+            valid_reads: self.order_bins.values().map(|time_bin| time_bin.valid_reads).sum(),
+            valid_bases: self.order_bins.values().map(|time_bin| time_bin.valid_bases).sum(),
+        };
+        let first_column = if let Some(&first_bin) = self.time_bins.keys().next() {
+            for (bin, time_bin) in &self.time_bins {
+                rows.push((((bin - first_bin) as u64 * self.time_bin).to_string(), time_bin));
+            }
+            if untimed.reads > 0 {
+                rows.push((String::from("-"), &untimed));
+            }
+            "start_minute"
+        } else {
+            for (bin, time_bin) in &self.order_bins {
+                rows.push(((bin * ORDER_BIN_READS + 1).to_string(), time_bin));
+            }
+            "first_read"
+        };
+        
+        writeln!(
+            file,
+            "{}\treads\tbases\tvalid_reads\tvalid_bases\tvalid_rate\tcumulative_reads\tcumulative_bases\tcumulative_valid_bases",
+            first_column
+        ).expect("Failed to write table header");
+        let (mut cumulative_reads, mut cumulative_bases, mut cumulative_valid_bases) = (0, 0, 0);
+        for (bin_start, time_bin) in rows {
+            cumulative_reads += time_bin.reads;
+            cumulative_bases += time_bin.bases;
+            cumulative_valid_bases += time_bin.valid_bases;
+            let valid_rate = if time_bin.reads > 0 { time_bin.valid_reads as f64 / time_bin.reads as f64 * 100.0 } else { 0.0 };
+            writeln!(
+                file,
+                "{}\t{}\t{}\t{}\t{}\t{:.2}\t{}\t{}\t{}",
+                bin_start,
+                time_bin.reads,
+                time_bin.bases,
+                time_bin.valid_reads,
+                time_bin.valid_bases,
+                valid_rate,
+                cumulative_reads,
+                cumulative_bases,
+                cumulative_valid_bases
+            ).expect("Failed to write time statistics");
+        }
+        info!("Time statistics written to {}", file_path.display());
+    }
+    
     /// Clean up memory to prevent excessive growth - optimized for performance
     pub fn cleanup_memory(&mut self) {
         // Only clean up if structures are truly oversized
@@ -153,6 +483,77 @@ impl StatisticsManager {
         }
     }
     
+    /// Write the edit distance histogram of accepted matches per pattern to score_histogram.tsv
+    ///
+    /// Patterns matching mostly at the largest distances point to a loose error
+    /// rate or a degraded barcode.
+    pub fn write_score_statistics(&self) {
+        let max_distance = self.distance_histograms
+            .values()
+            .filter_map(|histogram| histogram.keys().next_back())
+            .max()
+            .copied()
+            .unwrap_or(0);
+        let file_path = Path::new(&self.output_directory).join("score_histogram.tsv");
+        let mut file = File::create(&file_path).expect("Failed to create score histogram file");
+        
+        let distance_columns: Vec<String> = (0..=max_distance).map(|distance| format!("distance_{}", distance)).collect();
+        writeln!(file, "pattern\tmatches\tmean_distance\t{}", distance_columns.join("\t"))
+            .expect("Failed to write table header");
+        for (pattern_name, histogram) in &self.distance_histograms {
+            let matches: u64 = histogram.values().sum();
+            let distance_sum: u64 = histogram.iter().map(|(distance, count)| *distance as u64 * count).sum();
+            let counts: Vec<String> = (0..=max_distance)
+                .map(|distance| histogram.get(&distance).unwrap_or(&0).to_string())
+                .collect();
+            writeln!(
+                file,
+                "{}\t{}\t{:.3}\t{}",
+                pattern_name,
+                matches,
+                distance_sum as f64 / matches as f64,
+                counts.join("\t")
+            ).expect("Failed to write score histogram");
+        }
+    }
+    
+    /// Write forward and reverse strand read counts, of all reads and per barcode, to strand_stats.tsv
+    pub fn write_strand_statistics(&self) {
+        let file_path = Path::new(&self.output_directory).join("strand_stats.tsv");
+        let mut file = File::create(&file_path).expect("Failed to create strand statistics file");
+        writeln!(file, "barcode\treads\tfs\trs\tunknown\tfs_fraction").expect("Failed to write table header");
+        let rows = std::iter::once(("all", &self.strands))
+            .chain(self.barcode_strands.iter().map(|(barcode, strands)| (barcode.as_str(), strands)));
+        for (barcode, strands) in rows {
+            let stranded = strands.forward + strands.reverse;
+            let forward_fraction = if stranded > 0 { strands.forward as f64 / stranded as f64 } else { 0.0 };
+            writeln!(
+                file,
+                "{}\t{}\t{}\t{}\t{}\t{:.4}",
+                barcode,
+                stranded + strands.unknown,
+                strands.forward,
+                strands.reverse,
+                strands.unknown,
+                forward_fraction
+            ).expect("Failed to write strand statistics");
+        }
+    }
+    
+    /// Write read counts and rates per fusion pattern class to fusion_classes.tsv, if fusions were found
+    pub fn write_fusion_statistics(&self) {
+        if self.fusion_classes.is_empty() {
+            return;
+        }
+        let file_path = Path::new(&self.output_directory).join("fusion_classes.tsv");
+        let mut file = File::create(&file_path).expect("Failed to create fusion class statistics file");
+        writeln!(file, "class	reads	rate").expect("Failed to write table header");
+        for (fusion_class, reads) in &self.fusion_classes {
+            let rate = if self.total_reads > 0 { *reads as f64 / self.total_reads as f64 * 100.0 } else { 0.0 };
+            writeln!(file, "{}	{}	{:.2}", fusion_class, reads, rate).expect("Failed to write fusion class statistics");
+        }
+    }
+    
     /// Write valid statistics
     pub fn write_valid_statistics(&self) {
         self.write_name_statistics();
@@ -232,10 +633,165 @@ impl StatisticsManager {
             "Processed {}/{} reads (fusion/total), fusion rate: {:.2}%", 
             fusion_count, total_reads, fusion_rate
         );
+        let concatemer_count = *self.counters.get("concatemer").unwrap_or(&0);
+        if concatemer_count > 0 {
+            info!("Split {} concatemer reads into their inserts", concatemer_count);
+        }
         info!(
             "Processed {}/{} reads (valid/total), valid rate: {:.2}%", 
             valid_reads, total_reads, valid_rate
         );
+        info!(
+            "Strands: {} fs, {} rs, {} unknown",
+            self.strands.forward, self.strands.reverse, self.strands.unknown
+        );
+        if self.write_failures > 0 {
+            error!("{} reads could not be written to their output files", self.write_failures);
+        }
+        if self.split_failures > 0 {
+            error!("{} reads failed to be classified and are missing from the outputs", self.split_failures);
+        }
+    }
+    
+    /// Valid reads per barcode
+    ///
+    /// Barcodes are named by the matched pattern types, outermost round first
+    /// and unmatched rounds left out, like the default output layout.
+    fn barcode_counts(&self) -> BTreeMap<String, u32> {
+        let mut barcodes: BTreeMap<String, u32> = BTreeMap::new();
+        for (barcode_type, index_map) in &self.valid_type_counters {
+            for (index_type, primer_map) in index_map {
+                for (primer_type, count) in primer_map {
+                    *barcodes.entry(barcode_name([barcode_type, index_type, primer_type])).or_insert(0) += count;
+                }
+            }
+        }
+        barcodes
+    }
+    
+    /// Counters in the Prometheus text format for --metrics-port
+    pub fn prometheus_metrics(&self) -> String {
+        let mut body = String::new();
+        let valid_rate = if self.total_reads > 0 { self.valid_reads as f64 / self.total_reads as f64 } else { 0.0 };
+        write_metric(&mut body, "readchop_reads_total", "counter", "Reads processed", &[(None, self.total_reads as f64)]);
+        write_metric(&mut body, "readchop_bases_total", "counter", "Bases of the processed reads", &[(None, self.total_bases as f64)]);
+        write_metric(&mut body, "readchop_valid_reads_total", "counter", "Reads assigned to a barcode", &[(None, self.valid_reads as f64)]);
+        write_metric(&mut body, "readchop_valid_rate", "gauge", "Fraction of the processed reads assigned to a barcode", &[(None, valid_rate)]);
+        write_metric(&mut body, "readchop_write_failures_total", "counter", "Reads that could not be written to their output files", &[(None, self.write_failures as f64)]);
+        write_metric(&mut body, "readchop_split_failures_total", "counter", "Reads whose classification failed, missing from the outputs", &[(None, self.split_failures as f64)]);
+
+        let mut sequence_types: Vec<(&String, &u32)> = self.counters.iter().collect();
+        sequence_types.sort();
+        let samples: Vec<_> = sequence_types.iter()
+            .map(|(sequence_type, count)| (Some(("type", sequence_type.as_str())), **count as f64))
+            .collect();
+        write_metric(&mut body, "readchop_reads_by_type_total", "counter", "Reads processed, by assignment category such as valid, unknown or filtered", &samples);
+
+        let barcodes = self.barcode_counts();
+        let samples: Vec<_> = barcodes.iter()
+            .map(|(barcode, count)| (Some(("barcode", barcode.as_str())), *count as f64))
+            .collect();
+        write_metric(&mut body, "readchop_barcode_reads_total", "counter", "Valid reads per barcode", &samples);
+        body
+    }
+    
+    /// Record reads the writers failed to write
+    pub fn set_write_failures(&mut self, write_failures: u64) {
+        self.write_failures = write_failures;
+    }
+    
+    /// Record reads whose classification failed
+    pub fn set_split_failures(&mut self, split_failures: u64) {
+        self.split_failures = split_failures;
+    }
+    
+    /// Warn about expected barcodes that no valid read was assigned to
+    pub fn warn_missing_expected_barcodes(&self, expected_barcodes: &HashSet<String>, sample_sheet: &HashMap<String, String>) {
+        let mut assigned_names: HashSet<&str> = HashSet::new();
+        for (barcode, index_map) in &self.valid_name_counters {
+            assigned_names.insert(barcode);
+            for (index, primer_map) in index_map {
+                assigned_names.insert(index);
+                assigned_names.extend(primer_map.keys().map(String::as_str));
+            }
+        }
+        
+        let mut missing: Vec<&String> = expected_barcodes
+            .iter()
+            .filter(|name| {
+                let reported_name = sample_sheet.get(name.as_str()).unwrap_or(name);
+                !assigned_names.contains(reported_name.as_str())
+            })
+            .collect();
+        missing.sort();
+        for name in missing {
+            warn!("Expected barcode {} received no reads", name);
+        }
+    }
+    
+    /// Warn about under- or over-represented barcodes and write them to warnings.json
+    ///
+    /// Each barcode's share of the valid reads is compared to a uniform share,
+    /// or with a sample sheet to the share its sample is expected to get, so
+    /// samples that received no reads at all are reported too.
+    pub fn write_balance_warnings(&self) {
+        if self.balance_fold <= 0.0 {
+            return;
+        }
+        let barcodes = self.barcode_counts();
+        let expected: BTreeMap<String, (u32, f64)> = if self.sample_weights.is_empty() {
+            barcodes.iter().map(|(barcode, reads)| (barcode.clone(), (*reads, 1.0))).collect()
+        } else {
+            // Sample IDs replace the pattern types, so a barcode belongs to the sample it is named after
+            self.sample_weights.iter().map(|(sample, weight)| {
+                let reads = barcodes.iter()
+                    .filter(|(barcode, _)| barcode.split('/').any(|part| part == sample))
+                    .map(|(_, reads)| reads)
+                    .sum();
+                (sample.clone(), (reads, *weight))
+            }).collect()
+        };
+        let total_reads: u32 = expected.values().map(|(reads, _)| reads).sum();
+        let total_weight: f64 = expected.values().map(|(_, weight)| weight).sum();
+        
+        let mut warnings = Vec::new();
+        if expected.len() > 1 && total_reads > 0 && total_weight > 0.0 {
+            for (barcode, (reads, weight)) in &expected {
+                let fraction = *reads as f64 / total_reads as f64;
+                let expected_fraction = weight / total_weight;
+                if expected_fraction == 0.0 {
+                    continue;
+                }
+                let fold_change = fraction / expected_fraction;
+                let direction = if fold_change * self.balance_fold < 1.0 {
+                    "under"
+                } else if fold_change > self.balance_fold {
+                    "over"
+                } else {
+                    continue;
+                };
+                warn!(
+                    "Barcode {} is {}-represented: {} reads, {:.2}% of the assigned reads where {:.2}% were expected",
+                    barcode, direction, reads, fraction * 100.0, expected_fraction * 100.0
+                );
+                warnings.push(format!(
+                    "{{\"barcode\": {}, \"reads\": {}, \"fraction\": {:.6}, \"expected_fraction\": {:.6}, \"fold_change\": {:.4}, \"direction\": \"{}\"}}",
+                    json_string(barcode), reads, fraction, expected_fraction, fold_change, direction
+                ));
+            }
+        }
+        
+        let file_path = Path::new(&self.output_directory).join("warnings.json");
+        let mut file = File::create(&file_path).expect("Failed to create warnings file");
+        writeln!(
+            file,
+            "{{\"balance_fold\": {}, \"expected\": \"{}\", \"assigned_reads\": {}, \"barcodes\": {}, \"warnings\": [{}]}}",
+            self.balance_fold,
+            if self.sample_weights.is_empty() { "uniform" } else { "sample_sheet" },
+            total_reads,
+            expected.len(),
+            warnings.join(", ")
+        ).expect("Failed to write warnings file");
     }
     
     /// Write total statistics
@@ -261,6 +817,9 @@ impl StatisticsManager {
         let unknown_count = *self.counters.get("unknown").unwrap_or(&0) as f64;
         let filtered_count = *self.counters.get("filtered").unwrap_or(&0) as f64;
         let fusion_count = *self.counters.get("fusion").unwrap_or(&0) as f64;
+        let ambiguous_count = *self.counters.get("ambiguous").unwrap_or(&0) as f64;
+        let unexpected_count = *self.counters.get("unexpected").unwrap_or(&0) as f64;
+        let concatemer_count = *self.counters.get("concatemer").unwrap_or(&0) as f64;
 
         let valid_rate = if total_reads > 0.0 {
             valid_count / total_reads * 100.0
@@ -285,6 +844,24 @@ impl StatisticsManager {
         } else {
             0.0
         };
+        
+        let ambiguous_rate = if total_reads > 0.0 {
+            ambiguous_count / total_reads * 100.0
+        } else {
+            0.0
+        };
+        
+        let unexpected_rate = if total_reads > 0.0 {
+            unexpected_count / total_reads * 100.0
+        } else {
+            0.0
+        };
+        
+        let concatemer_rate = if total_reads > 0.0 {
+            concatemer_count / total_reads * 100.0
+        } else {
+            0.0
+        };
 
         let file_path = Path::new(&self.output_directory).join("total_info.tsv");
         let mut file = File::create(&file_path)
@@ -292,12 +869,12 @@ impl StatisticsManager {
         
         writeln!(
             file, 
-            "total\ttotal_bases\tbefore_read1_mean_length\tafter_read1_mean_length\tbefore_gc_content\tafter_gc_content\tfiltered\tfiltered_rate\tfusion\tfusion_rate\tunknown\tunknown_rate\tvalid_reads\tvalid_bases\tvalid_rate"
+            "total\ttotal_bases\tbefore_read1_mean_length\tafter_read1_mean_length\tbefore_gc_content\tafter_gc_content\tfiltered\tfiltered_rate\tfusion\tfusion_rate\tunknown\tunknown_rate\tvalid_reads\tvalid_bases\tvalid_rate\tambiguous\tambiguous_rate\tunexpected\tunexpected_rate\tqual_trimmed_bases\tpolyx_trimmed_bases\twrite_failed\tsplit_failed\tconcatemer\tconcatemer_rate"
         ).expect("Failed to write header");
         
         writeln!(
             file,
-            "{}\t{}\t{:.1}\t{:.1}\t{:.1}\t{:.1}\t{}\t{:.2}\t{}\t{:.2}\t{}\t{:.2}\t{}\t{}\t{:.2}",
+            "{}\t{}\t{:.1}\t{:.1}\t{:.1}\t{:.1}\t{}\t{:.2}\t{}\t{:.2}\t{}\t{:.2}\t{}\t{}\t{:.2}\t{}\t{:.2}\t{}\t{:.2}\t{}\t{}\t{}\t{}\t{}\t{:.2}",
             total_reads as u32,
             total_bases as u32,
             before_mean_length,
@@ -313,7 +890,128 @@ impl StatisticsManager {
             valid_count as u32,
             valid_bases as u32,
             valid_rate,
+            ambiguous_count as u32,
+            ambiguous_rate,
+            unexpected_count as u32,
+            unexpected_rate,
+            self.quality_trimmed_bases,
+            self.polyx_trimmed_bases,
+            self.write_failures,
+            self.split_failures,
+            concatemer_count as u32,
+            concatemer_rate,
         ).expect("Failed to write total statistics");
     }
     
+    /// Add the statistics an earlier run wrote to its output directory
+    ///
+    /// Sums total_info.tsv, the valid name and type tables, score_histogram.tsv and fusion_classes.tsv;
+    /// grouped, time and accuracy tables are not merged. Columns missing from
+    /// older runs count as 0.
+    pub fn merge_statistics(&mut self, directory: &str) {
+        let directory_path = Path::new(directory);
+        let (header, rows) = read_table(&directory_path.join("total_info.tsv"))
+            .unwrap_or_else(|| panic!("No total_info.tsv in {}", directory));
+        let Some(values) = rows.first() else {
+            panic!("Empty total_info.tsv in {}", directory);
+        };
+        let column = |name: &str| -> u64 {
+            header.iter().position(|column| column == name)
+                .and_then(|index| values.get(index))
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(0)
+        };
+        self.total_reads += column("total") as u32;
+        self.total_bases += column("total_bases") as u32;
+        self.valid_reads += column("valid_reads") as u32;
+        self.valid_bases += column("valid_bases") as u32;
+        self.quality_trimmed_bases += column("qual_trimmed_bases");
+        self.polyx_trimmed_bases += column("polyx_trimmed_bases");
+        self.write_failures += column("write_failed");
+        self.split_failures += column("split_failed");
+        for (counter, name) in [
+            ("valid", "valid_reads"),
+            ("filtered", "filtered"),
+            ("fusion", "fusion"),
+            ("unknown", "unknown"),
+            ("ambiguous", "ambiguous"),
+            ("unexpected", "unexpected"),
+            ("concatemer", "concatemer"),
+        ] {
+            *self.counters.entry(counter.to_string()).or_insert(0) += column(name) as u32;
+        }
+        
+        let mut entries: Vec<PathBuf> = std::fs::read_dir(directory_path)
+            .unwrap_or_else(|_| panic!("Unable to read output directory: {}", directory))
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect();
+        entries.sort();
+        for path in entries {
+            let file_name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            let counters = if file_name.ends_with("_validname.tsv") {
+                &mut self.valid_name_counters
+            } else if file_name.ends_with("_validtype.tsv") {
+                &mut self.valid_type_counters
+            } else {
+                continue;
+            };
+            let (_, rows) = read_table(&path).unwrap_or_default();
+            for row in rows {
+                if let [barcode, index, primer, count] = row.as_slice() {
+                    *counters.entry(barcode.clone()).or_default()
+                        .entry(index.clone()).or_default()
+                        .entry(primer.clone()).or_insert(0) += count.parse::<u32>().unwrap_or(0);
+                }
+            }
+        }
+        
+        if let Some((header, rows)) = read_table(&directory_path.join("score_histogram.tsv")) {
+            let distances: Vec<Option<i32>> = header.iter()
+                .map(|column| column.strip_prefix("distance_").and_then(|distance| distance.parse().ok()))
+                .collect();
+            for row in rows {
+                let histogram = self.distance_histograms.entry(row[0].clone()).or_default();
+                for (distance, count) in distances.iter().zip(&row) {
+                    if let Some(distance) = distance {
+                        *histogram.entry(*distance).or_insert(0) += count.parse::<u64>().unwrap_or(0);
+                    }
+                }
+            }
+        }
+        let (_, rows) = read_table(&directory_path.join("strand_stats.tsv")).unwrap_or_default();
+        for row in rows {
+            if let [barcode, _, forward, reverse, unknown, ..] = row.as_slice() {
+                let strands = if barcode == "all" { &mut self.strands } else { self.barcode_strands.entry(barcode.clone()).or_default() };
+                for (strand, reads) in [("fs", forward), ("rs", reverse), ("unknown", unknown)] {
+                    strands.add(strand, reads.parse().unwrap_or(0));
+                }
+            }
+        }
+        let (_, rows) = read_table(&directory_path.join("fusion_classes.tsv")).unwrap_or_default();
+        for row in rows {
+            if let [fusion_class, reads, ..] = row.as_slice() {
+                *self.fusion_classes.entry(fusion_class.clone()).or_insert(0) += reads.parse::<u64>().unwrap_or(0);
+            }
+        }
+        info!("Merged statistics from {}", directory);
+    }
+    
+}
+
+/// Barcode named by its matched pattern types, outermost round first, unmatched rounds left out
+fn barcode_name(pattern_types: [&String; 3]) -> String {
+    let name: Vec<&str> = pattern_types.into_iter()
+        .map(String::as_str)
+        .filter(|pattern_type| *pattern_type != "default")
+        .collect();
+    name.join("/")
+}
+
+/// Header and rows of a TSV file, None when it does not exist
+pub fn read_table(path: &Path) -> Option<(Vec<String>, Vec<Vec<String>>)> {
+    let content = std::fs::read_to_string(path).ok()?;
+    let mut lines = content.lines().filter(|line| !line.is_empty());
+    let split = |line: &str| line.split('\t').map(str::to_string).collect::<Vec<String>>();
+    let header = split(lines.next()?);
+    Some((header, lines.map(split).collect()))
 }
\ No newline at end of file
@@ -0,0 +1,187 @@
+use flate2::read::{GzDecoder, MultiGzDecoder};
+use flume::{bounded, Receiver, Sender};
+use log::info;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read};
+
+/// Decompressed chunk size used when the input is plain gzip
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Length of a BGZF block header up to and including BSIZE
+const BGZF_HEADER_SIZE: usize = 18;
+
+/// Decompressed chunk tagged with its position in the stream
+type Chunk = (usize, io::Result<Vec<u8>>);
+
+/// Gzip reader that decompresses on background threads
+///
+/// BGZF input (bgzip, most sequencer output) is split into its independent
+/// blocks and inflated on `threads` workers; any other gzip stream is inflated
+/// on a single background thread so decompression overlaps with parsing.
+pub struct ParallelGzDecoder {
+    receiver: Receiver<Chunk>,
+    pending: HashMap<usize, io::Result<Vec<u8>>>,
+    next_index: usize,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl ParallelGzDecoder {
+    /// Start decompressing `reader` with up to `threads` workers
+    pub fn new<R: Read + Send + 'static>(mut reader: BufReader<R>, threads: usize) -> Self {
+        let threads = threads.max(1);
+        let (sender, receiver) = bounded(threads * 4);
+        let is_bgzf = reader.fill_buf().map(is_bgzf_header).unwrap_or(false);
+
+        if is_bgzf {
+            info!("BGZF input detected, decompressing with {} threads", threads);
+            let (job_sender, job_receiver) = bounded::<(usize, Vec<u8>)>(threads * 4);
+            for _ in 0..threads {
+                let job_receiver = job_receiver.clone();
+                let sender = sender.clone();
+                std::thread::spawn(move || {
+                    for (index, block) in job_receiver.iter() {
+                        if sender.send((index, inflate_block(&block))).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            std::thread::spawn(move || split_bgzf_blocks(reader, job_sender, sender));
+        } else {
+            std::thread::spawn(move || inflate_stream(reader, sender));
+        }
+
+        Self {
+            receiver,
+            pending: HashMap::new(),
+            next_index: 0,
+            buffer: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// Move the next chunk in stream order into the buffer, false at end of stream
+    fn next_chunk(&mut self) -> io::Result<bool> {
+        loop {
+            if let Some(chunk) = self.pending.remove(&self.next_index) {
+                self.next_index += 1;
+                self.buffer = chunk?;
+                self.position = 0;
+                return Ok(true);
+            }
+            match self.receiver.recv() {
+                Ok((index, chunk)) => {
+                    self.pending.insert(index, chunk);
+                }
+                Err(_) if self.pending.is_empty() => return Ok(false),
+                Err(_) => {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Missing gzip block in decompressed stream"));
+                }
+            }
+        }
+    }
+}
+
+impl Read for ParallelGzDecoder {
+    fn read(&mut self, output: &mut [u8]) -> io::Result<usize> {
+        while self.position >= self.buffer.len() {
+            if !self.next_chunk()? {
+                return Ok(0);
+            }
+        }
+        let length = output.len().min(self.buffer.len() - self.position);
+        output[..length].copy_from_slice(&self.buffer[self.position..self.position + length]);
+        self.position += length;
+        Ok(length)
+    }
+}
+
+/// Check for a gzip member header carrying the BGZF `BC` extra subfield
+fn is_bgzf_header(header: &[u8]) -> bool {
+    header.len() >= BGZF_HEADER_SIZE
+        && header[0] == 0x1f
+        && header[1] == 0x8b
+        && header[2] == 8
+        && header[3] & 4 != 0
+        && u16::from_le_bytes([header[10], header[11]]) == 6
+        && header[12] == b'B'
+        && header[13] == b'C'
+        && u16::from_le_bytes([header[14], header[15]]) == 2
+}
+
+/// Read whole BGZF blocks and queue them for the workers
+fn split_bgzf_blocks<R: Read>(
+    mut reader: BufReader<R>,
+    job_sender: Sender<(usize, Vec<u8>)>,
+    sender: Sender<Chunk>,
+) {
+    for index in 0.. {
+        match read_bgzf_block(&mut reader) {
+            Ok(Some(block)) => {
+                if job_sender.send((index, block)).is_err() {
+                    break;
+                }
+            }
+            Ok(None) => break,
+            Err(error) => {
+                let _ = sender.send((index, Err(error)));
+                break;
+            }
+        }
+    }
+}
+
+/// Read the next complete BGZF block, None on a clean end of stream
+fn read_bgzf_block<R: Read>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut header = [0u8; BGZF_HEADER_SIZE];
+    let mut filled = 0;
+    while filled < BGZF_HEADER_SIZE {
+        match reader.read(&mut header[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated BGZF block")),
+            Ok(read) => filled += read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+
+    // BSIZE is the total block size minus one; header and footer take 26 bytes
+    let block_size = u16::from_le_bytes([header[16], header[17]]) as usize + 1;
+    if !is_bgzf_header(&header) || block_size < BGZF_HEADER_SIZE + 8 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid BGZF block header"));
+    }
+    let mut block = vec![0u8; block_size];
+    block[..BGZF_HEADER_SIZE].copy_from_slice(&header);
+    reader.read_exact(&mut block[BGZF_HEADER_SIZE..])?;
+    Ok(Some(block))
+}
+
+/// Inflate one complete BGZF block
+fn inflate_block(block: &[u8]) -> io::Result<Vec<u8>> {
+    let size = &block[block.len() - 4..];
+    let decompressed_size = u32::from_le_bytes([size[0], size[1], size[2], size[3]]) as usize;
+    let mut output = Vec::with_capacity(decompressed_size);
+    GzDecoder::new(block).read_to_end(&mut output)?;
+    Ok(output)
+}
+
+/// Inflate a plain gzip stream in fixed-size chunks
+fn inflate_stream<R: Read>(reader: BufReader<R>, sender: Sender<Chunk>) {
+    let mut decoder = MultiGzDecoder::new(reader);
+    for index in 0.. {
+        let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+        match (&mut decoder).take(CHUNK_SIZE as u64).read_to_end(&mut chunk) {
+            Ok(0) => break,
+            Ok(_) => {
+                if sender.send((index, Ok(chunk))).is_err() {
+                    break;
+                }
+            }
+            Err(error) => {
+                let _ = sender.send((index, Err(error)));
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use crate::fastq::ReadInfo;
+use log::info;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::Write;
+use std::path::Path;
+
+/// Key length used by `umi` mode without --dedup-length
+const DEFAULT_UMI_LENGTH: usize = 12;
+
+/// Key length used by `prefix` mode without --dedup-length
+const DEFAULT_PREFIX_LENGTH: usize = 100;
+
+/// How passing reads of one output file are counted
+#[derive(Default)]
+struct OutputDuplicates {
+    /// Hashes of the keys seen so far
+    seen: HashSet<u64>,
+    reads: u64,
+    duplicates: u64,
+}
+
+/// Duplicate detection among the reads written to each output file
+///
+/// Reads are keyed by `umi` (the `umi=` header field, else the first bases of
+/// the trimmed read), `exact` (the whole trimmed sequence) or `prefix` (its
+/// first bases), always in output orientation so both strands of a molecule
+/// share a key. Only 64-bit key hashes are kept, and the first read of each
+/// key is the one kept.
+pub struct Deduplicator {
+    mode: String,
+    key_length: usize,
+    tag: bool,
+    id_separator: String,
+    outputs: BTreeMap<String, OutputDuplicates>,
+}
+
+impl Deduplicator {
+    /// Create deduplicator for a --dedup mode, `action` being `drop` or `tag`
+    pub fn new(mode: &str, key_length: Option<usize>, action: &str, id_separator: &str) -> Self {
+        let default_length = if mode == "umi" { DEFAULT_UMI_LENGTH } else { DEFAULT_PREFIX_LENGTH };
+        Self {
+            mode: mode.to_string(),
+            key_length: key_length.unwrap_or(default_length),
+            tag: action == "tag",
+            id_separator: id_separator.to_string(),
+            outputs: BTreeMap::new(),
+        }
+    }
+
+    /// Hash of the duplicate key, None for reads that are not written
+    fn key_hash(&self, read_info: &ReadInfo) -> Option<u64> {
+        let mut hasher = DefaultHasher::new();
+        if self.mode == "umi"
+            && let Some(umi) = read_info.header_value("umi")
+        {
+            umi.as_bytes().hash(&mut hasher);
+            return Some(hasher.finish());
+        }
+        let sequence = read_info.output_sequence()?;
+        match self.mode.as_str() {
+            "exact" => sequence.hash(&mut hasher),
+            _ => sequence[..self.key_length.min(sequence.len())].hash(&mut hasher),
+        }
+        Some(hasher.finish())
+    }
+
+    /// Drop or tag the read if an earlier read of its output file had the same key
+    pub fn process(&mut self, read_info: &mut ReadInfo) {
+        let Some(key_hash) = self.key_hash(read_info) else {
+            return;
+        };
+        let output = self.outputs.entry(read_info.output_filename.clone()).or_default();
+        output.reads += 1;
+        if output.seen.insert(key_hash) {
+            return;
+        }
+        output.duplicates += 1;
+        if self.tag {
+            read_info.record_id = format!("{}{}dup", read_info.record_id, self.id_separator);
+        } else {
+            read_info.should_write_to_fastq = false;
+        }
+    }
+
+    /// Write reads, unique keys and duplication rate per output file to dedup_stats.tsv
+    pub fn write_statistics(&self, output_directory: &str) {
+        let file_path = Path::new(output_directory).join("dedup_stats.tsv");
+        let mut file = File::create(&file_path).expect("Failed to create duplicate statistics file");
+        writeln!(file, "output\treads\tunique\tduplicates\tduplication_rate").expect("Failed to write table header");
+
+        let (mut total_reads, mut total_duplicates) = (0, 0);
+        for (output_name, output) in &self.outputs {
+            total_reads += output.reads;
+            total_duplicates += output.duplicates;
+            writeln!(
+                file,
+                "{}\t{}\t{}\t{}\t{:.2}",
+                output_name,
+                output.reads,
+                output.reads - output.duplicates,
+                output.duplicates,
+                duplication_rate(output.reads, output.duplicates)
+            ).expect("Failed to write duplicate statistics");
+        }
+        info!(
+            "Duplicates ({} mode): {} of {} written reads ({:.2}%) {}",
+            self.mode,
+            total_duplicates,
+            total_reads,
+            duplication_rate(total_reads, total_duplicates),
+            if self.tag { "tagged" } else { "dropped" }
+        );
+    }
+}
+
+/// Percentage of reads that are duplicates
+fn duplication_rate(reads: u64, duplicates: u64) -> f64 {
+    if reads > 0 { duplicates as f64 / reads as f64 * 100.0 } else { 0.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bio::io::fastq::Record;
+
+    /// Passing read written to `output`
+    fn written_read(id: &str, description: Option<&str>, sequence: &[u8], output: &str) -> ReadInfo {
+        let quality = vec![b'I'; sequence.len()];
+        let mut read_info = ReadInfo::new(Record::with_attrs(id, description, sequence, &quality));
+        read_info.should_write_to_fastq = true;
+        read_info.output_filename = output.to_string();
+        read_info
+    }
+
+    /// IDs of the reads left to write after deduplication, in input order
+    fn kept_ids(deduplicator: &mut Deduplicator, mut reads: Vec<ReadInfo>) -> Vec<String> {
+        for read_info in &mut reads {
+            deduplicator.process(read_info);
+        }
+        reads.into_iter().filter(|read_info| read_info.should_write_to_fastq).map(|read_info| read_info.record_id).collect()
+    }
+
+    #[test]
+    fn test_umi_mode_prefers_header_over_leading_bases() {
+        let mut deduplicator = Deduplicator::new("umi", None, "drop", "%");
+        let reads = vec![
+            written_read("a", Some("umi=ACGTAC"), b"AAAAAAAAAAAAAAAACCCC", "BC01"),
+            written_read("b", Some("umi=ACGTAC"), b"GGGGGGGGGGGGGGGGGGGG", "BC01"),
+            written_read("c", Some("umi=TTTTTT"), b"AAAAAAAAAAAAAAAACCCC", "BC01"),
+            // Without a umi= field the first 12 bases are the key
+            written_read("d", None, b"CCCCCCCCCCCCAAAA", "BC01"),
+            written_read("e", Some("ch=12"), b"CCCCCCCCCCCCGGGG", "BC01"),
+            written_read("f", None, b"CCCCCCCCCCCGGGGG", "BC01"),
+        ];
+        assert_eq!(kept_ids(&mut deduplicator, reads), vec!["a", "c", "d", "f"]);
+    }
+
+    #[test]
+    fn test_exact_and_prefix_modes() {
+        let reads = || {
+            vec![
+                written_read("a", None, b"ACGTACGTAA", "BC01"),
+                written_read("b", None, b"ACGTACGTAA", "BC01"),
+                written_read("c", None, b"ACGTACGTAC", "BC01"),
+                written_read("d", None, b"ACGTTTTT", "BC01"),
+            ]
+        };
+        let mut exact = Deduplicator::new("exact", None, "drop", "%");
+        assert_eq!(kept_ids(&mut exact, reads()), vec!["a", "c", "d"]);
+        let mut prefix = Deduplicator::new("prefix", Some(4), "drop", "%");
+        assert_eq!(kept_ids(&mut prefix, reads()), vec!["a"]);
+        let mut longer_prefix = Deduplicator::new("prefix", Some(6), "drop", "%");
+        assert_eq!(kept_ids(&mut longer_prefix, reads()), vec!["a", "d"]);
+    }
+
+    #[test]
+    fn test_tag_keeps_duplicates_with_suffix() {
+        let mut deduplicator = Deduplicator::new("exact", None, "tag", "%");
+        let reads = vec![written_read("a", None, b"ACGT", "BC01"), written_read("b", None, b"ACGT", "BC01")];
+        assert_eq!(kept_ids(&mut deduplicator, reads), vec!["a", "b%dup"]);
+    }
+
+    #[test]
+    fn test_duplicates_are_counted_per_output() {
+        let mut deduplicator = Deduplicator::new("exact", None, "drop", "%");
+        let mut unwritten = written_read("e", None, b"ACGT", "BC01");
+        unwritten.should_write_to_fastq = false;
+        let reads = vec![
+            written_read("a", None, b"ACGT", "BC01"),
+            written_read("b", None, b"ACGT", "BC02"),
+            written_read("c", None, b"ACGT", "BC01"),
+            written_read("d", None, b"TTTT", "BC01"),
+            unwritten,
+        ];
+        assert_eq!(kept_ids(&mut deduplicator, reads), vec!["a", "b", "d"]);
+        let counts: Vec<(&str, u64, u64)> = deduplicator
+            .outputs
+            .iter()
+            .map(|(name, output)| (name.as_str(), output.reads, output.duplicates))
+            .collect();
+        assert_eq!(counts, vec![("BC01", 3, 1), ("BC02", 1, 0)]);
+    }
+}
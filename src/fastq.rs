@@ -1,50 +1,330 @@
-use crate::splitter::SplitType;
+use crate::control::ControlSocket;
+use crate::counter::ReadStats;
+use crate::decompress::ParallelGzDecoder;
+use crate::interrupt::is_interrupted;
+use crate::pattern::PatternConfiguration;
+use crate::remote::{expand_remote_glob, is_remote, RemoteReader};
+use crate::splitter::{Matcher, SplitType};
+use crate::utils::{complement_byte, homopolymer_tail_length, id_component, json_string, parse_utc_timestamp, quality_trim_window, reverse_complement_bytes, wildcard_match, SplitMix64};
 use bio::io::fastq::{Reader, Record};
 use flate2::read::MultiGzDecoder;
 use flume::{unbounded, Sender, Receiver};
 use log::info;
+use std::borrow::Cow;
 use std::ffi::OsStr;
 use std::{
     fs::File,
-    io::{BufReader, Read},
-    path::PathBuf,
+    io::{BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
 };
-use std::time::Instant;
-use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 /// Buffer size constant for I/O performance optimization - memory optimized
 const BUFFER_SIZE: usize = 2 * 1024 * 1024; // Reduced from 10MB to 2MB
 
-/// Check if file is gzip compressed format
-fn is_gzip_file(path: &PathBuf) -> bool {
-    match path.extension().and_then(OsStr::to_str) {
-        Some(ext) => ext == "gz",
-        None => false,
+/// Buffer size used with --low-latency
+const LOW_LATENCY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Leading bytes of a gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Whether a local input is a pipe or device rather than a regular file
+///
+/// FIFOs and `/dev/fd/N` paths from process substitution can only be read
+/// once, front to back, so they are never reopened for a second pass.
+pub fn is_stream_input(path: &Path) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    path.metadata().is_ok_and(|metadata| {
+        let file_type = metadata.file_type();
+        file_type.is_fifo() || file_type.is_char_device() || file_type.is_socket()
+    })
+}
+
+/// File name suffixes picked up when an input is a directory
+const FASTQ_SUFFIXES: [&str; 4] = [".fastq", ".fq", ".fastq.gz", ".fq.gz"];
+
+/// Expand directory and glob inputs into a sorted list of FASTQ files
+///
+/// Directories are searched recursively, and globs may use `*` and `?` in any
+/// component plus `**` for any number of directories; both only pick up files
+/// with a FASTQ suffix. Plain file paths and URLs are kept as given, `s3://`
+/// globs are listed with the AWS CLI.
+pub fn expand_input_paths(inputs: &[String]) -> Vec<String> {
+    let mut files = Vec::new();
+    let mut expanded = false;
+    
+    for input in inputs {
+        if is_remote(input) {
+            let objects = expand_remote_glob(input);
+            if input.contains(['*', '?']) {
+                let objects: Vec<PathBuf> = objects.into_iter().map(PathBuf::from).filter(|object| has_fastq_suffix(object)).collect();
+                if objects.is_empty() {
+                    panic!("No FASTQ files match: {}", input);
+                }
+                files.extend(objects);
+                expanded = true;
+            } else {
+                files.extend(objects.into_iter().map(PathBuf::from));
+            }
+            continue;
+        }
+        match find_input_files(input) {
+            Some(matches) if matches.is_empty() => panic!("No FASTQ files match: {}", input),
+            Some(matches) => {
+                files.extend(matches);
+                expanded = true;
+            }
+            None => files.push(PathBuf::from(input)),
+        }
+    }
+    
+    // The same file reached through two inputs is only read once
+    let mut seen = HashSet::new();
+    files.retain(|file| seen.insert(file.clone()));
+    
+    if expanded {
+        let total_size: u64 = files.iter().filter_map(|file| file.metadata().ok()).map(|metadata| metadata.len()).sum();
+        info!(
+            "Discovered {} input files ({:.1} MB), first: {}, last: {}",
+            files.len(),
+            total_size as f64 / 1024.0 / 1024.0,
+            files.first().map(|file| file.display().to_string()).unwrap_or_default(),
+            files.last().map(|file| file.display().to_string()).unwrap_or_default()
+        );
+    }
+    
+    files.into_iter().map(|file| file.display().to_string()).collect()
+}
+
+/// Sorted FASTQ files of a directory or glob input, None for a plain path
+fn find_input_files(input: &str) -> Option<Vec<PathBuf>> {
+    let path = Path::new(input);
+    let mut matches = Vec::new();
+    if input.contains(['*', '?']) {
+        for matched in expand_glob(path) {
+            if matched.is_dir() {
+                collect_fastq_files(&matched, &mut matches);
+            } else if has_fastq_suffix(&matched) {
+                matches.push(matched);
+            }
+        }
+    } else if path.is_dir() {
+        collect_fastq_files(path, &mut matches);
+    } else {
+        return None;
+    }
+    matches.sort();
+    Some(matches)
+}
+
+/// Recursively collect files with a FASTQ suffix below `directory`
+fn collect_fastq_files(directory: &Path, files: &mut Vec<PathBuf>) {
+    let entries = std::fs::read_dir(directory)
+        .unwrap_or_else(|_| panic!("Unable to read input directory: {}", directory.display()));
+    for entry in entries {
+        let Ok(entry) = entry else {
+            continue;
+        };
+        let path = entry.path();
+        if path.is_dir() {
+            collect_fastq_files(&path, files);
+        } else if has_fastq_suffix(&path) {
+            files.push(path);
+        }
+    }
+}
+
+/// Check if the file name ends with a FASTQ suffix
+fn has_fastq_suffix(path: &Path) -> bool {
+    path.file_name()
+        .and_then(OsStr::to_str)
+        .is_some_and(|name| FASTQ_SUFFIXES.iter().any(|suffix| name.ends_with(suffix)))
+}
+
+/// Paths matching a glob pattern, component by component
+fn expand_glob(pattern: &Path) -> Vec<PathBuf> {
+    let mut candidates = vec![PathBuf::new()];
+    for component in pattern.components() {
+        let component = component.as_os_str().to_string_lossy();
+        let mut next = Vec::new();
+        for base in &candidates {
+            let directory = if base.as_os_str().is_empty() { Path::new(".") } else { base.as_path() };
+            if component == "**" {
+                collect_directories(base, &mut next);
+            } else if component.contains(['*', '?']) {
+                let Ok(entries) = std::fs::read_dir(directory) else {
+                    continue;
+                };
+                for entry in entries.flatten() {
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    // Hidden files only match patterns that ask for them
+                    if (!name.starts_with('.') || component.starts_with('.')) && wildcard_match(&component, &name) {
+                        next.push(base.join(name));
+                    }
+                }
+            } else {
+                let candidate = base.join(component.as_ref());
+                if candidate.exists() {
+                    next.push(candidate);
+                }
+            }
+        }
+        candidates = next;
     }
+    candidates
+}
+
+/// Collect `base` and every directory below it
+fn collect_directories(base: &Path, directories: &mut Vec<PathBuf>) {
+    directories.push(base.to_path_buf());
+    let directory = if base.as_os_str().is_empty() { Path::new(".") } else { base };
+    if let Ok(entries) = std::fs::read_dir(directory) {
+        for entry in entries.flatten() {
+            let path = base.join(entry.file_name());
+            if path.is_dir() {
+                collect_directories(&path, directories);
+            }
+        }
+    }
+}
+
+/// Read subsetting applied while reading input
+pub struct ReadSelection {
+    /// Stop after this many selected reads, 0 for no limit
+    head: usize,
+    /// Fraction of reads kept
+    sample_fraction: f64,
+    /// Random number generator for sampling
+    random: SplitMix64,
+    /// Number of reads selected so far
+    selected: usize,
+}
+
+impl ReadSelection {
+    /// Create read selection
+    pub fn new(head: usize, sample_fraction: f64, seed: u64) -> Self {
+        Self {
+            head,
+            sample_fraction,
+            random: SplitMix64::new(seed),
+            selected: 0,
+        }
+    }
+    
+    /// Keep every read
+    pub fn all() -> Self {
+        Self::new(0, 1.0, 0)
+    }
+    
+    /// Check if the head limit has been reached
+    fn is_done(&self) -> bool {
+        self.head > 0 && self.selected >= self.head
+    }
+    
+    /// Decide whether the next read is kept
+    fn keep(&mut self) -> bool {
+        if self.sample_fraction < 1.0 && self.random.next_unit() >= self.sample_fraction {
+            return false;
+        }
+        self.selected += 1;
+        true
+    }
+}
+
+/// Input options for the FASTQ reader
+#[derive(Debug, Clone, Default)]
+pub struct ReaderOptions {
+    /// Use small read buffers
+    pub low_latency: bool,
+    /// Threads used to decompress each gzip input, 0 or 1 to decompress inline
+    pub decompress_threads: usize,
+    /// Number of input files read in parallel
+    pub reader_threads: usize,
+    /// Keep polling the inputs for new files
+    pub watch: Option<WatchOptions>,
+    /// Index read files (I1, I2) read in step with the inputs, which are then R1 and R2
+    pub index_reads: Vec<String>,
+    /// Inputs hold read pairs as consecutive records, R1 then R2
+    pub interleaved: bool,
+}
+
+/// Polling options for --watch
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    /// Time between input scans
+    pub interval: Duration,
+    /// Watching stops once this file or glob exists
+    pub sentinel: Option<String>,
+}
+
+/// Set once the reader thread panicked, so a truncated input is not taken for a complete one
+static READ_FAILED: AtomicBool = AtomicBool::new(false);
+
+/// Marks reading as failed when dropped by a panicking reader thread
+struct ReadFailureGuard;
+
+impl Drop for ReadFailureGuard {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            READ_FAILED.store(true, Ordering::Release);
+        }
+    }
+}
+
+/// Whether reading stopped on an error instead of at the end of the input
+pub fn reading_failed() -> bool {
+    READ_FAILED.load(Ordering::Acquire)
 }
 
 /// Create FASTQ reader, return receiver
-pub fn create_reader(files: Vec<String>) -> Receiver<ReadInfo> {
+pub fn create_reader(
+    files: Vec<String>,
+    options: ReaderOptions,
+    control: Option<Arc<ControlSocket>>,
+    selection: ReadSelection,
+) -> Receiver<ReadInfo> {
     let (sender, receiver) = unbounded();
     
     std::thread::spawn(move || {
+        let _failure_guard = ReadFailureGuard;
         let start_time = Instant::now();
+        let selection = Mutex::new(selection);
         
-        if files.is_empty() {
+        if !options.index_reads.is_empty() {
+            read_indexed_files(&files, &options, &sender, control.as_deref(), &selection);
+        } else if files.is_empty() && options.watch.is_none() {
             info!("No input files specified, reading from standard input...");
             let stdin_handle = std::io::stdin();
-            process_file(stdin_handle, &sender, None);
+            process_file(stdin_handle, &sender, None, &options, control.as_deref(), &selection);
         } else {
-            for file_path in files {
-                let path = PathBuf::from(&file_path);
-                if path.exists() {
-                    let file_handle = File::open(&path)
-                        .expect(&format!("Unable to open input file: {}", path.display()));
-                    process_file(file_handle, &sender, Some(path));
-                } else {
-                    panic!("File does not exist: {}", path.display());
+            let (file_sender, file_receiver) = unbounded();
+            let reader_count = match &options.watch {
+                Some(_) => options.reader_threads.max(1),
+                None => {
+                    for file_path in &files {
+                        file_sender.send(PathBuf::from(file_path)).expect("Failed to queue input file");
+                    }
+                    options.reader_threads.clamp(1, files.len())
                 }
+            };
+            if reader_count > 1 {
+                info!("Reading input files with {} reader threads", reader_count);
             }
+            
+            std::thread::scope(|scope| {
+                match &options.watch {
+                    Some(watch) => {
+                        scope.spawn(|| watch_input_files(&files, watch, control.as_deref(), &selection, file_sender));
+                    }
+                    None => drop(file_sender),
+                }
+                for _ in 0..reader_count {
+                    scope.spawn(|| read_files(&file_receiver, &sender, &options, control.as_deref(), &selection));
+                }
+            });
         }
 
         let elapsed_time = start_time.elapsed();
@@ -54,61 +334,314 @@ pub fn create_reader(files: Vec<String>) -> Receiver<ReadInfo> {
     receiver
 }
 
+/// Queue FASTQ files appearing under the watched inputs until the sentinel shows up
+///
+/// A file is queued once its size is unchanged between two scans, so files
+/// still being written by the sequencer are left for a later scan.
+fn watch_input_files(
+    inputs: &[String],
+    watch: &WatchOptions,
+    control: Option<&ControlSocket>,
+    selection: &Mutex<ReadSelection>,
+    file_sender: Sender<PathBuf>,
+) {
+    info!("Watching {} for new FASTQ files every {:?}", inputs.join(", "), watch.interval);
+    let mut queued: HashSet<PathBuf> = HashSet::new();
+    let mut previous_sizes: HashMap<PathBuf, u64> = HashMap::new();
+    
+    loop {
+        let finished = watch.sentinel.as_deref().is_some_and(sentinel_exists);
+        let stopped = control.is_some_and(|control| control.should_stop_after_current_file())
+            || selection.lock().unwrap().is_done()
+            || is_interrupted();
+        
+        let mut new_files = 0;
+        for input in inputs {
+            let files = find_input_files(input).unwrap_or_else(|| vec![PathBuf::from(input)]);
+            for file in files {
+                if queued.contains(&file) {
+                    continue;
+                }
+                let Ok(metadata) = file.metadata() else {
+                    continue;
+                };
+                // Files are complete once the sentinel exists
+                if finished || previous_sizes.get(&file) == Some(&metadata.len()) {
+                    previous_sizes.remove(&file);
+                    queued.insert(file.clone());
+                    if file_sender.send(file).is_err() {
+                        return;
+                    }
+                    new_files += 1;
+                } else {
+                    previous_sizes.insert(file, metadata.len());
+                }
+            }
+        }
+        if new_files > 0 {
+            info!("Queued {} new input files ({} in total)", new_files, queued.len());
+        }
+        
+        if finished {
+            info!("Sentinel found, stopping watch after {} input files", queued.len());
+            return;
+        }
+        if stopped {
+            info!("Stopping watch after {} input files", queued.len());
+            return;
+        }
+        let scan_time = Instant::now();
+        while scan_time.elapsed() < watch.interval && !is_interrupted() {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+/// Check if the sentinel file, or any file matching a sentinel glob, exists
+fn sentinel_exists(sentinel: &str) -> bool {
+    if sentinel.contains(['*', '?']) {
+        !expand_glob(Path::new(sentinel)).is_empty()
+    } else {
+        Path::new(sentinel).exists()
+    }
+}
+
+/// Read queued input files one after another until the queue is empty or reading should stop
+fn read_files(
+    file_receiver: &Receiver<PathBuf>,
+    sender: &Sender<ReadInfo>,
+    options: &ReaderOptions,
+    control: Option<&ControlSocket>,
+    selection: &Mutex<ReadSelection>,
+) {
+    for path in file_receiver.iter() {
+        process_file(open_input(&path), sender, Some(path.clone()), options, control, selection);
+        
+        if let Some(control) = control {
+            control.emit_file_done(&path);
+            if control.should_stop_after_current_file() {
+                info!("Stopping after {} as requested on the control socket", path.display());
+                break;
+            }
+        }
+        if selection.lock().unwrap().is_done() || is_interrupted() {
+            break;
+        }
+    }
+}
+
+/// Open a local input file, or start streaming a remote one
+fn open_input(path: &Path) -> Box<dyn Read + Send> {
+    if let Some(url) = path.to_str().filter(|path| is_remote(path)) {
+        return Box::new(RemoteReader::open(url));
+    }
+    // Pipes block here until their writer starts, which is expected
+    let file_handle = File::open(path)
+        .unwrap_or_else(|error| panic!("Unable to open input file {}: {}", path.display(), error));
+    Box::new(file_handle)
+}
+
+/// Read biological and index records in step, matching patterns in the index reads
+///
+/// The matched sequence is I1 followed by the reverse complement of I2, so a
+/// pattern pair names the I1 and I2 sequences as read. R1 and R2 become the
+/// mates written in place of the index sequence.
+fn read_indexed_files(
+    files: &[String],
+    options: &ReaderOptions,
+    sender: &Sender<ReadInfo>,
+    control: Option<&ControlSocket>,
+    selection: &Mutex<ReadSelection>,
+) {
+    if files.is_empty() || files.len() > 2 || (options.interleaved && files.len() > 1) {
+        panic!("Index reads need one or two input files (R1 and optionally R2, or one interleaved file), got {}", files.len());
+    }
+    let open_records = |file_path: &String| {
+        let path = PathBuf::from(file_path);
+        let buffered_reader = BufReader::with_capacity(BUFFER_SIZE, open_input(&path));
+        Reader::new(create_decoder(buffered_reader, Some(path), options.decompress_threads)).records()
+    };
+    let mut read_records: Vec<_> = files.iter().map(open_records).collect();
+    let mut index_records: Vec<_> = options.index_reads.iter().map(open_records).collect();
+    let source_file: Option<Arc<Path>> = Some(Arc::from(Path::new(&files[0])));
+    info!("Matching patterns in index reads: {}", options.index_reads.join(", "));
+    
+    loop {
+        let mates: Vec<Option<Record>> = if options.interleaved {
+            let records = &mut read_records[0];
+            (0..2).map(|_| records.next().map(|record| record.expect("Failed to read FASTQ record"))).collect()
+        } else {
+            read_records.iter_mut()
+                .map(|records| records.next().map(|record| record.expect("Failed to read FASTQ record")))
+                .collect()
+        };
+        let indexes: Vec<Option<Record>> = index_records.iter_mut()
+            .map(|records| records.next().map(|record| record.expect("Failed to read FASTQ record")))
+            .collect();
+        if mates.iter().chain(&indexes).all(Option::is_none) || is_interrupted() {
+            break;
+        }
+        let (Some(mates), Some(indexes)) = (mates.into_iter().collect::<Option<Vec<Record>>>(), indexes.into_iter().collect::<Option<Vec<Record>>>()) else {
+            panic!("Input and index read files hold different numbers of reads");
+        };
+        let read_name = pair_name(mates[0].id());
+        if let Some(record) = mates.iter().chain(&indexes).find(|record| pair_name(record.id()) != read_name) {
+            panic!("Input and index read files are out of step: {} and {}", mates[0].id(), record.id());
+        }
+        {
+            let mut selection = selection.lock().unwrap();
+            if selection.is_done() {
+                break;
+            }
+            if !selection.keep() {
+                continue;
+            }
+        }
+        
+        let mut read_info = ReadInfo::from_index_reads(mates, &indexes);
+        read_info.source_file = source_file.clone();
+        if let Some(control) = control {
+            control.wait_while_paused();
+        }
+        if sender.send(read_info).is_err() {
+            break;
+        }
+    }
+}
+
+/// Read name shared by both reads of a pair, without a `/1` or `/2` suffix
+fn pair_name(id: &str) -> &str {
+    id.strip_suffix("/1").or_else(|| id.strip_suffix("/2")).unwrap_or(id)
+}
+
+/// Read up to `limit` records from the start of the input files, skipping second reads of interleaved pairs
+pub fn read_first_records(files: &[String], limit: usize, interleaved: bool) -> Vec<ReadInfo> {
+    let mut read_infos = Vec::with_capacity(limit);
+    
+    for file_path in files {
+        let path = PathBuf::from(file_path);
+        let buffered_reader = BufReader::with_capacity(BUFFER_SIZE, open_input(&path));
+        let fastq_reader = Reader::new(create_decoder(buffered_reader, Some(path), 1));
+        
+        for record_result in fastq_reader.records().step_by(if interleaved { 2 } else { 1 }) {
+            if read_infos.len() >= limit {
+                return read_infos;
+            }
+            let record = record_result.expect("Failed to read FASTQ record");
+            read_infos.push(ReadInfo::new(record));
+        }
+    }
+    
+    read_infos
+}
+
 /// Process single file
-fn process_file<R: Read + 'static>(
+fn process_file<R: Read + Send + 'static>(
     file_handle: R, 
     sender: &Sender<ReadInfo>, 
-    file_path: Option<PathBuf>
+    file_path: Option<PathBuf>,
+    options: &ReaderOptions,
+    control: Option<&ControlSocket>,
+    selection: &Mutex<ReadSelection>,
 ) {
-    let buffered_reader = BufReader::with_capacity(BUFFER_SIZE, file_handle);
-    let decoder_handle = create_decoder(buffered_reader, file_path);
-    let fastq_reader = Reader::new(decoder_handle);
+    let buffer_size = if options.low_latency { LOW_LATENCY_BUFFER_SIZE } else { BUFFER_SIZE };
+    let source_file: Option<Arc<Path>> = file_path.as_deref().map(Arc::from);
+    let buffered_reader = BufReader::with_capacity(buffer_size, file_handle);
+    let decoder_handle = create_decoder(buffered_reader, file_path, options.decompress_threads);
+    let mut records = Reader::new(decoder_handle).records();
     
-    for record_result in fastq_reader.records() {
+    while let Some(record_result) = records.next() {
         let record = record_result.expect("Failed to read FASTQ record");
-        let read_info = ReadInfo::new(record);
-        sender.send(read_info).expect("Failed to send sequence information");
+        let mate = options.interleaved.then(|| {
+            let mate = records.next()
+                .unwrap_or_else(|| panic!("Interleaved input ends with an unpaired read: {}", record.id()))
+                .expect("Failed to read FASTQ record");
+            if pair_name(mate.id()) != pair_name(record.id()) {
+                panic!("Interleaved input is out of step: {} and {}", record.id(), mate.id());
+            }
+            mate
+        });
+        if is_interrupted() {
+            break;
+        }
+        {
+            let mut selection = selection.lock().unwrap();
+            if selection.is_done() {
+                break;
+            }
+            if !selection.keep() {
+                continue;
+            }
+        }
+        let mut read_info = ReadInfo::new(record);
+        read_info.mates.extend(mate);
+        read_info.source_file = source_file.clone();
+        if let Some(control) = control {
+            control.wait_while_paused();
+        }
+        // The consumer may stop early, e.g. view --limit
+        if sender.send(read_info).is_err() {
+            break;
+        }
     }
 }
 
 /// Create appropriate decoder
-fn create_decoder<R: Read + 'static>(
+///
+/// Gzip is recognized by its magic bytes rather than the file name, so pipes
+/// and `/dev/fd/N` paths are decoded like regular files. Peeking at the buffer
+/// consumes nothing from the stream.
+fn create_decoder<R: Read + Send + 'static>(
     buffered_reader: BufReader<R>, 
-    file_path: Option<PathBuf>
+    file_path: Option<PathBuf>,
+    decompress_threads: usize,
 ) -> Box<dyn Read> {
-    match file_path {
-        Some(path) if is_gzip_file(&path) => {
-            info!("Loading gzip compressed file: {:?}", path);
-            Box::new(MultiGzDecoder::new(buffered_reader)) as Box<dyn Read>
-        }
-        Some(path) => {
-            info!("Loading FASTQ file: {:?}", path);
-            Box::new(buffered_reader) as Box<dyn Read>
-        }
-        None => Box::new(buffered_reader) as Box<dyn Read>,
+    let mut buffered_reader = buffered_reader;
+    let is_gzip = buffered_reader.fill_buf()
+        .map(|buffer| buffer.starts_with(&GZIP_MAGIC))
+        .unwrap_or(false);
+    let source = match &file_path {
+        Some(path) => format!("{:?}", path),
+        None => "standard input".to_string(),
+    };
+    if is_gzip {
+        info!("Loading gzip compressed data from {}", source);
+        create_gzip_decoder(buffered_reader, decompress_threads)
+    } else {
+        info!("Loading FASTQ data from {}", source);
+        Box::new(buffered_reader) as Box<dyn Read>
     }
 }
 
-/// Lightweight statistics structure for memory optimization
-#[derive(Debug, Clone)]
-pub struct ReadInfoStats {
-    pub record_id: String,
-    pub sequence_type: String,
-    pub sequence_length: usize,
-    pub match_types: Vec<String>,
-    pub match_names: Vec<String>,
-    pub strand_orientation: String,
+/// Create gzip decoder, decompressing on background threads when more than one is allowed
+fn create_gzip_decoder<R: Read + Send + 'static>(buffered_reader: BufReader<R>, decompress_threads: usize) -> Box<dyn Read> {
+    if decompress_threads > 1 {
+        Box::new(ParallelGzDecoder::new(buffered_reader, decompress_threads))
+    } else {
+        Box::new(MultiGzDecoder::new(buffered_reader))
+    }
+}
+
+/// Where valid reads are cut, from the trim options
+#[derive(Clone, Copy)]
+struct TrimCut {
+    /// --trim-mode, selecting the round the cut is made at
+    mode: usize,
+    /// Left and right cut offsets of that round
+    offsets: (isize, isize),
+    /// Whether the round's left and right patterns stay in the read
+    kept_patterns: (bool, bool),
 }
 
 /// Sequence information structure - optimized for memory efficiency
 #[derive(Debug)]
 pub struct ReadInfo {
-    /// Original FASTQ record ID (only store ID, not full record)
+    /// Output record ID (starts as the original FASTQ ID)
     pub record_id: String,
-    /// Sequence data (only store when needed)
-    pub sequence: Option<Vec<u8>>,
-    /// Quality data (only store when needed)
-    pub quality: Option<Vec<u8>>,
+    /// Original FASTQ record ID
+    pub original_id: String,
+    /// Input record holding sequence and quality, released once the read is not written
+    record: Option<Record>,
     /// Split type vector
     pub split_types: Vec<SplitType>,
     /// Output filename
@@ -129,16 +662,39 @@ pub struct ReadInfo {
     pub sequence_window: (usize, usize),
     /// Trim positions for output
     pub trim_positions: (usize, usize),
+    /// Whether to reverse-complement the output record
+    pub reverse_output: bool,
+    /// Bases removed from the output record by quality trimming
+    pub quality_trimmed_bases: usize,
+    /// Bases removed from the output record by homopolymer tail trimming
+    pub polyx_trimmed_bases: usize,
+    /// Input file the read came from, None for standard input
+    pub source_file: Option<Arc<Path>>,
+    /// Values of the --group-by fields, in order
+    pub group_values: Vec<String>,
+    /// Seconds since the epoch from the `start_time` header field, read with --time-bin
+    pub start_time: Option<f64>,
+    /// Reads of a pair written untrimmed with this one: R1 and R2 when it is an index read, else the interleaved R2
+    pub mates: Vec<Record>,
+    /// Whether the record is an index read, written only through its mates
+    pub index_read: bool,
+    /// Length --min-length and the base counts apply to, that of R1 for index reads
+    pub read_length: usize,
+    /// Class of the fusion pattern found inside the read, empty when none was
+    pub fusion_class: String,
+    /// Fusion pattern found inside the read and its strand, `pattern:fs` or `pattern:rs`, empty when none was
+    pub fusion_match: String,
 }
 
 impl ReadInfo {
     /// Create new sequence information - memory optimized
     pub fn new(record: Record) -> Self {
         let sequence_length = record.seq().len();
+        let original_id = record.id().to_string();
         Self {
-            record_id: record.id().to_string(),
-            sequence: Some(record.seq().to_vec()),
-            quality: Some(record.qual().to_vec()),
+            record_id: original_id.clone(),
+            original_id,
+            record: Some(record),
             split_types: Vec::new(),
             output_filename: String::new(),
             strand_orientation: String::from("unknown"),
@@ -149,53 +705,259 @@ impl ReadInfo {
             sequence_length,
             sequence_window: (0, sequence_length),
             trim_positions: (0, sequence_length),
+            reverse_output: false,
+            quality_trimmed_bases: 0,
+            polyx_trimmed_bases: 0,
+            source_file: None,
+            group_values: Vec::new(),
+            start_time: None,
+            mates: Vec::new(),
+            index_read: false,
+            read_length: sequence_length,
+            fusion_class: String::new(),
+            fusion_match: String::new(),
         }
     }
     
+    /// Read matched in I1 followed by the reverse complement of I2, written as its mates R1 and R2
+    fn from_index_reads(mates: Vec<Record>, indexes: &[Record]) -> Self {
+        let mut sequence = indexes[0].seq().to_vec();
+        let mut quality = indexes[0].qual().to_vec();
+        if let Some(index2) = indexes.get(1) {
+            sequence.extend(reverse_complement_bytes(index2.seq()));
+            quality.extend(index2.qual().iter().rev());
+        }
+        let mut read_info = ReadInfo::new(Record::with_attrs(mates[0].id(), mates[0].desc(), &sequence, &quality));
+        read_info.read_length = mates[0].seq().len();
+        read_info.mates = mates;
+        read_info.index_read = true;
+        read_info
+    }
+    
     /// Update sequence information - memory optimized
-    pub fn update(
-        &mut self, 
-        pattern_match_types: &[String], 
-        write_type: &str, 
-        trim_mode: usize, 
-        min_length: usize, 
-        id_separator: &str
-    ) {
-        self.update_match_names(pattern_match_types);
-        self.update_output_filename(write_type, id_separator);
-        self.update_sequence_type(min_length, trim_mode);
+    pub fn update(&mut self, pattern_config: &PatternConfiguration) {
+        let trim_cut = TrimCut {
+            mode: pattern_config.trim_mode,
+            offsets: pattern_config.trim_offsets(),
+            kept_patterns: pattern_config.kept_patterns(),
+        };
+        let id_separator = &pattern_config.id_separator;
+        
+        if pattern_config.trim_only {
+            self.update_adapter_trim(pattern_config.min_length, pattern_config.max_distances[0]);
+            self.apply_polyx_trim(pattern_config.trim_polyx);
+            self.apply_quality_trim(pattern_config.qual_trim);
+            return;
+        }
+        
+        self.update_match_names(&pattern_config.pattern_match_types);
+        self.apply_expected_barcodes(&pattern_config.expected_barcodes);
+        self.apply_sample_sheet(&pattern_config.sample_sheet);
+        self.update_output_filename(&pattern_config.write_type, id_separator);
+        self.update_sequence_type(pattern_config.min_length, trim_cut);
         self.update_sequence_window();
-        self.update_write_decision(trim_mode, id_separator);
+        self.update_write_decision(trim_cut, id_separator, pattern_config.annotate_score);
+        self.apply_polyx_trim(pattern_config.trim_polyx);
+        self.apply_quality_trim(pattern_config.qual_trim);
         
-        // Clear sequence and quality data if not needed for output
+        // Reverse strand reads are flipped so every output shares one orientation
+        self.reverse_output = pattern_config.orient && self.strand_orientation == "rs";
+    }
+    
+    /// Clear sequence and quality data if not needed for output
+    pub fn release_unused_data(&mut self) {
         if !self.should_write_to_fastq {
-            self.sequence = None;
-            self.quality = None;
+            self.record = None;
+            self.mates = Vec::new();
         }
     }
     
-    /// Clear large data to free memory - new method for memory optimization
-    pub fn clear_large_data(&mut self) {
-        // Clear sequence and quality data regardless of write status
-        // These are the largest memory consumers
-        self.sequence = None;
-        self.quality = None;
-        
-        // Clear split_types if not needed for final output
+    /// Untrimmed sequence, None once released
+    pub fn sequence(&self) -> Option<&[u8]> {
+        self.record.as_ref().map(Record::seq)
+    }
+    
+    /// Untrimmed quality string, None once released
+    pub fn quality(&self) -> Option<&[u8]> {
+        self.record.as_ref().map(Record::qual)
+    }
+    
+    /// Prefix the output ID with the original read name
+    pub fn prefix_original_id(&mut self, id_separator: &str) {
+        self.record_id = format!("{}{}{}", self.original_id, id_separator, self.record_id);
+    }
+    
+    /// Move the annotation to the description, keeping the original read name as the ID
+    pub fn move_tags_to_description(&mut self) {
+        self.record_id = format!("{} {}", self.original_id, self.record_id);
+    }
+    
+    /// Record the --group-by values, splitting the output file by them with --group-output
+    pub fn apply_grouping(&mut self, group_by: &[String], group_output: bool) {
+        if group_by.is_empty() {
+            return;
+        }
+        self.group_values = group_by.iter().map(|field| self.group_value(field)).collect();
+        if group_output && self.should_write_to_fastq {
+            let group_name: Vec<String> = group_by
+                .iter()
+                .zip(&self.group_values)
+                .map(|(field, value)| format!("{}_{}", field, value.replace('/', "_")))
+                .collect();
+            self.output_filename = format!("{}/{}", self.output_filename, group_name.join("_"));
+        }
+    }
+    
+    /// Replace the output path by `template` rendered for the read
+    ///
+    /// `{output}` is the path chosen so far, `{run_id}` and `{date}` the given
+    /// values or the `runid` header field and the date of `start_time`; any
+    /// other field is a --group-by field. Missing values become `-`. Values are
+    /// single path components, `/` replaced by `_` and `.`, `..` or empty ones
+    /// by `-`, so header fields such as `runid=../../x` stay below the output
+    /// directory; the components of `{output}` are checked the same way.
+    pub fn apply_outdir_template(&mut self, template: &str, run_id: Option<&str>, run_date: Option<&str>) {
         if !self.should_write_to_fastq {
-            self.split_types.clear();
+            return;
+        }
+        let mut output_filename = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let end = start + rest[start..].find('}').expect("Template was validated");
+            output_filename.push_str(&rest[..start]);
+            let value = match &rest[start + 1..end] {
+                "output" => self.output_filename.split('/').map(path_component).collect::<Vec<_>>().join("/"),
+                "run_id" => path_component(&run_id.or(self.header_value("runid")).unwrap_or("-").replace('/', "_")),
+                "date" => path_component(
+                    &run_date
+                        .or(self.header_value("start_time").and_then(|start_time| start_time.get(..10)))
+                        .unwrap_or("-")
+                        .replace('/', "_"),
+                ),
+                field => path_component(&self.group_value(field).replace('/', "_")),
+            };
+            output_filename.push_str(&value);
+            rest = &rest[end + 1..];
+        }
+        output_filename.push_str(rest);
+        self.output_filename = output_filename;
+    }
+    
+    /// Read the sequencing start time from the `start_time` header field
+    pub fn read_start_time(&mut self) {
+        self.start_time = self.header_value("start_time").and_then(parse_utc_timestamp);
+    }
+    
+    /// Value of a `key=value` field of the FASTQ header, None once the record is released
+    pub fn header_value(&self, key: &str) -> Option<&str> {
+        self.record
+            .as_ref()
+            .and_then(|record| record.desc())
+            .and_then(|description| header_field(description, key))
+    }
+    
+    /// Value of a grouping field, `-` when the read has none
+    ///
+    /// Per-read fields are named as in the stats subcommand; anything else is read
+    /// from the `key=value` fields of the FASTQ header, `channel` standing for `ch`.
+    fn group_value(&self, field: &str) -> String {
+        if field == "sequence_type" {
+            return self.sequence_type.clone();
+        }
+        if let Some((name, round)) = crate::stats::split_group_field(field) {
+            let value = match name {
+                "name" => self.match_names.get(round - 1).cloned(),
+                "type" => self.match_types.get(round - 1).cloned(),
+                "match" => self.split_types.get(round - 1).map(|split_type| split_type.pattern_match.to_string()),
+                _ => self.split_types.get(round - 1).map(|split_type| split_type.pattern_strand.clone()),
+            };
+            return value.unwrap_or_else(|| String::from("-"));
+        }
+        let key = if field == "channel" { "ch" } else { field };
+        self.header_value(key).unwrap_or("-").to_string()
+    }
+    
+    /// Mark read as fusion of a class and decide whether and where it is written
+    pub fn mark_fusion(&mut self, fusion_action: &str, fusion_class: &str, id_separator: &str) {
+        self.sequence_type = "fusion".to_string();
+        self.fusion_class = fusion_class.to_string();
+        match fusion_action {
+            "write" => {
+                // Keep the whole read in a file named after the class
+                self.should_write_to_fastq = true;
+                self.output_filename = fusion_class.to_string();
+                self.trim_positions = (0, self.sequence_length);
+            }
+            "tag" => {
+                // Keep the normal assignment, only reads that would be written are tagged
+                if self.should_write_to_fastq {
+                    self.record_id = format!("{}{}{}", self.record_id, id_separator, id_component(fusion_class, id_separator));
+                }
+            }
+            _ => self.should_write_to_fastq = false,
         }
     }
     
-    /// Create lightweight copy for statistics - memory optimized
-    pub fn create_stats_copy(&self) -> ReadInfoStats {
-        ReadInfoStats {
-            record_id: self.record_id.clone(),
+    /// Create new read from a slice of this one, with a suffix appended to the original ID
+    pub fn sub_read(&self, start: usize, end: usize, suffix: &str) -> Option<ReadInfo> {
+        match &self.record {
+            Some(record) if start < end && end <= record.seq().len() => {
+                let record_id = format!("{}/{}", self.original_id, suffix);
+                let mut read_info = ReadInfo::new(Record::with_attrs(
+                    &record_id,
+                    record.desc(),
+                    &record.seq()[start..end],
+                    &record.qual()[start..end],
+                ));
+                read_info.source_file = self.source_file.clone();
+                Some(read_info)
+            }
+            _ => None,
+        }
+    }
+    
+    /// Fields counted by the statistics manager, without sequence data
+    pub fn stats(&self) -> ReadStats {
+        ReadStats {
             sequence_type: self.sequence_type.clone(),
-            sequence_length: self.sequence_length,
+            sequence_length: self.read_length,
             match_types: self.match_types.clone(),
             match_names: self.match_names.clone(),
-            strand_orientation: self.strand_orientation.clone(),
+            quality_trimmed_bases: self.quality_trimmed_bases,
+            polyx_trimmed_bases: self.polyx_trimmed_bases,
+            group_values: self.group_values.clone(),
+            start_time: self.start_time,
+            fusion_class: self.fusion_class.clone(),
+            strand: self.strand_orientation.clone(),
+            match_distances: self.split_types
+                .iter()
+                .flat_map(|split_type| [&split_type.left_matcher, &split_type.right_matcher])
+                .filter(|matcher| matcher.status)
+                .map(|matcher| (matcher.get_pattern().to_string(), matcher.get_score()))
+                .collect(),
+        }
+    }
+    
+    /// Mark assigned reads as unexpected when none of their pattern names is expected
+    fn apply_expected_barcodes(&mut self, expected_barcodes: &HashSet<String>) {
+        if expected_barcodes.is_empty() || self.sequence_type != "valid" {
+            return;
+        }
+        if !self.match_names.iter().any(|match_name| expected_barcodes.contains(match_name)) {
+            self.sequence_type = "unexpected".to_string();
+        }
+    }
+    
+    /// Replace matched pattern names or types listed in the sample sheet with their sample ID
+    fn apply_sample_sheet(&mut self, sample_sheet: &HashMap<String, String>) {
+        if sample_sheet.is_empty() {
+            return;
+        }
+        for (match_name, match_type) in self.match_names.iter_mut().zip(self.match_types.iter_mut()) {
+            if let Some(sample) = sample_sheet.get(match_name.as_str()).or_else(|| sample_sheet.get(match_type.as_str())) {
+                *match_name = sample.clone();
+                *match_type = sample.clone();
+            }
         }
     }
     
@@ -205,6 +967,22 @@ impl ReadInfo {
         
         for (index, split_type) in self.split_types.iter().enumerate() {
             match pattern_match_types.get(index) {
+                // Similar patterns scored too close to call
+                Some(_) if split_type.ambiguous => {
+                    self.match_types.push(String::from("unknown"));
+                    self.match_names.push(String::from("unknown"));
+                    if self.sequence_type != "unknown" {
+                        self.sequence_type = "ambiguous".to_string();
+                    }
+                }
+                // Both ends matched but do not form a known pair, keep the pair in the ID
+                Some(_) if split_type.pattern_match == "index_hop" => {
+                    self.match_types.push(split_type.pattern_name.clone());
+                    self.match_names.push(split_type.pattern_name.clone());
+                    if self.sequence_type != "unknown" {
+                        self.sequence_type = "index_hop".to_string();
+                    }
+                }
                 Some(match_type) if match_type >= &String::from(split_type.pattern_match) => {
                     self.match_types.push(split_type.pattern_type.clone());
                     self.match_names.push(split_type.pattern_name.clone());
@@ -233,19 +1011,69 @@ impl ReadInfo {
         }
     }
     
+    /// Cut adapters found at either end and keep the read under its own name in one output
+    ///
+    /// Match names record the adapters found at the start and end, `-` for none.
+    fn update_adapter_trim(&mut self, min_length: usize, max_distance: usize) {
+        let found = |matcher: &Matcher| matcher.status && matcher.get_score() <= max_distance as i32;
+        let (mut cut_left, mut cut_right) = (0, self.sequence_length);
+        let (mut left_name, mut right_name) = ("-", "-");
+        if let Some(split_type) = self.split_types.first() {
+            if found(&split_type.left_matcher) {
+                cut_left = split_type.left_matcher.yend;
+                left_name = split_type.left_matcher.get_pattern();
+            }
+            if found(&split_type.right_matcher) {
+                cut_right = split_type.right_matcher.ystart;
+                right_name = split_type.right_matcher.get_pattern();
+            }
+        }
+        
+        let adapters = format!("{}_{}", left_name, right_name);
+        let adapter_type = if adapters == "-_-" { "untrimmed" } else { "trimmed" };
+        self.match_types = vec![adapter_type.to_string(), String::from("default"), String::from("default")];
+        self.match_names = vec![adapters, String::from("default"), String::from("default")];
+        self.output_filename = "trimmed".to_string();
+        
+        if self.read_length <= min_length || cut_left >= cut_right {
+            self.sequence_type = "filtered".to_string();
+            return;
+        }
+        self.should_write_to_fastq = true;
+        self.trim_positions = (cut_left, cut_right);
+    }
+    
     /// Update output filename
+    ///
+    /// `names` and `type` nest one directory per round, innermost round first;
+    /// `flat` joins the round types with `_` without the `default` padding,
+    /// `barcode-only` keeps the outermost round's type and `none` writes every
+    /// read to `all`.
     fn update_output_filename(&mut self, write_type: &str, id_separator: &str) {
-        if write_type == "type" {
-            let mut reversed_types = self.match_types.clone();
-            reversed_types.reverse();
-            self.output_filename = reversed_types.join("/");
-            self.record_id = self.match_types.join(id_separator);
-        } else {
+        if write_type == "names" {
             let mut reversed_names = self.match_names.clone();
             reversed_names.reverse();
             self.output_filename = reversed_names.join("/");
-            self.record_id = self.match_names.join(id_separator);
+            self.record_id = id_components(&self.match_names, id_separator);
+            return;
         }
+        
+        self.record_id = id_components(&self.match_types, id_separator);
+        self.output_filename = match write_type {
+            "flat" => {
+                let rounds = self.split_types.len().clamp(1, self.match_types.len());
+                let mut reversed_types = self.match_types[..rounds].to_vec();
+                reversed_types.reverse();
+                reversed_types.join("_")
+            }
+            "barcode-only" => self.match_types[0].clone(),
+            "none" => "all".to_string(),
+            _ => {
+                let mut reversed_types = self.match_types.clone();
+                reversed_types.reverse();
+                reversed_types.join("/")
+            }
+        };
     }
     
     /// Update sequence window
@@ -260,18 +1088,23 @@ impl ReadInfo {
         }
     }
     
+    /// Part of the read between the innermost matches of all rounds
+    pub fn inner_window(&self) -> (usize, usize) {
+        self.split_types.iter().fold(self.sequence_window, |(start, end), split_type| {
+            (
+                if split_type.left_matcher.status { start.max(split_type.left_matcher.yend) } else { start },
+                if split_type.right_matcher.status { end.min(split_type.right_matcher.ystart) } else { end },
+            )
+        })
+    }
+    
     /// Update sequence type
-    fn update_sequence_type(&mut self, min_length: usize, trim_mode: usize) {
-        if self.sequence_length <= min_length {
+    fn update_sequence_type(&mut self, min_length: usize, trim_cut: TrimCut) {
+        if self.read_length <= min_length {
             self.sequence_type = "filtered".to_string();
         }
         
-        let (cut_left, mut cut_right) = self.calculate_trim_positions(trim_mode);
-        
-        // Fix cut_right handling - if cut_right is 0, set it to sequence length
-        if cut_right == 0 {
-            cut_right = self.sequence_length;
-        }
+        let (cut_left, cut_right) = self.calculate_trim_positions(trim_cut);
         
         if cut_left > cut_right {
             self.sequence_type = "unknown".to_string();
@@ -279,34 +1112,48 @@ impl ReadInfo {
         }
     }
     
-    /// Calculate trim positions
-    fn calculate_trim_positions(&self, trim_mode: usize) -> (usize, usize) {
-        if trim_mode == 0 {
-            if let Some(first_split) = self.split_types.first() {
-                (
-                    first_split.left_matcher.yend,
-                    first_split.right_matcher.ystart,
-                )
-            } else {
-                (0, self.sequence_length)
-            }
-        } else if trim_mode <= self.split_types.len() {
-            let split = &self.split_types[trim_mode - 1];
-            (split.left_matcher.ystart, split.right_matcher.yend)
-        } else {
-            (0, self.sequence_length)
-        }
+    /// Calculate trim positions, moving cuts at matched patterns inward by the trim offsets
+    fn calculate_trim_positions(&self, trim_cut: TrimCut) -> (usize, usize) {
+        let (cut_left, cut_right) = self.pattern_trim_positions(trim_cut);
+        let trim_offsets = trim_cut.offsets;
+        
+        // Fix cut_right handling - if cut_right is 0, set it to sequence length
+        let cut_right = if cut_right == 0 { self.sequence_length } else { cut_right };
+        
+        let Some(split) = self.split_types.get(trim_cut.mode.saturating_sub(1)) else {
+            return (cut_left, cut_right);
+        };
+        let shift = |position: usize, offset: isize| position.saturating_add_signed(offset).min(self.sequence_length);
+        (
+            if split.left_matcher.status { shift(cut_left, trim_offsets.0) } else { cut_left },
+            if split.right_matcher.status { shift(cut_right, -trim_offsets.1) } else { cut_right },
+        )
+    }
+    
+    /// Cut positions at the pattern ends of the round given by the trim mode
+    ///
+    /// Mode 0 cuts at the first round, kth modes at round k; each side cuts
+    /// outside its pattern when the pattern is kept and inside it otherwise.
+    fn pattern_trim_positions(&self, trim_cut: TrimCut) -> (usize, usize) {
+        let Some(split) = self.split_types.get(trim_cut.mode.saturating_sub(1)) else {
+            return (0, self.sequence_length);
+        };
+        let (keep_left, keep_right) = trim_cut.kept_patterns;
+        (
+            if keep_left { split.left_matcher.ystart } else { split.left_matcher.yend },
+            if keep_right { split.right_matcher.yend } else { split.right_matcher.ystart },
+        )
     }
     
     /// Update write decision - memory optimized
-    fn update_write_decision(&mut self, trim_mode: usize, id_separator: &str) {
-        if self.sequence_type == "valid" {
+    fn update_write_decision(&mut self, trim_cut: TrimCut, id_separator: &str, annotate_score: bool) {
+        if self.sequence_type == "valid" || self.sequence_type == "index_hop" {
             self.should_write_to_fastq = true;
-            let (cut_left, cut_right) = self.calculate_trim_positions(trim_mode);
-            let final_cut_right = if cut_right == 0 { self.sequence_length } else { cut_right };
-            
+            if self.sequence_type == "index_hop" {
+                self.output_filename = "index_hop".to_string();
+            }
             // Store trim positions instead of creating full record
-            self.trim_positions = (cut_left, final_cut_right);
+            self.trim_positions = self.calculate_trim_positions(trim_cut);
             self.record_id = format!("{}{}{}{}{}", 
                 self.record_id, 
                 id_separator, 
@@ -314,37 +1161,154 @@ impl ReadInfo {
                 id_separator, 
                 self.record_id
             );
+            
+            // Per-round match scores let downstream tools drop marginal assignments
+            if annotate_score {
+                for split_type in &self.split_types {
+                    self.record_id.push_str(id_separator);
+                    self.record_id.push_str(&split_type.to_score_tag());
+                }
+            }
         }
     }
     
-    /// Get output record - only create when needed
-    pub fn get_output_record(&self) -> Option<Record> {
+    /// Remove a homopolymer tail from the end next to the 3' primer
+    ///
+    /// The base is given as seen on forward strand reads, so reverse strand
+    /// reads lose its complement from the start of the window instead.
+    fn apply_polyx_trim(&mut self, trim_polyx: Option<(u8, usize)>) {
+        let Some((base, min_length)) = trim_polyx else {
+            return;
+        };
         if !self.should_write_to_fastq {
-            return None;
+            return;
         }
-        
-        if let (Some(seq), Some(qual)) = (&self.sequence, &self.quality) {
+        if let Some(seq) = self.sequence() {
             let (cut_left, cut_right) = self.trim_positions;
-            let final_cut_right = if cut_right == 0 { self.sequence_length } else { cut_right };
-            
-            Some(Record::with_attrs(
-                &self.record_id,
-                None,
-                &seq[cut_left..final_cut_right],
-                &qual[cut_left..final_cut_right],
-            ))
+            let window = &seq[cut_left..cut_right];
+            if self.strand_orientation == "rs" {
+                let length = homopolymer_tail_length(window.iter(), complement_byte(base).unwrap_or(base));
+                if length >= min_length {
+                    self.polyx_trimmed_bases = length;
+                    self.trim_positions = (cut_left + length, cut_right);
+                }
+            } else {
+                let length = homopolymer_tail_length(window.iter().rev(), base);
+                if length >= min_length {
+                    self.polyx_trimmed_bases = length;
+                    self.trim_positions = (cut_left, cut_right - length);
+                }
+            }
+            if self.trim_positions.0 == self.trim_positions.1 {
+                self.should_write_to_fastq = false;
+                self.sequence_type = "filtered".to_string();
+                self.polyx_trimmed_bases = 0;
+            }
+        }
+    }
+    
+    /// Shrink the trimmed output window by cutting low-quality ends
+    fn apply_quality_trim(&mut self, threshold: u8) {
+        if threshold == 0 || !self.should_write_to_fastq {
+            return;
+        }
+        if let Some(qual) = self.quality() {
+            let (cut_left, cut_right) = self.trim_positions;
+            let (start, stop) = quality_trim_window(&qual[cut_left..cut_right], threshold);
+            // Nothing left to write once every base is below the threshold
+            if start == stop {
+                self.should_write_to_fastq = false;
+                self.sequence_type = "filtered".to_string();
+                return;
+            }
+            self.quality_trimmed_bases = (cut_right - cut_left) - (stop - start);
+            self.trim_positions = (cut_left + start, cut_left + stop);
+        }
+    }
+    
+    /// Trimmed sequence in output orientation, None when the read is not written
+    pub fn output_sequence(&self) -> Option<Cow<'_, [u8]>> {
+        let record = self.record.as_ref().filter(|_| self.should_write_to_fastq)?;
+        if self.index_read {
+            return self.mates.first().map(|mate| Cow::Borrowed(mate.seq()));
+        }
+        let (cut_left, cut_right) = self.trim_positions;
+        let final_cut_right = if cut_right == 0 { self.sequence_length } else { cut_right };
+        let trimmed_sequence = &record.seq()[cut_left..final_cut_right];
+        Some(if self.reverse_output {
+            Cow::Owned(reverse_complement_bytes(trimmed_sequence))
         } else {
-            None
+            Cow::Borrowed(trimmed_sequence)
+        })
+    }
+    
+    /// Write the trimmed output record as FASTQ, nothing when the read is not written
+    ///
+    /// The record is cut from the input on the fly; only reverse-complemented
+    /// output needs new buffers.
+    pub fn write_output_record<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let Some(record) = self.record.as_ref().filter(|_| self.should_write_to_fastq) else {
+            return Ok(());
+        };
+        if self.index_read {
+            return match self.mates.first() {
+                Some(mate) => write_fastq(writer, &self.record_id, mate.seq(), mate.qual()),
+                None => Ok(()),
+            };
+        }
+        let (cut_left, cut_right) = self.trim_positions;
+        let final_cut_right = if cut_right == 0 { self.sequence_length } else { cut_right };
+        let trimmed_sequence = &record.seq()[cut_left..final_cut_right];
+        let trimmed_quality = &record.qual()[cut_left..final_cut_right];
+        
+        if self.reverse_output {
+            let reversed_quality: Vec<u8> = trimmed_quality.iter().rev().copied().collect();
+            write_fastq(writer, &self.record_id, &reverse_complement_bytes(trimmed_sequence), &reversed_quality)
+        } else {
+            write_fastq(writer, &self.record_id, trimmed_sequence, trimmed_quality)
+        }
+    }
+    
+    /// Write the second read of the pair untrimmed, nothing without one or when the read is not written
+    ///
+    /// The ID is that of the output record, so interleaved pairs keep matching names.
+    pub fn write_mate_record<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let mate = self.mates.get(usize::from(self.index_read)).filter(|_| self.should_write_to_fastq);
+        match mate {
+            Some(mate) => write_fastq(writer, &self.record_id, mate.seq(), mate.qual()),
+            None => Ok(()),
+        }
+    }
+    
+    /// Write the untrimmed record with the original ID, nothing when the read is not written
+    pub fn write_raw_record<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        match self.record.as_ref().filter(|_| self.should_write_to_fastq) {
+            Some(record) => write_fastq(writer, &self.original_id, record.seq(), record.qual()),
+            None => Ok(()),
         }
     }
     
-    /// Convert to TSV format string
+    /// Column names of the TSV lines for the given number of pattern rounds
+    pub fn tsv_header(rounds: usize) -> String {
+        let mut header = String::from("read_id\tlength\tsequence_type\toutput\ttrim_start\ttrim_end\tfusion");
+        for round in 1..=rounds {
+            header.push_str(&format!("\tmatch{0}\tname{0}\ttype{0}\tstrand{0}", round));
+        }
+        header
+    }
+    
+    /// Convert to TSV format string, the output and fusion match being `-` for reads without one
     pub fn to_tsv(&self) -> String {
+        let (trim_start, trim_end) = self.trim_positions;
         let mut tsv_line = format!(
-            "{}\t{}\t{}", 
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}", 
             self.record_id, 
             self.sequence_length, 
-            self.sequence_type
+            self.sequence_type,
+            if self.should_write_to_fastq { &self.output_filename } else { "-" },
+            trim_start,
+            if trim_end == 0 { self.sequence_length } else { trim_end },
+            if self.fusion_match.is_empty() { "-" } else { &self.fusion_match }
         );
         
         for split_type in &self.split_types {
@@ -354,4 +1318,104 @@ impl ReadInfo {
         tsv_line
     }
     
-}
\ No newline at end of file
+    /// One-line JSON object with the fields of the TSV log, for --json-log
+    ///
+    /// Unlike TSV columns, keys stay valid when fields are added.
+    pub fn to_json(&self) -> String {
+        let (trim_start, trim_end) = self.trim_positions;
+        let optional = |value: &str| if value.is_empty() { "null".to_string() } else { json_string(value) };
+        let matches: Vec<String> = self.split_types.iter().map(SplitType::to_json).collect();
+        format!(
+            "{{\"read_id\": {}, \"original_id\": {}, \"length\": {}, \"sequence_type\": {}, \"strand\": {}, \"output\": {}, \"trim_start\": {}, \"trim_end\": {}, \"fusion\": {}, \"matches\": [{}]}}",
+            json_string(&self.record_id),
+            json_string(&self.original_id),
+            self.sequence_length,
+            json_string(&self.sequence_type),
+            json_string(&self.strand_orientation),
+            if self.should_write_to_fastq { json_string(&self.output_filename) } else { "null".to_string() },
+            trim_start,
+            if trim_end == 0 { self.sequence_length } else { trim_end },
+            optional(&self.fusion_match),
+            matches.join(", ")
+        )
+    }
+    
+}
+
+/// Value of a `key=value` field in a FASTQ header description, as written by MinKNOW, Guppy and Dorado
+pub fn header_field<'a>(description: &'a str, key: &str) -> Option<&'a str> {
+    description
+        .split_ascii_whitespace()
+        .find_map(|field| field.split_once('=').filter(|(name, _)| *name == key).map(|(_, value)| value))
+}
+
+/// Names joined by the separator, each made safe as an ID component
+fn id_components(names: &[String], id_separator: &str) -> String {
+    let components: Vec<String> = names.iter().map(|name| id_component(name, id_separator)).collect();
+    components.join(id_separator)
+}
+
+/// Write one FASTQ record
+fn write_fastq<W: Write>(writer: &mut W, id: &str, sequence: &[u8], quality: &[u8]) -> std::io::Result<()> {
+    writer.write_all(b"@")?;
+    writer.write_all(id.as_bytes())?;
+    writer.write_all(b"\n")?;
+    writer.write_all(sequence)?;
+    writer.write_all(b"\n+\n")?;
+    writer.write_all(quality)?;
+    writer.write_all(b"\n")
+}
+
+/// Path component of a template value, `-` for one that is empty or names a directory itself
+fn path_component(value: &str) -> String {
+    match value {
+        "" | "." | ".." => "-".to_string(),
+        _ => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Record of `length` bases
+    fn record(id: &str, length: usize) -> Record {
+        Record::with_attrs(id, None, &vec![b'A'; length], &vec![b'I'; length])
+    }
+    
+    #[test]
+    fn test_index_reads_are_filtered_and_counted_by_read1() {
+        // The -m default
+        let min_length = 100;
+        let trim_cut = TrimCut { mode: 0, offsets: (0, 0), kept_patterns: (false, false) };
+        let indexes = [record("read/1", 8), record("read/2", 8)];
+        
+        let mut read_info = ReadInfo::from_index_reads(vec![record("read/1", 150), record("read/2", 150)], &indexes);
+        assert_eq!(read_info.sequence_length, 16);
+        read_info.update_sequence_type(min_length, trim_cut);
+        assert_eq!(read_info.sequence_type, "valid");
+        assert_eq!(read_info.stats().sequence_length, 150);
+        
+        let mut short_read = ReadInfo::from_index_reads(vec![record("read/1", 60)], &indexes[..1]);
+        short_read.update_sequence_type(min_length, trim_cut);
+        assert_eq!(short_read.sequence_type, "filtered");
+        assert_eq!(short_read.stats().sequence_length, 60);
+    }
+    
+    #[test]
+    fn test_outdir_template_keeps_hostile_header_values_below_outdir() {
+        let description = "runid=../../x start_time=../2024-01-01 barcode=.. flow_cell_id=";
+        let mut read_info = ReadInfo::new(Record::with_attrs("read", Some(description), b"ACGT", b"IIII"));
+        read_info.should_write_to_fastq = true;
+        read_info.output_filename = "BC01/..".to_string();
+        read_info.apply_outdir_template("{run_id}/{date}/{barcode}/{flow_cell_id}/{output}", None, None);
+        assert_eq!(read_info.output_filename, ".._.._x/.._2024-01/-/-/BC01/-");
+        assert!(read_info.output_filename.split('/').all(|component| !["", ".", ".."].contains(&component)));
+        
+        let mut read_info = ReadInfo::new(Record::with_attrs("read", None, b"ACGT", b"IIII"));
+        read_info.should_write_to_fastq = true;
+        read_info.output_filename = "BC01".to_string();
+        read_info.apply_outdir_template("{run_id}/{date}/{output}", Some(".."), Some("2024/01/01"));
+        assert_eq!(read_info.output_filename, "-/2024_01_01/BC01");
+    }
+}
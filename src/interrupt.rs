@@ -0,0 +1,40 @@
+use log::warn;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set once SIGINT or SIGTERM has been received
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Exit status reported after an interrupted run
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Install SIGINT/SIGTERM handlers that request an orderly shutdown
+///
+/// The first signal stops the readers so queued reads drain, writers finish
+/// their gzip streams and statistics are written; a second one exits at once.
+pub fn install_handler() {
+    let handler: extern "C" fn(libc::c_int) = handle_signal;
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler as libc::sighandler_t;
+        // Restart interrupted reads so input parsing is not cut short mid-record
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        for signal in [libc::SIGINT, libc::SIGTERM] {
+            if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+                warn!("Failed to install handler for signal {}", signal);
+            }
+        }
+    }
+}
+
+/// Whether an interrupt has been requested
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+extern "C" fn handle_signal(_signal: libc::c_int) {
+    // Only async-signal-safe work is allowed here
+    if INTERRUPTED.swap(true, Ordering::Relaxed) {
+        unsafe { libc::_exit(INTERRUPTED_EXIT_CODE) };
+    }
+}
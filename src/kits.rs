@@ -0,0 +1,194 @@
+use crate::args::{Args, Commands};
+use crate::utils::ScratchDirectory;
+use log::info;
+use std::path::Path;
+
+/// Barcode kit embedded in the binary
+pub struct Kit {
+    /// Vendor name given to --kit
+    pub name: &'static str,
+    /// One-line summary for kit listings
+    pub description: &'static str,
+    /// Barcode database of name and sequence, tab-separated
+    pub database: &'static str,
+    /// Pattern file pairing the barcodes of the kit
+    pub pairs: &'static str,
+}
+
+/// Embedded kits; further kits are added as a TSV and pattern file under `kits/` and an entry here
+pub const KITS: &[Kit] = &[
+    Kit {
+        name: "ONT-BC01-10",
+        description: "Oxford Nanopore barcodes 01-10, the same barcode at both read ends",
+        database: include_str!("../kits/ont_bc01_10.tsv"),
+        pairs: include_str!("../kits/ont_bc01_10.list"),
+    },
+    Kit {
+        name: "SQK-NBD114-24",
+        description: "Oxford Nanopore Native Barcoding Kit 24 V14, NB01-24 at both read ends",
+        database: include_str!("../kits/sqk_nbd114_24.tsv"),
+        pairs: include_str!("../kits/sqk_nbd114_24.list"),
+    },
+    Kit {
+        name: "SQK-NBD114-96",
+        description: "Oxford Nanopore Native Barcoding Kit 96 V14, NB01-96 at both read ends",
+        database: include_str!("../kits/sqk_nbd114_96.tsv"),
+        pairs: include_str!("../kits/sqk_nbd114_96.list"),
+    },
+    Kit {
+        name: "SQK-PBK004",
+        description: "Oxford Nanopore PCR Barcoding Kit, BC01-12 at both read ends",
+        database: include_str!("../kits/sqk_pbk004.tsv"),
+        pairs: include_str!("../kits/sqk_pbk004.list"),
+    },
+    Kit {
+        name: "SQK-PCB114-24",
+        description: "Oxford Nanopore cDNA-PCR Barcoding Kit V14, BC01-24 at both read ends",
+        database: include_str!("../kits/sqk_pcb114_24.tsv"),
+        pairs: include_str!("../kits/sqk_pcb114_24.list"),
+    },
+    Kit {
+        name: "EXP-PBC096",
+        description: "Oxford Nanopore PCR Barcoding Expansion 1-96, BC01-96 at both read ends",
+        database: include_str!("../kits/exp_pbc096.tsv"),
+        pairs: include_str!("../kits/exp_pbc096.list"),
+    },
+    Kit {
+        name: "SQK-RBK004",
+        description: "Oxford Nanopore Rapid Barcoding Kit, RB01-12 at the read start only",
+        database: include_str!("../kits/sqk_rbk004.tsv"),
+        pairs: include_str!("../kits/sqk_rbk004.list"),
+    },
+    Kit {
+        name: "SQK-RBK114-24",
+        description: "Oxford Nanopore Rapid Barcoding Kit 24 V14, RB01-24 at the read start only",
+        database: include_str!("../kits/sqk_rbk114_24.tsv"),
+        pairs: include_str!("../kits/sqk_rbk114_24.list"),
+    },
+    Kit {
+        name: "SQK-RBK114-96",
+        description: "Oxford Nanopore Rapid Barcoding Kit 96 V14, RB01-96 at the read start only",
+        database: include_str!("../kits/sqk_rbk114_96.tsv"),
+        pairs: include_str!("../kits/sqk_rbk114_96.list"),
+    },
+    Kit {
+        name: "NEXTERA-XT-V2-SET-A",
+        description: "Illumina Nextera XT Index Kit v2 Set A, i7 N701-N715 by i5 S502-S511, for --index-reads",
+        database: include_str!("../kits/nextera_xt_v2_set_a.tsv"),
+        pairs: include_str!("../kits/nextera_xt_v2_set_a.list"),
+    },
+    Kit {
+        name: "NEXTERA-XT-V2-SET-B",
+        description: "Illumina Nextera XT Index Kit v2 Set B, i7 N701-N715 by i5 S513-S522, for --index-reads",
+        database: include_str!("../kits/nextera_xt_v2_set_b.tsv"),
+        pairs: include_str!("../kits/nextera_xt_v2_set_b.list"),
+    },
+];
+
+/// Embedded kit of a name, ignoring case and accepting `.` for `-` as in `SQK-NBD114.96`
+pub fn find_kit(name: &str) -> Option<&'static Kit> {
+    let name = name.replace('.', "-");
+    KITS.iter().find(|kit| kit.name.eq_ignore_ascii_case(&name))
+}
+
+impl Kit {
+    /// Barcode names and sequences in database order
+    pub fn barcodes(&self) -> impl Iterator<Item = (&'static str, &'static str)> {
+        self.database.lines().filter_map(|line| line.split_once('\t'))
+    }
+
+    /// Pattern pairs listed in the pattern file
+    pub fn pair_count(&self) -> usize {
+        self.pairs.lines().filter(|line| !line.starts_with('#') && !line.is_empty()).count()
+    }
+
+    /// Write `<kit>.tsv` and `<kit>.list` to a directory, returning their paths
+    fn write_files(&self, directory: &Path) -> (String, String) {
+        std::fs::create_dir_all(directory)
+            .unwrap_or_else(|_| panic!("Unable to create kit directory: {}", directory.display()));
+        let database_file = directory.join(format!("{}.tsv", self.name));
+        let pattern_file = directory.join(format!("{}.list", self.name));
+        std::fs::write(&database_file, self.database).expect("Failed to write kit database");
+        std::fs::write(&pattern_file, self.pairs).expect("Failed to write kit pattern file");
+        (database_file.display().to_string(), pattern_file.display().to_string())
+    }
+}
+
+/// Write the --kit database and pattern file to a scratch directory and point `args` at them
+///
+/// The directory is removed when the returned value is dropped, so keep it for the whole run.
+pub fn apply_kit(args: &mut Args) -> Option<ScratchDirectory> {
+    let kit = find_kit(args.kit.as_deref()?).expect("Kit was validated");
+    let scratch_directory = ScratchDirectory::new(args.tmpdir.as_deref());
+    let (database_file, pattern_file) = kit.write_files(scratch_directory.path());
+
+    info!("Using kit {}: {} barcodes in {} pairs", kit.name, kit.barcodes().count(), kit.pair_count());
+    args.pattern_db_file = Some(database_file);
+    if !args.trim_only {
+        args.pattern_files = Some(vec![pattern_file]);
+    }
+    Some(scratch_directory)
+}
+
+/// Print each embedded kit with its barcode and pair counts and description
+pub fn handle_list_kits_command() {
+    println!("kit\tbarcodes\tpairs\tdescription");
+    for kit in KITS {
+        println!("{}\t{}\t{}\t{}", kit.name, kit.barcodes().count(), kit.pair_count(), kit.description);
+    }
+}
+
+/// Print the barcodes of a kit, or export its database and pattern file with `--output`
+pub fn handle_describe_kit_command(command: &Commands) {
+    let Commands::DescribeKit { kit, output } = command else {
+        panic!("Not a describe-kit command");
+    };
+    let kit = find_kit(kit).expect("Kit was validated");
+    match output {
+        Some(output) => {
+            let (database_file, pattern_file) = kit.write_files(Path::new(output));
+            info!("Kit {} exported to {} and {}", kit.name, database_file, pattern_file);
+        }
+        None => {
+            for (name, sequence) in kit.barcodes() {
+                println!("{}\t{}", name, sequence);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::PatternDatabase;
+
+    #[test]
+    fn test_kits_load_with_expected_barcodes() {
+        let expected = [
+            ("ONT-BC01-10", 10, 10),
+            ("SQK-NBD114-24", 24, 24),
+            ("SQK-NBD114-96", 96, 96),
+            ("SQK-PBK004", 12, 12),
+            ("SQK-PCB114-24", 24, 24),
+            ("EXP-PBC096", 96, 96),
+            ("SQK-RBK004", 12, 12),
+            ("SQK-RBK114-24", 24, 24),
+            ("SQK-RBK114-96", 96, 96),
+            ("NEXTERA-XT-V2-SET-A", 20, 96),
+            ("NEXTERA-XT-V2-SET-B", 20, 96),
+        ];
+        assert_eq!(KITS.len(), expected.len());
+        let directory = std::env::temp_dir().join(format!("readchop_kits_{}", std::process::id()));
+        for (name, barcodes, pairs) in expected {
+            let kit = find_kit(name).unwrap_or_else(|| panic!("Kit {} is not embedded", name));
+            let (database_file, pattern_file) = kit.write_files(&directory);
+            let mut pattern_database = PatternDatabase::new();
+            pattern_database.load_patterns(&database_file, "auto", &pattern_file, &[], true, true);
+            assert_eq!(kit.barcodes().count(), barcodes, "{}", name);
+            assert_eq!(pattern_database.pattern_pairs.len(), pairs, "{}", name);
+            assert_eq!(kit.pair_count(), pairs, "{}", name);
+        }
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(find_kit("sqk-nbd114.96").map(|kit| kit.name), Some("SQK-NBD114-96"));
+    }
+}
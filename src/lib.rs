@@ -0,0 +1,35 @@
+//! ReadChop splits long-read FASTQ files by barcode and adapter patterns
+//!
+//! The command-line tool is built on these modules; they are also used by the
+//! Python bindings in `readchop-py`.
+
+pub mod args;
+pub mod pattern;
+pub mod utils;
+pub mod counter;
+pub mod fastq;
+pub mod myers;
+pub mod splitter;
+pub mod writer;
+pub mod view;
+pub mod thread_pool;
+pub mod preview;
+pub mod control;
+pub mod decompress;
+pub mod dedup;
+pub mod remote;
+pub mod interrupt;
+pub mod logging;
+pub mod perf;
+pub mod report;
+pub mod stats;
+pub mod simulate;
+pub mod selftest;
+pub mod kits;
+pub mod autotune;
+pub mod score_cache;
+pub mod verify;
+pub mod checksum;
+pub mod metrics;
+pub mod parquet;
+pub mod tui;
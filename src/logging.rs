@@ -0,0 +1,83 @@
+use crate::utils::{format_utc_timestamp, json_string};
+use log::LevelFilter;
+use pretty_env_logger::env_logger::Target;
+use std::fs::File;
+use std::io::{self, Write};
+
+/// Logging settings from the command line
+pub struct LoggingOptions {
+    /// Number of `-v` flags, each raising the level by one step
+    pub verbose: u8,
+    /// Number of `-q` flags, each lowering the level by one step
+    pub quiet: u8,
+    /// File receiving a copy of every log line
+    pub log_file: Option<String>,
+    /// Write one JSON object per log line instead of text
+    pub json: bool,
+}
+
+/// Log level from the `-v`/`-q` counts, starting at info
+fn level_filter(verbose: u8, quiet: u8) -> LevelFilter {
+    const LEVELS: [LevelFilter; 6] = [
+        LevelFilter::Off,
+        LevelFilter::Error,
+        LevelFilter::Warn,
+        LevelFilter::Info,
+        LevelFilter::Debug,
+        LevelFilter::Trace,
+    ];
+    let level = (3 + verbose as isize - quiet as isize).clamp(0, LEVELS.len() as isize - 1);
+    LEVELS[level as usize]
+}
+
+/// Log output copied to standard error and a log file
+struct LogFileTee {
+    file: File,
+}
+
+impl Write for LogFileTee {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        self.file.write_all(buffer)?;
+        io::stderr().write_all(buffer)?;
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        io::stderr().flush()
+    }
+}
+
+/// Install the logger
+///
+/// Without `-v` or `-q`, `RUST_LOG` filters are honored on top of the info
+/// default. Log files receive plain text, so colors are only used when
+/// logging to the terminal alone.
+pub fn initialize_logging(options: &LoggingOptions) {
+    let mut builder = pretty_env_logger::formatted_builder();
+    builder.filter_level(level_filter(options.verbose, options.quiet));
+    if options.verbose == 0 && options.quiet == 0
+        && let Ok(filters) = std::env::var("RUST_LOG")
+    {
+        builder.parse_filters(&filters);
+    }
+
+    if options.json {
+        builder.format(|formatter, record| {
+            writeln!(
+                formatter,
+                "{{\"timestamp\": {}, \"level\": {}, \"target\": {}, \"message\": {}}}",
+                json_string(&format_utc_timestamp(std::time::SystemTime::now())),
+                json_string(record.level().as_str()),
+                json_string(record.target()),
+                json_string(&record.args().to_string())
+            )
+        });
+    }
+
+    if let Some(log_file) = &options.log_file {
+        let file = File::create(log_file).unwrap_or_else(|_| panic!("Unable to create log file: {}", log_file));
+        builder.target(Target::Pipe(Box::new(LogFileTee { file })));
+    }
+    builder.init();
+}
@@ -1,25 +1,20 @@
-mod args;
-mod pattern;
-mod utils;
-mod counter;
-mod fastq;
-mod myers;
-mod splitter;
-mod writer;
-mod view;
-mod thread_pool;
-
+use readchop::{args, dedup, logging, pattern, utils, counter, fastq, splitter, writer, view, thread_pool, preview, control, interrupt, perf, report, stats, simulate, selftest, kits, autotune, score_cache, verify, metrics};
 use clap::Parser;
 use log::info;
-use utils::ProcessInfo;
+use utils::{OutputDirectory, ProcessInfo, ScratchDirectory};
 use thread_pool::{ThreadMonitor, ThreadAllocationStrategy};
 
 fn main() {
-    // Initialize logging system
-    initialize_logging();
-    
     // Parse command line arguments
-    let args = args::Args::parse();
+    let mut args = args::Args::parse();
+    
+    // Initialize logging system
+    logging::initialize_logging(&logging::LoggingOptions {
+        verbose: args.verbose,
+        quiet: args.quiet,
+        log_file: args.log_file.clone(),
+        json: args.log_format == "json",
+    });
     info!("Starting ReadChop with command line arguments: {:?}", std::env::args().collect::<Vec<String>>());
     
     // Handle subcommands
@@ -28,111 +23,345 @@ fn main() {
         return;
     }
     
+    // Directories and globs become the FASTQ files they contain, watched inputs are listed as they grow
+    if !args.watch {
+        args.inputs = fastq::expand_input_paths(&args.inputs);
+    }
+    
+    // -t 0 sizes the pool to the machine
+    args.threads = utils::resolve_thread_count(args.threads);
+    
+    // Embedded kits are written out as a database and pattern file for the run
+    let _kit_directory = kits::apply_kit(&mut args);
+    
+    // Error rates chosen on a calibration pass replace -e for the main run
+    if args.auto_tune > 0 {
+        args.pattern_error_rate = autotune::tune_error_rates(&args);
+    }
+    
+    // Ctrl-C finishes the reads already queued instead of leaving broken outputs
+    interrupt::install_handler();
+    
     // Execute main sequence processing workflow
     execute_main_processing(&args);
-}
-
-/// Initialize logging system
-fn initialize_logging() {
-    unsafe {
-    // Check if RUST_LOG is already set in environment
-    if std::env::var("RUST_LOG").is_err() {
-        // Only set to "info" if RUST_LOG is not already set
-        std::env::set_var("RUST_LOG", "info");
+    
+    if interrupt::is_interrupted() {
+        std::process::exit(interrupt::INTERRUPTED_EXIT_CODE);
+    }
+    
+    if fastq::reading_failed() {
+        std::process::exit(1);
+    }
+    
+    // Outputs and statistics are kept, but a run that lost reads must not look successful
+    if splitter::split_failures() > 0 {
+        std::process::exit(1);
     }
-}
-    pretty_env_logger::init();
 }
 
 /// Handle subcommands
 fn handle_subcommand(command: &args::Commands) {
     match command {
+        #[cfg(feature = "encryption")]
         args::Commands::Encrypt { file } => {
-            pattern::encrypt_pattern_database(&file, "666666");
+            pattern::encrypt_pattern_database(file, "666666");
         }
         args::Commands::View { .. } => {
             view::handle_view_command(command);
         }
+        args::Commands::Stats { .. } => {
+            stats::handle_stats_command(command);
+        }
+        args::Commands::MergeStats { .. } => {
+            stats::handle_merge_stats_command(command);
+        }
+        args::Commands::Simulate { .. } => {
+            simulate::handle_simulate_command(command);
+        }
+        args::Commands::Selftest { .. } => {
+            let self_test = selftest::SelfTest::prepare(command);
+            execute_main_processing(&self_test.arguments());
+            self_test.verify();
+        }
+        args::Commands::Verify { .. } => {
+            if !verify::handle_verify_command(command) {
+                std::process::exit(1);
+            }
+        }
+        args::Commands::ListKits => {
+            kits::handle_list_kits_command();
+        }
+        args::Commands::DescribeKit { .. } => {
+            kits::handle_describe_kit_command(command);
+        }
     }
 }
 
 /// Execute main sequence processing workflow - memory optimized
 fn execute_main_processing(args: &args::Args) {
     let start_time = std::time::Instant::now();
+    let started_at = std::time::SystemTime::now();
+    
+    // Searches of repeated read ends are reused across reads
+    score_cache::set_capacity(args.score_cache);
+    if args.perf_report.is_some() {
+        perf::enable();
+    }
+    
+    // Outputs are staged next to the output directory and moved into place once complete
+    let output_directory = OutputDirectory::new(&args.outdir, args.force, !args.watch);
+    let outdir = output_directory.path();
     
     // Load pattern database
     let search_patterns = pattern::load_patterns(args);
     info!("Pattern database loaded successfully");
     
-    // Create thread monitor with balanced allocation strategy
-    let thread_strategy = ThreadAllocationStrategy::Balanced { 
-        processing_ratio: 0.8  // 80% for processing, 20% for writing
-    };
+    // Optionally check the projected distribution before committing to a full run
+    if args.preview > 0 && !preview::run_preview(args, &search_patterns) {
+        info!("Full run cancelled after preview");
+        return;
+    }
+    
+    // Create thread monitor with the requested allocation strategy
+    let thread_strategy = ThreadAllocationStrategy::from_name(&args.thread_strategy);
     let mut thread_monitor = ThreadMonitor::new(args.threads, thread_strategy);
     
     // Print thread allocation information
     thread_monitor.print_thread_stats();
     
+    // Optional control socket for external orchestration
+    let control = args.control_socket.as_deref().map(control::ControlSocket::start);
+    let metrics_server = args.metrics_port.map(metrics::MetricsServer::start);
+    let mut last_metrics_update: Option<std::time::Instant> = None;
+    
+    // Reads from parallel file readers interleave in arrival order
+    if args.ordered && args.reader_threads > 1 {
+        log::warn!("--ordered reads input files one at a time, ignoring --reader-threads {}", args.reader_threads);
+    }
+    
     // Create FASTQ reader
-    let read_receiver = fastq::create_reader(args.inputs.clone());
-    
-    // Create sequence splitter with controlled thread count
-    let split_receiver = splitter::create_splitter_receiver_controlled(
-        read_receiver, 
-        &search_patterns, 
-        thread_monitor.get_processing_threads(),
-        thread_monitor.get_thread_pool()
-    );
+    let read_selection = fastq::ReadSelection::new(args.head, args.sample_fraction, args.seed);
+    let reader_options = fastq::ReaderOptions {
+        low_latency: args.low_latency,
+        decompress_threads: args.decompress_threads,
+        reader_threads: if args.ordered { 1 } else { args.reader_threads },
+        watch: args.watch.then(|| fastq::WatchOptions {
+            interval: std::time::Duration::from_secs(args.watch_interval),
+            sentinel: args.watch_sentinel.clone(),
+        }),
+        index_reads: args.index_reads.clone(),
+        interleaved: args.interleaved,
+    };
+    let read_receiver = fastq::create_reader(args.inputs.clone(), reader_options, control.clone(), read_selection);
+    // Kept to sample the splitting backlog for channel statistics and adaptive rebalancing
+    let pending_reads = read_receiver.clone();
+    
+    // Create sequence splitter running on the thread pool
+    let split_receiver = if args.ordered {
+        splitter::create_splitter_receiver_ordered(read_receiver, &search_patterns, thread_monitor.get_thread_pool())
+    } else {
+        splitter::create_splitter_receiver_controlled(read_receiver, &search_patterns, thread_monitor.get_thread_pool())
+    };
+    
+    // Temporary spill files live here until the run finishes
+    let scratch_directory = ScratchDirectory::new(args.tmpdir.as_deref());
     
     // Initialize statistics and write manager with controlled thread count
-    let mut statistics_manager = counter::StatisticsManager::new(args.outdir.clone());
+    let mut statistics_manager = counter::StatisticsManager::new(outdir.to_string());
+    statistics_manager.set_group_by(args.group_by.clone());
+    statistics_manager.set_time_bin(args.time_bin);
+    statistics_manager.set_balance_check(args.balance_fold, &search_patterns.sample_weights);
+    if let Some(truth) = &args.truth {
+        statistics_manager.load_truth_labels(truth, search_patterns.pattern_arguments.len());
+    }
+    let paired_output = !args.interleaved && !args.index_reads.is_empty() && args.inputs.len() > 1;
+    if paired_output && (args.stdout || args.pipe.is_some() || args.output_url.is_some()) {
+        panic!("R2 can only go to a single stream with --interleaved");
+    }
     let mut file_writer_manager = writer::FileWriterManager::new_controlled(
-        args.outdir.clone(),
+        outdir.to_string(),
         thread_monitor.get_writing_threads(),
-        thread_monitor.get_thread_pool()
+        thread_monitor.get_thread_pool(),
+        writer::WriterOptions {
+            emit_raw_copy: args.emit_raw_copy,
+            low_latency: args.low_latency,
+            max_open_files: args.max_open_files,
+            min_reads_per_barcode: args.min_reads_per_barcode,
+            stdout: args.stdout,
+            pipe: args.pipe.clone(),
+            output_url: args.output_url.clone(),
+            shard_read_log: args.shard_read_log,
+            read_log_rounds: search_patterns.pattern_arguments.len(),
+            read_log: !args.no_read_log,
+            log_compression_level: args.log_compression_level,
+            rotate_read_log: args.rotate_read_log,
+            paired_output,
+            interleaved: args.interleaved,
+            external_compressor: args.external_compressor.clone(),
+            checksums: args.checksums.clone(),
+            json_log: args.json_log,
+            parquet_read_log: args.read_log_format == "parquet",
+        },
+        scratch_directory.path(),
     );
+    let mut deduplicator = args.dedup.as_deref()
+        .map(|mode| dedup::Deduplicator::new(mode, args.dedup_length, &args.dedup_action, &args.id_separator));
     let mut progress_tracker = ProcessInfo::new(args.log_interval);
+    let mut channel_monitor = perf::ChannelMonitor::new();
+    let mut last_progress_event = std::time::Instant::now();
     
     // Process each sequence - memory optimized
     let mut processed_count = 0;
-    for read_info in split_receiver {
-        // Create lightweight stats copy for statistics
-        let read_stats = read_info.create_stats_copy();
+    let mut last_statistics_flush = std::time::Instant::now();
+    let mut interrupt_reported = false;
+    loop {
+        // Wake up regularly so progress and statistics keep flowing while input is idle
+        let read_info = match split_receiver.recv_timeout(std::time::Duration::from_secs(1)) {
+            Ok(read_info) => Some(read_info),
+            Err(flume::RecvTimeoutError::Timeout) => None,
+            Err(flume::RecvTimeoutError::Disconnected) => break,
+        };
         
-        // Log record
-        file_writer_manager.logger.push(read_info.to_tsv());
+        if let Some(mut read_info) = read_info {
+            if let Some(deduplicator) = &mut deduplicator {
+                deduplicator.process(&mut read_info);
+            }
+            
+            // Counted fields only, the read itself moves on to the writer
+            let read_stats = read_info.stats();
+            
+            // Log record
+            if !args.no_read_log {
+                file_writer_manager.logger.push(read_info.to_tsv());
+            }
+            if let Some(json_logger) = &mut file_writer_manager.json_logger {
+                json_logger.push(read_info.to_json());
+            }
+            
+            // Records from all samples share one stream, so keep the read name in the ID
+            if args.tag_location == "description" {
+                read_info.move_tags_to_description();
+            } else if args.stdout {
+                read_info.prefix_original_id(&args.id_separator);
+            }
+            
+            // Update statistics
+            statistics_manager.process_read_stats(&read_stats);
+            statistics_manager.evaluate_truth(&read_info.original_id, &read_stats);
+            
+            // Write file with controlled thread management
+            file_writer_manager.write_controlled(read_info)
+                .expect("Failed to write sequence information");
+            
+            // Update progress
+            progress_tracker.info(&channel_monitor);
+            processed_count += 1;
+            
+            // Periodic memory cleanup - unified frequency for better performance
+            if !args.low_latency && processed_count % 500000 == 0 {
+                file_writer_manager.cleanup_memory();
+                statistics_manager.cleanup_memory();
+            }
+        }
         
-        // Update statistics using lightweight structure
-        statistics_manager.process_read_stats(&read_stats);
+        if channel_monitor.is_due() {
+            channel_monitor.sample([pending_reads.len(), split_receiver.len(), file_writer_manager.pending_writes()]);
+        }
         
-        // Write file with controlled thread management
-        file_writer_manager.write_controlled(read_info, thread_monitor.get_thread_pool())
-            .expect("Failed to write sequence information");
+        if thread_monitor.should_rebalance() {
+            thread_monitor.rebalance(pending_reads.len(), split_receiver.len() + file_writer_manager.pending_writes());
+        }
         
-        // Update progress
-        progress_tracker.info();
+        if !interrupt_reported && interrupt::is_interrupted() {
+            log::warn!("Interrupted, finishing queued reads and writing partial results (press Ctrl-C again to abort)");
+            interrupt_reported = true;
+        }
         
-        // Periodic memory cleanup - unified frequency for better performance
-        processed_count += 1;
-        if processed_count % 500000 == 0 {
-            file_writer_manager.cleanup_memory();
-            statistics_manager.cleanup_memory();
+        let mut flush_statistics = false;
+        if let Some(control) = &control {
+            if last_progress_event.elapsed() >= std::time::Duration::from_secs(1) {
+                control.emit_progress(processed_count, start_time.elapsed());
+                last_progress_event = std::time::Instant::now();
+            }
+            flush_statistics = control.take_flush_stats_request();
+        }
+        if let Some(metrics_server) = &metrics_server
+            && last_metrics_update.is_none_or(|last_update| last_update.elapsed() >= std::time::Duration::from_secs(1))
+        {
+            statistics_manager.set_write_failures(file_writer_manager.failed_reads());
+            statistics_manager.set_split_failures(splitter::split_failures());
+            metrics_server.update(statistics_manager.prometheus_metrics(), processed_count);
+            last_metrics_update = Some(std::time::Instant::now());
+        }
+        // Live runs keep the statistics files current between sequencer batches
+        if args.watch && last_statistics_flush.elapsed() >= std::time::Duration::from_secs(args.watch_interval) {
+            flush_statistics = true;
+        }
+        if flush_statistics {
+            std::fs::create_dir_all(outdir).expect("Failed to create output directory");
+            statistics_manager.set_write_failures(file_writer_manager.failed_reads());
+            statistics_manager.set_split_failures(splitter::split_failures());
+            statistics_manager.write_total_statistics();
+            statistics_manager.write_valid_statistics();
+            statistics_manager.write_score_statistics();
+            statistics_manager.write_fusion_statistics();
+            statistics_manager.write_strand_statistics();
+            statistics_manager.write_grouped_statistics();
+            statistics_manager.write_time_statistics();
+            statistics_manager.write_truth_statistics();
+            last_statistics_flush = std::time::Instant::now();
         }
     }
     
+    // Outputs of a partly read input are discarded with the staging directory
+    if fastq::reading_failed() {
+        log::error!("Reading the input failed, see the error above, outputs are not kept");
+        return;
+    }
+    
+    if !search_patterns.expected_barcodes.is_empty() {
+        statistics_manager.warn_missing_expected_barcodes(&search_patterns.expected_barcodes, &search_patterns.sample_sheet);
+    }
+    
+    if thread_strategy.is_adaptive() {
+        thread_monitor.print_thread_stats();
+    }
+    
+    file_writer_manager.write_low_count_reads()
+        .expect("Failed to write low count reads");
+    
     // Complete processing
     finalize_processing(
         &mut file_writer_manager,
-        &statistics_manager,
+        &mut statistics_manager,
         start_time,
-        &args.outdir
+        outdir
     );
+    
+    if let Some(deduplicator) = &deduplicator {
+        deduplicator.write_statistics(outdir);
+    }
+    
+    // Writing tasks have finished, so their time is complete
+    if let Some(perf_report) = &args.perf_report {
+        perf::write_perf_report(perf_report, &channel_monitor);
+    }
+    
+    // Watched inputs are only known once the run is over
+    let mut inputs = if args.watch { fastq::expand_input_paths(&args.inputs) } else { args.inputs.clone() };
+    inputs.extend(args.index_reads.iter().cloned());
+    report::write_run_info(args, outdir, &search_patterns, &inputs, started_at);
+    output_directory.commit();
+    
+    if let Some(control) = &control {
+        control.finish(processed_count, start_time.elapsed());
+    }
 }
 
 /// Complete processing and output results
 fn finalize_processing(
     file_writer_manager: &mut writer::FileWriterManager,
-    statistics_manager: &counter::StatisticsManager,
+    statistics_manager: &mut counter::StatisticsManager,
     start_time: std::time::Instant,
     output_dir: &str,
 ) {
@@ -140,18 +369,31 @@ fn finalize_processing(
     file_writer_manager.write_log_file(output_dir)
         .expect("Failed to write log file");
     
+    let processing_time = start_time.elapsed();
+    info!("Sequence splitting completed! Processing time: {:.4?}", processing_time);
+    
+    // Wait for all writing tasks so reads that failed to be written are counted
+    let write_result = file_writer_manager.finalize();
+    statistics_manager.set_write_failures(file_writer_manager.failed_reads());
+    statistics_manager.set_split_failures(splitter::split_failures());
+    
     // Write statistics
     statistics_manager.write_total_statistics();
     statistics_manager.write_valid_statistics();
+    statistics_manager.write_score_statistics();
+    statistics_manager.write_fusion_statistics();
+    statistics_manager.write_strand_statistics();
+    statistics_manager.write_grouped_statistics();
+    statistics_manager.write_time_statistics();
+    statistics_manager.write_truth_statistics();
     
     // Output statistics
     statistics_manager.print_statistics();
+    statistics_manager.write_balance_warnings();
     
-    let processing_time = start_time.elapsed();
-    info!("Sequence splitting completed! Processing time: {:.4?}", processing_time);
-    
-    // Wait for all write threads to complete
-    file_writer_manager.finalize();
+    write_result.expect("Failed to write output files");
+    file_writer_manager.write_checksums()
+        .expect("Failed to write checksums.txt");
     
     let total_time = start_time.elapsed();
     info!("All processing completed! Total time: {:.4?}", total_time);
@@ -0,0 +1,119 @@
+use log::{info, warn};
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Time a scrape may take to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Latest rendered metrics and the counts throughput is measured from
+struct MetricsState {
+    body: String,
+    /// Time and processed reads of the previous update
+    previous: Option<(Instant, usize)>,
+    throughput: f64,
+}
+
+/// HTTP endpoint serving run counters in the Prometheus text format
+///
+/// The main loop renders the counters once a second, so a scrape never waits
+/// on the pipeline and always sees one consistent snapshot.
+pub struct MetricsServer {
+    state: Mutex<MetricsState>,
+    started: Instant,
+}
+
+impl MetricsServer {
+    /// Bind the port on all interfaces and answer scrapes in the background
+    pub fn start(port: u16) -> Arc<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", port))
+            .unwrap_or_else(|error| panic!("Unable to bind metrics port {}: {}", port, error));
+        info!("Serving Prometheus metrics on http://0.0.0.0:{}/metrics", port);
+
+        let server = Arc::new(Self {
+            state: Mutex::new(MetricsState { body: String::new(), previous: None, throughput: 0.0 }),
+            started: Instant::now(),
+        });
+        let accept_server = Arc::clone(&server);
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let server = Arc::clone(&accept_server);
+                std::thread::spawn(move || {
+                    if let Err(error) = server.answer(stream) {
+                        warn!("Failed to answer metrics request: {}", error);
+                    }
+                });
+            }
+        });
+        server
+    }
+
+    /// Answer one HTTP request, serving the metrics on `/metrics` and `/`
+    fn answer(&self, stream: TcpStream) -> std::io::Result<()> {
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        // Headers are read so the client is not reset before it sees the response
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 0 && header.trim_end() != "" {
+            header.clear();
+        }
+
+        let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+        let (status, body) = match path.split('?').next() {
+            Some("/metrics" | "/") => ("200 OK", self.state.lock().unwrap().body.clone()),
+            _ => ("404 Not Found", "Metrics are served on /metrics\n".to_string()),
+        };
+        let mut stream = stream;
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )?;
+        stream.flush()
+    }
+
+    /// Replace the served metrics with `statistics`, adding throughput and uptime
+    pub fn update(&self, statistics: String, processed_reads: usize) {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        if let Some((previous_time, previous_reads)) = state.previous {
+            let seconds = now.duration_since(previous_time).as_secs_f64();
+            if seconds > 0.0 {
+                state.throughput = processed_reads.saturating_sub(previous_reads) as f64 / seconds;
+            }
+        }
+        state.previous = Some((now, processed_reads));
+
+        let mut body = statistics;
+        write_metric(&mut body, "readchop_throughput_reads_per_second", "gauge", "Reads processed per second since the previous update", &[(None, state.throughput)]);
+        write_metric(&mut body, "readchop_uptime_seconds", "gauge", "Seconds since the run started", &[(None, self.started.elapsed().as_secs_f64())]);
+        state.body = body;
+    }
+}
+
+/// Quote a label value, escaping backslashes, quotes and newlines
+fn label_value(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+/// Append one metric family, each sample optionally carrying one `name="value"` label
+pub fn write_metric(body: &mut String, name: &str, kind: &str, help: &str, samples: &[(Option<(&str, &str)>, f64)]) {
+    let _ = writeln!(body, "# HELP {} {}", name, help);
+    let _ = writeln!(body, "# TYPE {} {}", name, kind);
+    for (label, value) in samples {
+        match label {
+            Some((label_name, label)) => {
+                let _ = writeln!(body, "{}{{{}={}}} {}", name, label_name, label_value(label), value);
+            }
+            None => {
+                let _ = writeln!(body, "{} {}", name, value);
+            }
+        }
+    }
+}
@@ -1,61 +1,140 @@
 use bio::alignment::Alignment;
-use bio::pattern_matching::myers::MyersBuilder;
+use bio::pattern_matching::myers::{Myers, MyersBuilder};
+use memchr::memmem;
+
+/// Per-pattern override of the allowed edit distance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DistanceLimit {
+    /// Error rate applied to the pattern length
+    ErrorRate(f32),
+    /// Fixed maximum edit distance
+    MaxEdits(u8),
+}
+
+/// Pattern positions left out of the length an error rate is applied to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum EffectiveLength {
+    /// Leading and trailing N
+    #[default]
+    Flanking,
+    /// Every position other than A, C, G and T
+    Ambiguous,
+    /// No position, the full pattern length
+    Full,
+}
+
+impl EffectiveLength {
+    /// Policy of an --effective-length value, `flanking`, `all` or `none`
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "flanking" => EffectiveLength::Flanking,
+            "all" => EffectiveLength::Ambiguous,
+            "none" => EffectiveLength::Full,
+            _ => panic!("Unknown effective length policy: {}", name),
+        }
+    }
+    
+    /// --effective-length value of the policy
+    pub fn name(self) -> &'static str {
+        match self {
+            EffectiveLength::Flanking => "flanking",
+            EffectiveLength::Ambiguous => "all",
+            EffectiveLength::Full => "none",
+        }
+    }
+    
+    /// Length of the pattern positions counted under this policy
+    pub fn pattern_length(self, pattern: &[u8]) -> usize {
+        match self {
+            EffectiveLength::Flanking => {
+                let start = pattern.iter().position(|&base| base != b'N').unwrap_or(pattern.len());
+                let end = pattern.iter().rposition(|&base| base != b'N').map_or(start, |end| end + 1);
+                end - start
+            }
+            EffectiveLength::Ambiguous => pattern.iter().filter(|base| matches!(base, b'A' | b'C' | b'G' | b'T')).count(),
+            EffectiveLength::Full => pattern.len(),
+        }
+    }
+}
 
 /// Search pattern structure
+///
+/// The read is borrowed and the search window is a range over it, and the
+/// pattern buffer is reused, so searching allocates nothing per pattern.
 #[derive(Debug, Clone)]
-pub struct SearchPattern {
+pub struct SearchPattern<'a> {
     /// Raw text
-    pub raw_text: Vec<u8>,
-    /// Search text
-    pub text: Vec<u8>,
+    pub raw_text: &'a [u8],
     /// Raw text length
     pub raw_text_len: usize,
     /// Search pattern
     pub pattern: Vec<u8>,
     /// Distance ratio
     pub dist_ratio: f32,
+    /// Absolute cap on the error rate budget, None for no cap
+    pub max_edits: Option<u8>,
+    /// Pattern positions the error rate budget is not computed over
+    pub effective_length: EffectiveLength,
     /// Maximum distance
     pub max_dist: u8,
     /// Start position
     pub start: usize,
     /// End position
     pub end: usize,
+    /// Stop searching once a match scores at or below this distance, None to score every pattern
+    pub early_exit_score: Option<usize>,
 }
 
-impl SearchPattern {
-    /// Create a new search pattern
-    pub fn new(raw_text: Vec<u8>, distance_ratio: f32) -> Self {
+impl<'a> SearchPattern<'a> {
+    /// Create a new search pattern over a read
+    pub fn new(raw_text: &'a [u8], distance_ratio: f32) -> Self {
         Self {
-            raw_text: raw_text.clone(),
-            text: Vec::new(),
+            raw_text,
             raw_text_len: raw_text.len(),
             pattern: Vec::new(),
             dist_ratio: distance_ratio,
+            max_edits: None,
+            effective_length: EffectiveLength::default(),
             max_dist: 0,
             start: 0,
             end: 0,
+            early_exit_score: Some(0),
         }
     }
     
-    /// Update search parameters
-    pub fn update(&mut self, start_position: usize, end_position: usize, pattern: Vec<u8>) {
-        // Calculate pattern length after trimming N
-        let trimmed_pattern_length = String::from_utf8(pattern.clone())
-            .unwrap()
-            .trim_matches('N')
-            .len() as f32;
+    /// Calculate maximum distance allowed for a pattern, honoring its own limit if any
+    ///
+    /// Budgets from an error rate are capped at `max_edits` when it is stricter.
+    pub fn distance_budget(&self, pattern: &[u8], distance_limit: Option<DistanceLimit>) -> u8 {
+        let distance_ratio = match distance_limit {
+            Some(DistanceLimit::MaxEdits(max_edits)) => return max_edits,
+            Some(DistanceLimit::ErrorRate(error_rate)) => error_rate,
+            None => self.dist_ratio,
+        };
         
-        // Calculate maximum distance
-        self.max_dist = (trimmed_pattern_length * self.dist_ratio).floor() as u8;
+        let effective_pattern_length = self.effective_length.pattern_length(pattern) as f32;
+        let budget = (effective_pattern_length * distance_ratio).floor() as u8;
+        self.max_edits.map_or(budget, |max_edits| budget.min(max_edits))
+    }
+    
+    /// Update search parameters
+    pub fn update(
+        &mut self,
+        start_position: usize,
+        end_position: usize,
+        pattern: &[u8],
+        distance_limit: Option<DistanceLimit>,
+    ) {
+        self.max_dist = self.distance_budget(pattern, distance_limit);
         self.start = start_position;
         self.end = end_position;
-        self.text = self.raw_text[self.start..self.end].to_vec();
-        self.pattern = pattern;
+        self.pattern.clear();
+        self.pattern.extend_from_slice(pattern);
     }
     
     /// Get search text
-    pub fn get_search_text(&self) -> &[u8] {
-        &self.text
+    pub fn get_search_text(&self) -> &'a [u8] {
+        &self.raw_text[self.start..self.end]
     }
     
     
@@ -71,13 +150,33 @@ impl SearchPattern {
     
 }
 
-/// Perform best match search using Myers algorithm
-pub fn myers_best(search_pattern: &SearchPattern) -> Option<(i32, usize, usize)> {
-    // Create Myers builder for fuzzy matching
-    let mut myers = MyersBuilder::new()
-        .ambig(b'N', b"ACGT")
-        .build_64(search_pattern.pattern.clone());
+/// Find a verbatim occurrence of the pattern in the search window
+///
+/// Returns the leftmost exact hit with score 0, which is what `myers_best`
+/// would report for it. Patterns with ambiguity bases are left to Myers.
+pub fn exact_best(search_pattern: &SearchPattern) -> Option<(i32, usize, usize)> {
+    if search_pattern.pattern.is_empty() || search_pattern.pattern.contains(&b'N') {
+        return None;
+    }
     
+    memmem::find(search_pattern.get_search_text(), &search_pattern.pattern).map(|position| {
+        let start = position + search_pattern.get_start_position();
+        (0, start, start + search_pattern.pattern.len())
+    })
+}
+
+/// Build the Myers automaton of a pattern, pattern N matching any base
+///
+/// Patterns are compiled once when loaded; the automaton only keeps scratch
+/// state between searches, so one instance serves every read.
+pub fn compile_pattern(pattern: &[u8]) -> Myers<u64> {
+    MyersBuilder::new()
+        .ambig(b'N', b"ACGT")
+        .build_64(pattern)
+}
+
+/// Perform best match search using Myers algorithm with the automaton of the current pattern
+pub fn myers_best(search_pattern: &SearchPattern, myers: &mut Myers<u64>) -> Option<(i32, usize, usize)> {
     let mut alignment = Alignment::default();
     let mut matches = myers.find_all_lazy(search_pattern.get_search_text(), search_pattern.get_max_distance());
     
@@ -96,19 +195,312 @@ pub fn myers_best(search_pattern: &SearchPattern) -> Option<(i32, usize, usize)>
 }
 
 
+/// Mismatches between a pattern and equally long text, pattern N matching any base
+pub fn hamming_distance(pattern: &[u8], text: &[u8]) -> usize {
+    pattern
+        .iter()
+        .zip(text)
+        .filter(|&(&pattern_base, &text_base)| pattern_base != text_base && pattern_base != b'N')
+        .count()
+}
 
+/// Number of symbol classes used by the packed search: A, C, G, T, N, other
+const SYMBOL_CLASSES: usize = 6;
+
+/// Map a base to its symbol class
+fn symbol_class(base: u8) -> usize {
+    match base {
+        b'A' => 0,
+        b'C' => 1,
+        b'G' => 2,
+        b'T' => 3,
+        b'N' => 4,
+        _ => 5,
+    }
+}
+
+/// Several patterns packed side by side into one 128-bit word
+#[derive(Debug, Clone)]
+struct PackedWord {
+    /// Match masks per symbol class
+    peq: [u128; SYMBOL_CLASSES],
+    /// Bits occupied by patterns
+    used_bits: u128,
+    /// First row bit of every packed pattern
+    first_rows: u128,
+    /// Last row bit of every packed pattern
+    last_rows: u128,
+    /// (pattern index, last row bit, pattern length) per packed pattern
+    lanes: Vec<(usize, u32, usize)>,
+}
+
+impl PackedWord {
+    fn new() -> Self {
+        Self {
+            peq: [0; SYMBOL_CLASSES],
+            used_bits: 0,
+            first_rows: 0,
+            last_rows: 0,
+            lanes: Vec::new(),
+        }
+    }
+    
+    /// Add pattern at the given bit offset
+    fn push(&mut self, pattern_index: usize, pattern: &[u8], offset: u32) {
+        for (row, &base) in pattern.iter().enumerate() {
+            let bit = 1u128 << (offset as usize + row);
+            match symbol_class(base) {
+                // Pattern N matches any base, like MyersBuilder::ambig(b'N', b"ACGT")
+                4 => (0..5).for_each(|class| self.peq[class] |= bit),
+                class => self.peq[class] |= bit,
+            }
+            self.used_bits |= bit;
+        }
+        let last_row = offset + pattern.len() as u32 - 1;
+        self.first_rows |= 1u128 << offset;
+        self.last_rows |= 1u128 << last_row;
+        self.lanes.push((pattern_index, last_row, pattern.len()));
+    }
+    
+    /// Scan text and lower the best distance seen for each packed pattern
+    fn scan(&self, text: &[u8], best_distances: &mut [Option<usize>]) {
+        if text.is_empty() {
+            return;
+        }
+        
+        let mut pv = self.used_bits;
+        let mut mv = 0u128;
+        let last_row_bits: Vec<u128> = self.lanes.iter().map(|lane| 1u128 << lane.1).collect();
+        let mut scores: Vec<i32> = self.lanes.iter().map(|lane| lane.2 as i32).collect();
+        let mut lane_best = scores.clone();
+        
+        for &base in text {
+            let eq = self.peq[symbol_class(base)];
+            let xv = eq | mv;
+            // Segmented addition keeps carries from leaking into the next pattern
+            let xh = ((segmented_add(eq & pv, pv, self.last_rows) ^ pv) | eq) & self.used_bits;
+            let ph = (mv | !(xh | pv)) & self.used_bits;
+            let mh = pv & xh;
+            
+            // Branch-free score update keeps the inner loop predictable
+            for ((score, best), &last_row_bit) in scores.iter_mut().zip(lane_best.iter_mut()).zip(&last_row_bits) {
+                *score += (ph & last_row_bit != 0) as i32 - (mh & last_row_bit != 0) as i32;
+                *best = (*best).min(*score);
+            }
+            
+            // Search mode: the top row never carries a horizontal delta
+            let ph = (ph << 1) & !self.first_rows;
+            let mh = (mh << 1) & !self.first_rows;
+            pv = (mh | !(xv | ph)) & self.used_bits;
+            mv = ph & xv;
+        }
+        
+        for (lane, (pattern_index, _, _)) in self.lanes.iter().enumerate() {
+            let best = &mut best_distances[*pattern_index];
+            let lane_best = lane_best[lane] as usize;
+            *best = Some(best.map_or(lane_best, |distance| distance.min(lane_best)));
+        }
+    }
+}
+
+/// Add two words lane by lane, dropping carries out of each lane's top bit
+fn segmented_add(a: u128, b: u128, high_bits: u128) -> u128 {
+    ((a & !high_bits).wrapping_add(b & !high_bits)) ^ ((a ^ b) & high_bits)
+}
+
+/// Bit-parallel Myers search over a whole pattern set at once
+///
+/// Patterns are packed into shared 128-bit words so a panel of short barcodes
+/// costs a handful of passes over the window instead of one alignment each.
+/// Only edit distances are computed; coordinates come from `myers_best` on
+/// the winning pattern.
+#[derive(Debug, Clone)]
+pub struct MultiPatternMyers {
+    words: Vec<PackedWord>,
+    pattern_count: usize,
+}
+
+impl MultiPatternMyers {
+    /// Pack patterns, returning None if any pattern cannot be packed
+    ///
+    /// Pattern length is capped at 64 to match `myers_best`.
+    pub fn new(patterns: &[&[u8]]) -> Option<Self> {
+        let mut words = Vec::new();
+        let mut current_word = PackedWord::new();
+        let mut offset = 0u32;
+        
+        for (pattern_index, pattern) in patterns.iter().enumerate() {
+            if pattern.is_empty() || pattern.len() > 64 || pattern.iter().any(|&base| symbol_class(base) == 5) {
+                return None;
+            }
+            if offset as usize + pattern.len() > 128 {
+                words.push(std::mem::replace(&mut current_word, PackedWord::new()));
+                offset = 0;
+            }
+            current_word.push(pattern_index, pattern, offset);
+            offset += pattern.len() as u32;
+        }
+        if !current_word.lanes.is_empty() {
+            words.push(current_word);
+        }
+        
+        Some(Self {
+            words,
+            pattern_count: patterns.len(),
+        })
+    }
+    
+    /// Best edit distance of each pattern anywhere in the text, in input order
+    ///
+    /// Stops after the first word where `is_final(pattern index, distance)`
+    /// holds; patterns in later words stay None.
+    pub fn best_distances_until<F>(&self, text: &[u8], is_final: F) -> Vec<Option<usize>>
+    where
+        F: Fn(usize, usize) -> bool,
+    {
+        let mut best_distances = vec![None; self.pattern_count];
+        for word in &self.words {
+            word.scan(text, &mut best_distances);
+            let finished = word.lanes.iter().any(|(pattern_index, _, _)| {
+                best_distances[*pattern_index].is_some_and(|distance| is_final(*pattern_index, distance))
+            });
+            if finished {
+                break;
+            }
+        }
+        best_distances
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
     
+    #[test]
+    fn test_hamming_distance() {
+        assert_eq!(hamming_distance(b"ACGTACGT", b"ACGTACGT"), 0);
+        assert_eq!(hamming_distance(b"ACGTACGT", b"ACCTACGA"), 2);
+        assert_eq!(hamming_distance(b"ACNTACGT", b"ACGTACGT"), 0);
+    }
+    
     #[test]
     fn test_search_pattern_creation() {
-        let raw_text = b"ATCGATCG".to_vec();
+        let raw_text = b"ATCGATCG";
         let search_pattern = SearchPattern::new(raw_text, 0.1);
         
         assert_eq!(search_pattern.raw_text_len, 8);
         assert_eq!(search_pattern.dist_ratio, 0.1);
     }
     
-}
\ No newline at end of file
+    #[test]
+    fn test_distance_budget_overrides() {
+        let search_pattern = SearchPattern::new(b"ACGT", 0.2);
+        let pattern = b"NNACGTACGTACNN";
+        
+        assert_eq!(search_pattern.distance_budget(pattern, None), 2);
+        assert_eq!(search_pattern.distance_budget(pattern, Some(DistanceLimit::ErrorRate(0.1))), 1);
+        assert_eq!(search_pattern.distance_budget(pattern, Some(DistanceLimit::MaxEdits(4))), 4);
+        
+        // The edit cap only tightens rate budgets, explicit per-pattern edit limits stay as given
+        let mut capped = search_pattern.clone();
+        capped.max_edits = Some(1);
+        assert_eq!(capped.distance_budget(pattern, None), 1);
+        assert_eq!(capped.distance_budget(pattern, Some(DistanceLimit::MaxEdits(4))), 4);
+        capped.max_edits = Some(5);
+        assert_eq!(capped.distance_budget(pattern, None), 2);
+    }
+    
+    #[test]
+    fn test_effective_length_policies() {
+        let pattern = b"NNACGNNRTACNN";
+        assert_eq!(EffectiveLength::Flanking.pattern_length(pattern), 9);
+        assert_eq!(EffectiveLength::Ambiguous.pattern_length(pattern), 6);
+        assert_eq!(EffectiveLength::Full.pattern_length(pattern), 13);
+        assert_eq!(EffectiveLength::Flanking.pattern_length(b"NNN"), 0);
+    }
+    
+    #[test]
+    fn test_exact_best_matches_myers() {
+        let raw_text = b"TTTTACGTACGGATTTT".to_vec();
+        let mut search_pattern = SearchPattern::new(&raw_text, 0.2);
+        search_pattern.update(2, 17, b"ACGTACGGA", None);
+        
+        assert_eq!(exact_best(&search_pattern), Some((0, 4, 13)));
+        assert_eq!(exact_best(&search_pattern), myers_best(&search_pattern, &mut compile_pattern(b"ACGTACGGA")));
+        
+        search_pattern.update(2, 17, b"ACGNACGGA", None);
+        assert_eq!(exact_best(&search_pattern), None);
+    }
+    
+    #[test]
+    fn test_multi_pattern_distances_match_myers() {
+        let raw_text = b"GGTACGTTAGGCATTACGGATCCANTGGACCTTAGGCATGCAATCGA".to_vec();
+        let patterns: Vec<&[u8]> = vec![
+            b"ACGTTAGG", b"TTACGGATCC", b"GACCTAAGG", b"CATGNAAT",
+            b"AAAAAAAAAA", b"GGCATTACGGATCCATTGGACCTTAGGCATGCAATCGATCGATCGATCGAT",
+            b"CCTTAGGCATGCAATCGATTTTTTTTTTTTTTTTTTTTTT",
+        ];
+        let multi = MultiPatternMyers::new(&patterns).unwrap();
+        let distances = multi.best_distances_until(&raw_text, |_, _| false);
+        
+        for (pattern, distance) in patterns.iter().zip(distances) {
+            let mut search_pattern = SearchPattern::new(&raw_text, 1.0);
+            search_pattern.update(0, raw_text.len(), pattern, None);
+            search_pattern.max_dist = pattern.len() as u8;
+            let expected = myers_best(&search_pattern, &mut compile_pattern(pattern)).map(|result| result.0 as usize);
+            assert_eq!(distance, expected, "pattern {}", String::from_utf8_lossy(pattern));
+        }
+    }
+    
+    /// Compare setting up borrowed search windows with the former per-pattern copies on 10 kb reads,
+    /// run with `cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_search_windows() {
+        let mut random = crate::utils::SplitMix64::new(7);
+        let mut random_bases = |length: usize| -> Vec<u8> {
+            (0..length).map(|_| b"ACGT"[(random.next_u64() % 4) as usize]).collect()
+        };
+        let reads: Vec<Vec<u8>> = (0..200).map(|_| random_bases(10_000)).collect();
+        let patterns: Vec<Vec<u8>> = (0..24).map(|_| random_bases(24)).collect();
+        // Both read ends and the whole read, as searched for patterns, fusions and concatemers
+        let windows = [(0, 200), (9_800, 10_000), (0, 10_000)];
+        let rounds = 20;
+        // Only the window setup is timed, the search itself is the same either way
+        let time = |prepare: &dyn Fn(&[u8]) -> usize| {
+            let start = std::time::Instant::now();
+            let bases: usize = (0..rounds).flat_map(|_| &reads).map(|read| std::hint::black_box(prepare(read))).sum();
+            (start.elapsed(), bases)
+        };
+        let copied = time(&|read| {
+            // The read copied into the search, then window and pattern copied for every pattern
+            let raw_text = read.to_vec();
+            let mut bases = 0;
+            for &(start, end) in &windows {
+                for pattern in &patterns {
+                    let text = std::hint::black_box(raw_text[start..end].to_vec());
+                    let pattern = std::hint::black_box(pattern.clone());
+                    bases += text.len() + pattern.len();
+                }
+            }
+            bases
+        });
+        let borrowed = time(&|read| {
+            let mut search_pattern = SearchPattern::new(read, 0.1);
+            let mut bases = 0;
+            for &(start, end) in &windows {
+                for pattern in &patterns {
+                    search_pattern.update(start, end, pattern, None);
+                    let text = std::hint::black_box(search_pattern.get_search_text());
+                    bases += text.len() + search_pattern.pattern.len();
+                }
+            }
+            bases
+        });
+        assert_eq!(copied.1, borrowed.1);
+        println!(
+            "{} reads of 10 kb x {} patterns x {} windows x {}: copied windows {:?}, borrowed windows {:?}",
+            reads.len(), patterns.len(), windows.len(), rounds, copied.0, borrowed.0
+        );
+    }
+}
@@ -0,0 +1,591 @@
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::{Result, Write};
+
+/// Magic bytes opening and closing a Parquet file
+const PARQUET_MAGIC: &[u8; 4] = b"PAR1";
+
+/// Rows buffered per column before they are written out as one row group
+const ROW_GROUP_ROWS: usize = 100_000;
+
+/// Parquet physical types
+const INT32: i32 = 1;
+const INT64: i32 = 2;
+const BYTE_ARRAY: i32 = 6;
+
+/// Parquet repetition types
+const REQUIRED: i32 = 0;
+const OPTIONAL: i32 = 1;
+
+/// Converted type marking byte arrays as UTF-8 strings
+const UTF8: i32 = 0;
+
+/// Parquet encodings, values are PLAIN and definition levels RLE
+const PLAIN: i32 = 0;
+const RLE: i32 = 3;
+
+/// GZIP compression codec
+const GZIP: i32 = 2;
+
+/// Data page, version 1
+const DATA_PAGE: i32 = 0;
+
+/// Thrift compact protocol field types
+const THRIFT_I32: u8 = 5;
+const THRIFT_I64: u8 = 6;
+const THRIFT_BINARY: u8 = 8;
+const THRIFT_LIST: u8 = 9;
+const THRIFT_STRUCT: u8 = 12;
+
+/// Encoder of the Thrift compact protocol used by the Parquet metadata
+#[derive(Default)]
+struct CompactWriter {
+    bytes: Vec<u8>,
+    /// Last field ID of the current struct, fields being written as deltas
+    last_field: i16,
+    /// Last field IDs of the enclosing structs
+    enclosing_fields: Vec<i16>,
+}
+
+impl CompactWriter {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.bytes.push((value as u8 & 0x7f) | 0x80);
+            value >>= 7;
+        }
+        self.bytes.push(value as u8);
+    }
+
+    fn zigzag(&mut self, value: i64) {
+        self.varint(((value << 1) ^ (value >> 63)) as u64);
+    }
+
+    fn field(&mut self, id: i16, kind: u8) {
+        let delta = id - self.last_field;
+        if (1..=15).contains(&delta) {
+            self.bytes.push((delta as u8) << 4 | kind);
+        } else {
+            self.bytes.push(kind);
+            self.zigzag(id as i64);
+        }
+        self.last_field = id;
+    }
+
+    fn i32_field(&mut self, id: i16, value: i32) {
+        self.field(id, THRIFT_I32);
+        self.zigzag(value as i64);
+    }
+
+    fn i64_field(&mut self, id: i16, value: i64) {
+        self.field(id, THRIFT_I64);
+        self.zigzag(value);
+    }
+
+    fn binary(&mut self, value: &[u8]) {
+        self.varint(value.len() as u64);
+        self.bytes.extend_from_slice(value);
+    }
+
+    fn binary_field(&mut self, id: i16, value: &[u8]) {
+        self.field(id, THRIFT_BINARY);
+        self.binary(value);
+    }
+
+    fn list_field(&mut self, id: i16, element_kind: u8, size: usize) {
+        self.field(id, THRIFT_LIST);
+        if size < 15 {
+            self.bytes.push((size as u8) << 4 | element_kind);
+        } else {
+            self.bytes.push(0xf0 | element_kind);
+            self.varint(size as u64);
+        }
+    }
+
+    /// Start a struct, written as a field when `id` is given and as a list element otherwise
+    fn struct_begin(&mut self, id: Option<i16>) {
+        if let Some(id) = id {
+            self.field(id, THRIFT_STRUCT);
+        }
+        self.enclosing_fields.push(self.last_field);
+        self.last_field = 0;
+    }
+
+    fn struct_end(&mut self) {
+        self.bytes.push(0);
+        self.last_field = self.enclosing_fields.pop().unwrap_or(0);
+    }
+}
+
+/// Column chunk written for one row group
+struct ChunkInfo {
+    offset: u64,
+    values: usize,
+    uncompressed_size: usize,
+    compressed_size: usize,
+}
+
+/// One typed column and the values of the current row group
+struct Column {
+    name: String,
+    physical_type: i32,
+    optional: bool,
+    /// PLAIN-encoded values of the rows that have one
+    values: Vec<u8>,
+    /// Whether each row has a value, for optional columns
+    defined: Vec<bool>,
+    chunks: Vec<ChunkInfo>,
+}
+
+impl Column {
+    fn new(name: &str, physical_type: i32, optional: bool) -> Self {
+        Self { name: name.to_string(), physical_type, optional, values: Vec::new(), defined: Vec::new(), chunks: Vec::new() }
+    }
+
+    fn push_int(&mut self, value: Option<i64>) {
+        if let Some(value) = value {
+            match self.physical_type {
+                INT32 => self.values.extend_from_slice(&(value as i32).to_le_bytes()),
+                _ => self.values.extend_from_slice(&value.to_le_bytes()),
+            }
+        }
+        self.push_defined(value.is_some());
+    }
+
+    fn push_str(&mut self, value: Option<&str>) {
+        if let Some(value) = value {
+            self.values.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            self.values.extend_from_slice(value.as_bytes());
+        }
+        self.push_defined(value.is_some());
+    }
+
+    fn push_defined(&mut self, defined: bool) {
+        if !defined && !self.optional {
+            panic!("Missing value in required read log column {}", self.name);
+        }
+        self.defined.push(defined);
+    }
+
+    /// Write the buffered rows as one GZIP-compressed data page
+    fn write_chunk<W: Write>(&mut self, output: &mut PositionWriter<W>) -> Result<()> {
+        let rows = self.defined.len();
+        let mut page = Vec::new();
+        if self.optional {
+            let levels = definition_levels(&self.defined);
+            page.extend_from_slice(&(levels.len() as u32).to_le_bytes());
+            page.extend_from_slice(&levels);
+        }
+        page.append(&mut self.values);
+        self.defined.clear();
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&page)?;
+        let compressed = encoder.finish()?;
+
+        let mut header = CompactWriter::default();
+        header.i32_field(1, DATA_PAGE);
+        header.i32_field(2, page.len() as i32);
+        header.i32_field(3, compressed.len() as i32);
+        header.struct_begin(Some(5));
+        header.i32_field(1, rows as i32);
+        header.i32_field(2, PLAIN);
+        header.i32_field(3, RLE);
+        header.i32_field(4, RLE);
+        header.struct_end();
+        header.struct_end();
+
+        let offset = output.position;
+        output.write_all(&header.bytes)?;
+        output.write_all(&compressed)?;
+        self.chunks.push(ChunkInfo {
+            offset,
+            values: rows,
+            uncompressed_size: header.bytes.len() + page.len(),
+            compressed_size: header.bytes.len() + compressed.len(),
+        });
+        Ok(())
+    }
+}
+
+/// RLE-encoded definition levels of bit width 1, one run per stretch of equal levels
+fn definition_levels(defined: &[bool]) -> Vec<u8> {
+    let mut levels = CompactWriter::default();
+    let mut start = 0;
+    while start < defined.len() {
+        let run = defined[start..].iter().take_while(|&&value| value == defined[start]).count();
+        levels.varint((run as u64) << 1);
+        levels.bytes.push(defined[start] as u8);
+        start += run;
+    }
+    levels.bytes
+}
+
+/// Writer tracking the file offset the column chunks start at
+struct PositionWriter<W: Write> {
+    inner: W,
+    position: u64,
+}
+
+impl<W: Write> Write for PositionWriter<W> {
+    fn write(&mut self, buffer: &[u8]) -> Result<usize> {
+        let written = self.inner.write(buffer)?;
+        self.position += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Read log as a Parquet file with typed columns
+///
+/// The TSV log lines are split into typed columns: counts and coordinates
+/// become integers, and the matches of each round are split into the pattern,
+/// edit distance and coordinates of both ends, null where an end did not
+/// match. Rows are written in GZIP-compressed row groups of `ROW_GROUP_ROWS`,
+/// so memory stays bounded for logs of any size.
+pub struct ParquetReadLog<W: Write> {
+    output: PositionWriter<W>,
+    rounds: usize,
+    columns: Vec<Column>,
+    rows: usize,
+    /// Row count of each row group written
+    row_groups: Vec<usize>,
+}
+
+impl<W: Write> ParquetReadLog<W> {
+    /// Start a Parquet read log with the match columns of `rounds` pattern rounds
+    pub fn new(output: W, rounds: usize) -> Result<Self> {
+        let mut columns = vec![
+            Column::new("read_id", BYTE_ARRAY, false),
+            Column::new("length", INT64, false),
+            Column::new("sequence_type", BYTE_ARRAY, false),
+            Column::new("output", BYTE_ARRAY, true),
+            Column::new("trim_start", INT64, false),
+            Column::new("trim_end", INT64, false),
+            Column::new("fusion", BYTE_ARRAY, true),
+        ];
+        for round in 1..=rounds {
+            for name in ["match", "name", "type", "strand"] {
+                columns.push(Column::new(&format!("{}{}", name, round), BYTE_ARRAY, true));
+            }
+            for side in ["left", "right"] {
+                columns.push(Column::new(&format!("{}_pattern{}", side, round), BYTE_ARRAY, true));
+                columns.push(Column::new(&format!("{}_score{}", side, round), INT32, true));
+                columns.push(Column::new(&format!("{}_start{}", side, round), INT64, true));
+                columns.push(Column::new(&format!("{}_end{}", side, round), INT64, true));
+            }
+        }
+        let mut output = PositionWriter { inner: output, position: 0 };
+        output.write_all(PARQUET_MAGIC)?;
+        Ok(Self { output, rounds, columns, rows: 0, row_groups: Vec::new() })
+    }
+
+    /// Add one reads_log TSV line as a row
+    pub fn push_line(&mut self, line: &str) -> Result<()> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let text = |index: usize| fields.get(index).copied().filter(|value| *value != "-");
+        let number = |index: usize| text(index).and_then(|value| value.parse::<i64>().ok());
+
+        let mut columns = self.columns.iter_mut();
+        let mut next = || columns.next().expect("Row has more values than the schema");
+        next().push_str(Some(fields[0]));
+        next().push_int(number(1));
+        next().push_str(text(2));
+        next().push_str(text(3));
+        next().push_int(number(4));
+        next().push_int(number(5));
+        next().push_str(text(6));
+        for round in 0..self.rounds {
+            let base = 7 + round * 4;
+            let strand_field = fields.get(base + 3).copied().unwrap_or("");
+            let (strand, ends) = strand_field.split_once(':').unwrap_or((strand_field, ""));
+            for index in base..base + 3 {
+                next().push_str(fields.get(index).copied());
+            }
+            next().push_str(fields.get(base + 3).map(|_| strand));
+            let mut ends = ends.split(';');
+            for _side in 0..2 {
+                let end: Vec<&str> = ends
+                    .next()
+                    .unwrap_or("")
+                    .trim_matches(|character| character == '(' || character == ')')
+                    .split(',')
+                    .collect();
+                // Unmatched ends are logged with an empty pattern name
+                let matched = end.len() == 4 && !end[0].is_empty();
+                let end_value = |index: usize| if matched { end[index].parse::<i64>().ok() } else { None };
+                next().push_str(matched.then(|| end[0]));
+                next().push_int(end_value(1));
+                next().push_int(end_value(2));
+                next().push_int(end_value(3));
+            }
+        }
+
+        self.rows += 1;
+        if self.rows == ROW_GROUP_ROWS {
+            self.write_row_group()?;
+        }
+        Ok(())
+    }
+
+    fn write_row_group(&mut self) -> Result<()> {
+        if self.rows == 0 {
+            return Ok(());
+        }
+        for column in &mut self.columns {
+            column.write_chunk(&mut self.output)?;
+        }
+        self.row_groups.push(self.rows);
+        self.rows = 0;
+        Ok(())
+    }
+
+    /// Write the last row group and the file footer, returning the inner writer
+    pub fn finish(mut self) -> Result<W> {
+        self.write_row_group()?;
+        let metadata = self.file_metadata();
+        self.output.write_all(&metadata)?;
+        self.output.write_all(&(metadata.len() as u32).to_le_bytes())?;
+        self.output.write_all(PARQUET_MAGIC)?;
+        self.output.flush()?;
+        Ok(self.output.inner)
+    }
+
+    /// Thrift-encoded FileMetaData with the schema and the location of every column chunk
+    fn file_metadata(&self) -> Vec<u8> {
+        let mut metadata = CompactWriter::default();
+        metadata.i32_field(1, 1);
+
+        metadata.list_field(2, THRIFT_STRUCT, self.columns.len() + 1);
+        metadata.struct_begin(None);
+        metadata.binary_field(4, b"schema");
+        metadata.i32_field(5, self.columns.len() as i32);
+        metadata.struct_end();
+        for column in &self.columns {
+            metadata.struct_begin(None);
+            metadata.i32_field(1, column.physical_type);
+            metadata.i32_field(3, if column.optional { OPTIONAL } else { REQUIRED });
+            metadata.binary_field(4, column.name.as_bytes());
+            if column.physical_type == BYTE_ARRAY {
+                metadata.i32_field(6, UTF8);
+            }
+            metadata.struct_end();
+        }
+
+        metadata.i64_field(3, self.row_groups.iter().sum::<usize>() as i64);
+        metadata.list_field(4, THRIFT_STRUCT, self.row_groups.len());
+        for (group, rows) in self.row_groups.iter().enumerate() {
+            metadata.struct_begin(None);
+            metadata.list_field(1, THRIFT_STRUCT, self.columns.len());
+            for column in &self.columns {
+                let chunk = &column.chunks[group];
+                metadata.struct_begin(None);
+                metadata.i64_field(2, chunk.offset as i64);
+                metadata.struct_begin(Some(3));
+                metadata.i32_field(1, column.physical_type);
+                metadata.list_field(2, THRIFT_I32, 2);
+                metadata.zigzag(PLAIN as i64);
+                metadata.zigzag(RLE as i64);
+                metadata.list_field(3, THRIFT_BINARY, 1);
+                metadata.binary(column.name.as_bytes());
+                metadata.i32_field(4, GZIP);
+                metadata.i64_field(5, chunk.values as i64);
+                metadata.i64_field(6, chunk.uncompressed_size as i64);
+                metadata.i64_field(7, chunk.compressed_size as i64);
+                metadata.i64_field(9, chunk.offset as i64);
+                metadata.struct_end();
+                metadata.struct_end();
+            }
+            let group_size: usize = self.columns.iter().map(|column| column.chunks[group].uncompressed_size).sum();
+            metadata.i64_field(2, group_size as i64);
+            metadata.i64_field(3, *rows as i64);
+            metadata.struct_end();
+        }
+        metadata.binary_field(6, format!("readchop version {}", env!("CARGO_PKG_VERSION")).as_bytes());
+        metadata.struct_end();
+        metadata.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    /// Decoded Thrift compact protocol value, enough to walk the metadata written above
+    #[derive(Debug)]
+    enum Thrift {
+        Int(i64),
+        Binary(Vec<u8>),
+        List(Vec<Thrift>),
+        Struct(Vec<(i16, Thrift)>),
+    }
+
+    impl Thrift {
+        fn field(&self, id: i16) -> &Thrift {
+            match self {
+                Thrift::Struct(fields) => &fields.iter().find(|(field, _)| *field == id).expect("Missing field").1,
+                _ => panic!("Not a struct"),
+            }
+        }
+
+        fn int(&self) -> i64 {
+            match self {
+                Thrift::Int(value) => *value,
+                _ => panic!("Not an integer"),
+            }
+        }
+
+        fn binary(&self) -> &[u8] {
+            match self {
+                Thrift::Binary(value) => value,
+                _ => panic!("Not a binary"),
+            }
+        }
+
+        fn list(&self) -> &[Thrift] {
+            match self {
+                Thrift::List(values) => values,
+                _ => panic!("Not a list"),
+            }
+        }
+    }
+
+    /// Decoder of the Thrift compact protocol, the counterpart of `CompactWriter`
+    struct CompactReader<'a> {
+        bytes: &'a [u8],
+        position: usize,
+    }
+
+    impl CompactReader<'_> {
+        fn byte(&mut self) -> u8 {
+            self.position += 1;
+            self.bytes[self.position - 1]
+        }
+
+        fn varint(&mut self) -> u64 {
+            let mut value = 0;
+            let mut shift = 0;
+            loop {
+                let byte = self.byte();
+                value |= ((byte & 0x7f) as u64) << shift;
+                if byte < 0x80 {
+                    return value;
+                }
+                shift += 7;
+            }
+        }
+
+        fn zigzag(&mut self) -> i64 {
+            let value = self.varint();
+            (value >> 1) as i64 ^ -((value & 1) as i64)
+        }
+
+        fn value(&mut self, kind: u8) -> Thrift {
+            match kind {
+                THRIFT_I32 | THRIFT_I64 => Thrift::Int(self.zigzag()),
+                THRIFT_BINARY => {
+                    let length = self.varint() as usize;
+                    self.position += length;
+                    Thrift::Binary(self.bytes[self.position - length..self.position].to_vec())
+                }
+                THRIFT_LIST => {
+                    let header = self.byte();
+                    let size = if header >> 4 == 15 { self.varint() as usize } else { (header >> 4) as usize };
+                    Thrift::List((0..size).map(|_| self.value(header & 0x0f)).collect())
+                }
+                THRIFT_STRUCT => {
+                    let mut fields = Vec::new();
+                    let mut last_field = 0;
+                    loop {
+                        let header = self.byte();
+                        if header == 0 {
+                            return Thrift::Struct(fields);
+                        }
+                        last_field = match header >> 4 {
+                            0 => self.zigzag() as i16,
+                            delta => last_field + delta as i16,
+                        };
+                        fields.push((last_field, self.value(header & 0x0f)));
+                    }
+                }
+                _ => panic!("Unexpected Thrift type {}", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn test_footer_locates_every_page() {
+        let lines = [
+            "read1\t5039\tvalid\tBC05/BC01\t50\t5015\t-\tdual\tBC05_BC05\tONT-BC05\tfwd:(BC05,0,26,50);(BC05,1,5015,5039)\tsingle\tBC01_\tONT-BC01\trev:(BC01,2,60,84);(,99,0,0)",
+            "read2\t812\tunknown\t-\t0\t812\t-\tunknown\tunknown\tunknown\tunknown:(,99,0,0);(,99,0,0)",
+            "read3\t4100\tfusion\tfusion\t0\t4100\tBC02|BC03\tdual\tBC02_BC02\tONT-BC02\tfwd:(BC02,0,20,44);(BC02,0,4000,4024)",
+        ];
+        let mut log = ParquetReadLog::new(Vec::new(), 2).unwrap();
+        for line in lines {
+            log.push_line(line).unwrap();
+        }
+        let bytes = log.finish().unwrap();
+
+        assert_eq!(&bytes[..4], PARQUET_MAGIC);
+        assert_eq!(&bytes[bytes.len() - 4..], PARQUET_MAGIC);
+        let footer_length = u32::from_le_bytes(bytes[bytes.len() - 8..bytes.len() - 4].try_into().unwrap()) as usize;
+        let footer_start = bytes.len() - 8 - footer_length;
+        let mut reader = CompactReader { bytes: &bytes[..bytes.len() - 8], position: footer_start };
+        let metadata = reader.value(THRIFT_STRUCT);
+        assert_eq!(reader.position, bytes.len() - 8);
+
+        let columns = 7 + 2 * 12;
+        assert_eq!(metadata.field(3).int(), lines.len() as i64);
+        let schema = metadata.field(2).list();
+        assert_eq!(schema.len(), columns + 1);
+        let row_groups = metadata.field(4).list();
+        assert_eq!(row_groups.len(), 1);
+        assert_eq!(row_groups[0].field(3).int(), lines.len() as i64);
+
+        let chunks = row_groups[0].field(1).list();
+        assert_eq!(chunks.len(), columns);
+        let mut pages = Vec::new();
+        for (chunk, element) in chunks.iter().zip(&schema[1..]) {
+            let chunk_metadata = chunk.field(3);
+            assert_eq!(chunk_metadata.field(3).list()[0].binary(), element.field(4).binary());
+            assert_eq!(chunk_metadata.field(5).int(), lines.len() as i64);
+            let offset = chunk_metadata.field(9).int() as usize;
+            assert_eq!(chunk.field(2).int() as usize, offset);
+
+            let mut reader = CompactReader { bytes: &bytes[..footer_start], position: offset };
+            let page_header = reader.value(THRIFT_STRUCT);
+            assert_eq!(page_header.field(1).int(), DATA_PAGE as i64);
+            assert_eq!(page_header.field(5).field(1).int(), lines.len() as i64);
+            let compressed_size = page_header.field(3).int() as usize;
+            let header_size = reader.position - offset;
+            assert_eq!(chunk_metadata.field(7).int() as usize, header_size + compressed_size);
+
+            let mut page = Vec::new();
+            GzDecoder::new(&bytes[reader.position..reader.position + compressed_size]).read_to_end(&mut page).unwrap();
+            assert_eq!(page.len(), page_header.field(2).int() as usize);
+            assert_eq!(chunk_metadata.field(6).int() as usize, header_size + page.len());
+            pages.push(page);
+        }
+
+        // Nulls show up in the definition levels ahead of the values
+        let output_levels = definition_levels(&[true, false, true]);
+        assert_eq!(pages[3][..4], (output_levels.len() as u32).to_le_bytes());
+        assert_eq!(pages[3][4..4 + output_levels.len()], output_levels);
+        let right_pattern2 = 7 + 12 + 8;
+        let round2_levels = definition_levels(&[false, false, false]);
+        assert_eq!(pages[right_pattern2][4..4 + round2_levels.len()], round2_levels);
+    }
+
+    #[test]
+    fn test_definition_levels_are_run_length_encoded() {
+        // Run headers are the run length shifted left by one, followed by the level byte
+        assert_eq!(definition_levels(&[true, true, true, false, true]), vec![6, 1, 2, 0, 2, 1]);
+        let long_run = definition_levels(&[true; 200]);
+        assert_eq!(long_run, vec![0x90, 0x03, 1]);
+        assert!(definition_levels(&[]).is_empty());
+    }
+}
@@ -1,11 +1,15 @@
-use csv;
-use log::info;
-use std::collections::HashMap;
+use log::{info, warn};
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
 use crate::args::Args;
-use crate::utils::reverse_complement;
+use crate::myers::{compile_pattern, DistanceLimit, EffectiveLength, MultiPatternMyers};
+use bio::pattern_matching::myers::Myers;
+use crate::utils::{complement_base, reverse_complement, wildcard_match};
+#[cfg(feature = "encryption")]
 use age::secrecy::SecretString;
 use std::fs::File;
-use std::io::{Read, Write};
+use std::io::{BufRead, Read};
 
 /// Pattern parameter configuration structure
 #[derive(Debug, Clone)]
@@ -14,14 +18,85 @@ pub struct PatternConfiguration {
     pub pattern_match_types: Vec<String>,
     pub pattern_arguments: Vec<PatternArgument>,
     pub trim_mode: usize,
+    pub trim_offset_left: Vec<isize>,
+    pub trim_offset_right: Vec<isize>,
+    pub keep_pattern: Vec<String>,
     pub write_type: String,
     pub pattern_error_rates: Vec<(f32, f32)>,
     pub max_distances: Vec<usize>,
+    /// Absolute edit distance caps per round, empty for none
+    pub max_edits: Vec<u8>,
     pub position_shifts: Vec<usize>,
     pub min_length: usize,
     pub id_separator: String,
     pub fusion_database: FusionDatabase,
     pub fusion_error_rate: f32,
+    pub orient: bool,
+    pub fusion_action: String,
+    pub fusion_class_actions: HashMap<String, String>,
+    pub fusion_window: String,
+    pub fusion_min_end_distance: usize,
+    pub disabled_patterns: Vec<String>,
+    pub sample_sheet: HashMap<String, String>,
+    /// Expected relative read share of each sample, from the sample sheet's optional third column
+    pub sample_weights: HashMap<String, f64>,
+    pub expected_barcodes: HashSet<String>,
+    pub annotate_score: bool,
+    pub qual_trim: u8,
+    pub trim_polyx: Option<(u8, usize)>,
+    pub group_by: Vec<String>,
+    pub group_output: bool,
+    pub time_bin: u64,
+    pub recursive_split: bool,
+    pub trim_only: bool,
+    /// Output path layout with placeholders, see `ReadInfo::apply_outdir_template`
+    pub outdir_template: Option<String>,
+    pub run_id: Option<String>,
+    pub run_date: Option<String>,
+}
+
+impl Default for PatternConfiguration {
+    /// Configuration matching the command line defaults
+    fn default() -> Self {
+        Self {
+            window_size: vec![400, 400],
+            pattern_match_types: vec!["single".to_string()],
+            pattern_arguments: vec![],
+            trim_mode: 0,
+            trim_offset_left: vec![0],
+            trim_offset_right: vec![0],
+            keep_pattern: vec![],
+            write_type: "type".to_string(),
+            pattern_error_rates: vec![(0.2, 0.2)],
+            max_distances: vec![4],
+            max_edits: vec![],
+            position_shifts: vec![3],
+            min_length: 100,
+            id_separator: "%".to_string(),
+            fusion_database: FusionDatabase::new(),
+            fusion_error_rate: 0.2,
+            orient: false,
+            fusion_action: "drop".to_string(),
+            fusion_class_actions: HashMap::new(),
+            fusion_window: "insert".to_string(),
+            fusion_min_end_distance: 0,
+            disabled_patterns: vec![],
+            sample_sheet: HashMap::new(),
+            sample_weights: HashMap::new(),
+            expected_barcodes: HashSet::new(),
+            annotate_score: false,
+            qual_trim: 0,
+            trim_polyx: None,
+            group_by: vec![],
+            group_output: false,
+            time_bin: 0,
+            recursive_split: false,
+            trim_only: false,
+            outdir_template: None,
+            run_id: None,
+            run_date: None,
+        }
+    }
 }
 
 impl PatternConfiguration {
@@ -32,14 +107,38 @@ impl PatternConfiguration {
             pattern_match_types: args.pattern_match_type.clone(),
             pattern_arguments: vec![],
             trim_mode: args.trim_mode,
+            trim_offset_left: args.trim_offset_left.clone(),
+            trim_offset_right: args.trim_offset_right.clone(),
+            keep_pattern: args.keep_pattern.clone(),
             write_type: args.write_type.clone(),
             pattern_error_rates: args.pattern_error_rate.clone(),
             max_distances: args.max_distance.clone(),
+            max_edits: args.max_edits.clone(),
             position_shifts: args.position_shift.clone(),
             min_length: args.get_min_length(),
             id_separator: args.id_separator.clone(),
             fusion_database: FusionDatabase::new(),
             fusion_error_rate: args.fusion_error_rate,
+            orient: args.orient,
+            fusion_action: args.get_fusion_action(),
+            fusion_class_actions: args.fusion_class_actions.iter().cloned().collect(),
+            fusion_window: args.fusion_window.clone(),
+            fusion_min_end_distance: args.fusion_min_end_distance,
+            disabled_patterns: args.disabled_patterns.clone(),
+            sample_sheet: HashMap::new(),
+            sample_weights: HashMap::new(),
+            expected_barcodes: HashSet::new(),
+            annotate_score: args.annotate_score,
+            qual_trim: args.qual_trim,
+            trim_polyx: args.trim_polyx,
+            group_by: args.group_by.clone(),
+            group_output: args.group_output,
+            time_bin: args.time_bin,
+            recursive_split: args.recursive_split,
+            trim_only: args.trim_only,
+            outdir_template: args.outdir_template.clone(),
+            run_id: args.run_id.clone(),
+            run_date: args.run_date.clone(),
         };
         config.normalize_vectors();
         config
@@ -55,6 +154,52 @@ impl PatternConfiguration {
         Self::resize_vector(&mut self.position_shifts, MIN_VECTOR_LENGTH);
     }
     
+    /// Error rates, maximum distance and position shift for a pattern round
+    ///
+    /// Rounds beyond the given values reuse the last one.
+    pub fn round_parameters(&self, round: usize) -> ((f32, f32), usize, usize) {
+        fn nth_or_last<T: Copy>(values: &[T], index: usize) -> T {
+            values[index.min(values.len() - 1)]
+        }
+        (
+            nth_or_last(&self.pattern_error_rates, round),
+            nth_or_last(&self.max_distances, round),
+            nth_or_last(&self.position_shifts, round),
+        )
+    }
+    
+    /// Edit distance cap of a pattern round, the last value reused, None without --max-edits
+    pub fn round_max_edits(&self, round: usize) -> Option<u8> {
+        self.max_edits.get(round).or(self.max_edits.last()).copied()
+    }
+    
+    /// Left and right cut offsets for the round `trim_mode` cuts at
+    pub fn trim_offsets(&self) -> (isize, isize) {
+        let round = self.trim_mode.saturating_sub(1);
+        let nth_or_last = |values: &[isize]| values.get(round).or(values.last()).copied().unwrap_or(0);
+        (nth_or_last(&self.trim_offset_left), nth_or_last(&self.trim_offset_right))
+    }
+    
+    /// Whether the left and right patterns of the cut round stay in the read
+    ///
+    /// Taken from --keep-pattern when given, otherwise mode 0 trims both and
+    /// other modes keep both.
+    pub fn kept_patterns(&self) -> (bool, bool) {
+        let round = self.trim_mode.saturating_sub(1);
+        match self.keep_pattern.get(round).or(self.keep_pattern.last()).map(String::as_str) {
+            Some("left") => (true, false),
+            Some("right") => (false, true),
+            Some("both") => (true, true),
+            Some(_) => (false, false),
+            None => (self.trim_mode > 0, self.trim_mode > 0),
+        }
+    }
+    
+    /// Handling of fusion reads of a class, --fusion-action unless --fusion-class-action names it
+    pub fn fusion_action_for(&self, fusion_class: &str) -> &str {
+        self.fusion_class_actions.get(fusion_class).unwrap_or(&self.fusion_action)
+    }
+    
     /// Adjust vector to minimum length
     fn resize_vector<T: Clone + Default>(vector: &mut Vec<T>, min_length: usize) {
         if vector.len() < min_length {
@@ -70,14 +215,167 @@ pub struct PatternArgument {
     pub pattern_database: PatternDatabase,
     pub use_position_info: bool,
     pub pattern_error_rate: (f32, f32),
+    /// Edit distance cap applied when stricter than the error rate budget
+    pub max_edits: Option<u8>,
+    /// Pattern positions error rates are not applied to
+    pub effective_length: EffectiveLength,
     pub max_distance: usize,
     pub position_shift: usize,
+    pub early_exit_score: usize,
+    pub anchor: usize,
+    pub dual_policy: String,
+    pub min_score_margin: usize,
+    /// Barcode start and length from each read end, compared without alignment
+    pub fixed_position: Option<(usize, usize)>,
+}
+
+/// Number of recorded hits between search order updates
+const REORDER_INTERVAL: u64 = 1024;
+
+/// Search order state, adapted to observed hit frequency
+#[derive(Debug, Clone, Default)]
+struct SearchOrder {
+    names: Vec<String>,
+    hits: HashMap<String, u64>,
+    recorded_hits: u64,
+    /// Packed scan of the patterns in `names` order, built on first use and None inside if they cannot be packed
+    packed: Option<Option<MultiPatternMyers>>,
+}
+
+/// Source of pattern set versions, 0 being the empty set
+static NEXT_PATTERN_SET_VERSION: AtomicU64 = AtomicU64::new(1);
+
+/// Named pattern sequences searched by the splitter
+///
+/// Each running splitting task holds its own copy, so the search order can adapt to
+/// the patterns it actually sees without any locking.
+#[derive(Debug, Clone, Default)]
+pub struct PatternSet {
+    patterns: HashMap<String, String>,
+    /// Myers automaton of every pattern it can be built for, compiled on insert
+    compiled: HashMap<String, RefCell<Myers<u64>>>,
+    distance_limits: HashMap<String, DistanceLimit>,
+    order: RefCell<SearchOrder>,
+    /// Changes with the patterns, so cached searches of one set are never reused for another
+    version: u64,
+}
+
+impl PatternSet {
+    /// Create new pattern set
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Insert pattern sequence, compiling its automaton and resetting the search order
+    pub fn insert(&mut self, name: String, sequence: String) {
+        // Empty patterns and patterns over 64 bases have no automaton and fail when searched
+        if (1..=64).contains(&sequence.len()) {
+            self.compiled.insert(name.clone(), RefCell::new(compile_pattern(sequence.as_bytes())));
+        } else {
+            self.compiled.remove(&name);
+        }
+        self.patterns.insert(name, sequence);
+        let order = self.order.get_mut();
+        order.names.clear();
+        order.packed = None;
+        self.version = NEXT_PATTERN_SET_VERSION.fetch_add(1, Ordering::Relaxed);
+    }
+    
+    /// Set the edit distance override of a pattern
+    pub fn set_distance_limit(&mut self, name: String, distance_limit: Option<DistanceLimit>) {
+        match distance_limit {
+            Some(distance_limit) => self.distance_limits.insert(name, distance_limit),
+            None => self.distance_limits.remove(&name),
+        };
+        self.version = NEXT_PATTERN_SET_VERSION.fetch_add(1, Ordering::Relaxed);
+    }
+    
+    /// Get pattern sequence by name
+    pub fn get(&self, name: &str) -> Option<&String> {
+        self.patterns.get(name)
+    }
+    
+    /// Get edit distance override of a pattern
+    pub fn distance_limit(&self, name: &str) -> Option<DistanceLimit> {
+        self.distance_limits.get(name).copied()
+    }
+    
+    /// Check if set is empty
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+    
+    /// Version of the patterns and their distance limits, shared by clones
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+    
+    /// Length of the longest pattern
+    pub fn max_pattern_length(&self) -> usize {
+        self.patterns.values().map(|sequence| sequence.len()).max().unwrap_or(0)
+    }
+    
+    /// Prebuilt automaton of a pattern, borrowed for one search
+    pub fn compiled_pattern(&self, name: &str) -> Option<RefMut<'_, Myers<u64>>> {
+        self.compiled.get(name).map(RefCell::borrow_mut)
+    }
+    
+    /// Pattern names in current search order, most frequently hit first
+    pub fn ordered_names(&self) -> Ref<'_, [String]> {
+        self.refresh_order();
+        Ref::map(self.order.borrow(), |order| order.names.as_slice())
+    }
+    
+    /// Packed scan of all patterns in current search order, None if they cannot be packed
+    pub fn packed_patterns(&self) -> Ref<'_, Option<MultiPatternMyers>> {
+        self.refresh_order();
+        if self.order.borrow().packed.is_none() {
+            let mut order = self.order.borrow_mut();
+            let SearchOrder { names, packed, .. } = &mut *order;
+            let patterns: Vec<&[u8]> = names.iter().map(|name| self.patterns[name].as_bytes()).collect();
+            *packed = Some(MultiPatternMyers::new(&patterns));
+        }
+        Ref::map(self.order.borrow(), |order| order.packed.as_ref().expect("Packed patterns missing"))
+    }
+    
+    /// List the pattern names in name order after patterns were inserted
+    ///
+    /// Only borrows the order mutably when it is stale, so callers may hold it borrowed otherwise.
+    fn refresh_order(&self) {
+        if self.order.borrow().names.len() == self.patterns.len() {
+            return;
+        }
+        let mut names: Vec<String> = self.patterns.keys().cloned().collect();
+        names.sort();
+        let mut order = self.order.borrow_mut();
+        order.names = names;
+        order.packed = None;
+    }
+    
+    /// Record the winning pattern of a search
+    pub fn record_hit(&self, name: &str) {
+        let mut order = self.order.borrow_mut();
+        match order.hits.get_mut(name) {
+            Some(count) => *count += 1,
+            None => {
+                order.hits.insert(name.to_string(), 1);
+            }
+        }
+        
+        order.recorded_hits += 1;
+        if order.recorded_hits.is_multiple_of(REORDER_INTERVAL) {
+            let SearchOrder { names, hits, .. } = &mut *order;
+            names.sort_by_key(|name| std::cmp::Reverse(hits.get(name).copied().unwrap_or(0)));
+            order.packed = None;
+        }
+    }
 }
 
 /// Encrypt pattern database file
+#[cfg(feature = "encryption")]
 pub fn encrypt_pattern_database(file_path: &str, passphrase: &str) {
     let mut file = File::open(file_path)
-        .expect(&format!("Unable to find file: {}", file_path));
+        .unwrap_or_else(|_| panic!("Unable to find file: {}", file_path));
     
     let mut content = Vec::new();
     file.read_to_end(&mut content)
@@ -91,201 +389,589 @@ pub fn encrypt_pattern_database(file_path: &str, passphrase: &str) {
 
     // Write encrypted file
     let output_file = format!("{}.safe", file_path);
-    let mut output_file_handle = File::create(&output_file)
-        .expect("Failed to create encrypted file");
-    output_file_handle.write_all(&encrypted_data)
+    std::fs::write(&output_file, &encrypted_data)
         .expect("Failed to write encrypted data");
     
     info!("Pattern database file encrypted and saved to: {}", output_file);
 }
 
+/// Decrypt an encrypted pattern database file
+#[cfg(feature = "encryption")]
+fn decrypt_database(file_path: &str, passphrase: &str) -> Vec<u8> {
+    let secret_passphrase = SecretString::from(passphrase.to_owned());
+    let identity = age::scrypt::Identity::new(secret_passphrase);
+    let mut content = Vec::new();
+    let mut encrypted_file = File::open(file_path)
+        .unwrap_or_else(|_| panic!("Unable to find encrypted file: {}", file_path));
+    encrypted_file.read_to_end(&mut content)
+        .expect("Failed to read encrypted file");
+    age::decrypt(&identity, &content[..])
+        .expect("Failed to decrypt file")
+}
+
+/// Encrypted pattern databases need the encryption feature
+#[cfg(not(feature = "encryption"))]
+fn decrypt_database(file_path: &str, _passphrase: &str) -> Vec<u8> {
+    panic!("Encrypted pattern database {} needs a build with the encryption feature", file_path);
+}
+
+/// Database entry: sequence and optional edit distance override
+type DatabaseEntry = (String, Option<DistanceLimit>);
+
+/// Parse the optional third database column
+///
+/// Values with a decimal point are error rates, whole numbers are maximum edit distances.
+fn parse_distance_limit(name: &str, value: &str) -> Option<DistanceLimit> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+    let distance_limit = if value.contains('.') {
+        value.parse::<f32>().ok()
+            .filter(|error_rate| (0.0..=0.5).contains(error_rate))
+            .map(DistanceLimit::ErrorRate)
+    } else {
+        value.parse::<u8>().ok().map(DistanceLimit::MaxEdits)
+    };
+    Some(distance_limit.unwrap_or_else(|| {
+        panic!("Invalid error rate or edit distance for pattern {}: {}", name, value)
+    }))
+}
+
+/// Uppercase database sequence, with non-IUPAC characters replaced by N unless `strict_bases`
+///
+/// `location` names the file and line in error messages.
+fn normalize_sequence(sequence: &str, name: &str, location: &str, strict_bases: bool) -> String {
+    let sequence = sequence.to_ascii_uppercase();
+    let Some(invalid) = sequence.chars().find(|base| complement_base(*base).is_none()) else {
+        return sequence;
+    };
+    if strict_bases {
+        panic!("{}: invalid base '{}' in sequence of {}", location, invalid, name);
+    }
+    warn!("{}: invalid base '{}' in sequence of {}, treated as N", location, invalid, name);
+    sequence.chars().map(|base| if complement_base(base).is_some() { base } else { 'N' }).collect()
+}
+
+/// Line of a parsed record, for error messages
+fn record_line(record: &csv::StringRecord) -> u64 {
+    record.position().map_or(0, csv::Position::line)
+}
+
+/// Database format of a file, resolving `auto` from the extension or a leading `>`
+fn resolve_database_format<'a>(file_path: &str, database_format: &'a str, content: &[u8]) -> &'a str {
+    if database_format != "auto" {
+        return database_format;
+    }
+    let file_name = file_path.strip_suffix(".safe").unwrap_or(file_path).to_ascii_lowercase();
+    if [".fa", ".fasta", ".fna", ".fas"].iter().any(|extension| file_name.ends_with(extension))
+        || content.iter().find(|byte| !byte.is_ascii_whitespace()) == Some(&b'>')
+    {
+        "fasta"
+    } else if file_name.ends_with(".csv") {
+        "csv"
+    } else {
+        "tsv"
+    }
+}
+
+/// Read database records of name, sequence and optional edit distance override
+///
+/// Fields are trimmed and sequences uppercased, so files saved from
+/// spreadsheets with trailing spaces or Windows line endings load as intended.
+/// `csv` files are comma-delimited, `fasta` files are read by `read_fasta_records`.
+/// A name listed again is skipped, or fails with `fail_on_duplicates`.
+fn read_database_records<R: Read>(
+    reader: R,
+    source: &str,
+    database_format: &str,
+    strict_bases: bool,
+    fail_on_duplicates: bool,
+) -> HashMap<String, DatabaseEntry> {
+    if database_format == "fasta" {
+        return read_fasta_records(reader, source, strict_bases, fail_on_duplicates);
+    }
+    let delimiter = if database_format == "csv" { b',' } else { b'\t' };
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .delimiter(delimiter)
+        .from_reader(reader);
+    
+    let mut pattern_database = HashMap::new();
+    // Line number and text of the first record of each name
+    let mut first_lines: HashMap<String, (u64, String)> = HashMap::new();
+    for result in reader.records() {
+        let record = result.unwrap_or_else(|error| panic!("Failed to parse database {}: {}", source, error));
+        let location = format!("{} line {}", source, record_line(&record));
+        let (Some(name), Some(sequence)) = (record.get(0), record.get(1).filter(|sequence| !sequence.is_empty())) else {
+            panic!("{}: expected a pattern name and sequence", location);
+        };
+        let line = record.iter().collect::<Vec<_>>().join(&(delimiter as char).to_string());
+        if let Some((first_number, first_line)) = first_lines.get(name) {
+            report_duplicate(
+                format!("{}: pattern {} is listed again ('{}'), first at line {} ('{}')", location, name, line, first_number, first_line),
+                fail_on_duplicates,
+            );
+            continue;
+        }
+        let sequence = normalize_sequence(sequence, name, &location, strict_bases);
+        let distance_limit = record.get(2).and_then(|value| parse_distance_limit(name, value));
+        pattern_database.insert(name.to_string(), (sequence, distance_limit));
+        first_lines.insert(name.to_string(), (record_line(&record), line));
+    }
+    
+    pattern_database
+}
+
+/// Read FASTA database records, named by the first word of each header
+///
+/// Sequences may span several lines; FASTA records carry no edit distance override.
+fn read_fasta_records<R: Read>(reader: R, source: &str, strict_bases: bool, fail_on_duplicates: bool) -> HashMap<String, DatabaseEntry> {
+    let mut pattern_database: HashMap<String, DatabaseEntry> = HashMap::new();
+    // Header line and sequence of the first record of each name
+    let mut first_lines: HashMap<String, (usize, String)> = HashMap::new();
+    // Name, header line and sequence of the record being read
+    let mut current: Option<(String, usize, String)> = None;
+    let mut insert = |record: Option<(String, usize, String)>| {
+        let Some((name, line, sequence)) = record else {
+            return;
+        };
+        let location = format!("{} line {}", source, line);
+        if sequence.is_empty() {
+            panic!("{}: pattern {} has no sequence", location, name);
+        }
+        if let Some((first_line, first_sequence)) = first_lines.get(&name) {
+            report_duplicate(
+                format!("{}: pattern {} is listed again ({}), first at line {} ({})", location, name, sequence, first_line, first_sequence),
+                fail_on_duplicates,
+            );
+            return;
+        }
+        first_lines.insert(name.clone(), (line, sequence.clone()));
+        let sequence = normalize_sequence(&sequence, &name, &location, strict_bases);
+        pattern_database.insert(name, (sequence, None));
+    };
+    
+    for (index, line) in std::io::BufReader::new(reader).lines().enumerate() {
+        let line = line.unwrap_or_else(|error| panic!("Failed to read database {}: {}", source, error));
+        let line = line.trim();
+        if let Some(header) = line.strip_prefix('>') {
+            let name = header.split_whitespace().next()
+                .unwrap_or_else(|| panic!("{} line {}: FASTA header without a pattern name", source, index + 1));
+            insert(current.replace((name.to_string(), index + 1, String::new())));
+        } else if let Some((_, _, sequence)) = &mut current {
+            sequence.push_str(line);
+        } else if !line.is_empty() {
+            panic!("{} line {}: sequence before the first FASTA header", source, index + 1);
+        }
+    }
+    insert(current);
+    
+    pattern_database
+}
+
+/// Fail on a pattern or pair listed twice with `fail_on_duplicates`, else warn that the repeat is skipped
+fn report_duplicate(message: String, fail_on_duplicates: bool) {
+    if fail_on_duplicates {
+        panic!("{}", message);
+    }
+    warn!("{}, skipped", message);
+}
+
 /// Pattern database structure
 #[derive(Debug, Clone)]
 pub struct PatternDatabase {
     /// Forward patterns
-    pub forward_patterns: HashMap<String, String>,
+    pub forward_patterns: PatternSet,
     /// Reverse patterns
-    pub reverse_patterns: HashMap<String, String>,
+    pub reverse_patterns: PatternSet,
     /// Pattern type mapping
     pub pattern_types: HashMap<String, (String, String, String)>,
+    /// Loaded pairs as (forward key, reverse key, name), wildcard rows expanded
+    pub pattern_pairs: Vec<(String, String, String)>,
+}
+
+impl Default for PatternDatabase {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl PatternDatabase {
     /// Create new pattern database
     pub fn new() -> Self {
         Self {
-            forward_patterns: HashMap::new(),
-            reverse_patterns: HashMap::new(),
+            forward_patterns: PatternSet::new(),
+            reverse_patterns: PatternSet::new(),
             pattern_types: HashMap::new(),
+            pattern_pairs: Vec::new(),
         }
     }
     
     /// Load pattern data
-    pub fn load_patterns(&mut self, database_file: &str, pattern_file: &str) {
-        let pattern_database = self.load_database(database_file, "666666");
-        self.load_pattern_file(pattern_file, pattern_database);
+    pub fn load_patterns(
+        &mut self,
+        database_file: &str,
+        database_format: &str,
+        pattern_file: &str,
+        disabled_patterns: &[String],
+        strict_bases: bool,
+        fail_on_duplicates: bool,
+    ) {
+        let pattern_database = self.load_database(database_file, database_format, "666666", strict_bases, fail_on_duplicates);
+        for name in disabled_patterns {
+            if !pattern_database.contains_key(name) {
+                warn!("Disabled pattern not found in database: {}", name);
+            }
+        }
+        self.load_pattern_file(pattern_file, pattern_database, disabled_patterns, strict_bases, fail_on_duplicates);
+    }
+    
+    /// Load every database sequence as an adapter, searched as is at read starts and reverse complemented at read ends
+    pub fn load_adapters(&mut self, database_file: &str, database_format: &str, disabled_patterns: &[String], strict_bases: bool, fail_on_duplicates: bool) {
+        let pattern_database = self.load_database(database_file, database_format, "666666", strict_bases, fail_on_duplicates);
+        let mut names: Vec<&String> = pattern_database.keys().filter(|name| !disabled_patterns.contains(name)).collect();
+        names.sort();
+        let adapter_count = names.len();
+        for name in names {
+            let (sequence, distance_limit) = &pattern_database[name];
+            for pattern_set in [&mut self.forward_patterns, &mut self.reverse_patterns] {
+                pattern_set.set_distance_limit(name.clone(), *distance_limit);
+            }
+            self.forward_patterns.insert(name.clone(), sequence.clone());
+            self.reverse_patterns.insert(name.clone(), reverse_complement(sequence, strict_bases));
+        }
+        info!("Adapters loaded for trimming: {}", adapter_count);
     }
     
     /// Load database file
-    fn load_database(&self, file_path: &str, passphrase: &str) -> HashMap<String, String> {
-        let mut pattern_database = HashMap::new();
+    fn load_database(
+        &self,
+        file_path: &str,
+        database_format: &str,
+        passphrase: &str,
+        strict_bases: bool,
+        fail_on_duplicates: bool,
+    ) -> HashMap<String, DatabaseEntry> {
         let mut content = Vec::new();
 
         if file_path.ends_with(".safe") {
-            // Decrypt file
-            let secret_passphrase = SecretString::from(passphrase.to_owned());
-            let identity = age::scrypt::Identity::new(secret_passphrase);
-            let mut encrypted_file = File::open(file_path)
-                .expect(&format!("Unable to find encrypted file: {}", file_path));
-            encrypted_file.read_to_end(&mut content)
-                .expect("Failed to read encrypted file");
-            let decrypted_data = age::decrypt(&identity, &content[..])
-                .expect("Failed to decrypt file");
-            content = decrypted_data;
+            content = decrypt_database(file_path, passphrase);
         } else {
             // Read file directly
             let mut file = File::open(file_path)
-                .expect(&format!("Unable to find file: {}", file_path));
+                .unwrap_or_else(|_| panic!("Unable to find file: {}", file_path));
             file.read_to_end(&mut content)
                 .expect("Failed to read file");
         }
 
-        let cursor = std::io::Cursor::new(content);
-        let mut reader = csv::ReaderBuilder::new()
-            .has_headers(false)
-            .delimiter(b'\t')
-            .from_reader(cursor);
-
-        for result in reader.records() {
-            let record = result.expect("Failed to parse CSV record");
-            let name = &record[0];
-            let sequence = &record[1];
-            pattern_database.insert(name.to_string(), sequence.to_string());
-        }
-        
-        pattern_database
+        let database_format = resolve_database_format(file_path, database_format, &content);
+        read_database_records(std::io::Cursor::new(content), file_path, database_format, strict_bases, fail_on_duplicates)
     }
     
     /// Load pattern files
-    fn load_pattern_file(&mut self, file_path: &str, pattern_database: HashMap<String, String>) {
+    fn load_pattern_file(
+        &mut self,
+        file_path: &str,
+        pattern_database: HashMap<String, DatabaseEntry>,
+        disabled_patterns: &[String],
+        strict_bases: bool,
+        fail_on_duplicates: bool,
+    ) {
         let mut reader = csv::ReaderBuilder::new()
             .has_headers(true)
+            .flexible(true)
+            .trim(csv::Trim::All)
             .delimiter(b'\t')
             .from_path(file_path)
-            .expect(&format!("Unable to find pattern file: {}", file_path));
+            .unwrap_or_else(|_| panic!("Unable to find pattern file: {}", file_path));
             
+        // Wildcards pick from the enabled database patterns, in name order
+        let mut database_names: Vec<&String> = pattern_database
+            .keys()
+            .filter(|name| !disabled_patterns.contains(name))
+            .collect();
+        database_names.sort();
+        
+        // Line number and text of the row each pair was first listed on, by pair key in both orientations
+        let mut pair_lines: HashMap<String, (u64, String)> = HashMap::new();
         for result in reader.records() {
-            let record = result.expect("Failed to parse pattern file record");
-            let (forward_key, reverse_key, name) = (
-                record[0].to_string(), 
-                record[1].to_string(), 
-                record[2].to_string()
-            );
+            let record = result.unwrap_or_else(|error| panic!("Failed to parse pattern file {}: {}", file_path, error));
+            let (Some(forward_key), Some(reverse_key), Some(name)) = (record.get(0), record.get(1), record.get(2)) else {
+                panic!("{} line {}: expected forward pattern, reverse pattern and type columns", file_path, record_line(&record));
+            };
             
-            let forward_reverse_key = format!("{}_{}", forward_key, reverse_key);
-            let reverse_forward_key = format!("{}_{}", reverse_key, forward_key);
-            
-            let forward_sequence = pattern_database
-                .get(&forward_key)
-                .expect(&format!("Pattern not found in database: {}", forward_key))
-                .to_string();
-            let reverse_sequence = pattern_database
-                .get(&reverse_key)
-                .expect(&format!("Pattern not found in database: {}", reverse_key))
-                .to_string();
-            
-            // Store forward and reverse patterns
-            self.forward_patterns.insert(forward_key.clone(), forward_sequence.clone());
-            self.forward_patterns.insert(reverse_key.clone(), reverse_sequence.clone());
-            self.reverse_patterns.insert(forward_key.clone(), reverse_complement(&forward_sequence));
-            self.reverse_patterns.insert(reverse_key.clone(), reverse_complement(&reverse_sequence));
+            if !forward_key.contains(['*', '?']) && !reverse_key.contains(['*', '?']) {
+                // Disabled patterns are never searched, so reads carrying them cannot be assigned
+                if disabled_patterns.iter().any(|pattern| pattern == forward_key || pattern == reverse_key) {
+                    continue;
+                }
+                let line = format!("{}\t{}\t{}", forward_key, reverse_key, name);
+                if let Some((first_number, first_line)) = pair_lines.get(&format!("{}_{}", forward_key, reverse_key)) {
+                    report_duplicate(
+                        format!(
+                            "{} line {}: pair {}_{} is listed again ('{}'), first at line {} ('{}')",
+                            file_path, record_line(&record), forward_key, reverse_key, line, first_number, first_line
+                        ),
+                        fail_on_duplicates,
+                    );
+                    continue;
+                }
+                for pair_key in [format!("{}_{}", forward_key, reverse_key), format!("{}_{}", reverse_key, forward_key)] {
+                    pair_lines.insert(pair_key, (record_line(&record), line.clone()));
+                }
+                self.insert_pair(forward_key, reverse_key, name, &pattern_database, strict_bases);
+                continue;
+            }
             
-            // Store pattern type information
-            if forward_reverse_key != reverse_forward_key {
-                self.pattern_types.insert(
-                    forward_reverse_key.clone(), 
-                    (forward_reverse_key.clone(), name.clone(), "fs".to_string())
-                );
-                self.pattern_types.insert(
-                    reverse_forward_key.clone(), 
-                    (forward_reverse_key, name, "rs".to_string())
-                );
-            } else {
-                self.pattern_types.insert(
-                    forward_reverse_key.clone(), 
-                    (forward_reverse_key, name, "unknown".to_string())
-                );
+            let expand = |key: &str| -> Vec<&String> {
+                let names: Vec<&String> = database_names.iter().copied().filter(|database_name| wildcard_match(key, database_name)).collect();
+                if names.is_empty() {
+                    panic!("No database pattern matches {} in pattern file: {}", key, file_path);
+                }
+                names
+            };
+            for forward_name in expand(forward_key) {
+                for reverse_name in expand(reverse_key) {
+                    // The same pair read from the other strand is already known
+                    if self.pattern_types.contains_key(&format!("{}_{}", forward_name, reverse_name)) {
+                        continue;
+                    }
+                    let pair_name = expanded_pair_name(name, (forward_key, forward_name), (reverse_key, reverse_name));
+                    self.insert_pair(forward_name, reverse_name, &pair_name, &pattern_database, strict_bases);
+                }
             }
         }
         
         info!("Pattern file loaded successfully: {}", file_path);
     }
+    
+    /// Store both patterns of a pair on both strands with its type information
+    fn insert_pair(
+        &mut self,
+        forward_key: &str,
+        reverse_key: &str,
+        name: &str,
+        pattern_database: &HashMap<String, DatabaseEntry>,
+        strict_bases: bool,
+    ) {
+        let forward_reverse_key = format!("{}_{}", forward_key, reverse_key);
+        let reverse_forward_key = format!("{}_{}", reverse_key, forward_key);
+        
+        let (forward_sequence, forward_limit) = pattern_database
+            .get(forward_key)
+            .unwrap_or_else(|| panic!("Pattern not found in database: {}", forward_key))
+            .clone();
+        let (reverse_sequence, reverse_limit) = pattern_database
+            .get(reverse_key)
+            .unwrap_or_else(|| panic!("Pattern not found in database: {}", reverse_key))
+            .clone();
+        
+        // Per-pattern edit distance overrides apply on both strands
+        for pattern_set in [&mut self.forward_patterns, &mut self.reverse_patterns] {
+            pattern_set.set_distance_limit(forward_key.to_string(), forward_limit);
+            pattern_set.set_distance_limit(reverse_key.to_string(), reverse_limit);
+        }
+        
+        // Store forward and reverse patterns
+        self.forward_patterns.insert(forward_key.to_string(), forward_sequence.clone());
+        self.forward_patterns.insert(reverse_key.to_string(), reverse_sequence.clone());
+        self.reverse_patterns.insert(forward_key.to_string(), reverse_complement(&forward_sequence, strict_bases));
+        self.reverse_patterns.insert(reverse_key.to_string(), reverse_complement(&reverse_sequence, strict_bases));
+        
+        // Store pattern type information
+        let name = name.to_string();
+        if forward_reverse_key != reverse_forward_key {
+            self.pattern_types.insert(
+                forward_reverse_key.clone(), 
+                (forward_reverse_key.clone(), name.clone(), "fs".to_string())
+            );
+            self.pattern_types.insert(
+                reverse_forward_key.clone(), 
+                (forward_reverse_key, name.clone(), "rs".to_string())
+            );
+        } else {
+            self.pattern_types.insert(
+                forward_reverse_key.clone(), 
+                (forward_reverse_key, name.clone(), "unknown".to_string())
+            );
+        }
+        self.pattern_pairs.push((forward_key.to_string(), reverse_key.to_string(), name));
+    }
+}
+
+/// Name of a pair expanded from a wildcard row
+///
+/// `{forward}` and `{reverse}` in the name column are replaced by the pattern
+/// names; without them the names of the wildcard columns are appended.
+fn expanded_pair_name(name: &str, forward: (&str, &str), reverse: (&str, &str)) -> String {
+    if name.contains("{forward}") || name.contains("{reverse}") {
+        return name.replace("{forward}", forward.1).replace("{reverse}", reverse.1);
+    }
+    let mut pair_name = name.to_string();
+    for (key, pattern_name) in [forward, reverse] {
+        if key.contains(['*', '?']) {
+            pair_name.push('_');
+            pair_name.push_str(pattern_name);
+        }
+    }
+    pair_name
 }
 
+/// Class of fusion patterns listed without one
+pub const DEFAULT_FUSION_CLASS: &str = "fusion";
+
+/// Suffix of the fusion pattern names holding reverse complements
+const REVERSE_FUSION_SUFFIX: &str = "_rc";
+
 /// Fusion database structure
 #[derive(Debug, Clone)]
 pub struct FusionDatabase {
-    pub fusion_patterns: HashMap<String, String>,
+    pub fusion_patterns: PatternSet,
+    /// Class of each fusion pattern, from the optional second column of the fusion file
+    pub classes: HashMap<String, String>,
+    /// Names under which reverse complements of the listed patterns are searched
+    pub reverse_patterns: HashSet<String>,
+}
+
+impl Default for FusionDatabase {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl FusionDatabase {
     /// Create new fusion database
     pub fn new() -> Self {
         Self {
-            fusion_patterns: HashMap::new(),
+            fusion_patterns: PatternSet::new(),
+            classes: HashMap::new(),
+            reverse_patterns: HashSet::new(),
+        }
+    }
+    
+    /// Listed fusion pattern and strand, `fs` or `rs`, of a matched pattern name
+    pub fn pattern_strand<'a>(&self, matched_name: &'a str) -> (&'a str, &'static str) {
+        match matched_name.strip_suffix(REVERSE_FUSION_SUFFIX) {
+            Some(pattern_name) if self.reverse_patterns.contains(matched_name) => (pattern_name, "rs"),
+            _ => (matched_name, "fs"),
         }
     }
     
+    /// Class of a fusion pattern
+    pub fn class_of(&self, pattern_name: &str) -> &str {
+        self.classes.get(pattern_name).map_or(DEFAULT_FUSION_CLASS, String::as_str)
+    }
+    
     /// Check if database is empty
     pub fn is_empty(&self) -> bool {
         self.fusion_patterns.is_empty()
     }
     
     /// Load fusion pattern data
-    pub fn load_fusion_patterns(&mut self, database_file: &str, fusion_file: &str) {
-        let pattern_database = self.load_database(database_file);
+    pub fn load_fusion_patterns(&mut self, database_file: &str, database_format: &str, fusion_file: &str) {
+        let pattern_database = self.load_database(database_file, database_format);
         self.load_fusion_file(fusion_file, pattern_database);
     }
     
     /// Load database file
-    fn load_database(&self, file_path: &str) -> HashMap<String, String> {
-        let mut pattern_database = HashMap::new();
-        let mut reader = csv::ReaderBuilder::new()
-            .has_headers(false)
-            .delimiter(b'\t')
-            .from_path(file_path)
-            .expect(&format!("Unable to find database file: {}", file_path));
-            
-        for result in reader.records() {
-            let record = result.expect("Failed to parse database record");
-            let name = &record[0];
-            let sequence = &record[1];
-            pattern_database.insert(name.to_string(), sequence.to_string());
-        }
-        
-        pattern_database
+    fn load_database(&self, file_path: &str, database_format: &str) -> HashMap<String, DatabaseEntry> {
+        let content = std::fs::read(file_path)
+            .unwrap_or_else(|_| panic!("Unable to find database file: {}", file_path));
+        let database_format = resolve_database_format(file_path, database_format, &content);
+        read_database_records(content.as_slice(), file_path, database_format, false, false)
     }
     
-    /// Load fusion file
-    fn load_fusion_file(&mut self, file_path: &str, pattern_database: HashMap<String, String>) {
+    /// Load fusion file of pattern names with an optional class column, e.g. `chimera`, `spike-in` or `vector`
+    ///
+    /// Each pattern is also searched reverse complemented, unless it is its own reverse complement.
+    fn load_fusion_file(&mut self, file_path: &str, pattern_database: HashMap<String, DatabaseEntry>) {
         let mut reader = csv::ReaderBuilder::new()
             .has_headers(true)
+            .flexible(true)
+            .trim(csv::Trim::All)
             .delimiter(b'\t')
             .from_path(file_path)
-            .expect(&format!("Unable to find fusion file: {}", file_path));
+            .unwrap_or_else(|_| panic!("Unable to find fusion file: {}", file_path));
             
         for result in reader.records() {
             let record = result.expect("Failed to parse fusion file record");
             let fusion_pattern = record[0].to_string();
-            let fusion_sequence = pattern_database
+            let (fusion_sequence, distance_limit) = pattern_database
                 .get(&fusion_pattern)
-                .expect(&format!("Fusion pattern not found in database: {}", fusion_pattern))
-                .to_string();
+                .unwrap_or_else(|| panic!("Fusion pattern not found in database: {}", fusion_pattern))
+                .clone();
+            if let Some(fusion_class) = record.get(1).filter(|fusion_class| !fusion_class.is_empty()) {
+                self.classes.insert(fusion_pattern.clone(), fusion_class.to_string());
+            }
+            let reverse_sequence = reverse_complement(&fusion_sequence, false);
+            if reverse_sequence != fusion_sequence {
+                let reverse_pattern = format!("{}{}", fusion_pattern, REVERSE_FUSION_SUFFIX);
+                self.fusion_patterns.set_distance_limit(reverse_pattern.clone(), distance_limit);
+                self.fusion_patterns.insert(reverse_pattern.clone(), reverse_sequence);
+                self.reverse_patterns.insert(reverse_pattern);
+            }
+            self.fusion_patterns.set_distance_limit(fusion_pattern.clone(), distance_limit);
             self.fusion_patterns.insert(fusion_pattern, fusion_sequence);
         }
     }
 }
 
+/// Load sample sheet mapping pattern names or types to sample IDs
+///
+/// An optional third column gives the expected relative read share of the
+/// sample, 1 when left out.
+fn load_sample_sheet(file_path: &str) -> (HashMap<String, String>, HashMap<String, f64>) {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .comment(Some(b'#'))
+        .delimiter(b'\t')
+        .from_path(file_path)
+        .unwrap_or_else(|_| panic!("Unable to find sample sheet: {}", file_path));
+    
+    let mut sample_sheet = HashMap::new();
+    let mut sample_weights = HashMap::new();
+    for result in reader.records() {
+        let record = result.expect("Failed to parse sample sheet record");
+        if record.len() < 2 {
+            panic!("Sample sheet record needs a pattern and a sample: {}", record.iter().collect::<Vec<_>>().join("\t"));
+        }
+        if let Some(previous) = sample_sheet.insert(record[0].to_string(), record[1].to_string()) {
+            panic!("Duplicate sample sheet entry: {} ({} and {})", &record[0], previous, &record[1]);
+        }
+        let weight = match record.get(2) {
+            Some(weight) => weight.parse::<f64>().ok().filter(|weight| *weight >= 0.0)
+                .unwrap_or_else(|| panic!("Invalid expected share in sample sheet: {} ({})", weight, &record[0])),
+            None => 1.0,
+        };
+        sample_weights.insert(record[1].to_string(), weight);
+    }
+    
+    info!("Sample sheet loaded successfully: {} ({} samples)", file_path, sample_sheet.len());
+    (sample_sheet, sample_weights)
+}
+
+/// Load the expected pattern names, one per line with `#` comments
+fn load_expected_barcodes(file_path: &str) -> HashSet<String> {
+    let content = std::fs::read_to_string(file_path)
+        .unwrap_or_else(|_| panic!("Unable to find expected barcode list: {}", file_path));
+    let expected_barcodes: HashSet<String> = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect();
+    if expected_barcodes.is_empty() {
+        panic!("Expected barcode list is empty: {}", file_path);
+    }
+    
+    info!("Expected barcodes loaded successfully: {} ({} names)", file_path, expected_barcodes.len());
+    expected_barcodes
+}
+
 /// Load pattern configuration
 pub fn load_patterns(args: &Args) -> PatternConfiguration {
     info!("Loading pattern database file: {}", args.get_pattern_db_file());
@@ -296,21 +982,78 @@ pub fn load_patterns(args: &Args) -> PatternConfiguration {
     if args.is_fusion_detection_enabled() {
         pattern_config.fusion_database.load_fusion_patterns(
             &args.get_pattern_db_file(), 
+            &args.database_format,
             &args.fusion_file
         );
     }
     
+    // Load sample sheet
+    if let Some(sample_sheet_file) = &args.sample_sheet {
+        (pattern_config.sample_sheet, pattern_config.sample_weights) = load_sample_sheet(sample_sheet_file);
+    }
+    
+    // Load expected barcodes
+    if let Some(expected_barcodes_file) = &args.expected_barcodes {
+        pattern_config.expected_barcodes = load_expected_barcodes(expected_barcodes_file);
+    }
+    
+    if !pattern_config.disabled_patterns.is_empty() {
+        info!("Disabled patterns: {}", pattern_config.disabled_patterns.join(","));
+    }
+    
+    // Adapters are searched as a single round without pairing
+    if args.trim_only {
+        let mut pattern_database = PatternDatabase::new();
+        pattern_database.load_adapters(
+            &args.get_pattern_db_file(),
+            &args.database_format,
+            &pattern_config.disabled_patterns,
+            args.strict_bases,
+            args.duplicate_patterns == "fail",
+        );
+        let (pattern_error_rate, max_distance, position_shift) = pattern_config.round_parameters(0);
+        pattern_config.pattern_arguments.push(PatternArgument {
+            pattern_database,
+            use_position_info: false,
+            pattern_error_rate,
+            max_edits: pattern_config.round_max_edits(0),
+            effective_length: EffectiveLength::from_name(&args.effective_length),
+            max_distance,
+            position_shift,
+            early_exit_score: args.early_exit_score,
+            anchor: args.anchor,
+            dual_policy: args.dual_policy.clone(),
+            min_score_margin: 0,
+            fixed_position: None,
+        });
+    }
+    
     // Load pattern files
-    for pattern_file in args.get_pattern_files() {
+    for (round, pattern_file) in args.get_pattern_files().into_iter().enumerate() {
         let mut pattern_database = PatternDatabase::new();
-        pattern_database.load_patterns(&args.get_pattern_db_file(), &pattern_file);
+        pattern_database.load_patterns(
+            &args.get_pattern_db_file(),
+            &args.database_format,
+            &pattern_file,
+            &pattern_config.disabled_patterns,
+            args.strict_bases,
+            args.duplicate_patterns == "fail",
+        );
         
+        let (pattern_error_rate, max_distance, position_shift) = pattern_config.round_parameters(round);
         let pattern_argument = PatternArgument {
             pattern_database,
             use_position_info: args.use_position_info,
-            pattern_error_rate: pattern_config.pattern_error_rates[0],
-            max_distance: pattern_config.max_distances[0],
-            position_shift: pattern_config.position_shifts[0],
+            pattern_error_rate,
+            max_edits: pattern_config.round_max_edits(round),
+            effective_length: EffectiveLength::from_name(&args.effective_length),
+            max_distance,
+            position_shift,
+            early_exit_score: args.early_exit_score,
+            anchor: args.anchor,
+            dual_policy: args.dual_policy.clone(),
+            min_score_margin: args.min_score_margin,
+            fixed_position: args.fixed_position.get(round).copied().flatten(),
         };
         pattern_config.pattern_arguments.push(pattern_argument);
     }
@@ -331,4 +1074,99 @@ mod tests {
     fn test_pattern_database_loading() {
         // Test code can be added here
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_database_records_are_normalized() {
+        let content = "BC01 \t acgtn\r\nBC02\tAC-GT\t2\n";
+        let records = read_database_records(content.as_bytes(), "test.db", "tsv", false, false);
+        assert_eq!(records["BC01"].0, "ACGTN");
+        assert_eq!(records["BC02"].0, "ACNGT");
+        assert!(records["BC02"].1.is_some());
+    }
+    
+    #[test]
+    fn test_duplicate_database_names_keep_the_first_record() {
+        let content = "BC01\tACGT\nBC02\tTTTT\nBC01\tGGGG\n";
+        let records = read_database_records(content.as_bytes(), "test.db", "tsv", false, false);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records["BC01"].0, "ACGT");
+        let fasta = ">BC01\nACGT\n>BC01\nGGGG\n";
+        assert_eq!(read_database_records(fasta.as_bytes(), "test.fa", "fasta", false, false)["BC01"].0, "ACGT");
+        let strict = std::panic::catch_unwind(|| read_database_records(content.as_bytes(), "test.db", "tsv", false, true));
+        assert!(strict.is_err());
+    }
+    
+    #[test]
+    fn test_database_formats_are_detected() {
+        let content = ">BC01 native barcode 1\nAAGAAAGTTG\ntcggtg\n>BC02\nTCGATTCCGT\n";
+        let database_format = resolve_database_format("kit.txt", "auto", content.as_bytes());
+        assert_eq!(database_format, "fasta");
+        let records = read_database_records(content.as_bytes(), "kit.txt", database_format, false, false);
+        assert_eq!(records["BC01"].0, "AAGAAAGTTGTCGGTG");
+        assert_eq!(records["BC02"].0, "TCGATTCCGT");
+        
+        let content = "BC01,ACGT\nBC02,TGCA,0.1\n";
+        let database_format = resolve_database_format("barcodes.csv", "auto", content.as_bytes());
+        assert_eq!(database_format, "csv");
+        let records = read_database_records(content.as_bytes(), "barcodes.csv", database_format, false, false);
+        assert_eq!(records["BC02"].0, "TGCA");
+        assert!(records["BC02"].1.is_some());
+        assert_eq!(resolve_database_format("barcodes.db", "auto", b"BC01\tACGT\n"), "tsv");
+    }
+    
+    #[test]
+    fn test_pattern_set_orders_by_hits() {
+        let mut pattern_set = PatternSet::new();
+        pattern_set.insert("BC01".to_string(), "ACGT".to_string());
+        pattern_set.insert("BC02".to_string(), "TGCA".to_string());
+        assert_eq!(&*pattern_set.ordered_names(), ["BC01", "BC02"]);
+        
+        for _ in 0..REORDER_INTERVAL {
+            pattern_set.record_hit("BC02");
+        }
+        assert_eq!(&*pattern_set.ordered_names(), ["BC02", "BC01"]);
+    }
+    
+    #[test]
+    fn test_round_parameters_per_round() {
+        let config = PatternConfiguration {
+            pattern_error_rates: vec![(0.3, 0.3), (0.1, 0.1)],
+            max_distances: vec![6, 2],
+            position_shifts: vec![3],
+            ..PatternConfiguration::default()
+        };
+        assert_eq!(config.round_parameters(0), ((0.3, 0.3), 6, 3));
+        assert_eq!(config.round_parameters(1), ((0.1, 0.1), 2, 3));
+        assert_eq!(config.round_parameters(7), ((0.1, 0.1), 2, 3));
+    }
+    
+    #[test]
+    fn test_kept_patterns_default_to_trim_mode() {
+        let trim_all = PatternConfiguration::default();
+        assert_eq!(trim_all.kept_patterns(), (false, false));
+        let keep_round = PatternConfiguration { trim_mode: 2, ..PatternConfiguration::default() };
+        assert_eq!(keep_round.kept_patterns(), (true, true));
+        let per_round = PatternConfiguration {
+            trim_mode: 2,
+            keep_pattern: vec!["both".to_string(), "left".to_string()],
+            ..PatternConfiguration::default()
+        };
+        assert_eq!(per_round.kept_patterns(), (true, false));
+    }
+    
+    #[test]
+    fn test_wildcard_rows_expand_to_pairs() {
+        let pattern_file = std::env::temp_dir().join(format!("readchop_wildcard_{}.list", std::process::id()));
+        std::fs::write(&pattern_file, "#index_F\tindex_R\ttype\nBC0*\tBC01\tplate\n*\t*\t{forward}-{reverse}\n").unwrap();
+        let mut pattern_database = PatternDatabase::new();
+        pattern_database.load_patterns("example/ont_bc_pattern.db", "auto", pattern_file.to_str().unwrap(), &[], false, false);
+        std::fs::remove_file(&pattern_file).unwrap();
+        
+        let first_row = &pattern_database.pattern_pairs[0];
+        assert_eq!(first_row, &("BC01".to_string(), "BC01".to_string(), "plate_BC01".to_string()));
+        assert_eq!(pattern_database.pattern_types["BC02_BC03"].1, "BC02-BC03");
+        // Pairs already listed in either orientation are not added again
+        assert_eq!(pattern_database.pattern_types["BC01_BC05"].1, "plate_BC05");
+        assert_eq!(pattern_database.pattern_pairs.len(), 9 + 46);
+    }
+}
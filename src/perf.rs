@@ -0,0 +1,340 @@
+use log::info;
+use std::cell::OnceCell;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Whether counters are collected, off unless `--perf-report` is given
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Counters of every thread that recorded any, in registration order
+static THREADS: Mutex<Vec<(String, Arc<ThreadMetrics>)>> = Mutex::new(Vec::new());
+
+thread_local! {
+    static METRICS: OnceCell<Arc<ThreadMetrics>> = const { OnceCell::new() };
+}
+
+/// Counters of one thread, only ever updated by that thread
+#[derive(Default)]
+struct ThreadMetrics {
+    /// Splitting tasks run
+    split_tasks: AtomicU64,
+    /// Time spent in splitting tasks, including queueing results
+    split_nanos: AtomicU64,
+    /// Input reads classified
+    reads: AtomicU64,
+    /// Time spent matching patterns, fusion detection included
+    match_nanos: AtomicU64,
+    /// Single pattern alignments requested
+    alignments: AtomicU64,
+    /// Alignments answered by an exact hit without running Myers
+    exact_hits: AtomicU64,
+    /// Alignments that ran the Myers DP
+    myers_calls: AtomicU64,
+    /// Packed multi-pattern Myers scans
+    packed_scans: AtomicU64,
+    /// Window searches looked up in the score cache
+    cache_lookups: AtomicU64,
+    /// Window searches answered by the score cache
+    cache_hits: AtomicU64,
+    /// Writing tasks run
+    write_tasks: AtomicU64,
+    /// Time spent in writing tasks, compression included
+    write_nanos: AtomicU64,
+}
+
+/// Start collecting counters
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Whether counters are being collected
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Update the counters of the current thread, registering them on first use
+fn record(update: impl FnOnce(&ThreadMetrics)) {
+    if !is_enabled() {
+        return;
+    }
+    METRICS.with(|metrics| {
+        let metrics = metrics.get_or_init(|| {
+            let metrics = Arc::new(ThreadMetrics::default());
+            let name = std::thread::current().name().unwrap_or("unnamed").to_string();
+            THREADS.lock().unwrap().push((name, Arc::clone(&metrics)));
+            metrics
+        });
+        update(metrics);
+    });
+}
+
+/// Start timing, None when counters are off
+pub fn start() -> Option<Instant> {
+    is_enabled().then(Instant::now)
+}
+
+/// Nanoseconds since `timer` started
+fn elapsed_nanos(timer: Instant) -> u64 {
+    timer.elapsed().as_nanos() as u64
+}
+
+/// Record a finished splitting task
+pub fn record_split_task(timer: Option<Instant>) {
+    if let Some(timer) = timer {
+        record(|metrics| {
+            metrics.split_tasks.fetch_add(1, Ordering::Relaxed);
+            metrics.split_nanos.fetch_add(elapsed_nanos(timer), Ordering::Relaxed);
+        });
+    }
+}
+
+/// Record a finished writing task
+pub fn record_write_task(timer: Option<Instant>) {
+    if let Some(timer) = timer {
+        record(|metrics| {
+            metrics.write_tasks.fetch_add(1, Ordering::Relaxed);
+            metrics.write_nanos.fetch_add(elapsed_nanos(timer), Ordering::Relaxed);
+        });
+    }
+}
+
+/// Record one classified input read
+pub fn record_match(timer: Option<Instant>) {
+    if let Some(timer) = timer {
+        record(|metrics| {
+            metrics.reads.fetch_add(1, Ordering::Relaxed);
+            metrics.match_nanos.fetch_add(elapsed_nanos(timer), Ordering::Relaxed);
+        });
+    }
+}
+
+/// Record one pattern alignment, `exact` when the Myers DP was skipped
+pub fn record_alignment(exact: bool) {
+    record(|metrics| {
+        metrics.alignments.fetch_add(1, Ordering::Relaxed);
+        if exact {
+            metrics.exact_hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            metrics.myers_calls.fetch_add(1, Ordering::Relaxed);
+        }
+    });
+}
+
+/// Record one packed multi-pattern scan
+pub fn record_packed_scan() {
+    record(|metrics| {
+        metrics.packed_scans.fetch_add(1, Ordering::Relaxed);
+    });
+}
+
+/// Record one score cache lookup, if the cache was consulted
+pub fn record_cache_lookup(looked_up: bool, hit: bool) {
+    if !looked_up {
+        return;
+    }
+    record(|metrics| {
+        metrics.cache_lookups.fetch_add(1, Ordering::Relaxed);
+        if hit {
+            metrics.cache_hits.fetch_add(1, Ordering::Relaxed);
+        }
+    });
+}
+
+/// Counter values of one thread, or of all threads summed
+#[derive(Default)]
+struct MetricsSnapshot {
+    split_tasks: u64,
+    split_nanos: u64,
+    reads: u64,
+    match_nanos: u64,
+    alignments: u64,
+    exact_hits: u64,
+    myers_calls: u64,
+    packed_scans: u64,
+    cache_lookups: u64,
+    cache_hits: u64,
+    write_tasks: u64,
+    write_nanos: u64,
+}
+
+impl MetricsSnapshot {
+    /// Current values of a thread's counters
+    fn of(metrics: &ThreadMetrics) -> Self {
+        Self {
+            split_tasks: metrics.split_tasks.load(Ordering::Relaxed),
+            split_nanos: metrics.split_nanos.load(Ordering::Relaxed),
+            reads: metrics.reads.load(Ordering::Relaxed),
+            match_nanos: metrics.match_nanos.load(Ordering::Relaxed),
+            alignments: metrics.alignments.load(Ordering::Relaxed),
+            exact_hits: metrics.exact_hits.load(Ordering::Relaxed),
+            myers_calls: metrics.myers_calls.load(Ordering::Relaxed),
+            packed_scans: metrics.packed_scans.load(Ordering::Relaxed),
+            cache_lookups: metrics.cache_lookups.load(Ordering::Relaxed),
+            cache_hits: metrics.cache_hits.load(Ordering::Relaxed),
+            write_tasks: metrics.write_tasks.load(Ordering::Relaxed),
+            write_nanos: metrics.write_nanos.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Add another thread's values
+    fn add(&mut self, other: &Self) {
+        self.split_tasks += other.split_tasks;
+        self.split_nanos += other.split_nanos;
+        self.reads += other.reads;
+        self.match_nanos += other.match_nanos;
+        self.alignments += other.alignments;
+        self.exact_hits += other.exact_hits;
+        self.myers_calls += other.myers_calls;
+        self.packed_scans += other.packed_scans;
+        self.cache_lookups += other.cache_lookups;
+        self.cache_hits += other.cache_hits;
+        self.write_tasks += other.write_tasks;
+        self.write_nanos += other.write_nanos;
+    }
+
+    /// Report row for a thread
+    fn row(&self, thread: &str) -> String {
+        let average_match_us = if self.reads > 0 { self.match_nanos as f64 / self.reads as f64 / 1000.0 } else { 0.0 };
+        let exact_hit_rate = if self.alignments > 0 { self.exact_hits as f64 / self.alignments as f64 } else { 0.0 };
+        format!(
+            "{}\t{}\t{:.3}\t{}\t{:.3}\t{:.2}\t{}\t{}\t{}\t{:.4}\t{}\t{}\t{}\t{}\t{:.3}",
+            thread,
+            self.split_tasks,
+            self.split_nanos as f64 / 1e9,
+            self.reads,
+            self.match_nanos as f64 / 1e9,
+            average_match_us,
+            self.alignments,
+            self.exact_hits,
+            self.myers_calls,
+            exact_hit_rate,
+            self.packed_scans,
+            self.cache_lookups,
+            self.cache_hits,
+            self.write_tasks,
+            self.write_nanos as f64 / 1e9,
+        )
+    }
+}
+
+/// Interval between channel depth samples
+const CHANNEL_SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Mean depth in reads above which a channel is reported as congested
+const CONGESTED_DEPTH: f64 = 1024.0;
+
+/// Pipeline channels whose depth is sampled, in pipeline order
+pub const CHANNELS: [&str; 3] = ["reader_to_splitter", "splitter_to_writer", "writer_queues"];
+
+/// Depth samples of one channel
+#[derive(Default, Clone, Copy)]
+struct ChannelDepths {
+    samples: u64,
+    total: u64,
+    peak: usize,
+    last: usize,
+}
+
+/// Reads waiting between pipeline stages, sampled by the main loop
+///
+/// A backlog before the splitter means splitting cannot keep up with reading,
+/// one before the writer that the main loop falls behind, and one in the
+/// writer queues that compression is the bottleneck.
+pub struct ChannelMonitor {
+    depths: [ChannelDepths; CHANNELS.len()],
+    last_sample: Option<Instant>,
+}
+
+impl Default for ChannelMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChannelMonitor {
+    pub fn new() -> Self {
+        Self { depths: [ChannelDepths::default(); CHANNELS.len()], last_sample: None }
+    }
+
+    /// Whether the next sample is due
+    pub fn is_due(&self) -> bool {
+        self.last_sample.is_none_or(|last_sample| last_sample.elapsed() >= CHANNEL_SAMPLE_INTERVAL)
+    }
+
+    /// Record the current depth of each channel, in `CHANNELS` order
+    pub fn sample(&mut self, depths: [usize; CHANNELS.len()]) {
+        self.last_sample = Some(Instant::now());
+        for (channel, depth) in self.depths.iter_mut().zip(depths) {
+            channel.samples += 1;
+            channel.total += depth as u64;
+            channel.peak = channel.peak.max(depth);
+            channel.last = depth;
+        }
+    }
+
+    /// Latest depths for the progress log
+    pub fn summary(&self) -> String {
+        CHANNELS.iter()
+            .zip(&self.depths)
+            .map(|(name, channel)| format!("{} {} (peak {})", name, channel.last, channel.peak))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Channel with the largest mean depth, if any is above the congestion threshold
+    fn congested_channel(&self) -> Option<(&'static str, f64)> {
+        CHANNELS.iter()
+            .zip(&self.depths)
+            .map(|(name, channel)| (*name, mean_depth(channel)))
+            .filter(|(_, mean)| *mean >= CONGESTED_DEPTH)
+            .max_by(|left, right| left.1.total_cmp(&right.1))
+    }
+}
+
+/// Mean sampled depth of a channel
+fn mean_depth(channel: &ChannelDepths) -> f64 {
+    if channel.samples > 0 { channel.total as f64 / channel.samples as f64 } else { 0.0 }
+}
+
+/// Write per-thread counters with a total row, and log where the time went
+///
+/// Channel depths follow the thread table as a second table after a blank line.
+/// Call once the writing tasks have finished so their time is included.
+pub fn write_perf_report(path: &str, channels: &ChannelMonitor) {
+    let file = File::create(path).unwrap_or_else(|_| panic!("Unable to create performance report: {}", path));
+    let mut writer = BufWriter::new(file);
+    writeln!(
+        writer,
+        "thread\tsplit_tasks\tsplit_seconds\treads\tmatch_seconds\tavg_match_us\talignments\texact_hits\tmyers_calls\texact_hit_rate\tpacked_scans\tcache_lookups\tcache_hits\twrite_tasks\twrite_seconds"
+    ).expect("Failed to write performance report");
+
+    let mut total = MetricsSnapshot::default();
+    for (name, metrics) in THREADS.lock().unwrap().iter() {
+        let snapshot = MetricsSnapshot::of(metrics);
+        writeln!(writer, "{}", snapshot.row(name)).expect("Failed to write performance report");
+        total.add(&snapshot);
+    }
+    writeln!(writer, "{}", total.row("total")).expect("Failed to write performance report");
+
+    writeln!(writer, "\nchannel\tsamples\tmean_depth\tpeak_depth").expect("Failed to write performance report");
+    for (name, channel) in CHANNELS.iter().zip(&channels.depths) {
+        writeln!(writer, "{}\t{}\t{:.1}\t{}", name, channel.samples, mean_depth(channel), channel.peak)
+            .expect("Failed to write performance report");
+    }
+    writer.flush().expect("Failed to write performance report");
+
+    info!(
+        "Thread time: splitting {:.3}s ({:.3}s matching), writing and compression {:.3}s; performance report written to {}",
+        total.split_nanos as f64 / 1e9,
+        total.match_nanos as f64 / 1e9,
+        total.write_nanos as f64 / 1e9,
+        path
+    );
+    match channels.congested_channel() {
+        Some((name, mean)) => info!("Pipeline congestion: {} reads queued on average in {}", mean.round(), name),
+        None => info!("Pipeline congestion: no channel held more than {} reads on average", CONGESTED_DEPTH),
+    }
+}
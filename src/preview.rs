@@ -0,0 +1,68 @@
+use crate::args::Args;
+use crate::fastq::{is_stream_input, read_first_records};
+use crate::pattern::PatternConfiguration;
+use crate::splitter::classify_read;
+use log::info;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+/// Classify the first reads and report the projected per-sample distribution
+///
+/// Returns whether the full run should continue.
+pub fn run_preview(args: &Args, pattern_config: &PatternConfiguration) -> bool {
+    if args.inputs.is_empty() {
+        panic!("--preview requires input files, standard input cannot be read twice");
+    }
+    if let Some(input) = args.inputs.iter().find(|input| is_stream_input(Path::new(input))) {
+        panic!("--preview reads the input twice, so it cannot read the pipe {}", input);
+    }
+    
+    info!("Previewing the first {} reads before the full run", args.preview);
+    let read_infos = read_first_records(&args.inputs, args.preview, args.interleaved);
+    
+    // Valid reads are keyed by output file, everything else by sequence type
+    let mut distribution: HashMap<String, usize> = HashMap::new();
+    let mut classified_count = 0;
+    for read_info in read_infos {
+        for classified_read in classify_read(read_info, pattern_config) {
+            let key = if classified_read.should_write_to_fastq {
+                classified_read.output_filename
+            } else {
+                classified_read.sequence_type
+            };
+            *distribution.entry(key).or_insert(0) += 1;
+            classified_count += 1;
+        }
+    }
+    
+    print_distribution(&distribution, classified_count);
+    
+    args.yes || confirm("Continue with the full run? [y/N] ")
+}
+
+/// Print projected distribution, largest groups first
+fn print_distribution(distribution: &HashMap<String, usize>, total_reads: usize) {
+    let mut entries: Vec<(&String, &usize)> = distribution.iter().collect();
+    entries.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    
+    info!("Projected distribution over {} previewed reads:", total_reads);
+    for (name, count) in entries {
+        let fraction = if total_reads > 0 {
+            100.0 * *count as f64 / total_reads as f64
+        } else {
+            0.0
+        };
+        info!("  {}\t{}\t{:.2}%", name, count, fraction);
+    }
+}
+
+/// Ask for confirmation on the terminal
+fn confirm(prompt: &str) -> bool {
+    eprint!("{}", prompt);
+    std::io::stderr().flush().expect("Failed to flush prompt");
+    
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).expect("Failed to read confirmation");
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
@@ -0,0 +1,114 @@
+use crate::utils::wildcard_match;
+use log::info;
+use std::io::{self, Read};
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+/// URL schemes read through a download command instead of the file system
+const REMOTE_SCHEMES: [&str; 3] = ["s3://", "http://", "https://"];
+
+/// Check if an input names an object in S3 or on a web server
+pub fn is_remote(input: &str) -> bool {
+    REMOTE_SCHEMES.iter().any(|scheme| input.starts_with(scheme))
+}
+
+/// Objects below an `s3://` prefix whose name matches the glob in the last component
+///
+/// Listing uses `aws s3 ls`, so credentials and endpoints come from the usual
+/// AWS CLI configuration; HTTP(S) URLs cannot be listed and are kept as given.
+pub fn expand_remote_glob(input: &str) -> Vec<String> {
+    if !input.contains(['*', '?']) {
+        return vec![input.to_string()];
+    }
+    if !input.starts_with("s3://") {
+        panic!("Globs are only supported for s3:// inputs: {}", input);
+    }
+    let (prefix, pattern) = input.rsplit_once('/').expect("S3 URL has a path");
+    if prefix.contains(['*', '?']) {
+        panic!("S3 globs may only use wildcards in the object name: {}", input);
+    }
+
+    let output = Command::new("aws")
+        .args(["s3", "ls", &format!("{}/", prefix)])
+        .stderr(Stdio::inherit())
+        .output()
+        .unwrap_or_else(|error| panic!("Unable to run aws to list {}: {}", input, error));
+    if !output.status.success() {
+        panic!("Listing {} failed: aws exited with {}", input, output.status);
+    }
+
+    // Object lines are `date time size name`, prefixes are `PRE name/`
+    let listing = String::from_utf8_lossy(&output.stdout);
+    let mut objects: Vec<String> = listing
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let name = fields.get(3..).filter(|_| fields[0] != "PRE")?.join(" ");
+            wildcard_match(pattern, &name).then(|| format!("{}/{}", prefix, name))
+        })
+        .collect();
+    objects.sort();
+    objects
+}
+
+/// Stream of a remote object, downloaded by a child process as it is read
+///
+/// `https://` and `http://` URLs are fetched with `curl`, `s3://` URLs with
+/// `aws s3 cp`. A download that fails part way surfaces as a read error
+/// instead of silently truncating the input.
+pub struct RemoteReader {
+    url: String,
+    child: Child,
+    stdout: ChildStdout,
+    finished: bool,
+}
+
+impl RemoteReader {
+    /// Start downloading `url`
+    pub fn open(url: &str) -> Self {
+        let mut command = if url.starts_with("s3://") {
+            let mut command = Command::new("aws");
+            command.args(["s3", "cp", "--only-show-errors", url, "-"]);
+            command
+        } else {
+            let mut command = Command::new("curl");
+            command.args(["--silent", "--show-error", "--fail", "--location", "--retry", "3", url]);
+            command
+        };
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .unwrap_or_else(|error| panic!("Unable to start download of {}: {}", url, error));
+        let stdout = child.stdout.take().expect("Download output is piped");
+        info!("Streaming remote input: {}", url);
+        Self { url: url.to_string(), child, stdout, finished: false }
+    }
+}
+
+impl Read for RemoteReader {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        if self.finished {
+            return Ok(0);
+        }
+        let read = self.stdout.read(buffer)?;
+        if read == 0 && !buffer.is_empty() {
+            self.finished = true;
+            let status = self.child.wait()?;
+            if !status.success() {
+                return Err(io::Error::other(format!("Download of {} failed: {}", self.url, status)));
+            }
+        }
+        Ok(read)
+    }
+}
+
+impl Drop for RemoteReader {
+    fn drop(&mut self) {
+        // Reading may stop early, e.g. with --head
+        if !self.finished {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
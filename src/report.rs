@@ -0,0 +1,271 @@
+use crate::args::Args;
+use crate::pattern::PatternConfiguration;
+use crate::utils::{available_cpus, format_utc_timestamp, json_string};
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Name of the run metadata file in the output directory
+const RUN_INFO_FILE: &str = "run_info.json";
+
+/// Write `run_info.json` describing how and where the run was made
+///
+/// Records the command line, tool version, timestamps, host, input files with
+/// their SHA-256 checksums and the configuration as resolved for each round.
+pub fn write_run_info(args: &Args, output_directory: &str, pattern_config: &PatternConfiguration, inputs: &[String], started_at: SystemTime) {
+    let finished_at = SystemTime::now();
+    let elapsed = finished_at.duration_since(started_at).map(|duration| duration.as_secs_f64()).unwrap_or(0.0);
+
+    let input_entries: Vec<String> = inputs.iter().map(|input| input_entry(input)).collect();
+    let command: Vec<String> = std::env::args().map(|argument| json_string(&argument)).collect();
+
+    let fields = [
+        ("tool", json_string(env!("CARGO_PKG_NAME"))),
+        ("version", json_string(env!("CARGO_PKG_VERSION"))),
+        ("command", format!("[{}]", command.join(", "))),
+        ("started_at", json_string(&format_utc_timestamp(started_at))),
+        ("finished_at", json_string(&format_utc_timestamp(finished_at))),
+        ("elapsed_seconds", format!("{:.3}", elapsed)),
+        ("interrupted", crate::interrupt::is_interrupted().to_string()),
+        ("host", host_info()),
+        ("inputs", json_array(&input_entries, 1)),
+        ("configuration", configuration(args, pattern_config)),
+    ];
+
+    let path = Path::new(output_directory).join(RUN_INFO_FILE);
+    let mut file = File::create(&path).expect("Failed to create run info file");
+    writeln!(file, "{}", json_object(&fields, 0)).expect("Failed to write run info file");
+    info!("Run information written to {}", path.display());
+}
+
+/// Write `merge_info.json` listing the merged runs with their own run metadata
+///
+/// Directories without a `run_info.json` are listed with a null `run_info`.
+pub fn write_merge_info(output_directory: &str, directories: &[String]) {
+    let runs: Vec<String> = directories
+        .iter()
+        .map(|directory| {
+            let run_info = std::fs::read_to_string(Path::new(directory).join(RUN_INFO_FILE))
+                .map(|run_info| run_info.trim_end().replace('\n', "\n      "))
+                .unwrap_or_else(|_| {
+                    warn!("No {} in {}", RUN_INFO_FILE, directory);
+                    "null".to_string()
+                });
+            json_object(&[("directory", json_string(directory)), ("run_info", run_info)], 2)
+        })
+        .collect();
+    let fields = [
+        ("tool", json_string(env!("CARGO_PKG_NAME"))),
+        ("version", json_string(env!("CARGO_PKG_VERSION"))),
+        ("command", format!("[{}]", std::env::args().map(|argument| json_string(&argument)).collect::<Vec<String>>().join(", "))),
+        ("merged_at", json_string(&format_utc_timestamp(SystemTime::now()))),
+        ("runs", json_array(&runs, 1)),
+    ];
+
+    let path = Path::new(output_directory).join("merge_info.json");
+    let mut file = File::create(&path).expect("Failed to create merge info file");
+    writeln!(file, "{}", json_object(&fields, 0)).expect("Failed to write merge info file");
+    info!("Merge information written to {}", path.display());
+}
+
+/// Input path, size and checksum
+fn input_entry(input: &str) -> String {
+    // Remote objects are streamed once, so they are not read again for a checksum
+    if crate::remote::is_remote(input) {
+        return json_object(&[("path", json_string(input)), ("size", "null".to_string()), ("sha256", "null".to_string())], 2);
+    }
+    let path = Path::new(input);
+    // Pipes were drained by the run and reopening one would wait for a new writer
+    if crate::fastq::is_stream_input(path) {
+        return json_object(&[("path", json_string(input)), ("size", "null".to_string()), ("sha256", "null".to_string())], 2);
+    }
+    let size = path.metadata().map(|metadata| metadata.len().to_string()).unwrap_or_else(|_| "null".to_string());
+    let checksum = match sha256_file(path) {
+        Ok(checksum) => json_string(&checksum),
+        Err(error) => {
+            warn!("Failed to checksum input file {}: {}", input, error);
+            "null".to_string()
+        }
+    };
+    json_object(&[("path", json_string(input)), ("size", size), ("sha256", checksum)], 2)
+}
+
+/// Hex-encoded SHA-256 of a file's raw bytes
+fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut reader = BufReader::with_capacity(1024 * 1024, File::open(path)?);
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1024 * 1024];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Host name, operating system, architecture and available CPUs
+fn host_info() -> String {
+    let cpus = available_cpus();
+    json_object(
+        &[
+            ("hostname", json_string(&hostname())),
+            ("os", json_string(std::env::consts::OS)),
+            ("arch", json_string(std::env::consts::ARCH)),
+            ("cpus", cpus.to_string()),
+        ],
+        1,
+    )
+}
+
+/// Host name of this machine, empty if unavailable
+fn hostname() -> String {
+    let mut buffer = [0u8; 256];
+    let result = unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) };
+    if result != 0 {
+        return String::new();
+    }
+    let length = buffer.iter().position(|&byte| byte == 0).unwrap_or(buffer.len());
+    String::from_utf8_lossy(&buffer[..length]).into_owned()
+}
+
+/// Configuration after defaults have been applied and per-round vectors normalized
+fn configuration(args: &Args, pattern_config: &PatternConfiguration) -> String {
+    let pattern_files = args.get_pattern_files();
+    let rounds: Vec<String> = pattern_config
+        .pattern_arguments
+        .iter()
+        .enumerate()
+        .map(|(round, pattern_argument)| {
+            json_object(
+                &[
+                    ("pattern_file", json_string(pattern_files.get(round).map(String::as_str).unwrap_or(""))),
+                    ("match", json_string(pattern_config.pattern_match_types.get(round).map(String::as_str).unwrap_or(""))),
+                    ("error_rate", format!("[{}, {}]", pattern_argument.pattern_error_rate.0, pattern_argument.pattern_error_rate.1)),
+                    ("effective_length", json_string(pattern_argument.effective_length.name())),
+                    ("max_edits", pattern_argument.max_edits.map_or("null".to_string(), |max_edits| max_edits.to_string())),
+                    ("max_distance", pattern_argument.max_distance.to_string()),
+                    ("position_shift", pattern_argument.position_shift.to_string()),
+                    ("use_position_info", pattern_argument.use_position_info.to_string()),
+                    ("early_exit_score", pattern_argument.early_exit_score.to_string()),
+                    ("anchor", pattern_argument.anchor.to_string()),
+                    ("fixed_position", pattern_argument.fixed_position.map_or("null".to_string(), |(start, length)| format!("[{}, {}]", start, length))),
+                    ("dual_policy", json_string(&pattern_argument.dual_policy)),
+                    ("min_score_margin", pattern_argument.min_score_margin.to_string()),
+                ],
+                3,
+            )
+        })
+        .collect();
+    let disabled_patterns: Vec<String> = pattern_config.disabled_patterns.iter().map(|name| json_string(name)).collect();
+    let trim_polyx = match pattern_config.trim_polyx {
+        Some((base, min_length)) => json_string(&format!("{},{}", base as char, min_length)),
+        None => "null".to_string(),
+    };
+    let group_by: Vec<String> = args.group_by.iter().map(|field| json_string(field)).collect();
+    let trim_offsets = pattern_config.trim_offsets();
+    let kept_patterns = pattern_config.kept_patterns();
+    let optional = |value: &Option<String>| value.as_deref().map(json_string).unwrap_or_else(|| "null".to_string());
+
+    json_object(
+        &[
+            ("pattern_db", json_string(&args.get_pattern_db_file())),
+            ("db_format", json_string(&args.database_format)),
+            ("kit", optional(&args.kit)),
+            ("auto_tune", args.auto_tune.to_string()),
+            ("score_cache", args.score_cache.to_string()),
+            ("fusion_file", json_string(&args.fusion_file)),
+            ("sample_sheet", optional(&args.sample_sheet)),
+            ("balance_fold", args.balance_fold.to_string()),
+            ("expected_barcodes", optional(&args.expected_barcodes)),
+            ("truth", optional(&args.truth)),
+            ("perf_report", optional(&args.perf_report)),
+            ("outdir", json_string(&args.outdir)),
+            ("force", args.force.to_string()),
+            ("window_size", format!("{:?}", pattern_config.window_size)),
+            ("rounds", json_array(&rounds, 2)),
+            ("trim_mode", pattern_config.trim_mode.to_string()),
+            ("trim_offsets", format!("[{}, {}]", trim_offsets.0, trim_offsets.1)),
+            ("kept_patterns", format!("[{}, {}]", kept_patterns.0, kept_patterns.1)),
+            ("write_type", json_string(&pattern_config.write_type)),
+            ("min_length", pattern_config.min_length.to_string()),
+            ("id_separator", json_string(&pattern_config.id_separator)),
+            ("fusion_error_rate", pattern_config.fusion_error_rate.to_string()),
+            ("fusion_action", json_string(&pattern_config.fusion_action)),
+            ("fusion_class_actions", format!("[{}]", args.fusion_class_actions.iter().map(|(fusion_class, action)| json_string(&format!("{}={}", fusion_class, action))).collect::<Vec<String>>().join(", "))),
+            ("fusion_window", json_string(&pattern_config.fusion_window)),
+            ("fusion_min_distance_from_ends", pattern_config.fusion_min_end_distance.to_string()),
+            ("recursive_split", pattern_config.recursive_split.to_string()),
+            ("trim_only", pattern_config.trim_only.to_string()),
+            ("orient", pattern_config.orient.to_string()),
+            ("disabled_patterns", format!("[{}]", disabled_patterns.join(", "))),
+            ("annotate_score", pattern_config.annotate_score.to_string()),
+            ("qual_trim", pattern_config.qual_trim.to_string()),
+            ("trim_polyx", trim_polyx),
+            ("group_by", format!("[{}]", group_by.join(", "))),
+            ("group_output", args.group_output.to_string()),
+            ("outdir_template", optional(&args.outdir_template)),
+            ("run_id", optional(&args.run_id)),
+            ("run_date", optional(&args.run_date)),
+            ("time_bin", args.time_bin.to_string()),
+            ("dedup", optional(&args.dedup)),
+            ("dedup_length", args.dedup_length.map_or_else(|| "null".to_string(), |length| length.to_string())),
+            ("dedup_action", json_string(&args.dedup_action)),
+            ("strict_bases", args.strict_bases.to_string()),
+            ("duplicate_patterns", json_string(&args.duplicate_patterns)),
+            ("threads", args.threads.to_string()),
+            ("reader_threads", args.reader_threads.to_string()),
+            ("ordered", args.ordered.to_string()),
+            ("thread_strategy", json_string(&args.thread_strategy)),
+            ("decompress_threads", args.decompress_threads.to_string()),
+            ("head", args.head.to_string()),
+            ("sample_fraction", args.sample_fraction.to_string()),
+            ("seed", args.seed.to_string()),
+            ("stdout", args.stdout.to_string()),
+            ("tag_location", json_string(&args.tag_location)),
+            ("pipe", optional(&args.pipe)),
+            ("output_url", optional(&args.output_url)),
+            ("external_compressor", optional(&args.external_compressor)),
+            ("checksums", optional(&args.checksums)),
+            ("index_reads", format!("[{}]", args.index_reads.iter().map(|file| json_string(file)).collect::<Vec<String>>().join(", "))),
+            ("interleaved", args.interleaved.to_string()),
+            ("emit_raw_copy", args.emit_raw_copy.to_string()),
+            ("shard_read_log", args.shard_read_log.to_string()),
+            ("log_compression_level", args.log_compression_level.to_string()),
+            ("rotate_read_log", args.rotate_read_log.to_string()),
+            ("no_read_log", args.no_read_log.to_string()),
+            ("json_log", args.json_log.to_string()),
+            ("read_log_format", args.read_log_format.to_string()),
+            ("max_open_files", args.max_open_files.to_string()),
+            ("min_reads_per_barcode", args.min_reads_per_barcode.to_string()),
+            ("low_latency", args.low_latency.to_string()),
+            ("watch", args.watch.to_string()),
+            ("metrics_port", args.metrics_port.map_or_else(|| "null".to_string(), |port| port.to_string())),
+        ],
+        1,
+    )
+}
+
+/// Format key/value pairs as an indented JSON object
+fn json_object(fields: &[(&str, String)], depth: usize) -> String {
+    let indent = "  ".repeat(depth + 1);
+    let entries: Vec<String> = fields
+        .iter()
+        .map(|(key, value)| format!("{}{}: {}", indent, json_string(key), value))
+        .collect();
+    format!("{{\n{}\n{}}}", entries.join(",\n"), "  ".repeat(depth))
+}
+
+/// Format already encoded values as an indented JSON array
+fn json_array(values: &[String], depth: usize) -> String {
+    if values.is_empty() {
+        return "[]".to_string();
+    }
+    let indent = "  ".repeat(depth + 1);
+    let entries: Vec<String> = values.iter().map(|value| format!("{}{}", indent, value)).collect();
+    format!("[\n{}\n{}]", entries.join(",\n"), "  ".repeat(depth))
+}
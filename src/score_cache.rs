@@ -0,0 +1,58 @@
+use crate::splitter::Matcher;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Searches remembered per thread, 0 until `--score-cache` enables the cache
+static CAPACITY: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static CACHE: RefCell<ScoreCache> = RefCell::new(ScoreCache::default());
+}
+
+/// Search results of one thread keyed by a 64-bit hash of window and search settings
+///
+/// Approximately least recently used: entries fill the current generation,
+/// and once it holds half the capacity it becomes the previous generation,
+/// whose hits are moved back into the current one and the rest dropped on
+/// the next turnover.
+#[derive(Default)]
+struct ScoreCache {
+    current: HashMap<u64, Matcher>,
+    previous: HashMap<u64, Matcher>,
+}
+
+/// Keep up to `capacity` window searches per thread, 0 to disable
+pub fn set_capacity(capacity: usize) {
+    CAPACITY.store(capacity, Ordering::Relaxed);
+}
+
+/// Whether search results are cached
+pub fn is_enabled() -> bool {
+    CAPACITY.load(Ordering::Relaxed) > 0
+}
+
+/// Cached search result of a key
+pub fn get(key: u64) -> Option<Matcher> {
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if let Some(matcher) = cache.current.get(&key) {
+            return Some(matcher.clone());
+        }
+        let matcher = cache.previous.remove(&key)?;
+        cache.current.insert(key, matcher.clone());
+        Some(matcher)
+    })
+}
+
+/// Remember the search result of a key
+pub fn insert(key: u64, matcher: Matcher) {
+    let generation_size = (CAPACITY.load(Ordering::Relaxed) / 2).max(1);
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if cache.current.len() >= generation_size {
+            cache.previous = std::mem::take(&mut cache.current);
+        }
+        cache.current.insert(key, matcher);
+    });
+}
@@ -0,0 +1,147 @@
+use crate::args::{Args, Commands};
+use crate::counter::read_table;
+use crate::simulate::{load_pattern_pairs, write_simulated_reads, SimulationOptions};
+use crate::utils::ScratchDirectory;
+use clap::Parser;
+use flate2::read::MultiGzDecoder;
+use log::info;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+/// Barcodes of the bundled pattern database, the first four ONT native barcodes
+const TOY_DATABASE: &str = "\
+BC01\tAAGAAAGTTGTCGGTGTCTTTGTG
+BC02\tTCGATTCCGTTTGTAGTCGTCTGT
+BC03\tGAGTCTTGTGTCCCAGTTACCAGG
+BC04\tTTCGGATTCTATCGTGTTTCCCTA
+";
+
+/// Pattern file of the bundled dataset, one barcode on both ends per sample
+const TOY_PATTERNS: &str = "\
+#index_F\tindex_R\ttype
+BC01\tBC01\tsample1
+BC02\tBC02\tsample2
+BC03\tBC03\tsample3
+BC04\tBC04\tsample4
+";
+
+/// Fraction of the simulated reads that must be assigned
+const MIN_ASSIGNED_FRACTION: f64 = 0.9;
+
+/// Toy dataset and the run checked against it
+pub struct SelfTest {
+    directory: PathBuf,
+    /// Removes the files afterwards unless a directory was given
+    _scratch_directory: Option<ScratchDirectory>,
+    reads: usize,
+    /// Pattern pair names the reads were built from
+    pair_keys: HashSet<String>,
+}
+
+impl SelfTest {
+    /// Write the toy pattern database and simulated reads for the selftest subcommand
+    pub fn prepare(command: &Commands) -> Self {
+        let Commands::Selftest { reads, seed, directory } = command else {
+            panic!("Not a selftest command");
+        };
+        let (directory, scratch_directory) = match directory {
+            Some(directory) => (PathBuf::from(directory), None),
+            None => {
+                let scratch_directory = ScratchDirectory::new(None);
+                (scratch_directory.path().to_path_buf(), Some(scratch_directory))
+            }
+        };
+        std::fs::create_dir_all(&directory)
+            .unwrap_or_else(|_| panic!("Unable to create self-test directory: {}", directory.display()));
+        let output_directory = directory.join("output");
+        if output_directory.exists() {
+            std::fs::remove_dir_all(&output_directory)
+                .unwrap_or_else(|_| panic!("Unable to clear earlier self-test output: {}", output_directory.display()));
+        }
+
+        let mut self_test = Self { directory, _scratch_directory: scratch_directory, reads: *reads, pair_keys: HashSet::new() };
+        std::fs::write(self_test.path("patterns.db"), TOY_DATABASE).expect("Failed to write self-test pattern database");
+        std::fs::write(self_test.path("patterns.list"), TOY_PATTERNS).expect("Failed to write self-test pattern file");
+
+        let rounds = vec![load_pattern_pairs(&self_test.path("patterns.db"), &self_test.path("patterns.list"))];
+        let options = SimulationOptions { length: (300, 800), error_rate: 0.03, chimera_fraction: 0.0, flank: 30 };
+        write_simulated_reads(&rounds, &options, *reads, *seed, &self_test.path("reads.fq"), &self_test.path("truth.tsv"));
+        self_test.pair_keys = rounds[0].iter().map(|pair| pair.key.clone()).collect();
+        self_test
+    }
+
+    /// Path of a file in the self-test directory
+    fn path(&self, name: &str) -> String {
+        self.directory.join(name).display().to_string()
+    }
+
+    /// Command line of the run on the toy dataset
+    pub fn arguments(&self) -> Args {
+        Args::parse_from([
+            "readchop",
+            "-i", &self.path("reads.fq"),
+            "-d", &self.path("patterns.db"),
+            "-p", &self.path("patterns.list"),
+            "-o", &self.path("output"),
+            "--truth", &self.path("truth.tsv"),
+        ])
+    }
+
+    /// Check the read counts and assignments of the finished run, panicking on the first mismatch
+    pub fn verify(&self) {
+        let output_directory = self.directory.join("output");
+        let (header, rows) = read_table(&output_directory.join("total_info.tsv"))
+            .unwrap_or_else(|| panic!("Self-test failed: no total_info.tsv in {}", output_directory.display()));
+        let total_value = |column: &str| -> usize {
+            header.iter().position(|name| name == column)
+                .and_then(|index| rows.first()?.get(index)?.parse().ok())
+                .unwrap_or_else(|| panic!("Self-test failed: total_info.tsv has no {} count", column))
+        };
+        let (total, valid) = (total_value("total"), total_value("valid_reads"));
+        if total != self.reads {
+            panic!("Self-test failed: {} of {} simulated reads were processed", total, self.reads);
+        }
+        if (valid as f64) < self.reads as f64 * MIN_ASSIGNED_FRACTION {
+            panic!("Self-test failed: only {} of {} reads were assigned", valid, self.reads);
+        }
+        info!("Self-test: {} of {} reads assigned", valid, total);
+
+        // Reads assigned to another barcode than they were built from
+        let (header, rows) = read_table(&output_directory.join("confusion.tsv"))
+            .unwrap_or_else(|| panic!("Self-test failed: no confusion.tsv in {}", output_directory.display()));
+        for row in &rows {
+            for (predicted, count) in header.iter().zip(row).skip(1) {
+                if self.pair_keys.contains(predicted) && *predicted != row[0] && count != "0" {
+                    panic!("Self-test failed: {} reads of {} were assigned to {}", count, row[0], predicted);
+                }
+            }
+        }
+
+        let written = count_fastq_records(&output_directory);
+        if written != valid {
+            panic!("Self-test failed: {} reads were written to FASTQ but {} were assigned", written, valid);
+        }
+        info!("Self-test passed: every assigned read went to its own barcode and was written");
+    }
+}
+
+/// Records in the gzip FASTQ files below a directory
+fn count_fastq_records(directory: &Path) -> usize {
+    let entries = std::fs::read_dir(directory)
+        .unwrap_or_else(|_| panic!("Unable to read directory: {}", directory.display()));
+    entries
+        .map(|entry| entry.expect("Failed to read directory entry").path())
+        .map(|path| {
+            if path.is_dir() {
+                count_fastq_records(&path)
+            } else if path.to_string_lossy().ends_with(".fq.gz") {
+                let file = File::open(&path).unwrap_or_else(|_| panic!("Unable to open output file: {}", path.display()));
+                BufReader::new(MultiGzDecoder::new(file)).lines().count() / 4
+            } else {
+                0
+            }
+        })
+        .sum()
+}
@@ -0,0 +1,258 @@
+use crate::args::Commands;
+use crate::pattern::PatternDatabase;
+use crate::utils::{reverse_complement, reverse_complement_bytes, SplitMix64};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::info;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// Bases drawn for flanks, inserts and substitutions
+const BASES: [u8; 4] = [b'A', b'C', b'G', b'T'];
+
+/// Pattern pair listed in a pattern file
+pub struct PatternPair {
+    /// Pair name as reported by the splitter, `forward_reverse`
+    pub key: String,
+    /// Type column of the pattern file
+    pub name: String,
+    /// Sequence placed at the start of the molecule
+    pub forward: String,
+    /// Sequence whose reverse complement is placed at the end of the molecule
+    pub reverse: String,
+}
+
+/// Shape of the simulated reads
+pub struct SimulationOptions {
+    /// Insert length range, inclusive
+    pub length: (usize, usize),
+    /// Per-base error probability, split evenly across substitutions, insertions and deletions
+    pub error_rate: f64,
+    /// Fraction of reads made of two molecules
+    pub chimera_fraction: f64,
+    /// Maximum random bases outside the outermost patterns
+    pub flank: usize,
+}
+
+/// Simulated read with the pattern pairs it was built from
+pub struct SimulatedRead {
+    pub sequence: Vec<u8>,
+    pub quality: Vec<u8>,
+    /// `fs` when the molecule is in pattern file orientation, `rs` when reverse complemented
+    pub strand: &'static str,
+    pub chimera: bool,
+    /// Index of the chosen pair in each round
+    pub pairs: Vec<usize>,
+}
+
+/// Load the pattern pairs of a pattern file with their sequences
+pub fn load_pattern_pairs(database_file: &str, pattern_file: &str) -> Vec<PatternPair> {
+    let mut pattern_database = PatternDatabase::new();
+    pattern_database.load_patterns(database_file, "auto", pattern_file, &[], false, false);
+
+    let sequence = |key: &str| pattern_database.forward_patterns.get(key).cloned().expect("Pattern was loaded");
+    let pattern_pairs: Vec<PatternPair> = pattern_database
+        .pattern_pairs
+        .iter()
+        .map(|(forward_key, reverse_key, name)| PatternPair {
+            key: format!("{}_{}", forward_key, reverse_key),
+            name: name.clone(),
+            forward: sequence(forward_key),
+            reverse: sequence(reverse_key),
+        })
+        .collect();
+    if pattern_pairs.is_empty() {
+        panic!("No pattern pairs in pattern file: {}", pattern_file);
+    }
+    pattern_pairs
+}
+
+/// Random bases of the given length
+fn random_bases(random: &mut SplitMix64, length: usize) -> Vec<u8> {
+    (0..length).map(|_| BASES[random.next_index(BASES.len())]).collect()
+}
+
+/// One molecule: flank, forward patterns from the outermost round in, insert, reverse patterns, flank
+fn simulate_molecule(random: &mut SplitMix64, rounds: &[Vec<PatternPair>], pairs: &[usize], options: &SimulationOptions) -> Vec<u8> {
+    let (min_length, max_length) = options.length;
+    let flank_length = random.next_index(options.flank + 1);
+    let mut molecule = random_bases(random, flank_length);
+    for (round, &pair) in rounds.iter().zip(pairs) {
+        molecule.extend_from_slice(round[pair].forward.as_bytes());
+    }
+    let insert_length = min_length + random.next_index(max_length - min_length + 1);
+    molecule.extend(random_bases(random, insert_length));
+    for (round, &pair) in rounds.iter().zip(pairs).rev() {
+        molecule.extend_from_slice(reverse_complement(&round[pair].reverse, false).as_bytes());
+    }
+    let flank_length = random.next_index(options.flank + 1);
+    molecule.extend(random_bases(random, flank_length));
+    molecule
+}
+
+/// Apply substitutions, insertions and deletions at the configured rate
+fn add_errors(random: &mut SplitMix64, sequence: &[u8], error_rate: f64) -> Vec<u8> {
+    let mut mutated = Vec::with_capacity(sequence.len() + sequence.len() / 10);
+    for &base in sequence {
+        if random.next_unit() >= error_rate {
+            mutated.push(base);
+            continue;
+        }
+        match random.next_index(3) {
+            0 => {
+                let choices: Vec<u8> = BASES.iter().copied().filter(|&other| other != base).collect();
+                mutated.push(choices[random.next_index(choices.len())]);
+            }
+            1 => {
+                mutated.push(base);
+                mutated.push(BASES[random.next_index(BASES.len())]);
+            }
+            _ => {}
+        }
+    }
+    mutated
+}
+
+/// Phred+33 quality matching the simulated error rate
+fn quality_character(error_rate: f64) -> u8 {
+    let phred = if error_rate > 0.0 { (-10.0 * error_rate.log10()).round() } else { 40.0 };
+    phred.clamp(2.0, 40.0) as u8 + 33
+}
+
+/// Simulate one read, reverse complemented half of the time
+pub fn simulate_read(random: &mut SplitMix64, rounds: &[Vec<PatternPair>], options: &SimulationOptions) -> SimulatedRead {
+    let pairs: Vec<usize> = rounds.iter().map(|round| random.next_index(round.len())).collect();
+    let mut template = simulate_molecule(random, rounds, &pairs, options);
+
+    // Chimeras carry a second, independently chosen molecule after the first
+    let chimera = random.next_unit() < options.chimera_fraction;
+    if chimera {
+        let second_pairs: Vec<usize> = rounds.iter().map(|round| random.next_index(round.len())).collect();
+        template.extend(simulate_molecule(random, rounds, &second_pairs, options));
+    }
+
+    let strand = if random.next_unit() < 0.5 { "fs" } else { "rs" };
+    if strand == "rs" {
+        template = reverse_complement_bytes(&template);
+    }
+    let sequence = add_errors(random, &template, options.error_rate);
+    let quality = vec![quality_character(options.error_rate); sequence.len()];
+    SimulatedRead { sequence, quality, strand, chimera, pairs }
+}
+
+/// Open an output file, gzip compressed when the name ends with `.gz`
+fn create_output(path: &str) -> Box<dyn Write> {
+    let file = File::create(path).unwrap_or_else(|_| panic!("Unable to create output file: {}", path));
+    if path.ends_with(".gz") {
+        Box::new(BufWriter::new(GzEncoder::new(file, Compression::default())))
+    } else {
+        Box::new(BufWriter::new(file))
+    }
+}
+
+/// Handle simulate subcommand, write synthetic reads and their truth labels
+pub fn handle_simulate_command(simulate_args: &Commands) {
+    let Commands::Simulate { pattern_files, pattern_db_file, output, truth, reads, length, error_rate, chimera_fraction, flank, seed } = simulate_args else {
+        return;
+    };
+    let rounds: Vec<Vec<PatternPair>> = pattern_files
+        .iter()
+        .map(|pattern_file| load_pattern_pairs(pattern_db_file, pattern_file))
+        .collect();
+    let options = SimulationOptions {
+        length: *length,
+        error_rate: *error_rate,
+        chimera_fraction: *chimera_fraction,
+        flank: *flank,
+    };
+    write_simulated_reads(&rounds, &options, *reads, *seed, output, truth);
+}
+
+/// Write simulated reads named `sim_<n>` as FASTQ and their truth labels as TSV
+pub fn write_simulated_reads(rounds: &[Vec<PatternPair>], options: &SimulationOptions, reads: usize, seed: u64, output: &str, truth: &str) {
+    info!("Simulating {} reads into {}", reads, output);
+
+    let mut random = SplitMix64::new(seed);
+    let mut fastq_writer = create_output(output);
+    let mut truth_writer = create_output(truth);
+
+    let mut header = String::from("read_id\tstrand\tchimera");
+    for round in 1..=rounds.len() {
+        header.push_str(&format!("\tpattern{0}\ttype{0}", round));
+    }
+    writeln!(truth_writer, "{}", header).expect("Failed to write truth labels");
+
+    for read_number in 1..=reads {
+        let simulated_read = simulate_read(&mut random, rounds, options);
+        let read_id = format!("sim_{}", read_number);
+
+        fastq_writer.write_all(format!("@{}\n", read_id).as_bytes())
+            .and_then(|_| fastq_writer.write_all(&simulated_read.sequence))
+            .and_then(|_| fastq_writer.write_all(b"\n+\n"))
+            .and_then(|_| fastq_writer.write_all(&simulated_read.quality))
+            .and_then(|_| fastq_writer.write_all(b"\n"))
+            .expect("Failed to write simulated reads");
+
+        let mut labels = format!("{}\t{}\t{}", read_id, simulated_read.strand, u8::from(simulated_read.chimera));
+        for (round, &pair) in rounds.iter().zip(&simulated_read.pairs) {
+            labels.push_str(&format!("\t{}\t{}", round[pair].key, round[pair].name));
+        }
+        writeln!(truth_writer, "{}", labels).expect("Failed to write truth labels");
+    }
+
+    fastq_writer.flush().expect("Failed to write simulated reads");
+    truth_writer.flush().expect("Failed to write truth labels");
+    info!("Simulated reads written to {}, truth labels to {}", output, truth);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fastq::ReadInfo;
+    use crate::pattern::{PatternArgument, PatternConfiguration};
+    use crate::splitter::classify_read;
+    use bio::io::fastq::Record;
+
+    #[test]
+    fn test_simulated_reads_are_demultiplexed() {
+        let database_file = "example/ont_bc_pattern.db";
+        let pattern_file = "example/ont_bc_index.list";
+        let rounds = vec![load_pattern_pairs(database_file, pattern_file)];
+        let options = SimulationOptions { length: (300, 800), error_rate: 0.03, chimera_fraction: 0.0, flank: 30 };
+
+        let mut pattern_database = PatternDatabase::new();
+        pattern_database.load_patterns(database_file, "auto", pattern_file, &[], false, false);
+        let mut pattern_config = PatternConfiguration::default();
+        pattern_config.normalize_vectors();
+        pattern_config.pattern_arguments.push(PatternArgument {
+            pattern_database,
+            use_position_info: false,
+            pattern_error_rate: (0.2, 0.2),
+            max_edits: None,
+            effective_length: crate::myers::EffectiveLength::default(),
+            max_distance: 4,
+            position_shift: 3,
+            early_exit_score: 0,
+            anchor: 0,
+            dual_policy: "best-score".to_string(),
+            min_score_margin: 0,
+            fixed_position: None,
+        });
+
+        let mut random = SplitMix64::new(42);
+        let (mut assigned, mut correct) = (0, 0);
+        for read_number in 0..200 {
+            let simulated_read = simulate_read(&mut random, &rounds, &options);
+            let record = Record::with_attrs(&format!("sim_{}", read_number), None, &simulated_read.sequence, &simulated_read.quality);
+            let read_info = classify_read(ReadInfo::new(record), &pattern_config).remove(0);
+            if read_info.sequence_type == "valid" {
+                assigned += 1;
+                if read_info.match_names[0] == rounds[0][simulated_read.pairs[0]].key {
+                    correct += 1;
+                }
+            }
+        }
+        assert!(assigned >= 190, "only {} of 200 simulated reads assigned", assigned);
+        assert_eq!(correct, assigned);
+    }
+}
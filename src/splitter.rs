@@ -1,13 +1,20 @@
 use crate::fastq::ReadInfo;
-use crate::myers::myers_best;
+use crate::myers::{compile_pattern, exact_best, hamming_distance, myers_best};
 use crate::myers::SearchPattern;
-use crate::pattern::{PatternArgument, PatternConfiguration};
+use crate::perf;
+use crate::score_cache;
+use crate::pattern::{PatternArgument, PatternConfiguration, PatternSet};
 use crate::thread_pool::ThreadPoolManager;
+use crate::utils::json_string;
 // use bio::io::fastq::Record; // No longer needed with optimized ReadInfo structure
 use flume::Receiver;
 use std::cmp::min;
 use std::collections::HashMap;
-use std::time::Instant;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use log::error;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
 /// Read block structure for defining search range
 #[derive(Debug)]
@@ -26,11 +33,7 @@ impl ReadChunk {
             pattern_config.window_size[0]
         };
 
-        let right_bound = if pattern_config.window_size[1] > read_info.sequence_length {
-            0
-        } else {
-            read_info.sequence_length - pattern_config.window_size[1]
-        };
+        let right_bound = read_info.sequence_length.saturating_sub(pattern_config.window_size[1]);
 
         Self {
             left_bound,
@@ -49,6 +52,7 @@ pub struct SplitType {
     pub pattern_strand: String,      // strand orientation
     pub left_matcher: Matcher,        // left matcher
     pub right_matcher: Matcher,      // right matcher
+    pub ambiguous: bool,             // runner-up within the score margin
 }
 
 impl SplitType {
@@ -61,6 +65,7 @@ impl SplitType {
             pattern_strand: String::from("unknown"),
             left_matcher,
             right_matcher,
+            ambiguous: false,
         }
     }
     
@@ -83,18 +88,52 @@ impl SplitType {
         )
     }
     
+    /// JSON object with the match of both ends, for --json-log
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"match\": {}, \"name\": {}, \"type\": {}, \"strand\": {}, \"ambiguous\": {}, \"left\": {}, \"right\": {}}}",
+            json_string(self.pattern_match),
+            json_string(&self.pattern_name),
+            json_string(&self.pattern_type),
+            json_string(&self.pattern_strand),
+            self.ambiguous,
+            self.left_matcher.to_json(),
+            self.right_matcher.to_json(),
+        )
+    }
+    
+    /// Scores and coordinates of both ends for output IDs, e.g. `L0:23-47,R1:5035-5059`
+    pub fn to_score_tag(&self) -> String {
+        let side_tag = |side: &str, matcher: &Matcher| {
+            if matcher.status {
+                format!("{}{}:{}-{}", side, matcher.score, matcher.ystart, matcher.yend)
+            } else {
+                format!("{}-", side)
+            }
+        };
+        format!("{},{}", side_tag("L", &self.left_matcher), side_tag("R", &self.right_matcher))
+    }
+    
     /// Annotate pattern type
     pub fn annotate_pattern_type(
         &mut self,
         pattern_type_dict: &HashMap<String, (String, String, String)>,
         max_distance: i32,
+        dual_policy: &str,
     ) {
-        let (pattern_match, key) = self.get_match_key(max_distance, pattern_type_dict);
+        let (pattern_match, key) = self.get_match_key(max_distance, pattern_type_dict, dual_policy);
         
         if key == "_" || key == "unknown" {
             return;
         }
         
+        if pattern_match == "index_hop" {
+            self.pattern_match = pattern_match;
+            self.pattern_name = key;
+            self.pattern_type = String::from("index_hop");
+            return;
+        }
+        
         for (dict_key, value) in pattern_type_dict {
             if dict_key.contains(&key) {
                 self.pattern_match = pattern_match;
@@ -106,12 +145,21 @@ impl SplitType {
         }
     }
     
-    /// Get match key
+    /// Get match key according to the dual policy
+    ///
+    /// `best-score` falls back to the better scoring end when the pair is unknown,
+    /// `both-required` needs a known pair, and `either` accepts one end but
+    /// treats two disagreeing ends as an index hop.
     pub fn get_match_key(
         &self,
         max_distance: i32,
         pattern_type_dict: &HashMap<String, (String, String, String)>,
+        dual_policy: &str,
     ) -> (&'static str, String) {
+        if dual_policy != "best-score" {
+            return self.get_strict_match_key(pattern_type_dict, dual_policy == "either");
+        }
+        
         if self.right_matcher.status && self.left_matcher.status {
             let combined_pattern = format!("{}_{}", self.left_matcher.pattern, self.right_matcher.pattern);
             if pattern_type_dict.contains_key(&combined_pattern) {
@@ -134,6 +182,27 @@ impl SplitType {
             ("unknown", String::from("unknown"))
         }
     }
+    
+    /// Get match key for policies that never pick one end over a disagreeing other end
+    fn get_strict_match_key(
+        &self,
+        pattern_type_dict: &HashMap<String, (String, String, String)>,
+        allow_single_end: bool,
+    ) -> (&'static str, String) {
+        match (self.left_matcher.status, self.right_matcher.status) {
+            (true, true) => {
+                let combined_pattern = format!("{}_{}", self.left_matcher.pattern, self.right_matcher.pattern);
+                if pattern_type_dict.contains_key(&combined_pattern) {
+                    ("dual", combined_pattern)
+                } else {
+                    ("index_hop", combined_pattern)
+                }
+            }
+            (true, false) if allow_single_end => ("left", format!("{}_", self.left_matcher.pattern)),
+            (false, true) if allow_single_end => ("right", format!("_{}", self.right_matcher.pattern)),
+            _ => ("unknown", String::from("unknown")),
+        }
+    }
 }
 
 /// Matcher structure
@@ -141,17 +210,40 @@ impl SplitType {
 pub struct Matcher {
     pattern: String,
     score: i32,
+    /// Score of the best other pattern
+    runner_up_score: i32,
     pub ystart: usize,
     pub yend: usize,
     pub status: bool,
 }
 
+impl Default for Matcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Matcher {
+    /// JSON object with the matched pattern, edit distance and coordinates, null fields when unmatched
+    fn to_json(&self) -> String {
+        if !self.status {
+            return "{\"pattern\": null, \"score\": null, \"start\": null, \"end\": null}".to_string();
+        }
+        format!(
+            "{{\"pattern\": {}, \"score\": {}, \"start\": {}, \"end\": {}}}",
+            json_string(&self.pattern),
+            self.score,
+            self.ystart,
+            self.yend
+        )
+    }
+    
     /// Create new matcher
     pub fn new() -> Self {
         Self {
             pattern: String::from(""),
             score: 99,
+            runner_up_score: 99,
             ystart: 0,
             yend: 0,
             status: false,
@@ -162,6 +254,16 @@ impl Matcher {
     pub fn get_score(&self) -> i32 {
         self.score
     }
+    
+    /// Get matched pattern name
+    pub fn get_pattern(&self) -> &str {
+        &self.pattern
+    }
+    
+    /// Check if another pattern scored within `margin` edits of the match
+    pub fn is_ambiguous(&self, margin: usize) -> bool {
+        self.status && self.runner_up_score - self.score < margin as i32
+    }
 }
 
 /// Calculate start and end positions
@@ -186,7 +288,7 @@ fn calculate_start_end_positions(
         }
         "right" => {
             new_end = min(text_length, new_start + pattern_length + position_shift);
-            new_start = start.checked_sub(position_shift).unwrap_or(0);
+            new_start = start.saturating_sub(position_shift);
         }
         _ => {}
     }
@@ -198,7 +300,92 @@ fn calculate_start_end_positions(
 fn find_matcher(
     raw_start: usize,
     raw_end: usize,
-    pattern_database: &HashMap<String, String>,
+    pattern_set: &PatternSet,
+    search_pattern: &mut SearchPattern,
+    use_position_mutation: bool,
+    position_shift: usize,
+    orientation: &'static str,
+) -> Matcher {
+    // Windows repeat across reads with identical ends, and searching one gives the same result
+    let cache_key = (score_cache::is_enabled() && !use_position_mutation)
+        .then(|| window_cache_key(&search_pattern.raw_text[raw_start..raw_end], pattern_set, search_pattern));
+    let cached_matcher = cache_key.and_then(score_cache::get);
+    perf::record_cache_lookup(cache_key.is_some(), cached_matcher.is_some());
+    if let Some(mut matcher) = cached_matcher {
+        matcher.ystart += raw_start;
+        matcher.yend += raw_start;
+        if matcher.status {
+            pattern_set.record_hit(&matcher.pattern);
+        }
+        return matcher;
+    }
+    
+    // All patterns share one window without position mutation, so search them together
+    let packed_matcher = if use_position_mutation {
+        None
+    } else {
+        find_matcher_packed(raw_start, raw_end, pattern_set, search_pattern)
+    };
+    
+    let matcher = match packed_matcher {
+        Some(matcher) => matcher,
+        None => find_matcher_sequential(
+            raw_start,
+            raw_end,
+            pattern_set,
+            search_pattern,
+            use_position_mutation,
+            position_shift,
+            orientation,
+        ),
+    };
+    
+    // Coordinates are kept relative to the window, which starts elsewhere in reads of another length
+    if let Some(cache_key) = cache_key {
+        let mut cached_matcher = matcher.clone();
+        cached_matcher.ystart -= raw_start.min(cached_matcher.ystart);
+        cached_matcher.yend -= raw_start.min(cached_matcher.yend);
+        score_cache::insert(cache_key, cached_matcher);
+    }
+    
+    if matcher.status {
+        pattern_set.record_hit(&matcher.pattern);
+    }
+    
+    matcher
+}
+
+/// Hash of a search window with the patterns and budgets it is searched for
+fn window_cache_key(window: &[u8], pattern_set: &PatternSet, search_pattern: &SearchPattern) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    window.hash(&mut hasher);
+    pattern_set.version().hash(&mut hasher);
+    search_pattern.dist_ratio.to_bits().hash(&mut hasher);
+    search_pattern.max_edits.hash(&mut hasher);
+    search_pattern.effective_length.hash(&mut hasher);
+    search_pattern.early_exit_score.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Best alignment of the current pattern `name` of the set as (score, start, end)
+fn align_pattern(search_pattern: &SearchPattern, pattern_set: &PatternSet, name: &str) -> Option<(i32, usize, usize)> {
+    // Exact hits are common, so skip the Myers DP when the pattern occurs verbatim
+    if let Some(result) = exact_best(search_pattern) {
+        perf::record_alignment(true);
+        return Some(result);
+    }
+    perf::record_alignment(false);
+    match pattern_set.compiled_pattern(name) {
+        Some(mut myers) => myers_best(search_pattern, &mut myers),
+        None => myers_best(search_pattern, &mut compile_pattern(&search_pattern.pattern)),
+    }
+}
+
+/// Find matcher by aligning each pattern in turn
+fn find_matcher_sequential(
+    raw_start: usize,
+    raw_end: usize,
+    pattern_set: &PatternSet,
     search_pattern: &mut SearchPattern,
     use_position_mutation: bool,
     position_shift: usize,
@@ -206,8 +393,8 @@ fn find_matcher(
 ) -> Matcher {
     let mut matcher = Matcher::new();
     
-    for (key, value) in pattern_database.iter() {
-        let pattern = value.as_bytes().to_vec();
+    for key in pattern_set.ordered_names().iter() {
+        let pattern = pattern_set.get(key).expect("Pattern missing from set").as_bytes();
         let (start_pos, end_pos) = if use_position_mutation {
             calculate_start_end_positions(
                 raw_start,
@@ -221,22 +408,167 @@ fn find_matcher(
             (raw_start, raw_end)
         };
         
-        search_pattern.update(start_pos, end_pos, pattern);
+        search_pattern.update(start_pos, end_pos, pattern, pattern_set.distance_limit(key));
         
-        if let Some(result) = myers_best(search_pattern) {
+        if let Some(result) = align_pattern(search_pattern, pattern_set, key) {
             if result.0 < matcher.score {
+                matcher.runner_up_score = matcher.score;
                 matcher.pattern = key.to_string();
                 matcher.score = result.0;
                 matcher.ystart = result.1;
                 matcher.yend = result.2;
                 matcher.status = true;
+            } else if result.0 < matcher.runner_up_score {
+                matcher.runner_up_score = result.0;
+            }
+        }
+        
+        // Good enough, skip the remaining patterns
+        if matcher.status && search_pattern.early_exit_score.is_some_and(|score| matcher.score <= score as i32) {
+            break;
+        }
+    }
+    
+    matcher
+}
+
+/// Find matcher using one packed multi-pattern scan, None if patterns cannot be packed
+fn find_matcher_packed(
+    raw_start: usize,
+    raw_end: usize,
+    pattern_set: &PatternSet,
+    search_pattern: &mut SearchPattern,
+) -> Option<Matcher> {
+    // Packed first, it may still need to build its scan before the names are borrowed
+    let packed_patterns = pattern_set.packed_patterns();
+    let multi_pattern = packed_patterns.as_ref()?;
+    let names = pattern_set.ordered_names();
+    let patterns: Vec<&[u8]> = names.iter()
+        .map(|name| pattern_set.get(name).expect("Pattern missing from set").as_bytes())
+        .collect();
+    let budgets: Vec<usize> = names.iter().zip(&patterns)
+        .map(|(name, pattern)| search_pattern.distance_budget(pattern, pattern_set.distance_limit(name)) as usize)
+        .collect();
+    perf::record_packed_scan();
+    
+    // Patterns are packed in search order, so stop at the first good enough hit
+    let early_exit_score = search_pattern.early_exit_score;
+    let distances = multi_pattern.best_distances_until(
+        &search_pattern.raw_text[raw_start..raw_end],
+        |index, distance| distance <= budgets[index] && early_exit_score.is_some_and(|score| distance <= score),
+    );
+    
+    // Keep the first pattern with the lowest distance within its own budget
+    let mut best: Option<(usize, usize)> = None;
+    let mut runner_up_distance: Option<usize> = None;
+    for (index, distance) in distances.into_iter().enumerate() {
+        let Some(distance) = distance.filter(|&distance| distance <= budgets[index]) else {
+            continue;
+        };
+        match best {
+            Some((_, best_distance)) if distance >= best_distance => {
+                runner_up_distance = Some(runner_up_distance.map_or(distance, |runner_up| runner_up.min(distance)));
+            }
+            _ => {
+                runner_up_distance = best.map(|(_, best_distance)| best_distance);
+                best = Some((index, distance));
             }
         }
     }
     
+    let mut matcher = Matcher::new();
+    if let Some(runner_up_distance) = runner_up_distance {
+        matcher.runner_up_score = runner_up_distance as i32;
+    }
+    if let Some((index, _)) = best {
+        // Align only the winner to recover coordinates
+        search_pattern.update(raw_start, raw_end, patterns[index], pattern_set.distance_limit(&names[index]));
+        if let Some(result) = align_pattern(search_pattern, pattern_set, &names[index]) {
+            matcher.pattern = names[index].to_string();
+            matcher.score = result.0;
+            matcher.ystart = result.1;
+            matcher.yend = result.2;
+            matcher.status = true;
+        }
+    }
+    
+    Some(matcher)
+}
+
+/// Find matcher by Hamming distance at a fixed barcode position
+///
+/// Left patterns are compared `start` bases from the read start, right
+/// patterns, already reverse complemented, `start` bases from the read end.
+/// Only the first `length` bases of a pattern are compared, as seen on the
+/// forward strand.
+fn find_matcher_fixed(
+    sequence: &[u8],
+    (start, length): (usize, usize),
+    pattern_set: &PatternSet,
+    search_pattern: &SearchPattern,
+    orientation: &'static str,
+) -> Matcher {
+    let mut matcher = Matcher::new();
+    for name in pattern_set.ordered_names().iter() {
+        let pattern = pattern_set.get(name).expect("Pattern missing from set").as_bytes();
+        let compared_length = length.min(pattern.len());
+        let (pattern, window_start) = if orientation == "left" {
+            (&pattern[..compared_length], start)
+        } else {
+            let Some(window_start) = sequence.len().checked_sub(start + compared_length) else {
+                continue;
+            };
+            (&pattern[pattern.len() - compared_length..], window_start)
+        };
+        let Some(window) = sequence.get(window_start..window_start + compared_length) else {
+            continue;
+        };
+        
+        let distance = hamming_distance(pattern, window) as i32;
+        if distance > search_pattern.distance_budget(pattern, pattern_set.distance_limit(name)) as i32 {
+            continue;
+        }
+        if distance < matcher.score {
+            matcher.runner_up_score = matcher.score;
+            matcher.pattern = name.to_string();
+            matcher.score = distance;
+            matcher.ystart = window_start;
+            matcher.yend = window_start + compared_length;
+            matcher.status = true;
+        } else if distance < matcher.runner_up_score {
+            matcher.runner_up_score = distance;
+        }
+    }
+    
+    if matcher.status {
+        pattern_set.record_hit(&matcher.pattern);
+    }
     matcher
 }
 
+/// Search bounds for the left and right pattern, narrowed to the read ends when anchored
+fn anchored_bounds(
+    read_chunk: &ReadChunk,
+    sequence_length: usize,
+    anchor: usize,
+    forward_patterns: &PatternSet,
+    reverse_patterns: &PatternSet,
+) -> (usize, usize) {
+    if anchor == 0 {
+        return (read_chunk.left_bound, read_chunk.right_bound);
+    }
+    
+    // Leave room for the pattern itself plus insertions
+    let span = |pattern_set: &PatternSet| {
+        let max_pattern_length = pattern_set.max_pattern_length();
+        anchor + max_pattern_length + max_pattern_length / 2
+    };
+    (
+        min(read_chunk.left_bound, span(forward_patterns)),
+        read_chunk.right_bound.max(sequence_length.saturating_sub(span(reverse_patterns))),
+    )
+}
+
 /// Execute sequence splitting - memory optimized
 fn perform_sequence_splitting(
     sequence: &[u8], 
@@ -244,15 +576,41 @@ fn perform_sequence_splitting(
     pattern_argument: &PatternArgument
 ) -> SplitType {
     let pattern_database = &pattern_argument.pattern_database;
+    
+    // Fixed positions need no search text, only the distance budgets
+    if let Some(fixed_position) = pattern_argument.fixed_position {
+        let mut search_pattern = SearchPattern::new(&[], pattern_argument.pattern_error_rate.0);
+        search_pattern.max_edits = pattern_argument.max_edits;
+        search_pattern.effective_length = pattern_argument.effective_length;
+        let left_matcher = find_matcher_fixed(sequence, fixed_position, &pattern_database.forward_patterns, &search_pattern, "left");
+        search_pattern.dist_ratio = pattern_argument.pattern_error_rate.1;
+        let right_matcher = find_matcher_fixed(sequence, fixed_position, &pattern_database.reverse_patterns, &search_pattern, "right");
+        return annotate_split_type(left_matcher, right_matcher, pattern_argument);
+    }
+    
     let mut search_pattern = SearchPattern::new(
-        sequence.to_vec(), 
+        sequence, 
         pattern_argument.pattern_error_rate.0
     );
+    // The runner-up the score margin is checked against is only found when every pattern is scored
+    search_pattern.early_exit_score = (pattern_argument.min_score_margin == 0).then_some(pattern_argument.early_exit_score);
+    search_pattern.max_edits = pattern_argument.max_edits;
+    search_pattern.effective_length = pattern_argument.effective_length;
+    
+    // Anchored rounds only search the read ends, the round after a position hit keeps its own window
+    let anchor = if read_chunk.use_position_mutation { 0 } else { pattern_argument.anchor };
+    let (left_end, right_start) = anchored_bounds(
+        read_chunk,
+        sequence.len(),
+        anchor,
+        &pattern_database.forward_patterns,
+        &pattern_database.reverse_patterns,
+    );
     
     // Search left pattern
-    let left_matcher = find_matcher(
+    let mut left_matcher = find_matcher(
         0,
-        read_chunk.left_bound,
+        left_end,
         &pattern_database.forward_patterns,
         &mut search_pattern,
         read_chunk.use_position_mutation,
@@ -262,8 +620,8 @@ fn perform_sequence_splitting(
     
     // Search right pattern
     search_pattern.dist_ratio = pattern_argument.pattern_error_rate.1;
-    let right_matcher = find_matcher(
-        read_chunk.right_bound,
+    let mut right_matcher = find_matcher(
+        right_start,
         sequence.len(),
         &pattern_database.reverse_patterns,
         &mut search_pattern,
@@ -272,10 +630,32 @@ fn perform_sequence_splitting(
         "right",
     );
     
+    // Reject internal hits that only partly overlap the anchored window
+    if anchor > 0 {
+        if left_matcher.status && left_matcher.ystart > anchor {
+            left_matcher = Matcher::new();
+        }
+        if right_matcher.status && sequence.len() - right_matcher.yend > anchor {
+            right_matcher = Matcher::new();
+        }
+    }
+    
+    annotate_split_type(left_matcher, right_matcher, pattern_argument)
+}
+
+/// Pair both ends of a round and look up the pattern type
+fn annotate_split_type(left_matcher: Matcher, right_matcher: Matcher, pattern_argument: &PatternArgument) -> SplitType {
+    // Too close a runner-up makes the assignment a coin flip between similar patterns
+    let ambiguous = pattern_argument.min_score_margin > 0
+        && (left_matcher.is_ambiguous(pattern_argument.min_score_margin)
+            || right_matcher.is_ambiguous(pattern_argument.min_score_margin));
+    
     let mut split_type = SplitType::new(left_matcher, right_matcher);
+    split_type.ambiguous = ambiguous;
     split_type.annotate_pattern_type(
-        &pattern_database.pattern_types, 
-        pattern_argument.max_distance as i32
+        &pattern_argument.pattern_database.pattern_types, 
+        pattern_argument.max_distance as i32,
+        &pattern_argument.dual_policy,
     );
     
     split_type
@@ -290,7 +670,7 @@ pub fn perform_sequence_splitting_vector(
     let mut read_chunk = ReadChunk::new(pattern_config, read_info);
     
     // Get sequence data only when needed
-    let sequence = read_info.sequence.as_ref()
+    let sequence = read_info.sequence()
         .expect("Sequence data not available");
     
     for pattern_argument in &pattern_config.pattern_arguments {
@@ -314,18 +694,26 @@ pub fn perform_sequence_splitting_vector(
 }
 
 /// Detect fusion sequence - memory optimized
-fn detect_fusion_sequence(read_info: &ReadInfo, pattern_config: &PatternConfiguration) -> bool {
-    let (middle_start, middle_end) = read_info.sequence_window;
+///
+/// Only the --fusion-window region is searched, less --fusion-min-distance-from-ends on both sides.
+fn detect_fusion_sequence(read_info: &ReadInfo, pattern_config: &PatternConfiguration) -> Option<Matcher> {
+    let (window_start, window_end) = match pattern_config.fusion_window.as_str() {
+        "read" => (0, read_info.sequence_length),
+        "inner" => read_info.inner_window(),
+        _ => read_info.sequence_window,
+    };
+    let middle_start = window_start + pattern_config.fusion_min_end_distance;
+    let middle_end = window_end.saturating_sub(pattern_config.fusion_min_end_distance);
     
     if middle_end <= middle_start {
-        return false;
+        return None;
     }
     
     let fusion_database = &pattern_config.fusion_database.fusion_patterns;
-    let sequence = read_info.sequence.as_ref()
+    let sequence = read_info.sequence()
         .expect("Sequence data not available");
     let mut search_pattern = SearchPattern::new(
-        sequence.to_vec(), 
+        sequence, 
         pattern_config.fusion_error_rate
     );
 
@@ -340,65 +728,393 @@ fn detect_fusion_sequence(read_info: &ReadInfo, pattern_config: &PatternConfigur
         "middle",
     );
 
-    middle_matcher.status
+    middle_matcher.status.then_some(middle_matcher)
 }
 
+/// Maximum number of times a read is cut at internal adapters
+const MAX_FUSION_SPLIT_DEPTH: usize = 4;
+
+/// Maximum number of inserts cut from one concatemer read
+const MAX_CONCATEMER_INSERTS: usize = 32;
+
+/// Positions between `start` and `end` where one concatemer insert ends and the next begins
+///
+/// The outermost round's end patterns are searched away from both ends of the
+/// range; reverse patterns hold both pair members reverse complemented, so
+/// the end of an insert is found on either strand. Each hit cuts the range and
+/// both pieces are searched again.
+fn find_concatemer_boundaries(
+    search_pattern: &mut SearchPattern,
+    pattern_argument: &PatternArgument,
+    start: usize,
+    end: usize,
+    boundaries: &mut Vec<usize>,
+) {
+    let pattern_database = &pattern_argument.pattern_database;
+    let span = |pattern_set: &PatternSet| {
+        let max_pattern_length = pattern_set.max_pattern_length();
+        max_pattern_length + max_pattern_length / 2
+    };
+    let search_start = start + span(&pattern_database.forward_patterns);
+    let search_end = end.saturating_sub(span(&pattern_database.reverse_patterns));
+    if search_start >= search_end || boundaries.len() + 1 >= MAX_CONCATEMER_INSERTS {
+        return;
+    }
+    
+    let matcher = find_matcher(search_start, search_end, &pattern_database.reverse_patterns, search_pattern, false, 0, "middle");
+    if !matcher.status || matcher.score > pattern_argument.max_distance as i32 {
+        return;
+    }
+    boundaries.push(matcher.yend);
+    find_concatemer_boundaries(search_pattern, pattern_argument, start, matcher.yend, boundaries);
+    find_concatemer_boundaries(search_pattern, pattern_argument, matcher.yend, end, boundaries);
+}
+
+/// Inserts of a concatemer read as sub-reads suffixed `1`, `2`, ..., none for a single insert
+fn split_concatemer(read_info: &ReadInfo, pattern_config: &PatternConfiguration) -> Vec<ReadInfo> {
+    let Some(pattern_argument) = pattern_config.pattern_arguments.first() else {
+        return Vec::new();
+    };
+    let sequence = read_info.sequence()
+        .expect("Sequence data not available");
+    let mut search_pattern = SearchPattern::new(sequence, pattern_argument.pattern_error_rate.1);
+    search_pattern.max_edits = pattern_argument.max_edits;
+    search_pattern.effective_length = pattern_argument.effective_length;
+    
+    // Flanks outside the outermost patterns found on the whole read are not searched
+    let (mut start, mut end) = (0, sequence.len());
+    if let Some(split_type) = read_info.split_types.first() {
+        if split_type.left_matcher.status {
+            start = split_type.left_matcher.ystart;
+        }
+        if split_type.right_matcher.status {
+            end = split_type.right_matcher.yend;
+        }
+    }
+    let mut boundaries = Vec::new();
+    find_concatemer_boundaries(&mut search_pattern, pattern_argument, start, end, &mut boundaries);
+    if boundaries.is_empty() {
+        return Vec::new();
+    }
+    boundaries.sort_unstable();
+    boundaries.insert(0, 0);
+    boundaries.push(sequence.len());
+    
+    boundaries
+        .windows(2)
+        .enumerate()
+        .filter_map(|(index, insert)| read_info.sub_read(insert[0], insert[1], &(index + 1).to_string()))
+        .collect()
+}
+
+/// Classify single read, returning it followed by any sub-reads cut at internal adapters
+pub fn classify_read(read_info: ReadInfo, pattern_config: &PatternConfiguration) -> Vec<ReadInfo> {
+    let timer = perf::start();
+    let mut read_infos = Vec::with_capacity(1);
+    classify_read_recursive(read_info, pattern_config, 0, &mut read_infos);
+    perf::record_match(timer);
+    read_infos
+}
+
+/// Split, update sequence information and detect fusion, recursing into sub-reads
+fn classify_read_recursive(
+    mut read_info: ReadInfo,
+    pattern_config: &PatternConfiguration,
+    depth: usize,
+    read_infos: &mut Vec<ReadInfo>,
+) {
+    read_info.split_types = perform_sequence_splitting_vector(&read_info, pattern_config);
+    
+    // Update sequence information
+    read_info.update(pattern_config);
+    
+    // Concatemer inserts replace the read, so only input reads are cut
+    let mut sub_reads = if pattern_config.recursive_split && depth == 0 {
+        split_concatemer(&read_info, pattern_config)
+    } else {
+        Vec::new()
+    };
+    if !sub_reads.is_empty() {
+        read_info.sequence_type = "concatemer".to_string();
+        read_info.should_write_to_fastq = false;
+    }
+    
+    // Detect fusion sequence
+    if sub_reads.is_empty()
+        && !pattern_config.fusion_database.is_empty() 
+        && let Some(fusion_matcher) = detect_fusion_sequence(&read_info, pattern_config) 
+    {
+        let (fusion_pattern, fusion_strand) = pattern_config.fusion_database.pattern_strand(fusion_matcher.get_pattern());
+        let fusion_class = pattern_config.fusion_database.class_of(fusion_pattern);
+        let fusion_action = pattern_config.fusion_action_for(fusion_class);
+        read_info.mark_fusion(fusion_action, fusion_class, &pattern_config.id_separator);
+        read_info.fusion_match = format!("{}:{}", fusion_pattern, fusion_strand);
+        
+        // Cut at the internal match and classify both halves on their own
+        // Cutting R1 alone would leave its halves without a mate
+        if fusion_action == "split" && depth < MAX_FUSION_SPLIT_DEPTH && read_info.mates.is_empty() {
+            sub_reads.extend(read_info.sub_read(0, fusion_matcher.ystart, "1"));
+            sub_reads.extend(read_info.sub_read(fusion_matcher.yend, read_info.sequence_length, "2"));
+        }
+    }
+    
+    read_info.apply_grouping(&pattern_config.group_by, pattern_config.group_output);
+    if let Some(outdir_template) = &pattern_config.outdir_template {
+        read_info.apply_outdir_template(outdir_template, pattern_config.run_id.as_deref(), pattern_config.run_date.as_deref());
+    }
+    if pattern_config.time_bin > 0 {
+        read_info.read_start_time();
+    }
+    read_info.release_unused_data();
+    read_infos.push(read_info);
+    
+    for sub_read in sub_reads {
+        classify_read_recursive(sub_read, pattern_config, depth + 1, read_infos);
+    }
+}
+
+/// Reads classified by one pool task
+const SPLIT_BATCH_SIZE: usize = 256;
+
+/// Batches queued or running per pool thread before the dispatcher waits
+const BATCHES_PER_THREAD: usize = 4;
+
+/// Reads whose classification panicked, missing from every output
+static SPLIT_FAILURES: AtomicU64 = AtomicU64::new(0);
+
+/// Reads left out of the outputs because their classification panicked
+pub fn split_failures() -> u64 {
+    SPLIT_FAILURES.load(Ordering::Relaxed)
+}
 
-/// Create controlled splitter receiver with thread pool management
+/// Run `work` on one read, counting the read as failed when it panics
+///
+/// Only the failing read is lost; the rest of its batch is still emitted, so
+/// the ordered receiver never waits for a batch that will not arrive.
+fn classify_guarded<F>(work: &F, read_info: ReadInfo, pattern_config: &PatternConfiguration) -> Vec<ReadInfo>
+where
+    F: Fn(ReadInfo, &PatternConfiguration) -> Vec<ReadInfo>,
+{
+    let read_id = read_info.original_id.clone();
+    catch_unwind(AssertUnwindSafe(|| work(read_info, pattern_config))).unwrap_or_else(|_| {
+        SPLIT_FAILURES.fetch_add(1, Ordering::Relaxed);
+        error!("Classifying read {} panicked, the read is left out of the outputs", read_id);
+        Vec::new()
+    })
+}
+
+/// Slot of a queued batch, freed when its task finishes or panics
+struct BatchPermit(Receiver<()>);
+
+impl Drop for BatchPermit {
+    fn drop(&mut self) {
+        let _ = self.0.try_recv();
+    }
+}
+
+/// Copies of the pattern configuration handed to splitting tasks
+///
+/// Pattern sets adapt their search order without locking, so each running task
+/// checks out its own copy and returns it for the next batch.
+struct PatternConfigurationPool {
+    template: Mutex<PatternConfiguration>,
+    idle: Mutex<Vec<PatternConfiguration>>,
+}
+
+impl PatternConfigurationPool {
+    /// Create pool cloning `pattern_config` whenever every copy is in use
+    fn new(pattern_config: &PatternConfiguration) -> Self {
+        Self {
+            template: Mutex::new(pattern_config.clone()),
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Run `work` with a configuration no other task is using
+    fn with<T>(&self, work: impl FnOnce(&PatternConfiguration) -> T) -> T {
+        let idle = self.idle.lock().unwrap().pop();
+        let pattern_config = idle.unwrap_or_else(|| self.template.lock().unwrap().clone());
+        let result = work(&pattern_config);
+        self.idle.lock().unwrap().push(pattern_config);
+        result
+    }
+}
+
+/// Take the next read and whatever else is already waiting, up to one batch
+fn next_batch(read_receiver: &Receiver<ReadInfo>) -> Option<Vec<ReadInfo>> {
+    let mut batch = vec![read_receiver.recv().ok()?];
+    batch.extend(read_receiver.try_iter().take(SPLIT_BATCH_SIZE - 1));
+    Some(batch)
+}
+
+/// Queue batches of reads as splitting tasks, `work` receiving each batch with its index
+///
+/// The number of batches in flight is bounded, so a slow pool leaves reads in
+/// the input channel instead of piling up as queued tasks.
+fn dispatch_batches<F>(read_receiver: Receiver<ReadInfo>, thread_pool: &ThreadPoolManager, work: F)
+where
+    F: Fn(usize, Vec<ReadInfo>) + Send + Sync + 'static,
+{
+    let task_queue = thread_pool.task_queue();
+    let (permit_sender, permit_receiver) = flume::bounded(thread_pool.max_threads() * BATCHES_PER_THREAD);
+    let work = Arc::new(work);
+    std::thread::spawn(move || {
+        let mut batch_index = 0;
+        while let Some(batch) = next_batch(&read_receiver) {
+            permit_sender.send(()).expect("Batch permits are never closed");
+            let permit = BatchPermit(permit_receiver.clone());
+            let work = Arc::clone(&work);
+            task_queue.execute_processing(move || {
+                let _permit = permit;
+                work(batch_index, batch);
+            });
+            batch_index += 1;
+        }
+    });
+}
+
+/// Create controlled splitter receiver running on the thread pool
 pub fn create_splitter_receiver_controlled(
     read_receiver: Receiver<ReadInfo>,
     pattern_config: &PatternConfiguration,
-    thread_count: usize,
-    thread_pool: &mut ThreadPoolManager,
+    thread_pool: &ThreadPoolManager,
+) -> Receiver<ReadInfo> {
+    let (sender, receiver) = flume::unbounded();
+    let pattern_configs = PatternConfigurationPool::new(pattern_config);
+    dispatch_batches(read_receiver, thread_pool, move |_, batch| {
+        pattern_configs.with(|pattern_config| {
+            for read_info in batch {
+                for classified_read in classify_guarded(&classify_read, read_info, pattern_config) {
+                    sender.send(classified_read).expect("Failed to send sequence information");
+                }
+            }
+        });
+    });
+    receiver
+}
+
+/// Create splitter whose results keep the input read order
+pub fn create_splitter_receiver_ordered(
+    read_receiver: Receiver<ReadInfo>,
+    pattern_config: &PatternConfiguration,
+    thread_pool: &ThreadPoolManager,
 ) -> Receiver<ReadInfo> {
+    create_ordered_receiver(read_receiver, pattern_config, thread_pool, classify_read)
+}
+
+/// Run `work` on reads across the thread pool, emitting the results in input order
+///
+/// Batches are numbered as they are queued and results wait in a reordering
+/// buffer until every earlier batch has been emitted.
+pub fn create_ordered_receiver<F>(
+    read_receiver: Receiver<ReadInfo>,
+    pattern_config: &PatternConfiguration,
+    thread_pool: &ThreadPoolManager,
+    work: F,
+) -> Receiver<ReadInfo>
+where
+    F: Fn(ReadInfo, &PatternConfiguration) -> Vec<ReadInfo> + Send + Sync + 'static,
+{
+    let (result_sender, result_receiver) = flume::unbounded::<(usize, Vec<ReadInfo>)>();
     let (sender, receiver) = flume::unbounded();
+    let pattern_configs = PatternConfigurationPool::new(pattern_config);
     
-    // Allocate thread resources
-    let allocated_threads = thread_pool.allocate_threads(thread_count);
+    dispatch_batches(read_receiver, thread_pool, move |batch_index, batch| {
+        let read_infos = pattern_configs.with(|pattern_config| {
+            batch.into_iter().flat_map(|read_info| classify_guarded(&work, read_info, pattern_config)).collect()
+        });
+        let _ = result_sender.send((batch_index, read_infos));
+    });
     
-    for _thread_id in 0..allocated_threads {
-        let start_time = Instant::now();
-        let read_receiver = read_receiver.clone();
-        let sender = sender.clone();
-        let pattern_config = pattern_config.clone();
-        
-        // Use controlled thread creation
-        if let Some(_handle) = thread_pool.spawn_controlled_thread(move || {
-            let mut _processed_count = 0;
-            
-            for mut read_info in read_receiver.iter() {
-                read_info.split_types = perform_sequence_splitting_vector(&read_info, &pattern_config);
-                
-                // Update sequence information
-                read_info.update(
-                    &pattern_config.pattern_match_types,
-                    &pattern_config.write_type,
-                    pattern_config.trim_mode,
-                    pattern_config.min_length,
-                    &pattern_config.id_separator,
-                );
-                
-                // Detect fusion sequence
-                if !pattern_config.fusion_database.is_empty() 
-                    && detect_fusion_sequence(&read_info, &pattern_config) 
-                {
-                    read_info.sequence_type = "fusion".into();
-                    read_info.should_write_to_fastq = false;
+    std::thread::spawn(move || {
+        let mut pending: HashMap<usize, Vec<ReadInfo>> = HashMap::new();
+        let mut next_batch_index = 0;
+        for (batch_index, read_infos) in result_receiver.iter() {
+            pending.insert(batch_index, read_infos);
+            while let Some(read_infos) = pending.remove(&next_batch_index) {
+                for read_info in read_infos {
+                    if sender.send(read_info).is_err() {
+                        return;
+                    }
                 }
-                
-                sender.send(read_info).expect("Failed to send sequence information");
-                _processed_count += 1;
+                next_batch_index += 1;
             }
+        }
+    });
+    
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::PatternDatabase;
+    use crate::utils::reverse_complement;
+
+    /// Round with two barcodes one substitution apart on both read ends
+    fn close_barcode_round(min_score_margin: usize) -> PatternArgument {
+        let mut pattern_database = PatternDatabase::new();
+        for (name, sequence) in [("BC01", "AAGAAAGTTGTCGGTGTCTTTGTG"), ("BC02", "AAGAAAGTTGTCCGTGTCTTTGTG")] {
+            pattern_database.forward_patterns.insert(name.to_string(), sequence.to_string());
+            pattern_database.reverse_patterns.insert(name.to_string(), reverse_complement(sequence, true));
+        }
+        PatternArgument {
+            pattern_database,
+            use_position_info: false,
+            pattern_error_rate: (0.2, 0.2),
+            max_edits: None,
+            effective_length: crate::myers::EffectiveLength::default(),
+            max_distance: 4,
+            position_shift: 3,
+            early_exit_score: 0,
+            anchor: 0,
+            dual_policy: "best-score".to_string(),
+            min_score_margin,
+            fixed_position: None,
+        }
+    }
+
+    #[test]
+    fn test_min_score_margin_sees_runner_up_of_exact_hit() {
+        let barcode = "AAGAAAGTTGTCGGTGTCTTTGTG";
+        let sequence = format!("{}{}{}{}{}", "T".repeat(30), barcode, "C".repeat(200), reverse_complement(barcode, true), "T".repeat(30));
+        // Read end windows are searched in one packed scan, windows at known positions one pattern at a time
+        let read_chunks = [
+            ReadChunk { left_bound: 100, right_bound: sequence.len() - 100, use_position_mutation: false },
+            ReadChunk { left_bound: 30 + barcode.len(), right_bound: 30 + barcode.len() + 200, use_position_mutation: true },
+        ];
+        for read_chunk in &read_chunks {
+            let split_type = perform_sequence_splitting(sequence.as_bytes(), read_chunk, &close_barcode_round(2));
+            assert_eq!((split_type.left_matcher.get_pattern(), split_type.left_matcher.score), ("BC01", 0));
+            assert_eq!(split_type.left_matcher.runner_up_score, 1);
+            assert!(split_type.ambiguous);
             
-            let _elapsed_time = start_time.elapsed();
-            // Thread processing complete, no log output to avoid interference
-        }) {
-            // Thread creation successful, continue processing
-        } else {
-            // Thread creation failed, release resources
-            thread_pool.release_threads(1);
+            let split_type = perform_sequence_splitting(sequence.as_bytes(), read_chunk, &close_barcode_round(0));
+            assert_eq!(split_type.left_matcher.get_pattern(), "BC01");
+            assert!(!split_type.ambiguous);
         }
     }
     
-    receiver
-}
\ No newline at end of file
+    #[test]
+    fn test_ordered_receiver_survives_panicking_reads() {
+        let thread_pool = ThreadPoolManager::new(2, 2);
+        let (read_sender, read_receiver) = flume::unbounded();
+        for read_number in 0..1000 {
+            let record = bio::io::fastq::Record::with_attrs(&format!("read_{}", read_number), None, b"ACGT", b"IIII");
+            read_sender.send(ReadInfo::new(record)).unwrap();
+        }
+        drop(read_sender);
+        
+        let failures_before = split_failures();
+        let receiver = create_ordered_receiver(read_receiver, &PatternConfiguration::default(), &thread_pool, |read_info, _| {
+            if read_info.original_id == "read_300" {
+                panic!("Injected classification failure");
+            }
+            vec![read_info]
+        });
+        let read_ids: Vec<String> = receiver.iter().map(|read_info| read_info.original_id).collect();
+        let expected: Vec<String> = (0..1000).filter(|&read_number| read_number != 300).map(|read_number| format!("read_{}", read_number)).collect();
+        assert_eq!(read_ids, expected);
+        assert!(split_failures() > failures_before);
+    }
+}
@@ -0,0 +1,208 @@
+use crate::args::Commands;
+use crate::counter::{ReadStats, StatisticsManager};
+use crate::report;
+use flate2::read::MultiGzDecoder;
+use log::info;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+/// Leading columns of logs written before the header, output and trim columns were added
+const LEGACY_LEADING_COLUMNS: usize = 3;
+
+/// Columns logged for each round: match, name, type, strand with coordinates
+const ROUND_COLUMNS: usize = 4;
+
+/// One parsed reads_log line
+struct LoggedRead {
+    sequence_length: usize,
+    sequence_type: String,
+    rounds: Vec<LoggedRound>,
+}
+
+/// Match columns of one round
+struct LoggedRound {
+    pattern_match: String,
+    pattern_name: String,
+    pattern_type: String,
+    pattern_strand: String,
+    /// Pattern name and edit distance of the matched ends
+    match_distances: Vec<(String, i32)>,
+}
+
+impl LoggedRead {
+    /// Parse a reads_log line with the given number of leading columns
+    fn parse(line: &str, leading_columns: usize) -> Option<Self> {
+        let columns: Vec<&str> = line.split('\t').collect();
+        if columns.len() < leading_columns {
+            return None;
+        }
+        let rounds = columns[leading_columns..]
+            .chunks_exact(ROUND_COLUMNS)
+            .map(|round| LoggedRound {
+                pattern_match: round[0].to_string(),
+                pattern_name: round[1].to_string(),
+                pattern_type: round[2].to_string(),
+                pattern_strand: round[3].split(':').next().unwrap_or("unknown").to_string(),
+                match_distances: logged_distances(round[3]),
+            })
+            .collect();
+        Some(Self {
+            sequence_length: columns[1].parse().ok()?,
+            sequence_type: columns[2].to_string(),
+            rounds,
+        })
+    }
+
+    /// Statistics record equivalent to the one counted during the run
+    fn to_stats(&self) -> ReadStats {
+        let mut match_names: Vec<String> = self.rounds.iter().map(|round| round.pattern_name.clone()).collect();
+        let mut match_types: Vec<String> = self
+            .rounds
+            .iter()
+            .map(|round| {
+                // Index hops are counted under the pair name
+                if round.pattern_match == "index_hop" {
+                    round.pattern_name.clone()
+                } else {
+                    round.pattern_type.clone()
+                }
+            })
+            .collect();
+        while match_names.len() < 3 {
+            match_names.push(String::from("default"));
+        }
+        while match_types.len() < 3 {
+            match_types.push(String::from("default"));
+        }
+        ReadStats {
+            sequence_type: self.sequence_type.clone(),
+            sequence_length: self.sequence_length,
+            match_types,
+            match_names,
+            quality_trimmed_bases: 0,
+            polyx_trimmed_bases: 0,
+            group_values: Vec::new(),
+            start_time: None,
+            fusion_class: String::new(),
+            strand: self.strand(),
+            match_distances: self.rounds.iter().flat_map(|round| round.match_distances.clone()).collect(),
+        }
+    }
+
+    /// Strand of the read, the one all rounds matched on or `unknown`
+    fn strand(&self) -> String {
+        match self.rounds.first() {
+            Some(first) if first.pattern_strand != "unknown"
+                && self.rounds.iter().all(|round| round.pattern_strand == first.pattern_strand) => first.pattern_strand.clone(),
+            _ => String::from("unknown"),
+        }
+    }
+
+    /// Value of a grouping field such as `sequence_type` or `type2`
+    fn group_value(&self, field: &str) -> String {
+        if field == "sequence_type" {
+            return self.sequence_type.clone();
+        }
+        let (name, round) = split_group_field(field).expect("Group field was validated");
+        let Some(logged_round) = self.rounds.get(round - 1) else {
+            return String::from("-");
+        };
+        match name {
+            "match" => logged_round.pattern_match.clone(),
+            "name" => logged_round.pattern_name.clone(),
+            "type" => logged_round.pattern_type.clone(),
+            _ => logged_round.pattern_strand.clone(),
+        }
+    }
+}
+
+/// Matched ends of a `strand:(name,score,start,end);(...)` column, unmatched ends having no name
+fn logged_distances(column: &str) -> Vec<(String, i32)> {
+    let Some((_, ends)) = column.split_once(':') else {
+        return Vec::new();
+    };
+    ends.split(';')
+        .filter_map(|end| {
+            let mut fields = end.trim_matches(|character| character == '(' || character == ')').split(',');
+            let name = fields.next().filter(|name| !name.is_empty())?;
+            let distance = fields.next()?.parse().ok()?;
+            Some((name.to_string(), distance))
+        })
+        .collect()
+}
+
+/// Split a per-round grouping field into its name and 1-based round
+pub fn split_group_field(field: &str) -> Option<(&str, usize)> {
+    let split_at = field.find(|character: char| character.is_ascii_digit())?;
+    let (name, round) = field.split_at(split_at);
+    let round: usize = round.parse().ok()?;
+    (["match", "name", "type", "strand"].contains(&name) && round > 0).then_some((name, round))
+}
+
+/// Handle stats subcommand, recompute statistics from an existing read log
+pub fn handle_stats_command(stats_args: &Commands) {
+    let Commands::Stats { log, outdir, group_by } = stats_args else {
+        return;
+    };
+    info!("Recomputing statistics from {}", log);
+
+    std::fs::create_dir_all(outdir).expect("Failed to create output directory");
+    let mut statistics_manager = StatisticsManager::new(outdir.clone());
+    statistics_manager.set_group_by(group_by.clone());
+
+    let file = File::open(log).unwrap_or_else(|_| panic!("Unable to open read log: {}", log));
+    let reader: Box<dyn BufRead> = if log.ends_with(".gz") {
+        Box::new(BufReader::new(MultiGzDecoder::new(file)))
+    } else {
+        Box::new(BufReader::new(file))
+    };
+
+    // Logs without a header row come from older versions
+    let mut leading_columns = LEGACY_LEADING_COLUMNS;
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line.expect("Failed to read read log");
+        if line.is_empty() {
+            continue;
+        }
+        if line_number == 0 && line.starts_with("read_id\t") {
+            // Leading columns were added over time, the match columns start at match1
+            let columns: Vec<&str> = line.split('\t').collect();
+            leading_columns = columns.iter().position(|column| *column == "match1").unwrap_or(columns.len());
+            continue;
+        }
+        let logged_read = LoggedRead::parse(&line, leading_columns)
+            .unwrap_or_else(|| panic!("Malformed read log line {}: {}", line_number + 1, line));
+        let mut read_stats = logged_read.to_stats();
+        read_stats.group_values = group_by.iter().map(|field| logged_read.group_value(field)).collect();
+        statistics_manager.process_read_stats(&read_stats);
+    }
+
+    statistics_manager.write_total_statistics();
+    statistics_manager.write_valid_statistics();
+    statistics_manager.write_score_statistics();
+    statistics_manager.write_grouped_statistics();
+    statistics_manager.write_strand_statistics();
+    statistics_manager.print_statistics();
+}
+
+/// Handle merge-stats subcommand, sum the statistics of several output directories
+pub fn handle_merge_stats_command(merge_args: &Commands) {
+    let Commands::MergeStats { directories, outdir } = merge_args else {
+        return;
+    };
+    info!("Merging statistics of {} runs", directories.len());
+
+    std::fs::create_dir_all(outdir).expect("Failed to create output directory");
+    let mut statistics_manager = StatisticsManager::new(outdir.clone());
+    for directory in directories {
+        statistics_manager.merge_statistics(directory);
+    }
+
+    statistics_manager.write_total_statistics();
+    statistics_manager.write_valid_statistics();
+    statistics_manager.write_score_statistics();
+    statistics_manager.write_fusion_statistics();
+    statistics_manager.write_strand_statistics();
+    report::write_merge_info(outdir, directories);
+    statistics_manager.print_statistics();
+}
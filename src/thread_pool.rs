@@ -1,121 +1,206 @@
+use crate::perf;
+use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::thread;
-use log::info;
+use std::time::{Duration, Instant};
+use flume::{Receiver, Selector, Sender};
+use log::{error, info};
 
-/// Thread pool manager
+/// Unit of work queued on the pool
+type Task = Box<dyn FnOnce() + Send + 'static>;
+
+/// Longest an idle worker waits before re-reading its preferred stage
+const IDLE_WAIT: Duration = Duration::from_millis(50);
+
+/// Handle for queueing tasks on the pool, cheap to clone
+///
+/// Workers stop once every handle has been dropped and the queues are empty.
+#[derive(Clone)]
+pub struct TaskQueue {
+    processing: Sender<Task>,
+    writing: Sender<Task>,
+}
+
+impl TaskQueue {
+    /// Queue a splitting task
+    pub fn execute_processing<F>(&self, task: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.processing.send(Box::new(move || {
+            let timer = perf::start();
+            task();
+            perf::record_split_task(timer);
+        })).expect("Thread pool stopped");
+    }
+
+    /// Queue a writing task
+    pub fn execute_writing<F>(&self, task: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.writing.send(Box::new(move || {
+            let timer = perf::start();
+            task();
+            perf::record_write_task(timer);
+        })).expect("Thread pool stopped");
+    }
+}
+
+/// Fixed set of worker threads shared by splitting and writing
+///
+/// Each stage has its own task queue. The first `processing_threads` workers
+/// take splitting tasks first and the others writing tasks first; a worker
+/// whose preferred queue is empty steals from the other one, so no thread
+/// idles while work is queued and no task waits for a thread to be spawned.
 pub struct ThreadPoolManager {
     /// Maximum thread count limit
     max_threads: usize,
-    /// Current active thread count
-    active_threads: Arc<AtomicUsize>,
-    /// Thread handle storage
-    _thread_handles: Vec<thread::JoinHandle<()>>,
+    /// Queues shared with the workers
+    task_queue: TaskQueue,
+    /// Number of workers preferring splitting tasks
+    processing_threads: Arc<AtomicUsize>,
 }
 
 impl ThreadPoolManager {
-    /// Create new thread pool manager
-    pub fn new(max_threads: usize) -> Self {
-        // info!("Creating thread pool manager, max threads: {}", max_threads);
+    /// Start `max_threads` workers, the first `processing_threads` preferring splitting tasks
+    pub fn new(max_threads: usize, processing_threads: usize) -> Self {
+        let max_threads = max_threads.max(1);
+        let (processing, processing_receiver) = flume::unbounded();
+        let (writing, writing_receiver) = flume::unbounded();
+        let processing_threads = Arc::new(AtomicUsize::new(processing_threads));
+
+        for worker in 0..max_threads {
+            let processing_receiver = processing_receiver.clone();
+            let writing_receiver = writing_receiver.clone();
+            let processing_threads = Arc::clone(&processing_threads);
+            thread::Builder::new()
+                .name(format!("worker-{}", worker))
+                .spawn(move || run_worker(worker, &processing_receiver, &writing_receiver, &processing_threads))
+                .expect("Failed to start worker thread");
+        }
+
         Self {
             max_threads,
-            active_threads: Arc::new(AtomicUsize::new(0)),
-            _thread_handles: Vec::new(),
+            task_queue: TaskQueue { processing, writing },
+            processing_threads,
         }
     }
 
-
-    /// Get remaining available thread count
-    pub fn get_available_threads(&self) -> usize {
-        let active = self.active_threads.load(Ordering::Relaxed);
-        if active < self.max_threads {
-            self.max_threads - active
-        } else {
-            0
-        }
+    /// Handle for queueing tasks
+    pub fn task_queue(&self) -> TaskQueue {
+        self.task_queue.clone()
     }
 
-    /// Check if new thread can be created
-    pub fn can_spawn_thread(&self) -> bool {
-        self.active_threads.load(Ordering::Relaxed) < self.max_threads
+    /// Number of worker threads
+    pub fn max_threads(&self) -> usize {
+        self.max_threads
     }
 
-    /// Allocate thread resources
-    pub fn allocate_threads(&self, requested_threads: usize) -> usize {
-        let available = self.get_available_threads();
-        let allocated = std::cmp::min(requested_threads, available);
-        if allocated > 0 {
-            self.active_threads.fetch_add(allocated, Ordering::Relaxed);
-        }
-        allocated
+    /// Change how many workers prefer splitting tasks
+    pub fn set_processing_threads(&self, processing_threads: usize) {
+        self.processing_threads.store(processing_threads, Ordering::Relaxed);
     }
 
-    /// Release thread resources
-    pub fn release_threads(&self, count: usize) {
-        if count > 0 {
-            self.active_threads.fetch_sub(count, Ordering::Relaxed);
-        }
+    /// Get thread usage statistics: workers, queued splitting tasks, queued writing tasks
+    pub fn get_thread_stats(&self) -> (usize, usize, usize) {
+        (self.max_threads, self.task_queue.processing.len(), self.task_queue.writing.len())
     }
+}
 
-    /// Create controlled thread
-    pub fn spawn_controlled_thread<F, T>(&mut self, f: F) -> Option<thread::JoinHandle<T>>
-    where
-        F: FnOnce() -> T + Send + 'static,
-        T: Send + 'static,
-    {
-        if !self.can_spawn_thread() {
-            return None;
+/// Run queued tasks until every queue handle is dropped
+fn run_worker(worker: usize, processing: &Receiver<Task>, writing: &Receiver<Task>, processing_threads: &AtomicUsize) {
+    loop {
+        let (preferred, other) = if worker < processing_threads.load(Ordering::Relaxed) {
+            (processing, writing)
+        } else {
+            (writing, processing)
+        };
+        let task = match preferred.try_recv().or_else(|_| other.try_recv()) {
+            Ok(task) => task,
+            Err(_) if preferred.is_disconnected() && other.is_disconnected() => return,
+            // Wait on both queues, waking regularly to pick up a changed preference
+            Err(_) => match Selector::new()
+                .recv(preferred, Result::ok)
+                .recv(other, Result::ok)
+                .wait_timeout(IDLE_WAIT)
+            {
+                Ok(Some(task)) => task,
+                _ => continue,
+            },
+        };
+        // A failing task must not take its worker down with it
+        if catch_unwind(AssertUnwindSafe(task)).is_err() {
+            error!("Thread pool task panicked");
         }
-
-        self.active_threads.fetch_add(1, Ordering::Relaxed);
-        let active_threads = Arc::clone(&self.active_threads);
-        
-        let handle = thread::spawn(move || {
-            let result = f();
-            active_threads.fetch_sub(1, Ordering::Relaxed);
-            result
-        });
-
-        Some(handle)
     }
+}
 
-    /// Wait for all threads to complete
+/// Interval between channel depth samples under the adaptive strategy
+const REBALANCE_INTERVAL: Duration = Duration::from_millis(500);
 
-    /// Get thread usage statistics
-    pub fn get_thread_stats(&self) -> (usize, usize, usize) {
-        let active = self.active_threads.load(Ordering::Relaxed);
-        let available = self.get_available_threads();
-        (self.max_threads, active, available)
-    }
-}
+/// Backlog in reads below which a stage is not considered the bottleneck
+const REBALANCE_MIN_BACKLOG: usize = 1024;
 
 /// Thread allocation strategy
+#[derive(Clone, Copy, Debug)]
 pub enum ThreadAllocationStrategy {
     /// Balanced allocation: processing and writing threads allocated by ratio
     Balanced {
         processing_ratio: f32,  // Processing thread ratio (0.0-1.0)
     },
+    /// Processing first: every thread but one goes to splitting
+    Priority,
+    /// Even split between processing and writing
+    Fixed,
+    /// Start from a ratio, then move threads to the stage with the larger backlog
+    Adaptive {
+        processing_ratio: f32,
+    },
 }
 
 impl ThreadAllocationStrategy {
+    /// Strategy for a `--thread-strategy` value
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "priority" => ThreadAllocationStrategy::Priority,
+            "fixed" => ThreadAllocationStrategy::Fixed,
+            "adaptive" => ThreadAllocationStrategy::Adaptive { processing_ratio: 0.8 },
+            _ => ThreadAllocationStrategy::Balanced { processing_ratio: 0.8 },
+        }
+    }
+
+    /// Whether threads are reassigned while the run is in progress
+    pub fn is_adaptive(&self) -> bool {
+        matches!(self, ThreadAllocationStrategy::Adaptive { .. })
+    }
+
     /// Calculate thread allocation
+    ///
+    /// Each stage keeps at least one thread once there are two to share.
     pub fn calculate_allocation(&self, total_threads: usize) -> (usize, usize) {
-        match self {
-            ThreadAllocationStrategy::Balanced { processing_ratio } => {
-                let processing_threads = (total_threads as f32 * processing_ratio) as usize;
-                let writing_threads = total_threads - processing_threads;
-                (processing_threads.max(1), writing_threads)
+        let processing_threads = match self {
+            ThreadAllocationStrategy::Balanced { processing_ratio }
+            | ThreadAllocationStrategy::Adaptive { processing_ratio } => {
+                (total_threads as f32 * processing_ratio) as usize
             }
-        }
+            ThreadAllocationStrategy::Priority => total_threads.saturating_sub(1),
+            ThreadAllocationStrategy::Fixed => total_threads / 2,
+        };
+        let processing_threads = processing_threads.clamp(1, total_threads.saturating_sub(1).max(1));
+        (processing_threads, total_threads.saturating_sub(processing_threads))
     }
 }
 
 /// Thread usage monitor
 pub struct ThreadMonitor {
     thread_pool: ThreadPoolManager,
-    _allocation_strategy: ThreadAllocationStrategy,
+    allocation_strategy: ThreadAllocationStrategy,
+    total_threads: usize,
     processing_threads: usize,
     writing_threads: usize,
+    last_rebalance: Instant,
 }
 
 impl ThreadMonitor {
@@ -124,39 +209,72 @@ impl ThreadMonitor {
         let (processing_threads, writing_threads) = strategy.calculate_allocation(total_threads);
         
         info!(
-            "Thread allocation strategy: total_threads={}, processing_threads={}, writing_threads={}", 
-            total_threads, processing_threads, writing_threads
+            "Thread allocation strategy: {:?}, total_threads={}, processing_threads={}, writing_threads={}", 
+            strategy, total_threads, processing_threads, writing_threads
         );
 
         Self {
-            thread_pool: ThreadPoolManager::new(total_threads),
-            _allocation_strategy: strategy,
+            thread_pool: ThreadPoolManager::new(total_threads, processing_threads),
+            allocation_strategy: strategy,
+            total_threads,
             processing_threads,
             writing_threads,
+            last_rebalance: Instant::now(),
         }
     }
 
-    /// Get processing thread count
-    pub fn get_processing_threads(&self) -> usize {
-        self.processing_threads
-    }
-
     /// Get writing thread count
     pub fn get_writing_threads(&self) -> usize {
         self.writing_threads
     }
 
+    /// Most workers the adaptive strategy may give to processing, keeping one preferring writing
+    fn get_max_processing_threads(&self) -> usize {
+        self.total_threads.saturating_sub(1).max(1)
+    }
+
     /// Get thread pool manager
-    pub fn get_thread_pool(&mut self) -> &mut ThreadPoolManager {
-        &mut self.thread_pool
+    pub fn get_thread_pool(&self) -> &ThreadPoolManager {
+        &self.thread_pool
+    }
+
+    /// Whether the adaptive strategy is due to sample the stage backlogs
+    pub fn should_rebalance(&self) -> bool {
+        self.allocation_strategy.is_adaptive() && self.last_rebalance.elapsed() >= REBALANCE_INTERVAL
+    }
+
+    /// Move one worker's preference toward the stage with the larger backlog
+    ///
+    /// `processing_backlog` counts reads waiting to be split and
+    /// `writing_backlog` split reads waiting to be written.
+    pub fn rebalance(&mut self, processing_backlog: usize, writing_backlog: usize) {
+        self.last_rebalance = Instant::now();
+
+        let processing_threads = if processing_backlog > writing_backlog.max(REBALANCE_MIN_BACKLOG)
+            && self.processing_threads < self.get_max_processing_threads()
+        {
+            self.processing_threads + 1
+        } else if writing_backlog > processing_backlog.max(REBALANCE_MIN_BACKLOG) && self.processing_threads > 1 {
+            self.processing_threads - 1
+        } else {
+            return;
+        };
+
+        self.writing_threads = self.total_threads.saturating_sub(processing_threads);
+        self.processing_threads = processing_threads;
+        self.thread_pool.set_processing_threads(processing_threads);
+        info!(
+            "Rebalanced threads: processing_threads={}, writing_threads={} (processing backlog {}, writing backlog {})",
+            self.processing_threads, self.writing_threads, processing_backlog, writing_backlog
+        );
     }
 
     /// Print thread usage statistics
     pub fn print_thread_stats(&self) {
-        let (max, active, available) = self.thread_pool.get_thread_stats();
+        let (max, queued_processing, queued_writing) = self.thread_pool.get_thread_stats();
         info!(
-            "Thread usage statistics: max={}, active={}, available={}, processing_threads={}, writing_threads={}",
-            max, active, available, self.processing_threads, self.writing_threads
+            "Thread usage statistics: max={}, processing_threads={}, writing_threads={}, queued_processing_tasks={}, queued_writing_tasks={}",
+            max, self.processing_threads, self.writing_threads, queued_processing, queued_writing
         );
     }
 }
@@ -0,0 +1,499 @@
+use crate::fastq::ReadInfo;
+use crate::pattern::PatternConfiguration;
+use crate::splitter::perform_sequence_splitting_vector;
+use crate::utils::truncate_ansi;
+use crate::view::{barcode_positions, detected_patterns, push_highlighted, round_legend};
+use bio::io::fastq::Record;
+use flume::Receiver;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Change applied to every error rate by the `+` and `-` keys
+const ERROR_RATE_STEP: f32 = 0.01;
+
+/// Largest error rate accepted on the command line
+const MAX_ERROR_RATE: f32 = 0.5;
+
+/// Key bindings shown at the bottom of the screen
+const HELP: &str = "n/→ next  p/← previous  u next unmatched  s quality  +/- error rate  j/k scroll  q/Esc quit";
+
+/// Time the rest of an escape sequence may take to arrive before ESC counts as a key of its own
+const ESCAPE_SEQUENCE_TIMEOUT_MS: libc::c_int = 50;
+
+/// Longest wait for a key before checking again whether the terminal was resized
+const RESIZE_CHECK_MS: libc::c_int = 250;
+
+/// Set by SIGWINCH, cleared once the viewer redraws for the new size
+static RESIZED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_resize(_signal: libc::c_int) {
+    RESIZED.store(true, Ordering::Relaxed);
+}
+
+/// Keys understood by the viewer, with a resized terminal as a key of its own
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Key {
+    Next,
+    Previous,
+    NextUnmatched,
+    ToggleQuality,
+    RaiseErrorRate,
+    LowerErrorRate,
+    ScrollDown,
+    ScrollUp,
+    Resize,
+    Quit,
+    Other,
+}
+
+/// Key of an input byte, reading the rest of an escape sequence from `next_byte`
+fn decode_key(byte: u8, mut next_byte: impl FnMut() -> Option<u8>) -> Key {
+    match byte {
+        b'n' | b' ' => Key::Next,
+        b'p' | b'b' => Key::Previous,
+        b'u' => Key::NextUnmatched,
+        b's' => Key::ToggleQuality,
+        b'+' | b'=' => Key::RaiseErrorRate,
+        b'-' => Key::LowerErrorRate,
+        b'j' => Key::ScrollDown,
+        b'k' => Key::ScrollUp,
+        // Ctrl-C and Ctrl-\ no longer raise signals in raw mode
+        b'q' | 0x03 | 0x1c => Key::Quit,
+        0x1b => {
+            // Arrow keys arrive as ESC [ A-D right away, a bare ESC is followed by nothing
+            match next_byte() {
+                None => Key::Quit,
+                Some(b'[') => match next_byte() {
+                    Some(b'A') => Key::ScrollUp,
+                    Some(b'B') => Key::ScrollDown,
+                    Some(b'C') => Key::Next,
+                    Some(b'D') => Key::Previous,
+                    _ => Key::Other,
+                },
+                Some(_) => Key::Other,
+            }
+        }
+        _ => Key::Other,
+    }
+}
+
+/// Controlling terminal in raw mode on the alternate screen, restored on drop
+struct RawTerminal {
+    tty: File,
+    original: libc::termios,
+    /// SIGWINCH handling before the viewer started
+    original_resize_action: libc::sigaction,
+}
+
+impl RawTerminal {
+    /// Switch the controlling terminal to unbuffered input without echo
+    ///
+    /// Signal keys are read as input, so Ctrl-C quits through `Drop` and the
+    /// terminal is always restored. SIGWINCH interrupts the wait for a key so
+    /// the screen is redrawn for the new size.
+    fn enter() -> Self {
+        let tty = File::options()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+            .expect("Unable to open the terminal for --tui");
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(tty.as_raw_fd(), &mut original) } != 0 {
+            panic!("Unable to read terminal settings for --tui");
+        }
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        unsafe { libc::tcsetattr(tty.as_raw_fd(), libc::TCSANOW, &raw) };
+
+        let handler: extern "C" fn(libc::c_int) = handle_resize;
+        let mut original_resize_action: libc::sigaction = unsafe { std::mem::zeroed() };
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handler as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(libc::SIGWINCH, &action, &mut original_resize_action);
+        }
+
+        let mut terminal = Self { tty, original, original_resize_action };
+        terminal.write("\x1b[?1049h\x1b[?25l");
+        terminal
+    }
+
+    /// Terminal size as (columns, rows)
+    fn size(&self) -> (usize, usize) {
+        let mut window_size: libc::winsize = unsafe { std::mem::zeroed() };
+        let result = unsafe { libc::ioctl(self.tty.as_raw_fd(), libc::TIOCGWINSZ, &mut window_size) };
+        if result == 0 && window_size.ws_col > 0 && window_size.ws_row > 0 {
+            (window_size.ws_col as usize, window_size.ws_row as usize)
+        } else {
+            (80, 24)
+        }
+    }
+
+    /// Write text to the terminal immediately
+    fn write(&mut self, text: &str) {
+        let _ = self.tty.write_all(text.as_bytes());
+        let _ = self.tty.flush();
+    }
+
+    /// Next input byte if one arrives within `timeout_ms`
+    fn read_byte_within(&mut self, timeout_ms: libc::c_int) -> Option<u8> {
+        let mut poll_fd = libc::pollfd { fd: self.tty.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+        if unsafe { libc::poll(&mut poll_fd, 1, timeout_ms) } <= 0 {
+            return None;
+        }
+        let mut byte = [0u8; 1];
+        self.tty.read_exact(&mut byte).ok().map(|_| byte[0])
+    }
+
+    /// Block until a key is pressed or the terminal is resized
+    fn read_key(&mut self) -> Key {
+        loop {
+            if RESIZED.swap(false, Ordering::Relaxed) {
+                return Key::Resize;
+            }
+            // SIGWINCH cuts the wait short; the timeout covers one arriving just before it
+            let mut poll_fd = libc::pollfd { fd: self.tty.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+            if unsafe { libc::poll(&mut poll_fd, 1, RESIZE_CHECK_MS) } <= 0 {
+                continue;
+            }
+            let mut byte = [0u8; 1];
+            if self.tty.read_exact(&mut byte).is_err() {
+                return Key::Quit;
+            }
+            return decode_key(byte[0], || self.read_byte_within(ESCAPE_SEQUENCE_TIMEOUT_MS));
+        }
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        self.write("\x1b[?25h\x1b[?1049l");
+        unsafe {
+            libc::tcsetattr(self.tty.as_raw_fd(), libc::TCSANOW, &self.original);
+            libc::sigaction(libc::SIGWINCH, &self.original_resize_action, std::ptr::null_mut());
+        }
+    }
+}
+
+/// Interactive read pager state
+struct Viewer {
+    receiver: Receiver<ReadInfo>,
+    reads: Vec<ReadInfo>,
+    current: usize,
+    scroll: usize,
+    show_quality: bool,
+    status: String,
+    pattern_config: PatternConfiguration,
+    pattern_files: Vec<String>,
+}
+
+impl Viewer {
+    /// Make sure the read at `index` has been loaded, false past the end of input
+    fn load(&mut self, index: usize) -> bool {
+        while self.reads.len() <= index {
+            match self.receiver.recv() {
+                Ok(read_info) => self.reads.push(read_info),
+                Err(_) => return false,
+            }
+        }
+        true
+    }
+
+    /// Recognize a loaded read with the current settings, leaving the loaded copy untouched
+    fn recognize(&self, index: usize) -> ReadInfo {
+        let read = &self.reads[index];
+        let sequence = read.sequence().expect("Sequence data not available");
+        let quality = read.quality().expect("Quality data not available");
+        let mut read_info = ReadInfo::new(Record::with_attrs(&read.original_id, None, sequence, quality));
+        read_info.split_types = perform_sequence_splitting_vector(&read_info, &self.pattern_config);
+        read_info.update(&self.pattern_config);
+        read_info
+    }
+
+    /// Move to another read and reset the scroll position
+    fn show(&mut self, index: usize) {
+        self.current = index;
+        self.scroll = 0;
+        self.status.clear();
+    }
+
+    /// Move to the next read that is not assigned with the current settings
+    fn jump_to_unmatched(&mut self) {
+        let mut index = self.current + 1;
+        while self.load(index) {
+            if self.recognize(index).sequence_type != "valid" {
+                self.show(index);
+                return;
+            }
+            index += 1;
+        }
+        self.status = String::from("No unmatched read after this one");
+    }
+
+    /// Shift every round's error rates, re-matching the current read on the next render
+    fn adjust_error_rate(&mut self, change: f32) {
+        for pattern_argument in &mut self.pattern_config.pattern_arguments {
+            let (left, right) = pattern_argument.pattern_error_rate;
+            pattern_argument.pattern_error_rate = (
+                (left + change).clamp(0.0, MAX_ERROR_RATE),
+                (right + change).clamp(0.0, MAX_ERROR_RATE),
+            );
+        }
+    }
+
+    /// Error rates of all rounds for the header line
+    fn error_rates(&self) -> String {
+        let rates: Vec<String> = self
+            .pattern_config
+            .pattern_arguments
+            .iter()
+            .map(|pattern_argument| {
+                format!("{:.2},{:.2}", pattern_argument.pattern_error_rate.0, pattern_argument.pattern_error_rate.1)
+            })
+            .collect();
+        rates.join(" ")
+    }
+
+    /// Apply a key, false once the viewer should close
+    fn handle_key(&mut self, key: Key) -> bool {
+        match key {
+            Key::Next => {
+                if self.load(self.current + 1) {
+                    self.show(self.current + 1);
+                } else {
+                    self.status = String::from("Last read");
+                }
+            }
+            Key::Previous => self.show(self.current.saturating_sub(1)),
+            Key::NextUnmatched => self.jump_to_unmatched(),
+            Key::ToggleQuality => self.show_quality = !self.show_quality,
+            Key::RaiseErrorRate => self.adjust_error_rate(ERROR_RATE_STEP),
+            Key::LowerErrorRate => self.adjust_error_rate(-ERROR_RATE_STEP),
+            Key::ScrollDown => self.scroll += 1,
+            Key::ScrollUp => self.scroll = self.scroll.saturating_sub(1),
+            // The screen is redrawn for the new size after every key
+            Key::Resize | Key::Other => {}
+            Key::Quit => return false,
+        }
+        true
+    }
+
+    /// Screen of the current read for a terminal of (columns, rows), wrapping the sequence to its width
+    fn screen(&mut self, (width, height): (usize, usize)) -> String {
+        let read_info = self.recognize(self.current);
+        let sequence = read_info.sequence().unwrap_or_default();
+        let quality = read_info.quality().unwrap_or_default();
+        let positions = barcode_positions(&read_info.split_types, sequence.len());
+
+        let mut header = vec![
+            format!("Read {}/{}  Error rate: {}", self.current + 1, self.reads.len(), self.error_rates()),
+            round_legend(&self.pattern_files),
+            format!(
+                "Sequence ID: {} Length: {} Type: {} Names: {}",
+                read_info.original_id,
+                read_info.sequence_length,
+                read_info.sequence_type,
+                read_info.match_names.join(",")
+            ),
+            format!("Detected patterns: {}", detected_patterns(&read_info.split_types)),
+        ];
+        header.push(String::new());
+
+        let mut body = Vec::new();
+        for start in (0..sequence.len()).step_by(width.max(1)) {
+            let end = (start + width).min(sequence.len());
+            let mut row = String::new();
+            push_highlighted(&mut row, sequence, (start, end), &positions);
+            body.push(row);
+            if self.show_quality && end <= quality.len() {
+                body.push(format!("\x1b[2m{}\x1b[0m", String::from_utf8_lossy(&quality[start..end])));
+            }
+        }
+
+        let footer = if self.status.is_empty() { HELP.to_string() } else { format!("{}  |  {}", self.status, HELP) };
+        let body_height = height.saturating_sub(header.len() + 1);
+        self.scroll = self.scroll.min(body.len().saturating_sub(body_height));
+
+        let mut lines = header;
+        lines.extend(body.into_iter().skip(self.scroll).take(body_height));
+        while lines.len() + 1 < height {
+            lines.push(String::new());
+        }
+        lines.push(format!("\x1b[7m{}\x1b[0m", footer));
+
+        let screen: Vec<String> = lines.iter().map(|line| truncate_ansi(line, width)).collect();
+        screen.join("\n")
+    }
+
+    /// Draw the current read at the present terminal size
+    fn render(&mut self, terminal: &mut RawTerminal) {
+        let screen = self.screen(terminal.size());
+        terminal.write(&format!("\x1b[H\x1b[2J{}", screen));
+    }
+}
+
+/// Page through reads interactively, re-matching with the error rate adjusted live
+pub fn run_tui(receiver: Receiver<ReadInfo>, pattern_config: PatternConfiguration, pattern_files: Vec<String>) {
+    // Log lines would overwrite the screen
+    log::set_max_level(log::LevelFilter::Error);
+
+    let mut viewer = Viewer {
+        receiver,
+        reads: Vec::new(),
+        current: 0,
+        scroll: 0,
+        show_quality: false,
+        status: String::new(),
+        pattern_config,
+        pattern_files,
+    };
+    if !viewer.load(0) {
+        println!("No reads to display");
+        return;
+    }
+
+    let mut terminal = RawTerminal::enter();
+    loop {
+        viewer.render(&mut terminal);
+        if !viewer.handle_key(terminal.read_key()) {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::Args;
+    use clap::Parser;
+
+    /// Viewer over the first example reads with a read matching no barcode third
+    fn viewer() -> Viewer {
+        let args = Args::parse_from(["readchop", "-d", "example/ont_bc_pattern.db", "-p", "example/ont_bc_index.list"]);
+        let mut records: Vec<Record> = bio::io::fastq::Reader::from_file("example/example.fastq")
+            .unwrap()
+            .records()
+            .take(3)
+            .map(|record| record.unwrap())
+            .collect();
+        records.insert(2, Record::with_attrs("unmatched", None, &[b'C'; 300], &[b'I'; 300]));
+        let (sender, receiver) = flume::unbounded();
+        for record in records {
+            sender.send(ReadInfo::new(record)).unwrap();
+        }
+        Viewer {
+            receiver,
+            reads: Vec::new(),
+            current: 0,
+            scroll: 0,
+            show_quality: false,
+            status: String::new(),
+            pattern_config: crate::pattern::load_patterns(&args),
+            pattern_files: args.pattern_files.unwrap(),
+        }
+    }
+
+    /// Text of a screen line without its escape sequences
+    fn visible(line: &str) -> String {
+        let mut text = String::new();
+        let mut in_escape = false;
+        for character in line.chars() {
+            match character {
+                '\x1b' => in_escape = true,
+                _ if in_escape => in_escape = !character.is_ascii_alphabetic(),
+                _ => text.push(character),
+            }
+        }
+        text
+    }
+
+    #[test]
+    fn test_keys_decode() {
+        assert_eq!(decode_key(b'n', || None), Key::Next);
+        assert_eq!(decode_key(b'p', || None), Key::Previous);
+        assert_eq!(decode_key(b'j', || None), Key::ScrollDown);
+        assert_eq!(decode_key(b'k', || None), Key::ScrollUp);
+        assert_eq!(decode_key(0x03, || None), Key::Quit);
+        let mut arrow = b"[C".iter().copied();
+        assert_eq!(decode_key(0x1b, || arrow.next()), Key::Next);
+        // A bare Escape quits
+        assert_eq!(decode_key(0x1b, || None), Key::Quit);
+    }
+
+    #[test]
+    fn test_navigation_keys_move_between_reads_and_scroll() {
+        let mut viewer = viewer();
+        assert!(viewer.load(0));
+        assert!(viewer.handle_key(Key::ScrollDown));
+        viewer.handle_key(Key::ScrollDown);
+        assert_eq!(viewer.scroll, 2);
+        viewer.handle_key(Key::ScrollUp);
+        viewer.handle_key(Key::ScrollUp);
+        viewer.handle_key(Key::ScrollUp);
+        assert_eq!(viewer.scroll, 0);
+
+        viewer.handle_key(Key::ScrollDown);
+        viewer.handle_key(Key::Next);
+        assert_eq!((viewer.current, viewer.scroll), (1, 0));
+        viewer.handle_key(Key::Previous);
+        viewer.handle_key(Key::Previous);
+        assert_eq!(viewer.current, 0);
+
+        for _ in 0..4 {
+            viewer.handle_key(Key::Next);
+        }
+        assert_eq!((viewer.current, viewer.status.as_str()), (3, "Last read"));
+        assert!(!viewer.handle_key(Key::Quit));
+    }
+
+    #[test]
+    fn test_jump_to_unmatched_stops_at_unassigned_reads() {
+        let mut viewer = viewer();
+        assert!(viewer.load(0));
+        assert_eq!(viewer.recognize(0).sequence_type, "valid");
+        viewer.handle_key(Key::NextUnmatched);
+        assert_eq!(viewer.current, 2);
+        assert!(viewer.status.is_empty());
+        viewer.handle_key(Key::NextUnmatched);
+        assert_eq!((viewer.current, viewer.status.as_str()), (2, "No unmatched read after this one"));
+    }
+
+    #[test]
+    fn test_error_rate_stays_within_bounds() {
+        let mut viewer = viewer();
+        let initial = viewer.pattern_config.pattern_arguments[0].pattern_error_rate.0;
+        viewer.handle_key(Key::RaiseErrorRate);
+        assert!((viewer.pattern_config.pattern_arguments[0].pattern_error_rate.0 - initial - ERROR_RATE_STEP).abs() < 1e-6);
+        viewer.adjust_error_rate(1.0);
+        assert_eq!(viewer.pattern_config.pattern_arguments[0].pattern_error_rate, (MAX_ERROR_RATE, MAX_ERROR_RATE));
+        viewer.adjust_error_rate(-1.0);
+        viewer.handle_key(Key::LowerErrorRate);
+        assert_eq!(viewer.error_rates(), "0.00,0.00");
+    }
+
+    #[test]
+    fn test_screen_is_redrawn_for_the_resized_terminal() {
+        let mut viewer = viewer();
+        assert!(viewer.load(0));
+        let screen = viewer.screen((80, 24));
+        assert_eq!(screen.lines().count(), 24);
+
+        handle_resize(libc::SIGWINCH);
+        assert!(RESIZED.swap(false, Ordering::Relaxed));
+        assert!(viewer.handle_key(Key::Resize));
+        viewer.scroll = usize::MAX;
+        let screen = viewer.screen((40, 10));
+        let lines: Vec<String> = screen.lines().map(visible).collect();
+        assert_eq!(lines.len(), 10);
+        assert!(lines.iter().all(|line| line.chars().count() <= 40));
+        // The sequence wraps at the new width and the scroll position still shows a full page
+        let sequence_length = viewer.reads[0].sequence_length;
+        assert_eq!(viewer.scroll, sequence_length.div_ceil(40) - (10 - 6));
+        assert_eq!(lines[5].len(), 40);
+    }
+}
@@ -1,24 +1,391 @@
 use log::info;
+use std::path::{Path, PathBuf};
 
-/// Calculate the reverse complement of a DNA sequence
-pub fn reverse_complement(sequence: &str) -> String {
-    let mut complement = vec![' '; sequence.len()];
-    
-    for (i, nucleotide) in sequence.chars().enumerate() {
-        complement[sequence.len() - 1 - i] = match nucleotide {
-            'A' => 'T',
-            'T' => 'A',
-            'C' => 'G',
-            'G' => 'C',
-            'a' => 't',
-            't' => 'a',
-            'c' => 'g',
-            'g' => 'c',
-            _ => panic!("Invalid nucleotide character: {}", nucleotide),
-        };
+/// Complement of a single IUPAC nucleotide code, preserving case
+pub fn complement_base(nucleotide: char) -> Option<char> {
+    let complement = match nucleotide.to_ascii_uppercase() {
+        'A' => 'T',
+        'T' | 'U' => 'A',
+        'C' => 'G',
+        'G' => 'C',
+        'R' => 'Y',
+        'Y' => 'R',
+        'S' => 'S',
+        'W' => 'W',
+        'K' => 'M',
+        'M' => 'K',
+        'B' => 'V',
+        'V' => 'B',
+        'D' => 'H',
+        'H' => 'D',
+        'N' => 'N',
+        _ => return None,
+    };
+    if nucleotide.is_ascii_lowercase() {
+        Some(complement.to_ascii_lowercase())
+    } else {
+        Some(complement)
+    }
+}
+
+/// Byte complements of the IUPAC nucleotide codes in both cases, 0 for other bytes
+const COMPLEMENT_TABLE: [u8; 256] = {
+    let mut table = [0u8; 256];
+    let bases = b"ACGTURYSWKMBVDHN";
+    let complements = b"TGCAAYRSWMKVBHDN";
+    let mut index = 0;
+    while index < bases.len() {
+        table[bases[index] as usize] = complements[index];
+        table[bases[index].to_ascii_lowercase() as usize] = complements[index].to_ascii_lowercase();
+        index += 1;
+    }
+    table
+};
+
+/// Complement of a single IUPAC nucleotide byte, preserving case
+pub fn complement_byte(nucleotide: u8) -> Option<u8> {
+    match COMPLEMENT_TABLE[nucleotide as usize] {
+        0 => None,
+        complement => Some(complement),
+    }
+}
+
+/// Reverse complement of a byte sequence over the IUPAC alphabet, unknown bytes become N
+pub fn reverse_complement_bytes(sequence: &[u8]) -> Vec<u8> {
+    sequence
+        .iter()
+        .rev()
+        .map(|&nucleotide| complement_byte(nucleotide).unwrap_or(b'N'))
+        .collect()
+}
+
+/// Calculate the reverse complement of a DNA sequence over the IUPAC alphabet
+///
+/// Unknown characters panic when `strict` is set and become N otherwise.
+pub fn reverse_complement(sequence: &str, strict: bool) -> String {
+    // Non-ASCII text is complemented per character so each one becomes a single N
+    if !sequence.is_ascii() {
+        return sequence
+            .chars()
+            .rev()
+            .map(|nucleotide| match complement_base(nucleotide) {
+                Some(complement) => complement,
+                None if strict => panic!("Invalid nucleotide character: {}", nucleotide),
+                None => 'N',
+            })
+            .collect();
+    }
+    let complement: Vec<u8> = sequence
+        .bytes()
+        .rev()
+        .map(|nucleotide| match complement_byte(nucleotide) {
+            Some(complement) => complement,
+            None if strict => panic!("Invalid nucleotide character: {}", nucleotide as char),
+            None => b'N',
+        })
+        .collect();
+    String::from_utf8(complement).expect("Complements are ASCII")
+}
+
+/// Window left after trimming low-quality ends with the modified Mott algorithm
+///
+/// Qualities are Phred+33; each end is cut where the running sum of
+/// `threshold - quality` peaks, as in BWA and cutadapt.
+pub fn quality_trim_window(quality: &[u8], threshold: u8) -> (usize, usize) {
+    let threshold = threshold as i32;
+    let phred = |index: usize| quality[index] as i32 - 33;
+
+    let mut stop = quality.len();
+    let (mut sum, mut best) = (0, 0);
+    for index in (0..quality.len()).rev() {
+        sum += threshold - phred(index);
+        if sum < 0 {
+            break;
+        }
+        if sum > best {
+            best = sum;
+            stop = index;
+        }
+    }
+
+    let mut start = 0;
+    let (mut sum, mut best) = (0, 0);
+    for index in 0..stop {
+        sum += threshold - phred(index);
+        if sum < 0 {
+            break;
+        }
+        if sum > best {
+            best = sum;
+            start = index + 1;
+        }
+    }
+
+    (start, stop)
+}
+
+/// Length of the homopolymer run of `base` at the end of `sequence`
+///
+/// The run must start with `base`; one mismatch plus one more for every
+/// 8 bases of run are tolerated, and the run always ends on a matching base.
+pub fn homopolymer_tail_length<'a>(sequence: impl Iterator<Item = &'a u8>, base: u8) -> usize {
+    let mut length = 0;
+    let mut mismatches = 0;
+    for (index, nucleotide) in sequence.enumerate() {
+        if nucleotide.eq_ignore_ascii_case(&base) {
+            length = index + 1;
+        } else {
+            mismatches += 1;
+            if length == 0 || mismatches > 1 + length / 8 {
+                break;
+            }
+        }
+    }
+    length
+}
+
+/// Match a file name against a shell wildcard pattern with `*` and `?`
+pub fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut pattern_index, mut name_index) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while name_index < name.len() {
+        match pattern.get(pattern_index) {
+            Some('*') => {
+                backtrack = Some((pattern_index, name_index));
+                pattern_index += 1;
+            }
+            Some(&character) if character == '?' || character == name[name_index] => {
+                pattern_index += 1;
+                name_index += 1;
+            }
+            _ => match backtrack {
+                // Let the last star absorb one more character and retry
+                Some((star_index, star_name_index)) => {
+                    pattern_index = star_index + 1;
+                    name_index = star_name_index + 1;
+                    backtrack = Some((star_index, star_name_index + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[pattern_index..].iter().all(|&character| character == '*')
+}
+
+/// Make a name safe as one component of a FASTQ read ID
+///
+/// Whitespace ends the ID for most tools and `/` reads as a mate suffix, so
+/// both become `_`, as do control characters and the ID separator itself.
+pub fn id_component(name: &str, id_separator: &str) -> String {
+    let name = if id_separator.is_empty() { name.to_string() } else { name.replace(id_separator, "_") };
+    name.chars()
+        .map(|character| if character.is_whitespace() || character.is_control() || character == '/' { '_' } else { character })
+        .collect()
+}
+
+/// Quote a string as a JSON string literal
+pub fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for character in value.chars() {
+        match character {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Format a time as an ISO 8601 UTC timestamp with second precision
+pub fn format_utc_timestamp(time: std::time::SystemTime) -> String {
+    let seconds = time
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let (days, day_seconds) = ((seconds / 86_400) as i64, seconds % 86_400);
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let shifted = days + 719_468;
+    let era = shifted.div_euclid(146_097);
+    let day_of_era = shifted.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        day_seconds / 3600,
+        day_seconds % 3600 / 60,
+        day_seconds % 60
+    )
+}
+
+/// Seconds since the epoch of an ISO 8601 timestamp such as `2021-05-17T13:02:58.123+00:00`
+///
+/// Accepts fractional seconds and a `Z` or `±HH:MM` offset, as written in ONT read headers.
+pub fn parse_utc_timestamp(timestamp: &str) -> Option<f64> {
+    let (date, time) = timestamp.split_once('T')?;
+    let mut date_fields = date.splitn(3, '-').map(|field| field.parse::<i64>().ok());
+    let (year, month, day) = (date_fields.next()??, date_fields.next()??, date_fields.next()??);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let (clock, offset_seconds) = match time.find(['Z', '+', '-']) {
+        Some(position) if &time[position..] == "Z" => (&time[..position], 0),
+        Some(position) => {
+            let sign = if time.as_bytes()[position] == b'-' { -1 } else { 1 };
+            let (hours, minutes) = time[position + 1..].split_once(':').unwrap_or((&time[position + 1..], "0"));
+            (&time[..position], sign * (hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60))
+        }
+        None => (time, 0),
+    };
+    let mut clock_fields = clock.splitn(3, ':');
+    let hours: i64 = clock_fields.next()?.parse().ok()?;
+    let minutes: i64 = clock_fields.next()?.parse().ok()?;
+    let seconds: f64 = clock_fields.next().unwrap_or("0").parse().ok()?;
+
+    // Days since the epoch from the civil date (Howard Hinnant's algorithm)
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    Some((days * 86_400 + hours * 3600 + minutes * 60 - offset_seconds) as f64 + seconds)
+}
+
+/// Split text into ANSI escape sequences and visible characters
+fn ansi_tokens(text: &str) -> Vec<(bool, &str)> {
+    let mut tokens = Vec::new();
+    let mut characters = text.char_indices().peekable();
+    while let Some((start, character)) = characters.next() {
+        if character == '\x1b' && characters.peek().is_some_and(|&(_, next)| next == '[') {
+            characters.next();
+            let mut end = text.len();
+            for (index, parameter) in characters.by_ref() {
+                if ('\x40'..='\x7e').contains(&parameter) {
+                    end = index + parameter.len_utf8();
+                    break;
+                }
+            }
+            tokens.push((true, &text[start..end]));
+        } else {
+            tokens.push((false, &text[start..start + character.len_utf8()]));
+        }
+    }
+    tokens
+}
+
+/// Shorten text to `width` visible characters by replacing its middle with `...`
+///
+/// Escape sequences are never split: colors are reset before the marker and the
+/// color active at the resumed position is restored after it.
+pub fn truncate_ansi(text: &str, width: usize) -> String {
+    let tokens = ansi_tokens(text);
+    let visible = tokens.iter().filter(|(is_escape, _)| !is_escape).count();
+    if visible <= width {
+        return text.to_string();
+    }
+    let kept = width.saturating_sub(3);
+    let (front, back) = (kept - kept / 2, kept / 2);
+    let back_start = visible - back;
+
+    let mut truncated = String::new();
+    let mut escape_emitted = false;
+    let mut elided_escape = None;
+    let mut position = 0;
+    for (is_escape, token) in tokens {
+        if is_escape {
+            if position < front || position >= back_start {
+                // An escape where the text resumes supersedes the one elided before it
+                if position == back_start {
+                    elided_escape = None;
+                }
+                truncated.push_str(token);
+                escape_emitted = true;
+            } else {
+                elided_escape = (token != "\x1b[0m").then_some(token);
+            }
+            continue;
+        }
+        if position == front {
+            if escape_emitted {
+                truncated.push_str("\x1b[0m");
+            }
+            truncated.push_str(&"..."[..width.min(3)]);
+        }
+        if position == back_start && let Some(escape) = elided_escape.take() {
+            truncated.push_str(escape);
+        }
+        if position < front || position >= back_start {
+            truncated.push_str(token);
+        }
+        position += 1;
+    }
+    truncated
+}
+
+/// Small seeded random number generator (splitmix64)
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    /// Create generator from a seed
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+    
+    /// Next 64-bit value
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
     }
     
-    complement.into_iter().collect::<String>()
+    /// Next uniform value in [0, 1)
+    pub fn next_unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+    
+    /// Next uniform index in [0, bound)
+    pub fn next_index(&mut self, bound: usize) -> usize {
+        (self.next_unit() * bound as f64) as usize
+    }
+}
+
+/// CPUs available to the process, honoring affinity masks and cgroup quotas
+pub fn available_cpus() -> usize {
+    std::thread::available_parallelism().map(|count| count.get()).unwrap_or(1)
+}
+
+/// Thread count for `-t`, 0 standing for every available CPU
+pub fn resolve_thread_count(requested: usize) -> usize {
+    if requested > 0 {
+        return requested;
+    }
+    let cpus = available_cpus();
+    info!("Using {} threads, one per available CPU", cpus);
+    cpus
 }
 
 /// Process information tracker
@@ -40,19 +407,20 @@ impl ProcessInfo {
         }
     }
     
-    /// Update process information
-    pub fn info(&mut self) {
+    /// Update process information, logging rate and channel depths every `log_interval` reads
+    pub fn info(&mut self, channels: &crate::perf::ChannelMonitor) {
         self.processed_count += 1;
         
-        if self.processed_count % self.log_interval == 0 {
+        if self.processed_count.is_multiple_of(self.log_interval) {
             self.end_time = std::time::Instant::now();
             let elapsed = self.end_time.duration_since(self.start_time);
             let processing_rate = self.processed_count as f64 / elapsed.as_secs_f64();
             
             info!(
-                "Processed {} sequences, processing speed: {:.2} sequences/second", 
+                "Processed {} sequences, processing speed: {:.2} sequences/second, queued reads: {}", 
                 self.processed_count, 
-                processing_rate
+                processing_rate,
+                channels.summary()
             );
             
             self.start_time = std::time::Instant::now();
@@ -61,3 +429,295 @@ impl ProcessInfo {
     }
     
 }
+
+/// Per-run scratch directory for temporary spill files, removed on drop
+pub struct ScratchDirectory {
+    path: PathBuf,
+}
+
+impl ScratchDirectory {
+    /// Create a unique scratch directory under `base`, or the system temp directory
+    pub fn new(base: Option<&str>) -> Self {
+        let base_path = base.map(PathBuf::from).unwrap_or_else(std::env::temp_dir);
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.subsec_nanos())
+            .unwrap_or(0);
+        let path = base_path.join(format!("readchop-{}-{}", std::process::id(), nanos));
+        
+        std::fs::create_dir_all(&path)
+            .unwrap_or_else(|_| panic!("Unable to create temporary directory: {}", path.display()));
+        info!("Using temporary directory: {}", path.display());
+        
+        Self { path }
+    }
+    
+    /// Get scratch directory path
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ScratchDirectory {
+    fn drop(&mut self) {
+        if let Err(error) = std::fs::remove_dir_all(&self.path) {
+            log::warn!("Failed to remove temporary directory {}: {}", self.path.display(), error);
+        }
+    }
+}
+
+/// Files whose presence marks a directory as holding ReadChop outputs
+const OUTPUT_MARKERS: [&str; 2] = ["run_info.json", "total_info.tsv"];
+
+/// Output directory filled through a staging directory beside it
+///
+/// Outputs are written to a hidden sibling that `commit` moves into place, so
+/// a crashed run never leaves a directory that looks complete; the staging
+/// directory is removed on drop unless committed. Unstaged directories, used
+/// by live runs, are written in place.
+pub struct OutputDirectory {
+    path: PathBuf,
+    staging: Option<PathBuf>,
+    working_path: String,
+}
+
+impl OutputDirectory {
+    /// Check `path` holds no earlier outputs unless `force`, and create the staging directory
+    pub fn new(path: &str, force: bool, staged: bool) -> Self {
+        let final_path = PathBuf::from(path);
+        if !force && OUTPUT_MARKERS.iter().any(|marker| final_path.join(marker).exists()) {
+            panic!("Output directory {} already holds ReadChop outputs, use --force to overwrite them", path);
+        }
+        let staging = final_path.file_name().filter(|_| staged).map(|name| {
+            let staging = final_path.with_file_name(format!(".{}.readchop-{}", name.to_string_lossy(), std::process::id()));
+            if staging.exists() {
+                std::fs::remove_dir_all(&staging)
+                    .unwrap_or_else(|_| panic!("Unable to clear staging directory: {}", staging.display()));
+            }
+            std::fs::create_dir_all(&staging)
+                .unwrap_or_else(|_| panic!("Unable to create staging directory: {}", staging.display()));
+            info!("Writing outputs to staging directory: {}", staging.display());
+            staging
+        });
+        let working_path = staging.as_deref().unwrap_or(&final_path).to_string_lossy().into_owned();
+        Self { path: final_path, staging, working_path }
+    }
+    
+    /// Directory the outputs are written to
+    pub fn path(&self) -> &str {
+        &self.working_path
+    }
+    
+    /// Move the staged outputs into the output directory
+    ///
+    /// A missing or empty output directory is replaced in one rename; otherwise
+    /// each staged entry replaces the entry of the same name.
+    pub fn commit(mut self) {
+        let Some(staging) = self.staging.take() else {
+            return;
+        };
+        let is_empty = |path: &Path| path.read_dir().map(|mut entries| entries.next().is_none()).unwrap_or(true);
+        if !self.path.exists() || (self.path.is_dir() && is_empty(&self.path)) {
+            if self.path.exists() {
+                std::fs::remove_dir(&self.path).unwrap_or_else(|_| panic!("Unable to replace output directory: {}", self.path.display()));
+            }
+            std::fs::rename(&staging, &self.path)
+                .unwrap_or_else(|error| panic!("Unable to move outputs to {}: {}", self.path.display(), error));
+        } else {
+            let entries = staging.read_dir().expect("Failed to list staged outputs");
+            for entry in entries {
+                let entry = entry.expect("Failed to list staged outputs");
+                let target = self.path.join(entry.file_name());
+                if target.is_dir() {
+                    std::fs::remove_dir_all(&target)
+                } else if target.exists() {
+                    std::fs::remove_file(&target)
+                } else {
+                    Ok(())
+                }.unwrap_or_else(|error| panic!("Unable to replace {}: {}", target.display(), error));
+                std::fs::rename(entry.path(), &target)
+                    .unwrap_or_else(|error| panic!("Unable to move outputs to {}: {}", target.display(), error));
+            }
+            std::fs::remove_dir(&staging).expect("Failed to remove staging directory");
+        }
+        info!("Outputs moved to {}", self.path.display());
+    }
+}
+
+impl Drop for OutputDirectory {
+    fn drop(&mut self) {
+        if let Some(staging) = &self.staging {
+            log::warn!("Removing incomplete outputs in {}", staging.display());
+            if let Err(error) = std::fs::remove_dir_all(staging) {
+                log::warn!("Failed to remove staging directory {}: {}", staging.display(), error);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IUPAC: &[u8] = b"ACGTRYSWKMBDHVNacgtryswkmbdhvn";
+
+    #[test]
+    fn test_id_component_replaces_unsafe_characters() {
+        assert_eq!(id_component("BC01", "%"), "BC01");
+        assert_eq!(id_component("sample 1/rep\t2", "%"), "sample_1_rep_2");
+        assert_eq!(id_component("a%b", "%"), "a_b");
+        assert_eq!(id_component("a::b", "::"), "a_b");
+    }
+
+    /// Deterministic pseudo-random IUPAC sequences of varying length
+    fn generated_sequences() -> Vec<String> {
+        let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+        (0..200)
+            .map(|length| {
+                (0..length)
+                    .map(|_| {
+                        state ^= state << 13;
+                        state ^= state >> 7;
+                        state ^= state << 17;
+                        IUPAC[(state % IUPAC.len() as u64) as usize] as char
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_reverse_complement_is_involution() {
+        for sequence in generated_sequences() {
+            let once = reverse_complement(&sequence, true);
+            assert_eq!(once.len(), sequence.len());
+            assert_eq!(reverse_complement(&once, true), sequence);
+        }
+    }
+
+    #[test]
+    fn test_reverse_complement_matches_bio_for_acgtn() {
+        for sequence in generated_sequences() {
+            let sequence: String = sequence
+                .chars()
+                .filter(|nucleotide| "ACGTN".contains(*nucleotide))
+                .collect();
+            let expected = bio::alphabets::dna::revcomp(sequence.as_bytes());
+            assert_eq!(reverse_complement(&sequence, true).as_bytes(), &expected[..]);
+        }
+    }
+
+    #[test]
+    fn test_reverse_complement_unknown_bases() {
+        assert_eq!(reverse_complement("AC-GU", false), "ACNGT");
+    }
+
+    #[test]
+    fn test_parse_utc_timestamp_inverts_format() {
+        let time = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_621_256_578);
+        assert_eq!(parse_utc_timestamp(&format_utc_timestamp(time)), Some(1_621_256_578.0));
+        assert_eq!(parse_utc_timestamp("2021-05-17T15:02:58.5+02:00"), Some(1_621_256_578.5));
+        assert_eq!(parse_utc_timestamp("2000-02-29T00:00:00Z"), Some(951_782_400.0));
+        assert_eq!(parse_utc_timestamp("yesterday"), None);
+    }
+
+    #[test]
+    fn test_complement_byte_matches_complement_base() {
+        for byte in 0..=u8::MAX {
+            let expected = byte.is_ascii().then(|| complement_base(byte as char)).flatten();
+            assert_eq!(complement_byte(byte).map(char::from), expected);
+        }
+        assert_eq!(reverse_complement_bytes(b"acGT-n"), b"nNACgt");
+        assert_eq!(reverse_complement("ACα", false), "NGT");
+    }
+
+    /// Compare the byte table with the former per-character implementation,
+    /// run with `cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_reverse_complement() {
+        let sequence: String = generated_sequences().concat().repeat(20);
+        let rounds = 200;
+        let time = |work: &dyn Fn() -> usize| {
+            let start = std::time::Instant::now();
+            let length: usize = (0..rounds).map(|_| std::hint::black_box(work())).sum();
+            assert_eq!(length, sequence.len() * rounds);
+            start.elapsed()
+        };
+        let per_character = time(&|| {
+            let complement: String = sequence.chars().rev().map(|nucleotide| complement_base(nucleotide).unwrap_or('N')).collect();
+            complement.len()
+        });
+        let string = time(&|| reverse_complement(std::hint::black_box(&sequence), true).len());
+        let bytes = time(&|| std::hint::black_box(reverse_complement_bytes(std::hint::black_box(sequence.as_bytes()))).len());
+        println!(
+            "reverse complement of {} bp x {}: per character {:?}, reverse_complement {:?}, reverse_complement_bytes {:?}",
+            sequence.len(), rounds, per_character, string, bytes
+        );
+    }
+
+    /// Phred+33 encode a list of quality scores
+    fn phred33(scores: &[u8]) -> Vec<u8> {
+        scores.iter().map(|score| score + 33).collect()
+    }
+
+    #[test]
+    fn test_quality_trim_window() {
+        assert_eq!(quality_trim_window(&phred33(&[30, 30, 30, 30]), 20), (0, 4));
+        assert_eq!(quality_trim_window(&phred33(&[30, 30, 30, 5, 5]), 20), (0, 3));
+        assert_eq!(quality_trim_window(&phred33(&[2, 3, 30, 30, 30]), 20), (2, 5));
+        // A single good base inside a bad tail does not stop the trimming
+        assert_eq!(quality_trim_window(&phred33(&[8, 30, 30, 40, 5, 25, 5, 5]), 20), (1, 4));
+        assert_eq!(quality_trim_window(&phred33(&[5, 5, 5]), 20), (0, 0));
+        assert_eq!(quality_trim_window(&[], 20), (0, 0));
+    }
+
+    #[test]
+    fn test_homopolymer_tail_length() {
+        let tail = |sequence: &[u8], base| homopolymer_tail_length(sequence.iter().rev(), base);
+        assert_eq!(tail(b"ACGTAAAAA", b'A'), 5);
+        assert_eq!(tail(b"ACGTaaaaa", b'A'), 5);
+        assert_eq!(tail(b"ACGTAAAAC", b'A'), 0);
+        assert_eq!(tail(b"CCCCAAAAAAAGAAAA", b'A'), 12);
+        assert_eq!(tail(b"CCCCAAAAGAAAA", b'A'), 9);
+        assert_eq!(tail(b"CCGCCAAAA", b'A'), 4);
+        assert_eq!(tail(b"", b'A'), 0);
+        assert_eq!(homopolymer_tail_length(b"TTTTTGCA".iter(), b'T'), 5);
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*.fastq.gz", "FAL1234_pass_0.fastq.gz"));
+        assert!(wildcard_match("barcode??", "barcode07"));
+        assert!(wildcard_match("*_pass_*", "run_pass_12.fq"));
+        assert!(wildcard_match("*", ""));
+        assert!(!wildcard_match("*.fq", "reads.fq.gz"));
+        assert!(!wildcard_match("barcode?", "barcode07"));
+        assert!(!wildcard_match("a*b*c", "abcb"));
+    }
+
+    #[test]
+    fn test_format_utc_timestamp() {
+        let at = |seconds| std::time::UNIX_EPOCH + std::time::Duration::from_secs(seconds);
+        assert_eq!(format_utc_timestamp(at(0)), "1970-01-01T00:00:00Z");
+        assert_eq!(format_utc_timestamp(at(951_782_400)), "2000-02-29T00:00:00Z");
+        assert_eq!(format_utc_timestamp(at(1_758_196_800 + 3_661)), "2025-09-18T13:01:01Z");
+    }
+
+    #[test]
+    fn test_truncate_ansi() {
+        let red = "ACGT\x1b[31mGGGG\x1b[0mTTTT";
+        assert_eq!(truncate_ansi(red, 12), red);
+        assert_eq!(truncate_ansi(red, 9), "ACG...TTT");
+        assert_eq!(truncate_ansi(red, 11), "ACGT...\x1b[0mTTTT");
+        assert_eq!(truncate_ansi("\x1b[32mAAAA\x1b[0mCCCCCC", 7), "\x1b[32mAA\x1b[0m...CC");
+        assert_eq!(truncate_ansi("αβγδεζηθ", 5), "α...θ");
+        assert_eq!(truncate_ansi("AAAAAAAA\x1b[33mCCCCCCCC\x1b[0m", 9), "AAA...\x1b[33mCCC\x1b[0m");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid nucleotide character")]
+    fn test_reverse_complement_strict_rejects_unknown() {
+        reverse_complement("AC-G", true);
+    }
+}
@@ -0,0 +1,256 @@
+use crate::args::Commands;
+use crate::counter::read_table;
+use crate::writer::LOW_COUNT_OUTPUT;
+use flate2::read::MultiGzDecoder;
+use log::{error, info, warn};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// One line of the verification report
+struct Check {
+    /// Output file, or the statistics table and count checked
+    subject: String,
+    found: String,
+    expected: String,
+    /// `ok`, or what is wrong
+    status: String,
+}
+
+/// Handle verify subcommand, returning whether every check passed
+///
+/// Each `.fq.gz` below the output directory is decompressed in full and its
+/// records parsed; its record count is compared with the reads the read logs
+/// assign to it, and the read logs with the counts of total_info.tsv.
+pub fn handle_verify_command(verify_args: &Commands) -> bool {
+    let Commands::Verify { outdir, report } = verify_args else {
+        panic!("Not a verify command");
+    };
+    let output_directory = Path::new(outdir);
+    if !output_directory.is_dir() {
+        panic!("Output directory not found: {}", outdir);
+    }
+    info!("Verifying outputs in {}", outdir);
+
+    let mut checks = Vec::new();
+    let logged = read_logged_counts(output_directory);
+    if let Some(logged) = &logged {
+        checks.extend(check_statistics(output_directory, logged));
+    } else {
+        warn!("No read log found, only checking file integrity");
+    }
+
+    let mut fastq_files = Vec::new();
+    find_fastq_files(output_directory, &mut fastq_files);
+    fastq_files.sort();
+    let first_file_check = checks.len();
+    let mut file_outputs = Vec::new();
+    for path in &fastq_files {
+        let name = path.strip_prefix(output_directory).unwrap_or(path).display().to_string();
+        let output = name.trim_end_matches(".fq.gz").to_string();
+        let expected = logged.as_ref().and_then(|logged| expected_records(&output, logged));
+        let (found, status) = match count_fastq_records(path) {
+            Ok(records) if expected.is_some_and(|expected| expected != records) => (records.to_string(), "count_mismatch".to_string()),
+            Ok(records) => (records.to_string(), "ok".to_string()),
+            Err(message) => ("-".to_string(), message),
+        };
+        let expected = expected.map_or("-".to_string(), |expected| expected.to_string());
+        let check = Check { subject: name, found, expected, status };
+        file_outputs.push(output);
+        checks.push(check);
+    }
+
+    // Outputs the read log names but no file holds went to low_count.fq.gz below --min-reads-per-barcode, or are missing
+    if let Some(logged) = &logged {
+        let low_count_check = file_outputs.iter().position(|output| output == LOW_COUNT_OUTPUT).map(|index| first_file_check + index);
+        let mut low_count_reads = 0;
+        for (output, &reads) in &logged.outputs {
+            if file_outputs.contains(output) {
+                continue;
+            }
+            if low_count_check.is_some() {
+                low_count_reads += reads;
+                continue;
+            }
+            checks.push(Check {
+                subject: format!("{}.fq.gz", output),
+                found: "-".to_string(),
+                expected: reads.to_string(),
+                status: "missing".to_string(),
+            });
+        }
+        if let Some(index) = low_count_check {
+            let check = &mut checks[index];
+            check.expected = low_count_reads.to_string();
+            if check.status == "ok" && check.found != check.expected {
+                check.status = "count_mismatch".to_string();
+            }
+        }
+    }
+
+    let report_path = report.clone().unwrap_or_else(|| output_directory.join("verify_report.tsv").display().to_string());
+    write_report(&report_path, &checks);
+    let failures = checks.iter().filter(|check| check.status != "ok").count();
+    if failures == 0 {
+        info!("Verification passed: {} checks, report written to {}", checks.len(), report_path);
+    } else {
+        for check in checks.iter().filter(|check| check.status != "ok") {
+            error!("{}: {} (found {}, expected {})", check.subject, check.status, check.found, check.expected);
+        }
+        error!("Verification failed: {} of {} checks, report written to {}", failures, checks.len(), report_path);
+    }
+    failures == 0
+}
+
+/// Read counts of the read logs of a run
+struct LoggedCounts {
+    total: usize,
+    valid: usize,
+    /// Reads written per output file, without `.fq.gz`
+    outputs: BTreeMap<String, usize>,
+}
+
+/// Count reads per output over reads_log.gz, its rotated parts or the per-output shards
+fn read_logged_counts(output_directory: &Path) -> Option<LoggedCounts> {
+    let mut log_files = Vec::new();
+    find_files(output_directory, &mut log_files, &|name| {
+        name.ends_with("reads_log.gz") || name.starts_with("reads_log.") && name.ends_with(".gz")
+    });
+    if log_files.is_empty() {
+        return None;
+    }
+
+    let mut logged = LoggedCounts { total: 0, valid: 0, outputs: BTreeMap::new() };
+    for log_file in log_files {
+        let file = File::open(&log_file).unwrap_or_else(|_| panic!("Unable to open read log: {}", log_file.display()));
+        let mut lines = BufReader::new(MultiGzDecoder::new(file)).lines();
+        let Some(header) = lines.next() else {
+            continue;
+        };
+        let header = header.unwrap_or_else(|error| panic!("Failed to read {}: {}", log_file.display(), error));
+        let columns: Vec<&str> = header.split('\t').collect();
+        let (Some(type_column), Some(output_column)) = (
+            columns.iter().position(|column| *column == "sequence_type"),
+            columns.iter().position(|column| *column == "output"),
+        ) else {
+            warn!("Read log {} has no output column, written by an older version", log_file.display());
+            return None;
+        };
+        for line in lines {
+            let line = line.unwrap_or_else(|error| panic!("Failed to read {}: {}", log_file.display(), error));
+            let fields: Vec<&str> = line.split('\t').collect();
+            logged.total += 1;
+            if fields.get(type_column) == Some(&"valid") {
+                logged.valid += 1;
+            }
+            if let Some(output) = fields.get(output_column).filter(|output| **output != "-") {
+                *logged.outputs.entry(output.to_string()).or_insert(0) += 1;
+            }
+        }
+    }
+    Some(logged)
+}
+
+/// Compare the read log counts with total_info.tsv
+fn check_statistics(output_directory: &Path, logged: &LoggedCounts) -> Vec<Check> {
+    let Some((header, rows)) = read_table(&output_directory.join("total_info.tsv")) else {
+        return vec![Check {
+            subject: "total_info.tsv".to_string(),
+            found: "-".to_string(),
+            expected: "-".to_string(),
+            status: "missing".to_string(),
+        }];
+    };
+    [("total", logged.total), ("valid_reads", logged.valid)]
+        .into_iter()
+        .map(|(column, expected)| {
+            let found = header.iter().position(|name| name == column)
+                .and_then(|index| rows.first()?.get(index)?.parse::<usize>().ok());
+            let status = match found {
+                Some(found) if found == expected => "ok",
+                Some(_) => "count_mismatch",
+                None => "malformed",
+            };
+            Check {
+                subject: format!("total_info.tsv:{}", column),
+                found: found.map_or("-".to_string(), |found| found.to_string()),
+                expected: expected.to_string(),
+                status: status.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Reads the read logs assign to an output file, None for files they do not account for
+///
+/// Mates in `<output>_R2` and copies under `raw/` hold as many reads as their output.
+fn expected_records(output: &str, logged: &LoggedCounts) -> Option<usize> {
+    if let Some(&reads) = logged.outputs.get(output) {
+        return Some(reads);
+    }
+    let counterpart = output.strip_suffix("_R2").or_else(|| output.strip_prefix("raw/"))?;
+    logged.outputs.get(counterpart).copied()
+}
+
+/// Records of a gzip FASTQ file, or what is wrong with it
+fn count_fastq_records(path: &Path) -> Result<usize, String> {
+    let file = File::open(path).map_err(|error| format!("unreadable: {}", error))?;
+    let mut lines = BufReader::new(MultiGzDecoder::new(file)).lines();
+    let mut records = 0;
+    while let Some(header) = lines.next() {
+        let record_line = records * 4 + 1;
+        let header = decompressed_line(header)?;
+        let mut fields = Vec::with_capacity(3);
+        for _ in 0..3 {
+            let line = lines.next().ok_or_else(|| format!("truncated record at line {}", record_line))?;
+            fields.push(decompressed_line(line)?);
+        }
+        let (sequence, separator, quality) = (&fields[0], &fields[1], &fields[2]);
+        if !header.starts_with('@') || !separator.starts_with('+') {
+            return Err(format!("malformed record at line {}", record_line));
+        }
+        if sequence.len() != quality.len() {
+            return Err(format!("sequence and quality lengths differ at line {}", record_line));
+        }
+        if !sequence.bytes().all(|base| base.is_ascii_alphabetic()) || !quality.bytes().all(|score| (b'!'..=b'~').contains(&score)) {
+            return Err(format!("invalid characters at line {}", record_line));
+        }
+        records += 1;
+    }
+    Ok(records)
+}
+
+/// Line of a gzip stream, with read errors reported as corruption
+fn decompressed_line(line: std::io::Result<String>) -> Result<String, String> {
+    line.map_err(|error| format!("corrupt gzip: {}", error))
+}
+
+/// Gzip FASTQ files below a directory
+fn find_fastq_files(directory: &Path, files: &mut Vec<PathBuf>) {
+    find_files(directory, files, &|name| name.ends_with(".fq.gz"));
+}
+
+/// Files below a directory whose name passes `filter`
+fn find_files(directory: &Path, files: &mut Vec<PathBuf>, filter: &dyn Fn(&str) -> bool) {
+    let entries = std::fs::read_dir(directory)
+        .unwrap_or_else(|_| panic!("Unable to read directory: {}", directory.display()));
+    for entry in entries {
+        let path = entry.expect("Failed to read directory entry").path();
+        if path.is_dir() {
+            find_files(&path, files, filter);
+        } else if path.file_name().is_some_and(|name| filter(&name.to_string_lossy())) {
+            files.push(path);
+        }
+    }
+}
+
+/// Write one line per check with its status
+fn write_report(path: &str, checks: &[Check]) {
+    let mut file = File::create(path).unwrap_or_else(|_| panic!("Unable to create verification report: {}", path));
+    writeln!(file, "subject\tfound\texpected\tstatus").expect("Failed to write verification report");
+    for check in checks {
+        writeln!(file, "{}\t{}\t{}\t{}", check.subject, check.found, check.expected, check.status)
+            .expect("Failed to write verification report");
+    }
+}
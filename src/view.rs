@@ -1,8 +1,10 @@
 use crate::args::Commands;
 use crate::fastq::ReadInfo;
 use crate::pattern::PatternConfiguration;
-use crate::splitter::perform_sequence_splitting_vector;
+use crate::splitter::{create_ordered_receiver, perform_sequence_splitting_vector};
+use crate::thread_pool::ThreadPoolManager;
 use flume::Receiver;
+use crate::utils::{json_string, truncate_ansi};
 use log::info;
 
 /// Handle view subcommand, real-time preview of barcode recognition results
@@ -13,142 +15,274 @@ pub fn handle_view_command(view_args: &Commands) {
     let pattern_config = PatternConfiguration::new_from_view_args(view_args);
     
     // Create FASTQ reader
-    let inputs = match view_args {
-        Commands::View { inputs, .. } => inputs.clone(),
+    let (inputs, pattern_files, threads, limit, only, format, width, tui) = match view_args {
+        Commands::View { inputs, pattern_files, threads, limit, only, format, width, tui, .. } => (
+            crate::fastq::expand_input_paths(inputs),
+            pattern_files.clone(),
+            crate::utils::resolve_thread_count(*threads),
+            *limit,
+            only.clone(),
+            format.clone(),
+            width.or_else(terminal_width).unwrap_or(0),
+            *tui,
+        ),
         _ => return,
     };
-    let read_receiver: Receiver<ReadInfo> = crate::fastq::create_reader(inputs);
+    let read_receiver: Receiver<ReadInfo> = crate::fastq::create_reader(inputs, crate::fastq::ReaderOptions::default(), None, crate::fastq::ReadSelection::all());
+    
+    if tui {
+        crate::tui::run_tui(read_receiver, pattern_config, pattern_files);
+        return;
+    }
+    
+    // Execute barcode recognition on the thread pool, classifying each read as the main
+    // pipeline would but keeping the sequence for display
+    let thread_pool = ThreadPoolManager::new(threads, threads);
+    let recognized_receiver = create_ordered_receiver(read_receiver, &pattern_config, &thread_pool, |mut read_info, worker_config| {
+        read_info.split_types = perform_sequence_splitting_vector(&read_info, worker_config);
+        read_info.update(worker_config);
+        vec![read_info]
+    });
+    
+    match format.as_str() {
+        "tsv" => print_tsv_header(pattern_config.pattern_arguments.len()),
+        "pretty" => print_round_legend(&pattern_files),
+        _ => {}
+    }
     
     // Process each sequence
-    for read_info in read_receiver.iter() {
-        // Execute barcode recognition
-        let split_types = perform_sequence_splitting_vector(&read_info, &pattern_config);
+    let mut printed_count = 0;
+    for read_info in recognized_receiver.iter() {
+        let matched = read_info.sequence_type == "valid";
+        match only.as_deref() {
+            Some("matched") if !matched => continue,
+            Some("unmatched") if matched => continue,
+            _ => {}
+        }
         
         // Output results
-        print_sequence_result(&read_info, &split_types);
+        match format.as_str() {
+            "json" => println!("{}", sequence_result_json(&read_info)),
+            "tsv" => println!("{}", sequence_result_tsv(&read_info)),
+            _ => print_sequence_result(&read_info, &read_info.split_types, width),
+        }
+        
+        printed_count += 1;
+        if limit > 0 && printed_count >= limit {
+            break;
+        }
+    }
+}
+
+/// Print the TSV column names for the given number of pattern rounds
+fn print_tsv_header(rounds: usize) {
+    let mut header = String::from("id\tlength\tsequence_type");
+    for round in 1..=rounds {
+        header.push_str(&format!("\tmatch{0}\tname{0}\ttype{0}\tstrand{0}", round));
+    }
+    println!("{}", header);
+}
+
+/// Recognition result as a TSV line with the reads_log match columns, keyed by the original read ID
+fn sequence_result_tsv(read_info: &ReadInfo) -> String {
+    let mut tsv_line = format!("{}\t{}\t{}", read_info.original_id, read_info.sequence_length, read_info.sequence_type);
+    for split_type in &read_info.split_types {
+        tsv_line.push('\t');
+        tsv_line.push_str(&split_type.to_info());
+    }
+    tsv_line
+}
+
+/// Recognition result as a single-line JSON object
+fn sequence_result_json(read_info: &ReadInfo) -> String {
+    let matcher_json = |matcher: &crate::splitter::Matcher| {
+        if matcher.status {
+            format!(
+                "{{\"pattern\":{},\"score\":{},\"start\":{},\"end\":{}}}",
+                json_string(matcher.get_pattern()),
+                matcher.get_score(),
+                matcher.ystart,
+                matcher.yend
+            )
+        } else {
+            "null".to_string()
+        }
+    };
+    let rounds: Vec<String> = read_info
+        .split_types
+        .iter()
+        .map(|split_type| {
+            format!(
+                "{{\"match\":{},\"name\":{},\"type\":{},\"strand\":{},\"left\":{},\"right\":{}}}",
+                json_string(split_type.pattern_match),
+                json_string(&split_type.pattern_name),
+                json_string(&split_type.pattern_type),
+                json_string(&split_type.pattern_strand),
+                matcher_json(&split_type.left_matcher),
+                matcher_json(&split_type.right_matcher)
+            )
+        })
+        .collect();
+    format!(
+        "{{\"id\":{},\"length\":{},\"sequence_type\":{},\"rounds\":[{}]}}",
+        json_string(&read_info.original_id),
+        read_info.sequence_length,
+        json_string(&read_info.sequence_type),
+        rounds.join(",")
+    )
+}
+
+/// ANSI colors assigned to pattern rounds in order, reused when there are more rounds
+const ROUND_COLORS: [&str; 6] = ["\x1b[31m", "\x1b[32m", "\x1b[33m", "\x1b[34m", "\x1b[35m", "\x1b[36m"];
+
+/// ANSI reset sequence
+const COLOR_END: &str = "\x1b[0m";
+
+/// Sequences longer than this are shortened around their matched regions
+const MAX_DISPLAY_LENGTH: usize = 200;
+
+/// Unmatched bases kept on each side of a match when shortening a sequence
+const MATCH_CONTEXT: usize = 20;
+
+/// Color used to highlight matches of a pattern round
+pub fn round_color(round: usize) -> &'static str {
+    ROUND_COLORS[round % ROUND_COLORS.len()]
+}
+
+/// Width of the terminal attached to stdout, falling back to `COLUMNS`
+fn terminal_width() -> Option<usize> {
+    let mut window_size: libc::winsize = unsafe { std::mem::zeroed() };
+    let result = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut window_size) };
+    if result == 0 && window_size.ws_col > 0 {
+        return Some(window_size.ws_col as usize);
+    }
+    std::env::var("COLUMNS").ok()?.parse().ok()
+}
+
+/// Print which color belongs to which pattern round
+fn print_round_legend(pattern_files: &[String]) {
+    println!("{}", round_legend(pattern_files));
+    println!();
+}
+
+/// Legend line listing each round's pattern file in its color
+pub fn round_legend(pattern_files: &[String]) -> String {
+    let entries: Vec<String> = pattern_files
+        .iter()
+        .enumerate()
+        .map(|(round, pattern_file)| format!("{}round{} {}{}", round_color(round), round + 1, pattern_file, COLOR_END))
+        .collect();
+    format!("Legend: {}", entries.join("  "))
+}
+
+/// Regions of the sequence to display, each match widened by some context
+///
+/// Short sequences are shown whole; long ones keep only the merged regions
+/// around matches, or both ends when nothing matched.
+fn display_regions(sequence_length: usize, barcode_positions: &[(usize, usize, usize)]) -> Vec<(usize, usize)> {
+    if sequence_length <= MAX_DISPLAY_LENGTH {
+        return vec![(0, sequence_length)];
     }
+    if barcode_positions.is_empty() {
+        let half = MAX_DISPLAY_LENGTH / 2;
+        return vec![(0, half), (sequence_length - half, sequence_length)];
+    }
+    let mut regions: Vec<(usize, usize)> = Vec::new();
+    for (start, end, _round) in barcode_positions {
+        let region_start = start.saturating_sub(MATCH_CONTEXT);
+        let region_end = (end + MATCH_CONTEXT).min(sequence_length);
+        match regions.last_mut() {
+            Some(last) if region_start <= last.1 => last.1 = last.1.max(region_end),
+            _ => regions.push((region_start, region_end)),
+        }
+    }
+    regions
+}
+
+/// Append part of the sequence, coloring the bases covered by matches
+pub fn push_highlighted(output: &mut String, sequence: &[u8], region: (usize, usize), barcode_positions: &[(usize, usize, usize)]) {
+    let mut position = region.0;
+    for (start, end, round) in barcode_positions {
+        // Matches are sorted by start; overlapping parts keep the earlier round's color
+        let start = (*start).max(position);
+        let end = (*end).min(region.1);
+        if start >= end {
+            continue;
+        }
+        output.push_str(&String::from_utf8_lossy(&sequence[position..start]));
+        output.push_str(round_color(*round));
+        output.push_str(&String::from_utf8_lossy(&sequence[start..end]));
+        output.push_str(COLOR_END);
+        position = end;
+    }
+    output.push_str(&String::from_utf8_lossy(&sequence[position..region.1]));
 }
 
 /// Print single sequence recognition results with color highlighting
-fn print_sequence_result(read_info: &ReadInfo, split_types: &[crate::splitter::SplitType]) {
+fn print_sequence_result(read_info: &ReadInfo, split_types: &[crate::splitter::SplitType], width: usize) {
     // Output sequence ID and length
-    println!("Sequence ID: {} Length: {}", read_info.record_id, read_info.sequence_length);
+    println!("Sequence ID: {} Length: {}", read_info.original_id, read_info.sequence_length);
     
     // Get sequence
-    let sequence = read_info.sequence.as_ref()
+    let sequence = read_info.sequence()
         .expect("Sequence data not available");
-    let mut barcode_positions = Vec::new();
-    
-    // Collect all detected barcode positions
-    for split_type in split_types {
-        if split_type.left_matcher.status {
-            barcode_positions.push((
-                split_type.left_matcher.ystart,
-                split_type.left_matcher.yend,
-                &split_type.pattern_name,
-                split_type.left_matcher.get_score(),
-            ));
-        }
-        if split_type.right_matcher.status {
-            barcode_positions.push((
-                split_type.right_matcher.ystart,
-                split_type.right_matcher.yend,
-                &split_type.pattern_name,
-                split_type.right_matcher.get_score(),
-            ));
-        }
-    }
-    
-    // Sort by position
-    barcode_positions.sort_by_key(|x| x.0);
+    let barcode_positions = barcode_positions(split_types, sequence.len());
     
-    // Build highlighted sequence
-    let red_start = "\x1b[31m";  // Red start
-    let red_end = "\x1b[0m";     // Color end
+    // Build highlighted sequence, eliding long unmatched stretches
     let mut highlighted_sequence = String::new();
+    let regions = display_regions(sequence.len(), &barcode_positions);
     let mut last_position = 0;
-    
-    for (start, end, _pattern_name, _errors) in &barcode_positions {
-        // Add sequence before barcode
-        if *start > last_position {
-            highlighted_sequence.push_str(&String::from_utf8_lossy(&sequence[last_position..*start]));
+    for region in regions {
+        if region.0 > last_position {
+            highlighted_sequence.push_str(&format!("...{}bp...", region.0 - last_position));
         }
-        
-        // Add red highlighted barcode
-        let barcode_sequence = &sequence[*start..*end];
-        highlighted_sequence.push_str(&format!(
-            "{}{}{}",
-            red_start,
-            String::from_utf8_lossy(barcode_sequence),
-            red_end
-        ));
-        
-        last_position = *end;
+        push_highlighted(&mut highlighted_sequence, sequence, region, &barcode_positions);
+        last_position = region.1;
     }
-    
-    // Add remaining sequence
     if last_position < sequence.len() {
-        highlighted_sequence.push_str(&String::from_utf8_lossy(&sequence[last_position..]));
+        highlighted_sequence.push_str(&format!("...{}bp...", sequence.len() - last_position));
     }
-    
-    // Smart truncation: preserve ANSI escape sequence integrity
-    if highlighted_sequence.len() > 200 {
-        let truncated = smart_truncate_preserve_ansi(&highlighted_sequence, 200);
-        println!("Sequence: {}", truncated);
-    } else {
-        println!("Sequence: {}", highlighted_sequence);
+    let label = "Sequence: ";
+    if width > label.len() {
+        highlighted_sequence = truncate_ansi(&highlighted_sequence, width - label.len());
     }
+    println!("{}{}", label, highlighted_sequence);
     
-    // Output detected pattern information
-    print!("Detected patterns: ");
-    for (i, split_type) in split_types.iter().enumerate() {
-        if i > 0 {
-            print!(" ");
-        }
-        
-        if split_type.left_matcher.status {
-            print!("({},{},{},{})", 
-                split_type.pattern_name,
-                split_type.left_matcher.get_score(),
-                split_type.left_matcher.ystart,
-                split_type.left_matcher.yend
-            );
-        }
-        
-        if split_type.right_matcher.status {
-            if split_type.left_matcher.status {
-                print!(" ");
+    println!("Detected patterns: {}", detected_patterns(split_types));
+    println!(); // Empty line separator
+}
+
+/// Detected match positions as (start, end, round), sorted by start
+pub fn barcode_positions(split_types: &[crate::splitter::SplitType], sequence_length: usize) -> Vec<(usize, usize, usize)> {
+    let mut barcode_positions = Vec::new();
+    for (round, split_type) in split_types.iter().enumerate() {
+        for matcher in [&split_type.left_matcher, &split_type.right_matcher] {
+            if matcher.status {
+                barcode_positions.push((matcher.ystart, matcher.yend.min(sequence_length), round));
             }
-            print!("({},{},{},{})", 
-                split_type.pattern_name,
-                split_type.right_matcher.get_score(),
-                split_type.right_matcher.ystart,
-                split_type.right_matcher.yend
-            );
         }
     }
-    println!();
-    println!(); // Empty line separator
+    barcode_positions.sort_by_key(|x| x.0);
+    barcode_positions
 }
 
-/// Smart truncate string while preserving ANSI escape sequence integrity
-fn smart_truncate_preserve_ansi(text: &str, max_length: usize) -> String {
-    if text.len() <= max_length {
-        return text.to_string();
-    }
-    
-    // Simple truncation: take first 100 characters + "..." + last 100 characters
-    let front_length = 100;
-    let back_length = 100;
-    
-    if text.len() <= front_length + back_length + 3 {
-        return text.to_string();
-    }
-    
-    let front = &text[..front_length];
-    let back = &text[text.len()-back_length..];
-    
-    format!("{}...{}", front, back)
+/// Detected patterns as (name,score,start,end) groups colored by round
+pub fn detected_patterns(split_types: &[crate::splitter::SplitType]) -> String {
+    let rounds: Vec<String> = split_types
+        .iter()
+        .enumerate()
+        .map(|(round, split_type)| {
+            let matches: Vec<String> = [&split_type.left_matcher, &split_type.right_matcher]
+                .into_iter()
+                .filter(|matcher| matcher.status)
+                .map(|matcher| {
+                    format!("({},{},{},{})", split_type.pattern_name, matcher.get_score(), matcher.ystart, matcher.yend)
+                })
+                .collect();
+            format!("{}{}{}", round_color(round), matches.join(" "), COLOR_END)
+        })
+        .collect();
+    rounds.join(" ")
 }
 
 impl PatternConfiguration {
@@ -183,20 +317,7 @@ impl PatternConfiguration {
                 pattern_files.clone(), 
                 *use_position_info
             ),
-            _ => return PatternConfiguration {
-                window_size: vec![400, 400],
-                pattern_match_types: vec!["single".to_string()],
-                pattern_arguments: vec![],
-                trim_mode: 0,
-                write_type: "names".to_string(),
-                pattern_error_rates: vec![(0.2, 0.2)],
-                max_distances: vec![4],
-                position_shifts: vec![3],
-                min_length: 100,
-                id_separator: "%".to_string(),
-                fusion_database: crate::pattern::FusionDatabase::new(),
-                fusion_error_rate: 0.2,
-            },
+            _ => return PatternConfiguration::default(),
         };
         
         let mut pattern_config = PatternConfiguration {
@@ -210,24 +331,31 @@ impl PatternConfiguration {
             position_shifts,
             min_length,
             id_separator,
-            fusion_database: crate::pattern::FusionDatabase::new(),
-            fusion_error_rate: 0.2,
+            ..PatternConfiguration::default()
         };
         
         pattern_config.normalize_vectors();
         
         // Load pattern database
         info!("Loading pattern database file: {}", pattern_db_file);
-        for pattern_file in &pattern_files {
+        for (round, pattern_file) in pattern_files.iter().enumerate() {
             let mut pattern_database = crate::pattern::PatternDatabase::new();
-            pattern_database.load_patterns(&pattern_db_file, pattern_file);
+            pattern_database.load_patterns(&pattern_db_file, "auto", pattern_file, &[], false, false);
             
+            let (pattern_error_rate, max_distance, position_shift) = pattern_config.round_parameters(round);
             let pattern_argument = crate::pattern::PatternArgument {
                 pattern_database,
                 use_position_info,
-                pattern_error_rate: pattern_config.pattern_error_rates[0],
-                max_distance: pattern_config.max_distances[0],
-                position_shift: pattern_config.position_shifts[0],
+                pattern_error_rate,
+                max_edits: pattern_config.round_max_edits(round),
+                effective_length: crate::myers::EffectiveLength::default(),
+                max_distance,
+                position_shift,
+                early_exit_score: 0,
+                anchor: 0,
+                dual_policy: "best-score".to_string(),
+                min_score_margin: 0,
+                fixed_position: None,
             };
             pattern_config.pattern_arguments.push(pattern_argument);
         }
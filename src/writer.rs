@@ -1,169 +1,900 @@
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::Write;
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, Write};
 use flate2::write::GzEncoder;
 use flate2::Compression;
-use log::{info,debug};
-use std::io::Result;
-use std::path::Path;
+use log::{error, info};
+use std::io::{Error, Result};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
 use std::fs::create_dir_all;
+use crate::checksum::{ChecksumRegistry, ChecksumWriter, FileChecksum};
 use crate::fastq::ReadInfo;
-use crate::thread_pool::ThreadPoolManager;
+use crate::parquet::ParquetReadLog;
+use crate::thread_pool::{TaskQueue, ThreadPoolManager};
 use std::io::BufWriter;
-use std::thread;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use flume::{Receiver, Sender, unbounded};
 
+/// Open output: its read queue, drained by writing tasks on the thread pool
+struct OutputWriter {
+    sender: Sender<ReadInfo>,
+    output: Arc<OutputQueue>,
+    /// Output path, used in error messages
+    path: PathBuf,
+    /// Write counter value at last use, for LRU eviction
+    last_used: u64,
+    /// Reads queued since the output was opened
+    queued: u64,
+}
+
+impl OutputWriter {
+    /// Queue a read, scheduling a writing task unless one is already pending
+    fn send(&mut self, read_info: ReadInfo, task_queue: &TaskQueue) {
+        self.queued += 1;
+        self.sender.send(read_info).expect("Output queue is owned by its writer");
+        if !self.output.scheduled.swap(true, Ordering::AcqRel) {
+            let output = Arc::clone(&self.output);
+            task_queue.execute_writing(move || output.drain());
+        }
+    }
+
+    /// Close the queue and finish the file on the thread pool
+    fn close(self, task_queue: &TaskQueue) -> PendingClose {
+        drop(self.sender);
+        let (result_sender, result_receiver) = flume::bounded(1);
+        let output = self.output;
+        task_queue.execute_writing(move || {
+            let _ = result_sender.send(output.finish());
+        });
+        PendingClose { path: self.path, queued: self.queued, result_receiver }
+    }
+}
+
+/// Output being finished by a writing task
+struct PendingClose {
+    path: PathBuf,
+    /// Reads lost if the output fails
+    queued: u64,
+    result_receiver: Receiver<Result<()>>,
+}
+
+impl PendingClose {
+    /// Wait for the file to be finished
+    fn wait(self) -> Result<()> {
+        let path = self.path;
+        match self.result_receiver.recv() {
+            Ok(result) => result.map_err(|error| Error::new(error.kind(), format!("{}: {}", path.display(), error))),
+            Err(_) => Err(Error::other(format!("Writing task for {} panicked", path.display()))),
+        }
+    }
+}
+
+/// Destination of one output
+enum OutputSink {
+    /// Gzip file with an optional untrimmed copy and second read file
+    Gzip { writer: Box<GzFile>, raw_writer: Option<Box<GzFile>>, mate_writer: Option<Box<GzFile>> },
+    /// Uncompressed FASTQ fed to a shell command
+    Pipe { writer: BufWriter<ChildStdin>, child: Child },
+    /// Gzip stream uploaded by `aws s3 cp`
+    Upload { writer: Box<BufWriter<GzEncoder<ChildStdin>>>, child: Child },
+    /// Uncompressed standard output
+    Stdout(BufWriter<std::io::Stdout>),
+}
+
+impl OutputSink {
+    /// Write every read queued so far
+    fn write_available(&mut self, receiver: &Receiver<ReadInfo>, low_latency: bool, interleaved: bool) -> Result<()> {
+        match self {
+            OutputSink::Gzip { writer, raw_writer, mate_writer } => {
+                write_records(writer, raw_writer.as_mut(), mate_writer.as_mut(), receiver, low_latency, interleaved)
+            }
+            OutputSink::Pipe { writer, .. } => write_records(writer, None::<&mut File>, None::<&mut File>, receiver, low_latency, interleaved),
+            OutputSink::Upload { writer, .. } => write_records(writer, None::<&mut File>, None::<&mut File>, receiver, low_latency, interleaved),
+            OutputSink::Stdout(writer) => write_records(writer, None::<&mut File>, None::<&mut File>, receiver, low_latency, interleaved),
+        }
+    }
+
+    /// Flush the output, close gzip files and wait for commands to exit
+    fn finish(self) -> Result<()> {
+        match self {
+            OutputSink::Gzip { writer, raw_writer, mate_writer } => {
+                finish_gz_writer(*writer)?;
+                for extra_writer in [raw_writer, mate_writer].into_iter().flatten() {
+                    finish_gz_writer(*extra_writer)?;
+                }
+                Ok(())
+            }
+            OutputSink::Pipe { writer, child } => {
+                // Dropping the pipe signals end of input to the command
+                drop(writer.into_inner().map_err(|error| error.into_error())?);
+                wait_for_command(child)
+            }
+            OutputSink::Upload { writer, child } => {
+                let encoder = writer.into_inner().map_err(|error| error.into_error())?;
+                drop(encoder.finish()?);
+                wait_for_command(child)
+            }
+            OutputSink::Stdout(mut writer) => writer.flush(),
+        }
+    }
+}
+
+/// Wait for an output command, failing unless it exits successfully
+fn wait_for_command(mut child: Child) -> Result<()> {
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::other(format!("output command failed: {}", status)))
+    }
+}
+
+/// Read queue and destination of one output, written by one task at a time
+struct OutputQueue {
+    receiver: Receiver<ReadInfo>,
+    /// Destination, taken when the output is finished, and the first write error
+    sink: Mutex<(Option<OutputSink>, Option<Error>)>,
+    /// Whether a drain task is queued or running
+    scheduled: AtomicBool,
+    /// Set once a write failed, checked before queueing more reads
+    failed: AtomicBool,
+    low_latency: bool,
+    interleaved: bool,
+}
+
+impl OutputQueue {
+    /// Create queue writing to `sink`
+    fn new(sink: OutputSink, options: &WriterOptions) -> (Sender<ReadInfo>, Arc<Self>) {
+        let (sender, receiver) = unbounded();
+        let output = Arc::new(Self {
+            receiver,
+            sink: Mutex::new((Some(sink), None)),
+            scheduled: AtomicBool::new(false),
+            failed: AtomicBool::new(false),
+            low_latency: options.low_latency,
+            interleaved: options.interleaved,
+        });
+        (sender, output)
+    }
+
+    /// Write queued reads until the queue is empty, keeping the scheduled flag consistent
+    fn drain(&self) {
+        loop {
+            self.write_available();
+            self.scheduled.store(false, Ordering::Release);
+            // A read queued after the last write may have seen the flag still set
+            if self.failed.load(Ordering::Acquire)
+                || self.receiver.is_empty()
+                || self.scheduled.swap(true, Ordering::AcqRel)
+            {
+                break;
+            }
+        }
+    }
+
+    /// Write queued reads, recording the first error
+    fn write_available(&self) {
+        let mut guard = self.sink.lock().unwrap();
+        let (sink, write_error) = &mut *guard;
+        if write_error.is_some() {
+            return;
+        }
+        if let Some(sink) = sink
+            && let Err(error) = sink.write_available(&self.receiver, self.low_latency, self.interleaved)
+        {
+            *write_error = Some(error);
+            self.failed.store(true, Ordering::Release);
+        }
+    }
+
+    /// Write the remaining reads and close the destination
+    fn finish(&self) -> Result<()> {
+        self.write_available();
+        let mut guard = self.sink.lock().unwrap();
+        let (sink, write_error) = &mut *guard;
+        if let Some(error) = write_error.take() {
+            return Err(error);
+        }
+        sink.take().map_or(Ok(()), OutputSink::finish)
+    }
+}
+
+/// File wrapper counting the bytes handed to the operating system
+struct CountingFile {
+    file: File,
+    /// File length when opened, non-zero when appending
+    start: u64,
+    written: u64,
+    /// Digest of the written bytes with --checksums
+    checksum: Option<FileChecksum>,
+}
+
+impl Write for CountingFile {
+    fn write(&mut self, buffer: &[u8]) -> Result<usize> {
+        let written = self.file.write(buffer)?;
+        self.written += written as u64;
+        if let Some(checksum) = self.checksum.as_mut() {
+            checksum.update(&buffer[..written]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Buffered gzip output file
+type GzWriter = BufWriter<GzEncoder<CountingFile>>;
+
+/// Gzip output file, compressed on the writing thread or by an external command
+enum GzFile {
+    Internal(GzWriter),
+    /// Uncompressed records streamed into a compressor writing the file, e.g. pigz
+    External { writer: BufWriter<ChildStdin>, child: Child },
+}
+
+impl Write for GzFile {
+    fn write(&mut self, buffer: &[u8]) -> Result<usize> {
+        match self {
+            GzFile::Internal(writer) => writer.write(buffer),
+            GzFile::External { writer, .. } => writer.write(buffer),
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match self {
+            GzFile::Internal(writer) => writer.flush(),
+            GzFile::External { writer, .. } => writer.flush(),
+        }
+    }
+}
+
+/// Output options for the file write manager
+#[derive(Debug, Clone, Default)]
+pub struct WriterOptions {
+    /// Also write untrimmed records under raw/
+    pub emit_raw_copy: bool,
+    /// Use small buffers and flush whenever a writer runs idle
+    pub low_latency: bool,
+    /// Maximum number of simultaneously open writers, 0 for no limit
+    pub max_open_files: usize,
+    /// Write every record to standard output instead of per-sample files
+    pub stdout: bool,
+    /// Outputs with fewer reads than this go to low_count.fq.gz, 0 or 1 to disable
+    pub min_reads_per_barcode: usize,
+    /// Shell command each output is piped to, `{name}` standing for its output path without suffix
+    pub pipe: Option<String>,
+    /// `s3://` prefix outputs are uploaded below instead of being written locally
+    pub output_url: Option<String>,
+    /// Split the read log by output instead of writing one reads_log.gz
+    pub shard_read_log: bool,
+    /// Pattern rounds named in the read log header
+    pub read_log_rounds: usize,
+    /// Write the read log at all
+    pub read_log: bool,
+    /// Gzip level of the read log, 0 to 9
+    pub log_compression_level: u32,
+    /// Reads per read log file, 0 for a single file
+    pub rotate_read_log: u64,
+    /// Write the second read of each pair to `<output>_R2.fq.gz`
+    pub paired_output: bool,
+    /// Write the second read of each pair right after the first, to the same output
+    pub interleaved: bool,
+    /// Shell command compressing its standard input into each output file, None to compress in process
+    pub external_compressor: Option<String>,
+    /// Also write one JSON object per read to reads_log.jsonl.gz
+    pub json_log: bool,
+    /// Write the read log as reads_log.parquet instead of reads_log.gz
+    pub parquet_read_log: bool,
+    /// Checksum algorithm of checksums.txt, `md5` or `sha256`, None to skip it
+    pub checksums: Option<String>,
+}
+
+/// Key of the single writer used in stdout mode
+const STDOUT_WRITER: &str = "-";
+
+/// Output receiving the reads of outputs below the minimum read count
+pub const LOW_COUNT_OUTPUT: &str = "low_count";
+
+/// Read log shard of the reads that are not written
+const UNWRITTEN_READ_LOG: &str = "unwritten";
+
 /// File write manager
 pub struct FileWriterManager {
-    /// Writer mapping
-    writers: HashMap<String, Sender<ReadInfo>>,
+    /// Open writers by output filename
+    writers: HashMap<String, OutputWriter>,
+    /// Output filenames created during this run, reopened in append mode
+    created_files: HashSet<String>,
+    /// Output options
+    options: WriterOptions,
+    /// Number of records written, used as LRU clock
+    write_counter: u64,
+    /// Reads held back until their output reaches the minimum read count
+    pending_reads: HashMap<String, Vec<ReadInfo>>,
+    /// Outputs that reached the minimum read count and are written directly
+    released_outputs: HashSet<String>,
+    /// Outputs whose writing failed, their further reads are counted as failed
+    failed_outputs: HashSet<String>,
+    /// Reads that could not be written because their output failed
+    failed_reads: u64,
+    /// First write error, returned when the manager is finalized
+    first_error: Option<Error>,
     /// Output directory
     output_directory: String,
+    /// Thread pool queue running the writing tasks
+    task_queue: TaskQueue,
+    /// Digests of the written files with --checksums
+    checksums: Option<Arc<ChecksumRegistry>>,
     /// Logger
-    pub logger: Vec<String>,
-    /// Thread handles
-    thread_handles: Vec<thread::JoinHandle<()>>,
+    pub logger: ReadLog,
+    /// JSON read log, with --json-log
+    pub json_logger: Option<ReadLog>,
 }
 
 impl FileWriterManager {
 
-    /// Create controlled file write manager with thread pool management
+    /// Create controlled file write manager writing on the thread pool
     pub fn new_controlled(
         output_directory: String, 
-        _max_writing_threads: usize,
-        _thread_pool: &mut ThreadPoolManager
+        max_writing_threads: usize,
+        thread_pool: &ThreadPoolManager,
+        options: WriterOptions,
+        scratch_directory: &Path,
     ) -> Self {
-        info!("Creating controlled file writer manager, max writing threads: {}", _max_writing_threads);
+        info!("Creating controlled file writer manager, preferred writing threads: {}", max_writing_threads);
+        let checksums = options.checksums.as_deref().map(ChecksumRegistry::new);
+        let json_log = options.json_log;
         Self {
             writers: HashMap::new(),
+            created_files: HashSet::new(),
+            options,
+            write_counter: 0,
+            pending_reads: HashMap::new(),
+            released_outputs: HashSet::new(),
+            failed_outputs: HashSet::new(),
+            failed_reads: 0,
+            first_error: None,
             output_directory,
-            logger: Vec::new(),
-            thread_handles: Vec::new(),
+            task_queue: thread_pool.task_queue(),
+            checksums,
+            logger: ReadLog::new(scratch_directory, "reads_log.tsv"),
+            json_logger: json_log.then(|| ReadLog::new(scratch_directory, "reads_log.jsonl")),
         }
     }
 
 
     /// Write sequence information with controlled thread management
-    pub fn write_controlled(&mut self, read_info: ReadInfo, thread_pool: &mut ThreadPoolManager) -> Result<()> {
+    pub fn write_controlled(&mut self, read_info: ReadInfo) -> Result<()> {
         if !read_info.should_write_to_fastq {
             return Ok(());
         }
         
-        let output_filename = read_info.output_filename.clone();
+        // In stdout mode every record goes through one writer
+        if self.options.stdout {
+            return self.send_to_writer(STDOUT_WRITER.to_string(), read_info);
+        }
         
+        // Hold reads back until their output is known to reach the minimum read count
+        let output_filename = read_info.output_filename.clone();
+        if self.options.min_reads_per_barcode > 1 && !self.released_outputs.contains(&output_filename) {
+            let pending = self.pending_reads.entry(output_filename.clone()).or_default();
+            pending.push(read_info);
+            if pending.len() < self.options.min_reads_per_barcode {
+                return Ok(());
+            }
+            let pending = self.pending_reads.remove(&output_filename).unwrap_or_default();
+            self.released_outputs.insert(output_filename.clone());
+            for read_info in pending {
+                self.send_to_writer(output_filename.clone(), read_info)?;
+            }
+            return Ok(());
+        }
+        self.send_to_writer(output_filename, read_info)
+    }
+    
+    /// Write reads of outputs that stayed below the minimum read count to low_count.fq.gz
+    pub fn write_low_count_reads(&mut self) -> Result<()> {
+        if self.pending_reads.is_empty() {
+            return Ok(());
+        }
+        let mut pending_reads: Vec<(String, Vec<ReadInfo>)> = self.pending_reads.drain().collect();
+        pending_reads.sort_by(|left, right| left.0.cmp(&right.0));
+        let read_count: usize = pending_reads.iter().map(|(_, reads)| reads.len()).sum();
+        info!(
+            "{} outputs with fewer than {} reads ({} reads) written to {}.fq.gz",
+            pending_reads.len(),
+            self.options.min_reads_per_barcode,
+            read_count,
+            LOW_COUNT_OUTPUT
+        );
+        for (_, reads) in pending_reads {
+            for read_info in reads {
+                self.send_to_writer(LOW_COUNT_OUTPUT.to_string(), read_info)?;
+            }
+        }
+        Ok(())
+    }
+    
+    /// Hand a read to the writer of `output_filename`, opening it when needed
+    fn send_to_writer(&mut self, output_filename: String, read_info: ReadInfo) -> Result<()> {
+        if self.failed_outputs.contains(&output_filename) {
+            self.failed_reads += 1;
+            return Ok(());
+        }
         if !self.writers.contains_key(&output_filename) {
-            self.create_writer_for_filename_controlled(&output_filename, thread_pool);
+            if self.options.stdout {
+                self.create_stdout_writer();
+            } else {
+                if self.options.max_open_files > 0 && self.writers.len() >= self.options.max_open_files {
+                    self.evict_least_recently_used()?;
+                }
+                self.create_writer_for_filename_controlled(&output_filename);
+            }
         }
         
-        if let Some(sender) = self.writers.get(&output_filename) {
-            sender.send(read_info)
-                .expect("Failed to send sequence information to writer");
+        self.write_counter += 1;
+        // A failed output reports its write error when closed
+        if self.writers.get(&output_filename).is_some_and(|output_writer| output_writer.output.failed.load(Ordering::Acquire))
+            && let Some(output_writer) = self.writers.remove(&output_filename)
+        {
+            let pending_close = output_writer.close(&self.task_queue);
+            self.finish_close(output_filename, pending_close);
+            self.failed_reads += 1;
+            return Ok(());
         }
+        if let Some(output_writer) = self.writers.get_mut(&output_filename) {
+            output_writer.last_used = self.write_counter;
+            output_writer.send(read_info, &self.task_queue);
+        }
+        
+        Ok(())
+    }
+    
+    /// Close the least recently used writer and wait for its file to be finished
+    fn evict_least_recently_used(&mut self) -> Result<()> {
+        let least_recently_used = self.writers
+            .iter()
+            .min_by_key(|(_, output_writer)| output_writer.last_used)
+            .map(|(output_filename, _)| output_filename.clone());
         
+        if let Some(output_filename) = least_recently_used
+            && let Some(output_writer) = self.writers.remove(&output_filename)
+        {
+            let pending_close = output_writer.close(&self.task_queue);
+            self.finish_close(output_filename, pending_close);
+        }
         Ok(())
     }
+    
+    /// Wait for a closed output, counting its reads as failed if it could not be written
+    fn finish_close(&mut self, output_filename: String, pending_close: PendingClose) {
+        let queued = pending_close.queued;
+        if let Err(error) = pending_close.wait() {
+            error!("Failed to write output file {} ({} reads not written)", error, queued);
+            self.failed_reads += queued;
+            self.failed_outputs.insert(output_filename);
+            self.first_error.get_or_insert(error);
+        }
+    }
+    
+    /// Reads that could not be written to their output
+    pub fn failed_reads(&self) -> u64 {
+        self.failed_reads
+    }
+
 
+    /// Create the single uncompressed writer for stdout mode
+    fn create_stdout_writer(&mut self) {
+        let buffer_size = if self.options.low_latency { LOW_LATENCY_BUFFER_SIZE } else { BUFFER_SIZE };
+        let sink = OutputSink::Stdout(BufWriter::with_capacity(buffer_size, std::io::stdout()));
+        let (sender, output) = OutputQueue::new(sink, &self.options);
+        self.writers.insert(STDOUT_WRITER.to_string(), OutputWriter {
+            sender,
+            output,
+            path: PathBuf::from("standard output"),
+            last_used: self.write_counter,
+            queued: 0,
+        });
+    }
 
-    /// Create controlled writer for filename with thread pool management
-    fn create_writer_for_filename_controlled(&mut self, output_filename: &str, thread_pool: &mut ThreadPoolManager) {
-        // Check if new writing thread can be created
-        if !thread_pool.can_spawn_thread() {
-            // info!("Cannot create new writing thread, thread pool is full");
+    /// Create controlled writer for filename, written by tasks on the thread pool
+    fn create_writer_for_filename_controlled(&mut self, output_filename: &str) {
+        // Files closed by eviction get another gzip member appended
+        let append = !self.created_files.insert(output_filename.to_string());
+        
+        let buffer_size = if self.options.low_latency { LOW_LATENCY_BUFFER_SIZE } else { BUFFER_SIZE };
+        if let Some((sink, path)) = self.create_command_sink(output_filename, buffer_size) {
+            self.insert_writer(output_filename, sink, path);
             return;
         }
-
-        let (sender, receiver) = unbounded();
+        
         let file_path = Path::new(&self.output_directory)
             .join(format!("{}.fq.gz", output_filename));
-        let file_directory = file_path.parent().unwrap();
-        
-        create_dir_all(&file_directory)
-            .expect("Failed to create output directory");
-        
-        let file = File::create(&file_path)
-            .expect("Failed to create output file");
+        let writer = Box::new(create_gz_writer(&file_path, buffer_size, append, self.options.external_compressor.as_deref(), self.checksums.as_ref()));
         
-        let encoder = GzEncoder::new(file, Compression::default());
-        let writer = BufWriter::with_capacity(256_000, encoder); // Further reduced to 256KB for memory optimization
-        
-        self.start_writing_thread_controlled(writer, receiver, thread_pool);
-        self.writers.insert(output_filename.to_string(), sender);
-    }
-
-
-    /// Start controlled write thread with thread pool management - memory optimized
-    fn start_writing_thread_controlled(&mut self, mut writer: BufWriter<GzEncoder<File>>, receiver: Receiver<ReadInfo>, thread_pool: &mut ThreadPoolManager) {
-        if let Some(handle) = thread_pool.spawn_controlled_thread(move || {
-            for read_info in receiver.iter() {
-                if let Some(output_record) = read_info.get_output_record() {
-                    let record_id = output_record.id();
-                    let sequence = std::str::from_utf8(output_record.seq())
-                        .expect("Sequence is not valid UTF-8");
-                    let quality = std::str::from_utf8(output_record.qual())
-                        .expect("Quality scores are not valid UTF-8");
-                    
-                    let record_string = format!("@{}\n{}\n+\n{}\n", record_id, sequence, quality);
-                    write!(writer, "{}", record_string)
-                        .expect("Failed to write sequence record");
-                }
-            }
-        }) {
-            self.thread_handles.push(handle);
+        // Raw copies mirror the trimmed layout under raw/
+        let raw_writer = if self.options.emit_raw_copy {
+            let raw_file_path = Path::new(&self.output_directory)
+                .join("raw")
+                .join(format!("{}.fq.gz", output_filename));
+            Some(Box::new(create_gz_writer(&raw_file_path, buffer_size, append, self.options.external_compressor.as_deref(), self.checksums.as_ref())))
         } else {
-            info!("Cannot create controlled writing thread");
+            None
+        };
+        let mate_writer = self.options.paired_output.then(|| {
+            let mate_file_path = Path::new(&self.output_directory).join(format!("{}_R2.fq.gz", output_filename));
+            Box::new(create_gz_writer(&mate_file_path, buffer_size, append, self.options.external_compressor.as_deref(), self.checksums.as_ref()))
+        });
+        
+        self.insert_writer(output_filename, OutputSink::Gzip { writer, raw_writer, mate_writer }, file_path);
+    }
+    
+    /// Start the --pipe command or S3 upload of an output, None when writing local files
+    fn create_command_sink(&self, output_filename: &str, buffer_size: usize) -> Option<(OutputSink, PathBuf)> {
+        if let Some(template) = &self.options.pipe {
+            let name = Path::new(&self.output_directory).join(output_filename);
+            create_dir_all(name.parent().unwrap()).expect("Failed to create output directory");
+            let command = template.replace("{name}", &name.display().to_string());
+            let mut child = spawn_output_command(Command::new("sh").args(["-c", &command]), &command);
+            let writer = BufWriter::with_capacity(buffer_size, child.stdin.take().expect("Command input is piped"));
+            return Some((OutputSink::Pipe { writer, child }, PathBuf::from(command)));
         }
+        let output_url = self.options.output_url.as_ref()?;
+        let url = format!("{}/{}.fq.gz", output_url.trim_end_matches('/'), output_filename);
+        let mut child = spawn_output_command(Command::new("aws").args(["s3", "cp", "--only-show-errors", "-", &url]), &url);
+        let encoder = GzEncoder::new(child.stdin.take().expect("Command input is piped"), Compression::default());
+        let writer = Box::new(BufWriter::with_capacity(buffer_size, encoder));
+        Some((OutputSink::Upload { writer, child }, PathBuf::from(url)))
+    }
+    
+    /// Register an opened output
+    fn insert_writer(&mut self, output_filename: &str, sink: OutputSink, path: PathBuf) {
+        let (sender, output) = OutputQueue::new(sink, &self.options);
+        self.writers.insert(output_filename.to_string(), OutputWriter {
+            sender,
+            output,
+            path,
+            last_used: self.write_counter,
+            queued: 0,
+        });
     }
 
     /// Write log file
-    pub fn write_log_file(&self, output_directory: &str) -> Result<()> {
+    pub fn write_log_file(&mut self, output_directory: &str) -> Result<()> {
+        self.write_json_log(output_directory)?;
+        if !self.options.read_log {
+            return Ok(());
+        }
         let directory_path = Path::new(output_directory);
-        create_dir_all(&directory_path)?;
+        create_dir_all(directory_path)?;
+        let header = ReadInfo::tsv_header(self.options.read_log_rounds);
+        if self.options.shard_read_log {
+            return self.write_log_shards(directory_path, &header);
+        }
+        if self.options.rotate_read_log > 0 {
+            return self.write_rotated_logs(directory_path, &header);
+        }
+        if self.options.parquet_read_log {
+            return self.write_parquet_log(directory_path);
+        }
         
         info!("Writing logs to reads_log.gz");
         let file_path = directory_path.join("reads_log.gz");
-        let file = File::create(file_path)?;
-        let mut encoder = GzEncoder::new(file, Compression::default());
+        let file = File::create(&file_path)?;
+        let file = ChecksumWriter::new(file, start_checksum(self.checksums.as_ref(), &file_path, false));
+        let mut encoder = GzEncoder::new(file, Compression::new(self.options.log_compression_level));
+        
+        writeln!(encoder, "{}", header)?;
+        self.logger.copy_to(&mut encoder)?;
         
-        for line in &self.logger {
-            encoder.write_all(line.as_ref())?;
-            encoder.write_all(b"\n")?;
+        encoder.finish()?.finish()?;
+        Ok(())
+    }
+    
+    /// Write the JSON read log to reads_log.jsonl.gz, if --json-log was given
+    fn write_json_log(&mut self, output_directory: &str) -> Result<()> {
+        let Some(json_logger) = self.json_logger.as_mut() else {
+            return Ok(());
+        };
+        info!("Writing JSON logs to reads_log.jsonl.gz");
+        let directory_path = Path::new(output_directory);
+        create_dir_all(directory_path)?;
+        let file_path = directory_path.join("reads_log.jsonl.gz");
+        let file = BufWriter::new(File::create(&file_path)?);
+        let file = ChecksumWriter::new(file, start_checksum(self.checksums.as_ref(), &file_path, false));
+        let mut encoder = GzEncoder::new(file, Compression::new(self.options.log_compression_level));
+        json_logger.copy_to(&mut encoder)?;
+        encoder.finish()?.finish()?;
+        Ok(())
+    }
+    
+    /// Write the log lines as typed columns to reads_log.parquet
+    fn write_parquet_log(&mut self, directory_path: &Path) -> Result<()> {
+        info!("Writing logs to reads_log.parquet");
+        let file_path = directory_path.join("reads_log.parquet");
+        let file = BufWriter::new(File::create(&file_path)?);
+        let file = ChecksumWriter::new(file, start_checksum(self.checksums.as_ref(), &file_path, false));
+        let mut parquet_log = ParquetReadLog::new(file, self.options.read_log_rounds)?;
+        for line in self.logger.lines()? {
+            parquet_log.push_line(&line?)?;
         }
+        parquet_log.finish()?.finish()?;
+        Ok(())
+    }
+    
+    /// Write the log lines to numbered files of --rotate-read-log reads each, every file with the header
+    fn write_rotated_logs(&mut self, directory_path: &Path, header: &str) -> Result<()> {
+        info!("Writing logs to reads_log.NNNN.gz, {} reads per file", self.options.rotate_read_log);
+        let compression = Compression::new(self.options.log_compression_level);
+        let checksums = self.checksums.clone();
+        let open_part = |part: u64| -> Result<GzEncoder<ChecksumWriter<BufWriter<File>>>> {
+            let file_path = directory_path.join(format!("reads_log.{:04}.gz", part));
+            let file = BufWriter::new(File::create(&file_path)?);
+            let file = ChecksumWriter::new(file, start_checksum(checksums.as_ref(), &file_path, false));
+            let mut encoder = GzEncoder::new(file, compression);
+            writeln!(encoder, "{}", header)?;
+            Ok(encoder)
+        };
         
-        encoder.finish()?;
+        let mut part = 1;
+        let mut encoder = open_part(part)?;
+        for (line_number, line) in self.logger.lines()?.enumerate() {
+            if line_number > 0 && (line_number as u64).is_multiple_of(self.options.rotate_read_log) {
+                encoder.finish()?.finish()?;
+                part += 1;
+                encoder = open_part(part)?;
+            }
+            writeln!(encoder, "{}", line?)?;
+        }
+        encoder.finish()?.finish()?;
+        Ok(())
+    }
+    
+    /// Write the log lines of each output to `<output>.reads_log.gz`
+    ///
+    /// Reads that are not written go to unwritten.reads_log.gz. With
+    /// --max-open-files every shard is closed once the limit is reached and
+    /// later lines are appended as a new gzip member.
+    fn write_log_shards(&mut self, directory_path: &Path, header: &str) -> Result<()> {
+        info!("Writing logs to <output>.reads_log.gz per output");
+        let mut shards: HashMap<String, GzEncoder<ChecksumWriter<BufWriter<File>>>> = HashMap::new();
+        let mut created_shards: HashSet<String> = HashSet::new();
+        for line in self.logger.lines()? {
+            let line = line?;
+            let output = match line.split('\t').nth(3) {
+                Some("-") | None => UNWRITTEN_READ_LOG,
+                Some(output) => output,
+            };
+            if !shards.contains_key(output) {
+                if self.options.max_open_files > 0 && shards.len() >= self.options.max_open_files {
+                    for (_, shard) in shards.drain() {
+                        shard.finish()?.finish()?;
+                    }
+                }
+                let file_path = directory_path.join(format!("{}.reads_log.gz", output));
+                create_dir_all(file_path.parent().unwrap_or(directory_path))?;
+                let created = created_shards.insert(output.to_string());
+                let file = OpenOptions::new().create(true).write(true).append(!created).truncate(created).open(&file_path)?;
+                let file = ChecksumWriter::new(BufWriter::new(file), start_checksum(self.checksums.as_ref(), &file_path, !created));
+                let mut shard = GzEncoder::new(file, Compression::new(self.options.log_compression_level));
+                if created {
+                    writeln!(shard, "{}", header)?;
+                }
+                shards.insert(output.to_string(), shard);
+            }
+            let shard = shards.get_mut(output).expect("Shard was just opened");
+            writeln!(shard, "{}", line)?;
+        }
+        for (_, shard) in shards {
+            shard.finish()?.finish()?;
+        }
         Ok(())
     }
     
-    /// Complete writing and wait for all threads to finish
-    pub fn finalize(&mut self) {
+    /// Complete writing and wait for all outputs to be finished
+    ///
+    /// Every writer is closed even when some fail; the first error is returned.
+    pub fn finalize(&mut self) -> Result<()> {
         info!("Writing FASTQ files, this may take some time...");
         
-        // Clear writers, this will cause receivers to disconnect
-        self.writers.clear();
-        
-        // Wait for all write threads to complete
-        for handle in self.thread_handles.drain(..) {
-            handle.join().expect("Writing thread panicked");
+        // Queue every close first so the files are finished in parallel
+        let pending_closes: Vec<(String, PendingClose)> = self.writers
+            .drain()
+            .map(|(output_filename, output_writer)| (output_filename, output_writer.close(&self.task_queue)))
+            .collect();
+        for (output_filename, pending_close) in pending_closes {
+            self.finish_close(output_filename, pending_close);
         }
+        self.first_error.take().map_or(Ok(()), Err)
     }
     
-    /// Clean up memory by clearing completed writers - optimized for performance
-    pub fn cleanup_memory(&mut self) {
-        // Only clean up completed thread handles if we have many
-        if self.thread_handles.len() > 100 {
-            self.thread_handles.retain(|handle| !handle.is_finished());
+    /// Write checksums.txt for the finished files, if --checksums was given
+    pub fn write_checksums(&self) -> Result<()> {
+        match &self.checksums {
+            Some(checksums) => checksums.write_table(&self.output_directory),
+            None => Ok(()),
         }
-        
+    }
+    
+    /// Reads queued for the writing tasks
+    pub fn pending_writes(&self) -> usize {
+        self.writers.values().map(|output_writer| output_writer.sender.len()).sum()
+    }
+    
+    /// Clean up memory - optimized for performance
+    pub fn cleanup_memory(&mut self) {
         // Only shrink if capacity is significantly larger than current size
-        if self.thread_handles.capacity() > self.thread_handles.len() * 3 && 
-           self.thread_handles.capacity() > 500 {
-            self.thread_handles.shrink_to_fit();
+        if self.writers.capacity() > self.writers.len() * 3 && 
+           self.writers.capacity() > 500 {
+            self.writers.shrink_to_fit();
         }
-        
-        // Clear logger only if it gets very large
-        if self.logger.len() > 500000 {
-            debug!("Clearing logger to free memory (size: {})", self.logger.len());
-            self.logger.clear();
+    }
+}
+
+/// Per-read TSV log spilled to a temporary file instead of memory
+pub struct ReadLog {
+    path: PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl ReadLog {
+    /// Create read log file `name` in the scratch directory
+    pub fn new(scratch_directory: &Path, name: &str) -> Self {
+        let path = scratch_directory.join(name);
+        let file = File::create(&path)
+            .expect("Failed to create temporary read log");
+        Self {
+            path,
+            writer: BufWriter::with_capacity(256_000, file),
         }
     }
     
-}
\ No newline at end of file
+    /// Append a log line
+    pub fn push(&mut self, line: String) {
+        self.writer.write_all(line.as_bytes())
+            .and_then(|_| self.writer.write_all(b"\n"))
+            .expect("Failed to write temporary read log");
+    }
+    
+    /// Copy all logged lines to a writer
+    fn copy_to<W: Write>(&mut self, output: &mut W) -> Result<()> {
+        self.writer.flush()?;
+        let mut file = File::open(&self.path)?;
+        std::io::copy(&mut file, output)?;
+        Ok(())
+    }
+    
+    /// Read back all logged lines
+    fn lines(&mut self) -> Result<std::io::Lines<std::io::BufReader<File>>> {
+        self.writer.flush()?;
+        Ok(std::io::BufReader::new(File::open(&self.path)?).lines())
+    }
+}
+
+/// Output buffer size
+const BUFFER_SIZE: usize = 256_000; // Further reduced to 256KB for memory optimization
+
+/// Output buffer size used with --low-latency
+const LOW_LATENCY_BUFFER_SIZE: usize = 16 * 1024;
+
+/// Start an output command reading records from its standard input
+fn spawn_output_command(command: &mut Command, description: &str) -> Child {
+    info!("Streaming output to: {}", description);
+    command
+        .stdin(Stdio::piped())
+        .spawn()
+        .unwrap_or_else(|error| panic!("Unable to start output command {}: {}", description, error))
+}
+
+/// Start hashing a file when checksums are collected
+fn start_checksum(checksums: Option<&Arc<ChecksumRegistry>>, file_path: &Path, append: bool) -> Option<FileChecksum> {
+    checksums.map(|checksums| checksums.start(file_path, append))
+}
+
+/// Create buffered gzip writer, creating parent directories as needed
+///
+/// In append mode a new gzip member is added after the existing content.
+/// With a compressor command the file becomes the standard output of
+/// `sh -c <command>`, which receives the uncompressed records.
+fn create_gz_writer(
+    file_path: &Path,
+    buffer_size: usize,
+    append: bool,
+    compressor: Option<&str>,
+    checksums: Option<&Arc<ChecksumRegistry>>,
+) -> GzFile {
+    let file_directory = file_path.parent().unwrap();
+    
+    create_dir_all(file_directory)
+        .expect("Failed to create output directory");
+    
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(file_path)
+        .expect("Failed to create output file");
+    
+    if let Some(compressor) = compressor {
+        // The compressor writes the file, so it is hashed once complete
+        if let Some(checksums) = checksums {
+            checksums.hash_when_written(file_path);
+        }
+        let description = format!("{} > {}", compressor, file_path.display());
+        let mut child = spawn_output_command(Command::new("sh").args(["-c", compressor]).stdout(file), &description);
+        let writer = BufWriter::with_capacity(buffer_size, child.stdin.take().expect("Command input is piped"));
+        return GzFile::External { writer, child };
+    }
+    let start = file.metadata().expect("Failed to read output file size").len();
+    let checksum = start_checksum(checksums, file_path, append);
+    let encoder = GzEncoder::new(CountingFile { file, start, written: 0, checksum }, Compression::default());
+    GzFile::Internal(BufWriter::with_capacity(buffer_size, encoder))
+}
+
+/// Flush buffered records, write the gzip trailer and check the file received every byte
+///
+/// Externally compressed files are done once their compressor exits successfully.
+fn finish_gz_writer(writer: GzFile) -> Result<()> {
+    let writer = match writer {
+        GzFile::Internal(writer) => writer,
+        GzFile::External { writer, child } => {
+            drop(writer.into_inner().map_err(|error| error.into_error())?);
+            return wait_for_command(child);
+        }
+    };
+    let encoder = writer.into_inner().map_err(|error| error.into_error())?;
+    let counting_file = encoder.finish()?;
+    counting_file.file.sync_data()?;
+    
+    let file_size = counting_file.file.metadata()?.len();
+    let expected_size = counting_file.start + counting_file.written;
+    if file_size != expected_size {
+        return Err(Error::other(format!(
+            "file holds {} bytes but {} were expected",
+            file_size, expected_size
+        )));
+    }
+    if let Some(checksum) = counting_file.checksum {
+        checksum.finish();
+    }
+    Ok(())
+}
+
+/// Write the records queued in a writer channel
+fn write_records<W: Write, R: Write, M: Write>(
+    writer: &mut W,
+    mut raw_writer: Option<&mut R>,
+    mut mate_writer: Option<&mut M>,
+    receiver: &Receiver<ReadInfo>,
+    low_latency: bool,
+    interleaved: bool,
+) -> Result<()> {
+    for read_info in receiver.try_iter() {
+        read_info.write_output_record(writer)?;
+        if interleaved {
+            read_info.write_mate_record(writer)?;
+        }
+        if let Some(raw_writer) = raw_writer.as_mut() {
+            read_info.write_raw_record(raw_writer)?;
+        }
+        if let Some(mate_writer) = mate_writer.as_mut() {
+            read_info.write_mate_record(mate_writer)?;
+        }
+    }
+    
+    // Push buffered records through to disk as soon as the queue runs dry
+    if low_latency {
+        writer.flush()?;
+        if let Some(raw_writer) = raw_writer.as_mut() {
+            raw_writer.flush()?;
+        }
+        if let Some(mate_writer) = mate_writer.as_mut() {
+            mate_writer.flush()?;
+        }
+    }
+    Ok(())
+}
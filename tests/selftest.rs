@@ -0,0 +1,10 @@
+use std::process::Command;
+
+#[test]
+fn test_selftest_passes() {
+    let output = Command::new(env!("CARGO_BIN_EXE_readchop"))
+        .args(["selftest", "--reads", "100"])
+        .output()
+        .expect("Failed to run readchop");
+    assert!(output.status.success(), "selftest failed:\n{}", String::from_utf8_lossy(&output.stderr));
+}